@@ -0,0 +1,52 @@
+//! Scalar vs SIMD IDCT
+//!
+//! Usage: cargo bench --bench idct --features simd
+//!
+//! Without the `simd` feature both entries run the scalar transform.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use tjpgdec_rs::bench::{block_idct, block_idct_scalar};
+
+/// 一批伪随机的反量化系数块，低频多、高频少，接近真实图像
+fn blocks() -> Vec<[i32; 64]> {
+    let mut seed = 0x1234_5678u32;
+    (0..1024)
+        .map(|n| {
+            let mut block = [0i32; 64];
+            for (i, v) in block.iter_mut().enumerate().take(1 + n % 64) {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                let amplitude = 4096 >> (i / 8);
+                *v = (seed >> 12) as i32 % (2 * amplitude) - amplitude;
+            }
+            block
+        })
+        .collect()
+}
+
+fn bench_idct(c: &mut Criterion) {
+    let blocks = blocks();
+    let mut group = c.benchmark_group("idct");
+    group.throughput(Throughput::Elements(blocks.len() as u64));
+
+    let mut out = [0i16; 64];
+    group.bench_function("scalar", |b| {
+        b.iter(|| {
+            for block in &blocks {
+                block_idct_scalar(&mut block.clone(), &mut out);
+                black_box(&out);
+            }
+        })
+    });
+    group.bench_function("simd", |b| {
+        b.iter(|| {
+            for block in &blocks {
+                block_idct(&mut block.clone(), &mut out);
+                black_box(&out);
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_idct);
+criterion_main!(benches);
@@ -134,7 +134,7 @@ foreach ($ModeInfo in $ModesToTest) {
     
     # Clean and rebuild
     $ErrorActionPreference = "Continue"
-    cmd /c "cargo build --example $RustExampleName --release --no-default-features --features $Feature 2>&1" | Out-Null
+    cmd /c "cargo build --example $RustExampleName --release --no-default-features --features std,$Feature 2>&1" | Out-Null
     $BuildResult = $LASTEXITCODE
     $ErrorActionPreference = "Stop"
     
@@ -143,7 +143,7 @@ foreach ($ModeInfo in $ModesToTest) {
     if ($BuildResult -ne 0 -or -not (Test-Path $RustExePath)) {
         # Try debug build
         $ErrorActionPreference = "Continue"
-        cmd /c "cargo build --example $RustExampleName --no-default-features --features $Feature 2>&1" | Out-Null
+        cmd /c "cargo build --example $RustExampleName --no-default-features --features std,$Feature 2>&1" | Out-Null
         $ErrorActionPreference = "Stop"
         $RustExePath = Join-Path $ProjectRoot "target\debug\examples\jpg2bmp.exe"
     }
@@ -0,0 +1,60 @@
+//! Benchmark: full `decompress()` vs header-less `decompress_fast()`
+//!
+//! Decodes the same JPEG repeatedly, simulating a fixed-format video stream
+//! where every frame has identical headers.
+//!
+//! Run: cargo run --release --example fast_frames [jpeg_file] [frames]
+
+use std::env;
+use std::time::Instant;
+use tjpgdec_rs::{JpegDecoder, MemoryPool, RECOMMENDED_POOL_SIZE, Result};
+
+fn main() -> Result<()> {
+    let args: Vec<String> = env::args().collect();
+    let jpeg_path = args.get(1).map(String::as_str).unwrap_or("test_images/test1.jpg");
+    let frames: u32 = args.get(2).and_then(|n| n.parse().ok()).unwrap_or(200);
+
+    let jpeg_data = match std::fs::read(jpeg_path) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("Error reading file '{}': {}", jpeg_path, e);
+            eprintln!("Usage: cargo run --release --example fast_frames [jpeg_file] [frames]");
+            std::process::exit(1);
+        }
+    };
+
+    let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+    let mut mcu_buffer = Vec::new();
+    let mut work_buffer = Vec::new();
+
+    // 完整流程：每帧重新解析头部
+    let start = Instant::now();
+    for _ in 0..frames {
+        let mut pool = MemoryPool::new(&mut pool_buffer);
+        let mut decoder = JpegDecoder::new();
+        decoder.prepare(&jpeg_data, &mut pool)?;
+        mcu_buffer.resize(decoder.mcu_buffer_size(), 0i16);
+        work_buffer.resize(decoder.work_buffer_size(), 0u8);
+        decoder.decompress(&jpeg_data, 0, &mut mcu_buffer, &mut work_buffer, &mut |_, _, _| Ok(true))?;
+    }
+    let full = start.elapsed();
+
+    // 快速流程：prepare 一次，之后只解码扫描数据
+    let mut pool = MemoryPool::new(&mut pool_buffer);
+    let mut decoder = JpegDecoder::new();
+    decoder.prepare(&jpeg_data, &mut pool)?;
+    let (scan_start, scan_end) = decoder.scan_range(&jpeg_data)?;
+    let scan = &jpeg_data[scan_start..scan_end + 2];
+
+    let start = Instant::now();
+    for _ in 0..frames {
+        decoder.decompress_fast(scan, 0, &mut mcu_buffer, &mut work_buffer, &mut |_, _, _| Ok(true))?;
+    }
+    let fast = start.elapsed();
+
+    println!("Image: {}x{}, {} frames", decoder.width(), decoder.height(), frames);
+    println!("decompress():      {:>8.1} fps", frames as f64 / full.as_secs_f64());
+    println!("decompress_fast(): {:>8.1} fps", frames as f64 / fast.as_secs_f64());
+
+    Ok(())
+}
@@ -7,7 +7,7 @@ use std::fs::File;
 use std::io::{Read, Write};
 use std::path::Path;
 
-use tjpgdec_rs::{JpegDecoder, Rectangle, MemoryPool, RECOMMENDED_POOL_SIZE, fastdecode_level};
+use tjpgdec_rs::{JpegDecoder, MemoryPool, RECOMMENDED_POOL_SIZE, fastdecode_level};
 
 /// BMP文件头 (14 bytes)
 #[repr(C, packed)]
@@ -92,9 +92,9 @@ fn save_bmp(filename: &str, framebuffer: &[u8], width: u32, height: u32) -> std:
         
         // RGB -> BGR 转换
         for x in 0..width as usize {
-            row_buffer[x * 3 + 0] = src_row[x * 3 + 2]; // B
+            row_buffer[x * 3] = src_row[x * 3 + 2]; // B
             row_buffer[x * 3 + 1] = src_row[x * 3 + 1]; // G
-            row_buffer[x * 3 + 2] = src_row[x * 3 + 0]; // R
+            row_buffer[x * 3 + 2] = src_row[x * 3]; // R
         }
         
         file.write_all(&row_buffer)?;
@@ -184,45 +184,17 @@ fn main() {
     println!("Image size: {} x {}", width, height);
     println!("Components: {}", components);
     
-    // 获取所需缓冲区大小
-    let mcu_size = decoder.mcu_buffer_size();
-    let work_size = decoder.work_buffer_size();
+    println!("Decompressing...");
     
-    println!("MCU buffer size: {} (i16 elements)", mcu_size);
-    println!("Work buffer size: {} bytes", work_size);
-    
-    // 分配缓冲区（使用内存节约版本）
-    let mut mcu_buffer = vec![0i16; mcu_size];
-    let mut work_buffer = vec![0u8; work_size];
-    let mut framebuffer = vec![0u8; (width * height * 3) as usize];
-    let fb_width = width as usize;
-    
-    // 解码回调函数 - 将MCU数据复制到framebuffer
-    let mut callback = |_decoder: &JpegDecoder, bitmap: &[u8], rect: &Rectangle| -> Result<bool, tjpgdec_rs::Error> {
-        let rect_width = (rect.right - rect.left + 1) as usize;
-        let bytes_per_row = rect_width * 3;
-        
-        for y in rect.top..=rect.bottom {
-            let src_offset = ((y - rect.top) as usize) * bytes_per_row;
-            let dst_offset = (y as usize) * fb_width * 3 + (rect.left as usize) * 3;
-            
-            if src_offset + bytes_per_row <= bitmap.len() 
-               && dst_offset + bytes_per_row <= framebuffer.len() {
-                framebuffer[dst_offset..dst_offset + bytes_per_row]
-                    .copy_from_slice(&bitmap[src_offset..src_offset + bytes_per_row]);
-            }
+    // 分配缓冲区、解码并拼接到 framebuffer
+    let (framebuffer, _, _) = match decoder.decode_to_vec(&jpeg_data, 0) {
+        Ok(result) => result,
+        Err(e) => {
+            println!("Error: decompress() failed: {:?}", e);
+            std::process::exit(1);
         }
-        
-        Ok(true) // 继续解码
     };
     
-    println!("Decompressing with external buffers (memory-efficient mode)...");
-    
-    if let Err(e) = decoder.decompress(&jpeg_data, 0, &mut mcu_buffer, &mut work_buffer, &mut callback) {
-        println!("Error: decompress() failed: {:?}", e);
-        std::process::exit(1);
-    }
-    
     println!("Decompression completed successfully!");
     
     // 保存为BMP
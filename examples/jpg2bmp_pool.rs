@@ -116,7 +116,7 @@ fn main() -> Result<()> {
     println!("Pool (internal structures): {} bytes", pool.used());
     println!("MCU buffer: {} bytes", mcu_size * 2);
     println!("Work buffer: {} bytes", work_size);
-    println!("Total: {} bytes", pool.used() + mcu_size * 2 + work_size);
+    println!("Total: {} bytes", pool.used() + decoder.external_memory_bytes());
     println!("\nC version comparison:");
     println!("  Pool size (JD_FASTDECODE=2): ~9644 bytes");
 
@@ -0,0 +1,43 @@
+//! JPEG to PPM/PGM converter
+//!
+//! Usage: cargo run --example jpg2ppm <input.jpg> [output.ppm] [scale]
+//!
+//! Color images are written as PPM, grayscale images as PGM. Both open in
+//! most image viewers (GIMP, IrfanView, feh, macOS Preview, ...).
+
+use std::env;
+
+use tjpgdec_rs::{decode_frame, write_pgm, write_ppm, OutputFormat};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("Usage: {} <input.jpg> [output.ppm] [scale 0-5]", args[0]);
+        std::process::exit(1);
+    }
+
+    let jpeg_data = std::fs::read(&args[1]).unwrap_or_else(|e| {
+        eprintln!("Error reading '{}': {}", args[1], e);
+        std::process::exit(1);
+    });
+    let scale = args.get(3).and_then(|s| s.parse().ok()).unwrap_or(0);
+
+    let frame = decode_frame(&jpeg_data, scale).unwrap_or_else(|e| {
+        eprintln!("Decode failed: {}", e);
+        std::process::exit(1);
+    });
+
+    let gray = frame.format() == OutputFormat::Grayscale;
+    let output = args.get(2).cloned().unwrap_or_else(|| if gray { "output.pgm" } else { "output.ppm" }.to_string());
+    let result = if gray {
+        write_pgm(&output, frame.as_bytes(), frame.width(), frame.height())
+    } else {
+        write_ppm(&output, frame.as_bytes(), frame.width(), frame.height())
+    };
+    if let Err(e) = result {
+        eprintln!("Error writing '{}': {}", output, e);
+        std::process::exit(1);
+    }
+
+    println!("{}x{} -> {}", frame.width(), frame.height(), output);
+}
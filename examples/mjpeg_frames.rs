@@ -0,0 +1,57 @@
+//! Motion JPEG: `prepare()` once, then `decompress_scan()` for every frame
+//!
+//! Treats the given JPEG files as consecutive frames of one MJPEG stream.
+//! The Huffman and quantization tables are built from the first frame only;
+//! later frames are decoded with them and take no memory from the pool.
+//! All frames must have the same size and tables.
+//!
+//! Run: cargo run --release --example mjpeg_frames [frame.jpg ...]
+
+use std::env;
+use tjpgdec_rs::{crc32, JpegDecoder, MemoryPool, RECOMMENDED_POOL_SIZE, Result};
+
+fn main() -> Result<()> {
+    let mut paths: Vec<String> = env::args().skip(1).collect();
+    if paths.is_empty() {
+        // 默认把同一张图当作两帧
+        paths = vec!["test_images/test1.jpg".into(), "test_images/test1.jpg".into()];
+    }
+
+    let mut frames = Vec::new();
+    for path in &paths {
+        match std::fs::read(path) {
+            Ok(data) => frames.push(data),
+            Err(e) => {
+                eprintln!("Error reading file '{}': {}", path, e);
+                eprintln!("Usage: cargo run --release --example mjpeg_frames [frame.jpg ...]");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+    let mut pool = MemoryPool::new(&mut pool_buffer);
+    let mut decoder = JpegDecoder::new();
+    decoder.prepare(&frames[0], &mut pool)?;
+    println!("Stream: {}x{}, tables use {} bytes of pool", decoder.width(), decoder.height(), pool.used());
+
+    let mut mcu_buffer = vec![0i16; decoder.mcu_buffer_size()];
+    let mut work_buffer = vec![0u8; decoder.work_buffer_size()];
+    let width = decoder.width() as usize;
+    let bpp = decoder.bytes_per_pixel();
+    let mut image = vec![0u8; width * decoder.height() as usize * bpp];
+
+    for (i, frame) in frames.iter().enumerate() {
+        decoder.decompress_scan(frame, 0, &mut mcu_buffer, &mut work_buffer, &mut |_, bitmap, rect| {
+            let line = rect.width() as usize * bpp;
+            for row in 0..rect.height() as usize {
+                let dst = ((rect.top as usize + row) * width + rect.left as usize) * bpp;
+                image[dst..dst + line].copy_from_slice(&bitmap[row * line..(row + 1) * line]);
+            }
+            Ok(true)
+        })?;
+        println!("Frame {}: {} bytes, pixel CRC-32 {:08X}, pool {} bytes", i, frame.len(), crc32(&image), pool.used());
+    }
+
+    Ok(())
+}
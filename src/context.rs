@@ -0,0 +1,218 @@
+//! Reusable decode context
+//!
+//! Bundles the decoder settings, the memory pool and the MCU/work buffers
+//! for pipelines that decode many frames (e.g. MJPEG). The buffers grow to
+//! fit the first frame and are reused afterwards; the pool is reset before
+//! every frame, so nothing is allocated while decoding same-sized frames.
+
+use crate::decoder::{JpegDecoder, OutputCallback, MAX_SCALE};
+use crate::pool::{MemoryPool, RECOMMENDED_POOL_SIZE};
+use crate::types::{Error, Result, Warning};
+use alloc::{vec, vec::Vec};
+
+/// Decoder, memory pool and scratch buffers in one reusable object
+///
+/// ```rust,no_run
+/// use tjpgdec_rs::{DecodeContext, OutputFormat};
+///
+/// # fn frames() -> Vec<Vec<u8>> { Vec::new() }
+/// let mut ctx = DecodeContext::new();
+/// ctx.decoder_mut().set_output_format(OutputFormat::Rgb565)?;
+/// for frame in frames() {
+///     ctx.decode(&frame, &mut |_decoder, bitmap, rect| {
+///         // Blit `bitmap` to `rect`
+///         Ok(true)
+///     })?;
+/// }
+/// # Ok::<(), tjpgdec_rs::Error>(())
+/// ```
+pub struct DecodeContext {
+    /// 每帧复制一份作为解码器，只保存设置，从不 prepare
+    settings: JpegDecoder<'static>,
+    scale: u8,
+    pool_buffer: Vec<u8>,
+    mcu_buffer: Vec<i16>,
+    work_buffer: Vec<u8>,
+    frame: Option<FrameInfo>,
+}
+
+/// What the per-frame decoder reported about the last decoded frame
+///
+/// Kept by `DecodeContext::decode()` after the frame's decoder (which
+/// borrows the context's pool) is dropped. Read it with
+/// `DecodeContext::frame()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameInfo {
+    /// Output width (`JpegDecoder::width()`)
+    pub width: u16,
+    /// Output height (`JpegDecoder::height()`)
+    pub height: u16,
+    /// EXIF orientation (`JpegDecoder::orientation()`)
+    pub orientation: u8,
+    /// Bytes of the frame consumed (`JpegDecoder::bytes_consumed()`)
+    pub bytes_consumed: usize,
+    /// The scan ended early and was filled (`JpegDecoder::was_truncated()`)
+    pub truncated: bool,
+    warnings: [Warning; Warning::COUNT],
+    warning_count: u8,
+}
+
+impl FrameInfo {
+    fn new(decoder: &JpegDecoder) -> Self {
+        let mut warnings = [Warning::RestartOutOfSequence; Warning::COUNT];
+        let found = decoder.warnings();
+        warnings[..found.len()].copy_from_slice(found);
+        Self {
+            width: decoder.width(),
+            height: decoder.height(),
+            orientation: decoder.orientation(),
+            bytes_consumed: decoder.bytes_consumed(),
+            truncated: decoder.was_truncated(),
+            warnings,
+            warning_count: found.len() as u8,
+        }
+    }
+
+    /// Non-fatal warnings collected while decoding (`JpegDecoder::warnings()`)
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings[..self.warning_count as usize]
+    }
+}
+
+impl DecodeContext {
+    /// Create a context with a `RECOMMENDED_POOL_SIZE` byte pool
+    pub fn new() -> Self {
+        Self::with_pool_size(RECOMMENDED_POOL_SIZE)
+    }
+
+    /// Create a context with a pool of `pool_size` bytes
+    pub fn with_pool_size(pool_size: usize) -> Self {
+        Self {
+            settings: JpegDecoder::new(),
+            scale: 0,
+            pool_buffer: vec![0; pool_size],
+            mcu_buffer: Vec::new(),
+            work_buffer: Vec::new(),
+            frame: None,
+        }
+    }
+
+    /// Get the decoder whose settings apply to every frame
+    ///
+    /// Configure output format, rotation, limits etc. here. The decoder
+    /// handed to the callback is a per-frame copy of it; don't call
+    /// `prepare()` on this one.
+    pub fn decoder_mut(&mut self) -> &mut JpegDecoder<'static> {
+        &mut self.settings
+    }
+
+    /// Get what the decoder reported about the last frame
+    ///
+    /// `None` before the first `decode()` and when the last frame's headers
+    /// couldn't be parsed. After a failed decompress it describes the frame
+    /// up to the point of failure.
+    pub fn frame(&self) -> Option<&FrameInfo> {
+        self.frame.as_ref()
+    }
+
+    /// Set the scale factor used by `decode()` (0=1/1, 1=1/2, 2=1/4, 3=1/8,
+    /// 4=1/16, 5=1/32)
+    pub fn set_scale(&mut self, scale: u8) -> Result<()> {
+        if scale > MAX_SCALE {
+            return Err(Error::Parameter);
+        }
+        self.scale = scale;
+        Ok(())
+    }
+
+    /// Decode one complete JPEG frame
+    ///
+    /// Resets the pool, parses the headers, grows the MCU and work buffers
+    /// if this frame needs more than any before, then decompresses through
+    /// `callback` like `JpegDecoder::decompress()`. The frame's dimensions,
+    /// warnings etc. are available from `frame()` afterwards.
+    pub fn decode(&mut self, data: &[u8], callback: OutputCallback) -> Result<()> {
+        self.frame = None;
+        let mut pool = MemoryPool::new(&mut self.pool_buffer);
+        let mut decoder = self.settings.clone();
+        decoder.prepare(data, &mut pool)?;
+
+        // 只增不减，同尺寸的帧不会重新分配
+        let mcu_size = decoder.mcu_buffer_size();
+        let work_size = decoder.work_buffer_size_for_scale(self.scale);
+        if self.mcu_buffer.len() < mcu_size {
+            self.mcu_buffer.resize(mcu_size, 0);
+        }
+        if self.work_buffer.len() < work_size {
+            self.work_buffer.resize(work_size, 0);
+        }
+
+        let result = decoder.decompress(data, self.scale, &mut self.mcu_buffer, &mut self.work_buffer, callback);
+        self.frame = Some(FrameInfo::new(&decoder));
+        result
+    }
+}
+
+impl Default for DecodeContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{blit_rect, decode, encode, pattern, EncodeOptions};
+    use crate::types::OutputFormat;
+
+    #[test]
+    fn test_reuse_across_frames() {
+        let opts = EncodeOptions { sampling: (2, 2), ..EncodeOptions::default() };
+        let frames = [
+            encode(32, 16, &pattern(32, 16, 3), &opts),
+            encode(32, 16, &[77u8; 32 * 16 * 3], &opts),
+        ];
+
+        let mut ctx = DecodeContext::new();
+        ctx.decoder_mut().set_output_format(OutputFormat::Rgb888).unwrap();
+        let mut buffers = None;
+        for frame in &frames {
+            let mut fb = vec![0u8; 32 * 16 * 3];
+            ctx.decode(frame, &mut |decoder, bitmap, rect| {
+                assert_eq!(decoder.width(), 32);
+                blit_rect(&mut fb, 32, 3, bitmap, rect);
+                Ok(true)
+            })
+            .unwrap();
+            assert_eq!(fb, decode(frame, 0, 3).2);
+
+            // 第二帧沿用第一帧的缓冲区
+            let current = (ctx.mcu_buffer.as_ptr(), ctx.work_buffer.as_ptr(), ctx.work_buffer.capacity());
+            assert_eq!(*buffers.get_or_insert(current), current);
+        }
+
+        assert_eq!(ctx.set_scale(6), Err(Error::Parameter));
+    }
+
+    #[test]
+    fn test_frame_info_after_decode() {
+        let mut jpeg = encode(24, 16, &pattern(24, 16, 3), &EncodeOptions::default());
+        let len = jpeg.len();
+        jpeg.extend_from_slice(&[0u8; 5]);
+
+        let mut ctx = DecodeContext::new();
+        assert!(ctx.frame().is_none());
+        ctx.set_scale(1).unwrap();
+        ctx.decode(&jpeg, &mut |_, _, _| Ok(true)).unwrap();
+
+        let frame = ctx.frame().unwrap();
+        assert_eq!((frame.width, frame.height), (12, 8));
+        assert_eq!(frame.warnings(), [Warning::TrailingData]);
+        assert_eq!(frame.bytes_consumed, len);
+        assert!(!frame.truncated);
+
+        // 头部无法解析时清除上一帧的信息
+        assert!(ctx.decode(&jpeg[..20], &mut |_, _, _| Ok(true)).is_err());
+        assert!(ctx.frame().is_none());
+    }
+}
@@ -1,9 +1,20 @@
 //! JPEG decoder implementation
 
-use crate::huffman::{BitStream, HuffmanTable};
-use crate::idct::{block_idct, color};
-use crate::pool::MemoryPool;
-use crate::types::{Error, OutputFormat, Rectangle, Result, SamplingFactor};
+use crate::huffman::{BitState, BitStream, HuffmanTable};
+use crate::crc32::crc32;
+use crate::idct::{block_idct, block_idct_dc, block_idct_in_place, color};
+use crate::pool::{Allocator, Budget};
+use crate::reader::{self, JpegReader};
+use crate::exif;
+use crate::marker::MarkerIter;
+#[cfg(feature = "alloc")]
+use crate::pool::{MemoryPool, RECOMMENDED_POOL_SIZE};
+#[cfg(feature = "alloc")]
+use alloc::{vec, vec::Vec};
+#[cfg(feature = "std")]
+use crate::types::Frame;
+use crate::tables::byte_clip;
+use crate::types::{ChromaSiting, ChromaUpsample, ColorMatrix, ComponentInfo, DensityUnit, Error, GamutMode, McuInfo, McuLayout, MonoMode, OutputFormat, Rectangle, Result, Rotation, SamplingFactor, Warning};
 
 /// JPEG marker codes
 mod markers {
@@ -33,6 +44,194 @@ mod markers {
 /// * `Err(e)` - Error occurred
 pub type OutputCallback<'a> = &'a mut dyn FnMut(&JpegDecoder, &[u8], &Rectangle) -> Result<bool>;
 
+/// Plane output callback type
+/// 
+/// Called once per component plane per MCU by `decompress_planes()` with the
+/// plane index (0 = Y, 1 = Cb, 2 = Cr), its samples and their rectangle in
+/// plane coordinates. Return `Ok(true)` to continue, `Ok(false)` to abort.
+pub type PlaneCallback<'a> = &'a mut dyn FnMut(&JpegDecoder, u8, &[u8], &Rectangle) -> Result<bool>;
+
+/// Raw MCU callback type
+/// 
+/// Called once per MCU by `decompress_raw_ycbcr()` with the decoded `i16`
+/// blocks of the MCU, the MCU's rectangle clipped to the image and the
+/// block layout needed to index them. Return `Ok(true)` to continue,
+/// `Ok(false)` to abort.
+pub type RawMcuCallback<'a> = &'a mut dyn FnMut(&JpegDecoder, &[i16], &Rectangle, &McuLayout) -> Result<bool>;
+
+/// Run-length span callback type
+/// 
+/// Called by `decompress_rle()` with one pixel value (in the current output
+/// format), the scaled position `(x, y)` of the first pixel and the number
+/// of identical pixels to its right. Return `Ok(true)` to continue,
+/// `Ok(false)` to abort.
+pub type SpanCallback<'a> = &'a mut dyn FnMut(&JpegDecoder, &[u8], u16, u16, u16) -> Result<bool>;
+
+/// Scanline callback type
+/// 
+/// Called by `decompress_scanlines()` with one padded output row and its
+/// scaled y coordinate. Return `Ok(true)` to continue, `Ok(false)` to abort.
+pub type ScanlineCallback<'a> = &'a mut dyn FnMut(&JpegDecoder, &[u8], u16) -> Result<bool>;
+
+/// 4x4 tile callback type
+/// 
+/// Called by `decompress_4x4_tiles()` with 16 RGB888 pixels in row-major
+/// order and the scaled position `(x, y)` of the tile's top-left pixel.
+/// Return `Ok(true)` to continue, `Ok(false)` to abort.
+pub type TileCallback<'a> = &'a mut dyn FnMut(&JpegDecoder, &[u8; 48], u16, u16) -> Result<bool>;
+
+/// MCU complexity callback type
+/// 
+/// Called by `decompress_with_complexity()` before each MCU is output, with
+/// the MCU's rectangle in full-resolution image coordinates (clipped to the
+/// image) and the number of nonzero AC coefficients across all its blocks.
+pub type ComplexityCallback<'a> = &'a mut dyn FnMut(&Rectangle, u16);
+
+/// Output callback type with MCU position
+/// 
+/// Called by `decompress_with_info()` like `OutputCallback`, plus the
+/// position of the MCU the pixels came from.
+pub type McuInfoCallback<'a> = &'a mut dyn FnMut(&JpegDecoder, &[u8], &Rectangle, &McuInfo) -> Result<bool>;
+
+/// Completion callback type
+/// 
+/// Called by `decompress_into()` after the pixels of the rectangle have
+/// been written to the destination buffer. Return values as for
+/// `OutputCallback`.
+pub type NotifyCallback<'a> = &'a mut dyn FnMut(&JpegDecoder, &Rectangle) -> Result<bool>;
+
+/// Yield check type
+/// 
+/// Polled by `decompress_resumable()` before each MCU row; returning `true`
+/// pauses the decode there. Being a closure, it can capture a deadline, an
+/// MCU budget or an RTOS handle.
+pub type YieldCheck<'a> = &'a mut dyn FnMut() -> bool;
+
+/// Resumable decode position
+/// 
+/// Returned by `decompress_resumable()` when the decode yields or the
+/// callback pauses it. Pass it back unchanged, together with the same JPEG
+/// data, to continue where it stopped (see `resume()`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeState {
+    bits: BitState,
+    dc_values: [i16; MAX_COMPONENTS],
+    next_mcu: u32,
+    restart_counter: u16,
+    restart_marker: u8,
+    scale: u8,
+}
+
+impl DecodeState {
+    /// Index of the next MCU to be decoded (MCUs already done)
+    pub fn next_mcu(&self) -> u32 {
+        self.next_mcu
+    }
+}
+
+/// MCU-by-MCU decode of one image
+/// 
+/// Created by `JpegDecoder::mcus()`. Each `next()` call decodes the next
+/// MCU and returns its output rectangle together with the converted
+/// pixels, laid out exactly as the bitmap of an `OutputCallback`. The
+/// pixels live in the work buffer, so the slice is only valid until the
+/// next `next()` call; copy it out before asking for the next MCU.
+/// 
+/// Because of that borrow this is a lending iterator rather than an
+/// `Iterator`, so loop with `while let`:
+/// 
+/// ```no_run
+/// # use tjpgdec_rs::{JpegDecoder, MemoryPool, RECOMMENDED_POOL_SIZE};
+/// # let data = std::fs::read("image.jpg").unwrap();
+/// # let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+/// # let mut pool = MemoryPool::new(&mut pool_buffer);
+/// let mut decoder = JpegDecoder::new();
+/// decoder.prepare(&data, &mut pool)?;
+/// let mut mcu_buffer = vec![0i16; decoder.mcu_buffer_size()];
+/// let mut work_buffer = vec![0u8; decoder.work_buffer_size()];
+/// 
+/// let mut mcus = decoder.mcus(&data, 0, &mut mcu_buffer, &mut work_buffer);
+/// while let Some(mcu) = mcus.next() {
+///     let (rect, pixels) = mcu?;
+///     println!("{:?}: {} bytes", rect, pixels.len());
+/// }
+/// # Ok::<(), tjpgdec_rs::Error>(())
+/// ```
+pub struct McuIter<'d, 'a> {
+    decoder: &'d mut JpegDecoder<'a>,
+    data: &'d [u8],
+    scale: u8,
+    mcu_buffer: &'d mut [i16],
+    work_buffer: &'d mut [u8],
+    state: Option<DecodeState>,
+    done: bool,
+}
+
+impl<'d, 'a> McuIter<'d, 'a> {
+    /// Decode the next MCU
+    /// 
+    /// Returns `None` once the whole image has been output. After an error
+    /// the iterator is finished and keeps returning `None`.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Result<(Rectangle, &[u8])>> {
+        while !self.done {
+            let mut out = None;
+            let result = self.decoder.decompress_resumable(
+                self.data,
+                self.scale,
+                self.mcu_buffer,
+                self.work_buffer,
+                self.state,
+                None,
+                &mut |_, _, rect| {
+                    out = Some(*rect);
+                    Ok(false)
+                },
+            );
+
+            match result {
+                Ok(state) => {
+                    self.done = state.is_none();
+                    self.state = state;
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+
+            // 回调拿到的是整个 work_buffer（旋转时为后半部分），像素从头开始
+            if let Some(rect) = out {
+                let start = if self.decoder.rotation == Rotation::None { 0 } else { self.work_buffer.len() / 2 };
+                let len = self.decoder.row_bytes(rect.width() as usize) * rect.height() as usize;
+                return Some(Ok((rect, &self.work_buffer[start..start + len])));
+            }
+        }
+        None
+    }
+
+    /// Position to resume from with `decompress_resumable()`
+    /// 
+    /// `None` before the first MCU and after the last one.
+    pub fn state(&self) -> Option<DecodeState> {
+        self.state
+    }
+}
+
+/// `decode_scan` 对每个 MCU 的处理方式
+#[derive(Clone, Copy)]
+enum ScanMode {
+    /// 完整重建所有 MCU
+    Full,
+    /// 跳过 IDCT，每个块只在第一个样本写入其平均值
+    DcOnly,
+    /// 只重建并输出与区域（原始分辨率坐标）相交的 MCU
+    Region(Rectangle),
+}
+
+/// 解码后的 MCU 接收者（MCU 数据, x, y, 非零 AC 系数个数）
+type McuSink<'s, 'a> = &'s mut dyn FnMut(&JpegDecoder<'a>, &[i16], u16, u16, u16) -> Result<()>;
+
 /// Calculate required workspace memory pool size
 /// 
 /// # Returns
@@ -58,9 +257,274 @@ pub fn calculate_pool_size(_width: u16, _height: u16, fast_decode: bool) -> usiz
     size.max(c_min_size)
 }
 
+/// Worst-case pool bytes `prepare()` needs, usable in constant expressions
+///
+/// Sizes a static pool at compile time:
+///
+/// ```
+/// use tjpgdec_rs::{workspace_size_for, SamplingFactor};
+///
+/// const POOL_SIZE: usize = workspace_size_for(SamplingFactor::Yuv420, 1);
+/// static mut POOL: [u8; POOL_SIZE] = [0; POOL_SIZE];
+/// ```
+///
+/// Covers a baseline file that defines each of its tables once: four
+/// quantization tables (64 `i32` each), two DC Huffman tables with at most
+/// 12 codes and two AC tables with at most 162 codes (the T.81 limits for
+/// 8-bit samples). Every Huffman table takes 3 bytes per code, the
+/// `HuffmanTable` struct and, at level 2, a 1024-entry `u16` lookup table.
+/// Each allocation is counted with its worst-case alignment padding.
+///
+/// The sampling factor does not change the result: unlike the C version's
+/// single workspace, the MCU and work buffers are passed by the caller
+/// (`mcu_buffer_size()` / `work_buffer_size()`). For levels 0 and 1 the
+/// result stays below `MIN_WORKSPACE_SIZE`, which includes those buffers.
+/// Level 2 exceeds 9644 bytes because every lookup table here is 16-bit,
+/// where the C version uses 8-bit tables for DC.
+///
+/// # Parameters
+///
+/// * `sampling` - Worst-case sampling factor of the images to decode
+/// * `level` - fast-decode level the crate is built with (`fastdecode_level()`)
+pub const fn workspace_size_for(sampling: SamplingFactor, level: u8) -> usize {
+    // 表占用的内存与采样方式无关
+    let _ = sampling;
+    const fn padded(size: usize, align: usize) -> usize {
+        // 起始位置最多补 align - 1 字节，大小向上取整到 align
+        (align - 1) + size.div_ceil(align) * align
+    }
+    const fn huffman(codes: usize, level: u8) -> usize {
+        // fast-decode-2 的查找表：1 << HUFF_BIT 个 u16
+        let lut = if level >= 2 { padded((1 << 10) * 2, 2) } else { 0 };
+        padded(codes * 2, 2)
+            + padded(codes, 1)
+            + lut
+            + padded(core::mem::size_of::<HuffmanTable>(), core::mem::align_of::<HuffmanTable>())
+    }
+    4 * padded(64 * 4, 4) + 2 * huffman(12, level) + 2 * huffman(162, level)
+}
+
+/// APPn 段是否带有已知的标识（JFIF、Exif、ICC、XMP、Adobe 等）
+fn is_known_app_segment(segment: &[u8]) -> bool {
+    const KNOWN: [&[u8]; 7] = [
+        b"JFIF\0",
+        b"JFXX\0",
+        b"Exif\0",
+        b"ICC_PROFILE\0",
+        b"http://ns.adobe.com/",
+        b"Adobe",
+        b"MPF\0",
+    ];
+    KNOWN.iter().any(|id| segment.starts_with(id))
+}
+
+/// 解析 JFIF APP0 段的像素密度（单位, X, Y）
+/// 
+/// 标识、单位和非零密度都必须合法，否则视为非 JFIF 段
+fn jfif_density(segment: &[u8]) -> Option<(DensityUnit, u16, u16)> {
+    // "JFIF\0", 版本 2 字节, 单位, X/Y 密度, 缩略图宽高
+    if segment.len() < 14 || !segment.starts_with(b"JFIF\0") {
+        return None;
+    }
+    let unit = match segment[7] {
+        0 => DensityUnit::AspectRatio,
+        1 => DensityUnit::PerInch,
+        2 => DensityUnit::PerCm,
+        _ => return None,
+    };
+    let x = u16::from_be_bytes([segment[8], segment[9]]);
+    let y = u16::from_be_bytes([segment[10], segment[11]]);
+    if x == 0 || y == 0 {
+        return None;
+    }
+    Some((unit, x, y))
+}
+
+/// Quickly check whether `data` looks like a JPEG file
+/// 
+/// Only checks the SOI magic (`0xFF 0xD8`) and that a marker follows it,
+/// without parsing any segment. Never panics, whatever the input length.
+pub fn is_jpeg(data: &[u8]) -> bool {
+    if data.len() < 4 || data[0] != 0xFF || data[1] != 0xD8 {
+        return false;
+    }
+    // 跳过填充字节后应是一个 marker
+    match data[2..].iter().position(|&b| b != 0xFF) {
+        Some(n) => n >= 1 && data[2 + n] != 0x00,
+        None => false,
+    }
+}
+
+/// Locate the top-level SOI marker of a JPEG image within `data`
+/// 
+/// Searches from `from` for an `0xFF 0xD8` whose segments can be walked by
+/// their lengths up to SOS. The segments walked from a candidate are
+/// skipped as a whole, so an SOI inside an APPn payload (such as an
+/// embedded EXIF thumbnail) is never mistaken for the main image, and stray
+/// `0xFF 0xD8` bytes in leading garbage are rejected.
+/// 
+/// # Returns
+/// 
+/// Offset of the SOI marker, or `None` if no valid image is found.
+pub fn find_soi(data: &[u8], from: usize) -> Option<usize> {
+    let mut i = from;
+    while i + 1 < data.len() {
+        if data[i] != 0xFF || data[i + 1] != 0xD8 {
+            i += 1;
+            continue;
+        }
+
+        // 按段长度遍历到 SOS；失败时从遍历到的位置之后继续搜索
+        let mut pos = i + 2;
+        loop {
+            while pos + 1 < data.len() && data[pos] == 0xFF && data[pos + 1] == 0xFF {
+                pos += 1;
+            }
+            if pos + 4 > data.len() || data[pos] != 0xFF {
+                break;
+            }
+            match data[pos + 1] {
+                markers::SOS => return Some(i),
+                markers::EOI | 0xD8 | 0x00 => break,
+                _ => {}
+            }
+            let length = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+            if length < 2 || pos + 2 + length > data.len() {
+                break;
+            }
+            pos += 2 + length;
+        }
+        i = pos.max(i + 2);
+    }
+    None
+}
+
+/// Decode a whole image into a new RGB888 buffer
+/// 
+/// Convenience wrapper around `JpegDecoder::decode_to_vec()` that also
+/// allocates the memory pool, for `no_std` targets with a global allocator
+/// (the `alloc` feature) as well as `std` builds. Only this wrapper and
+/// `decode_to_vec()` allocate; the rest of the decoder never does.
+/// 
+/// # Parameters
+/// 
+/// * `data` - Complete JPEG file data
+/// * `scale` - Scale factor (0=1/1, 1=1/2, 2=1/4, 3=1/8, 4=1/16, 5=1/32)
+/// 
+/// # Returns
+/// 
+/// `(pixels, width, height)` with `width * height * 3` bytes of pixels.
+#[cfg(feature = "alloc")]
+pub fn decode_to_vec(data: &[u8], scale: u8) -> Result<(Vec<u8>, u16, u16)> {
+    let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+    let mut pool = MemoryPool::new(&mut pool_buffer);
+    let mut decoder = JpegDecoder::new();
+    decoder.prepare(data, &mut pool)?;
+    decoder.decode_to_vec(data, scale)
+}
+
+/// Decode a DC-only RGB888 preview at 1/8 scale into a new buffer
+/// 
+/// Convenience wrapper around `JpegDecoder::decompress_dc()` that allocates
+/// its own workspace. Returns `(width, height, pixels)` with
+/// `width = ceil(image width / 8)` and `height = ceil(image height / 8)`.
+#[cfg(feature = "std")]
+pub fn decode_dc_preview(data: &[u8]) -> Result<(u16, u16, Vec<u8>)> {
+    let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+    let mut pool = MemoryPool::new(&mut pool_buffer);
+    let mut decoder = JpegDecoder::new();
+    decoder.prepare(data, &mut pool)?;
+
+    let mut mcu_buffer = vec![0i16; decoder.mcu_buffer_size()];
+    let mut pixels = vec![0u8; decoder.width.div_ceil(8) as usize * decoder.height.div_ceil(8) as usize * 3];
+    let (width, height) = decoder.decompress_dc(data, &mut mcu_buffer, &mut pixels)?;
+    Ok((width, height, pixels))
+}
+
+/// Decode a whole image into a new `Frame`
+/// 
+/// Convenience wrapper around `JpegDecoder::decompress_to_buffer()` that
+/// allocates its own workspace and framebuffer. Color images decode to
+/// RGB888, grayscale images to `OutputFormat::Grayscale`.
+/// 
+/// # Parameters
+/// 
+/// * `data` - Complete JPEG file data
+/// * `scale` - Scale factor (0=1/1, 1=1/2, 2=1/4, 3=1/8, 4=1/16, 5=1/32)
+#[cfg(feature = "std")]
+pub fn decode_frame(data: &[u8], scale: u8) -> Result<Frame> {
+    let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+    let mut pool = MemoryPool::new(&mut pool_buffer);
+    let mut decoder = JpegDecoder::new();
+    decoder.prepare(data, &mut pool)?;
+    if scale > MAX_SCALE {
+        return Err(Error::Parameter);
+    }
+
+    let mut mcu_buffer = vec![0i16; decoder.mcu_buffer_size()];
+    let mut work_buffer = vec![0u8; decoder.work_buffer_size_for_scale(scale)];
+    let mut pixels = vec![0u8; decoder.output_byte_size(scale)];
+    let width = decoder.width >> scale;
+    let height = decoder.height >> scale;
+    let stride = width as usize * decoder.output_bpp();
+    decoder.decompress_to_buffer(data, scale, &mut mcu_buffer, &mut work_buffer, &mut pixels, stride, 0, 0)?;
+
+    let format = if decoder.luma_only() { OutputFormat::Grayscale } else { OutputFormat::Rgb888 };
+    Frame::new(width, height, format, pixels)
+}
+
+/// Decode a whole image and return the CRC-32 of its RGB888 pixels
+/// 
+/// Golden-test helper: the checksum covers every RGB byte in raster order
+/// (grayscale images expanded to gray triples), so builds with different
+/// fast-decode levels can be compared by a single `u32`.
+/// 
+/// # Parameters
+/// 
+/// * `data` - Complete JPEG file data
+/// * `scale` - Scale factor (0=1/1, 1=1/2, 2=1/4, 3=1/8, 4=1/16, 5=1/32)
+#[cfg(feature = "testing")]
+pub fn decode_and_hash(data: &[u8], scale: u8) -> Result<u32> {
+    let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+    let mut pool = MemoryPool::new(&mut pool_buffer);
+    let mut decoder = JpegDecoder::new();
+    decoder.prepare(data, &mut pool)?;
+    let (pixels, _, _) = decoder.decode_to_vec(data, scale)?;
+    Ok(crc32(&pixels))
+}
+
+/// `JpegDecoder::flags` 的位
+const FLAG_STRICT_RESTART: u8 = 1 << 0;
+const FLAG_SWAP_565: u8 = 1 << 1;
+/// 遇到了渐进式 SOF
+const FLAG_PROGRESSIVE: u8 = 1 << 2;
+/// 只重建 DC 系数（每个块输出单一颜色）
+const FLAG_DC_ONLY: u8 = 1 << 3;
+/// 扫描数据提前结束时用填充色补齐剩余 MCU
+const FLAG_ALLOW_TRUNCATED: u8 = 1 << 4;
+/// 上一次解码遇到了提前结束的扫描数据
+const FLAG_TRUNCATED: u8 = 1 << 5;
+/// 当前头部已解析过 SOF
+const FLAG_HAVE_SOF: u8 = 1 << 6;
+
+/// `decompress()` 支持的最大缩放（1/32）
+pub(crate) const MAX_SCALE: u8 = 5;
+/// 区域解码和可恢复解码支持的最大缩放（1/8）：跨 MCU 的累加和无法跳过或暂停
+const MAX_MCU_SCALE: u8 = 3;
+
+/// 最多支持的分量数（CMYK/YCCK 为 4 个）
+const MAX_COMPONENTS: usize = if cfg!(feature = "cmyk") { 4 } else { 3 };
+/// 没有 Adobe APP14 段
+#[cfg(feature = "cmyk")]
+const NO_ADOBE: u8 = 0xFF;
+/// APP14 颜色变换：YCCK
+#[cfg(feature = "cmyk")]
+const ADOBE_YCCK: u8 = 2;
+
 /// JPEG decoder
 /// 
-/// Compact decoder structure (~120 bytes)
+/// Compact decoder structure: at most 144 bytes on 64-bit targets (152
+/// with the `cmyk` feature)
 /// 
 /// # Example
 /// 
@@ -73,28 +537,54 @@ pub fn calculate_pool_size(_width: u16, _height: u16, fast_decode: bool) -> usiz
 /// 
 /// // decoder.prepare(jpeg_data, &mut pool)?;
 /// ```
+#[derive(Clone)]
 pub struct JpegDecoder<'a> {
     pub(crate) width: u16,
     pub(crate) height: u16,
     num_components: u8,
     sampling: SamplingFactor,
     
-    // Huffman表指针（存储原始指针以避免生命周期问题）
-    huff_dc: [*const HuffmanTable<'a>; 2],
-    huff_ac: [*const HuffmanTable<'a>; 2],
+    // Huffman表（存放在内存池中）
+    huff_dc: [Option<&'a HuffmanTable<'a>>; 2],
+    huff_ac: [Option<&'a HuffmanTable<'a>>; 2],
     
-    // 量化表指针
-    qtables: [*const [i32; 64]; 4],
-    qtable_ids: [u8; 3],
+    // 量化表（存放在内存池中）
+    qtables: [Option<&'a [i32; 64]>; 4],
+    components: [ComponentInfo; MAX_COMPONENTS],
     
-    dc_values: [i16; 3],
+    dc_values: [i16; MAX_COMPONENTS],
     restart_interval: u16,
-    _output_format: OutputFormat,
+    // FLAG_* 位
+    flags: u8,
+    // SOS 中的 Huffman 表选择：位 i 为分量 i 的 DC 表，位 i + 4 为 AC 表
+    scan_tables: u8,
+    // Adobe APP14 的颜色变换（NO_ADOBE 表示没有该段）
+    #[cfg(feature = "cmyk")]
+    adobe_transform: u8,
+    output_format: OutputFormat,
+    mono_mode: MonoMode,
+    gamut_mode: GamutMode,
+    color_matrix: ColorMatrix,
+    chroma_siting: ChromaSiting,
+    chroma_upsample: ChromaUpsample,
+    rotation: Rotation,
+    // 截断后补齐 MCU 的 RGB 颜色
+    truncated_fill: [u8; 3],
+    // (0, 0) 表示不检查
+    expected_dimensions: (u16, u16),
+    // EXIF 方向（1..=8）
+    orientation: u8,
+    density: Option<(DensityUnit, u16, u16)>,
+    // u32::MAX 表示不限制
+    pool_limit: u32,
+    // u32::MAX 表示不限制
+    max_output: u32,
+    warnings: [Warning; Warning::COUNT],
+    warning_count: u8,
     scale: u8,
-    sos_position: usize,
-    
-    // 生命周期标记
-    _marker: core::marker::PhantomData<&'a ()>,
+    sos_position: u32,
+    // 最近一次解码结束处（EOI 之后）在输入中的偏移
+    scan_end: u32,
 }
 
 impl<'a> JpegDecoder<'a> {
@@ -107,16 +597,34 @@ impl<'a> JpegDecoder<'a> {
             height: 0,
             num_components: 0,
             sampling: SamplingFactor::Yuv444,
-            huff_dc: [core::ptr::null(); 2],
-            huff_ac: [core::ptr::null(); 2],
-            qtables: [core::ptr::null(); 4],
-            qtable_ids: [0; 3],
-            dc_values: [0; 3],
+            huff_dc: [None; 2],
+            huff_ac: [None; 2],
+            qtables: [None; 4],
+            components: [ComponentInfo::default(); MAX_COMPONENTS],
+            dc_values: [0; MAX_COMPONENTS],
             restart_interval: 0,
-            _output_format: OutputFormat::Rgb565,
+            flags: 0,
+            scan_tables: 0,
+            #[cfg(feature = "cmyk")]
+            adobe_transform: NO_ADOBE,
+            output_format: OutputFormat::Rgb888,
+            mono_mode: MonoMode::Threshold(128),
+            gamut_mode: GamutMode::Clamp,
+            color_matrix: ColorMatrix::Bt601,
+            chroma_siting: ChromaSiting::Centered,
+            chroma_upsample: ChromaUpsample::Nearest,
+            rotation: Rotation::None,
+            truncated_fill: [128; 3],
+            expected_dimensions: (0, 0),
+            orientation: 1,
+            density: None,
+            pool_limit: u32::MAX,
+            max_output: u32::MAX,
+            warnings: [Warning::TrailingData; Warning::COUNT],
+            warning_count: 0,
             scale: 0,
             sos_position: 0,
-            _marker: core::marker::PhantomData,
+            scan_end: 0,
         }
     }
 
@@ -142,7 +650,39 @@ impl<'a> JpegDecoder<'a> {
     /// decoder.prepare(jpeg_data, &mut pool)?;
     /// # Ok::<(), tjpgdec_rs::Error>(())
     /// ```
-    pub fn prepare(&mut self, data: &[u8], pool: &mut MemoryPool<'a>) -> Result<()> {
+    pub fn prepare<A: Allocator<'a>>(&mut self, data: &[u8], pool: &mut A) -> Result<()> {
+        match self.pool_limit {
+            u32::MAX => self.parse_headers(data, pool),
+            limit => self.parse_headers(data, &mut Budget::new(pool, limit as usize)),
+        }
+    }
+
+    /// Limit how many bytes `prepare()` may take from the allocator
+    /// 
+    /// For pools shared with other subsystems: once the decoder's own
+    /// allocations would exceed `limit` bytes, `prepare()` fails with
+    /// `Error::InsufficientMemory` even if the pool has space left. The
+    /// tally counts requested bytes, not alignment padding inserted by the
+    /// allocator. Pass `None` to remove the limit (the default).
+    pub fn set_pool_limit(&mut self, limit: Option<usize>) {
+        // 超过 4 GiB 的上限等同于不限制
+        self.pool_limit = limit.map_or(u32::MAX, |l| l.min(u32::MAX as usize) as u32);
+    }
+
+    /// Limit how many bytes one decode call may pass to the output callback
+    /// 
+    /// For callbacks that accumulate into a bounded buffer: once the pixel
+    /// data handed out by `decompress()` (and the other callback-based
+    /// `decompress_*` functions) would exceed `limit` bytes in total, the
+    /// decode stops with `Error::InsufficientMemory` before the offending
+    /// MCU reaches the callback. The count restarts with every call. Pass
+    /// `None` to remove the limit (the default).
+    pub fn set_max_output_bytes(&mut self, limit: Option<usize>) {
+        // 超过 4 GiB 的上限等同于不限制
+        self.max_output = limit.map_or(u32::MAX, |l| l.min(u32::MAX as usize) as u32);
+    }
+
+    fn parse_headers<A: Allocator<'a>>(&mut self, data: &[u8], pool: &mut A) -> Result<()> {
         let mut pos = 0;
 
         if data.len() < 2 {
@@ -156,6 +696,8 @@ impl<'a> JpegDecoder<'a> {
             return Err(Error::FormatError);
         }
 
+        self.reset_header_state();
+
         loop {
             if pos + 4 > data.len() {
                 return Err(Error::Input);
@@ -176,32 +718,223 @@ impl<'a> JpegDecoder<'a> {
             }
 
             let segment = &data[seg_start..seg_start + seg_len];
-            
-            match (marker & 0xFF) as u8 {
-                markers::SOF0 => self.parse_sof(segment)?,
-                markers::DHT => self.parse_dht(segment, pool)?,
-                markers::DQT => self.parse_dqt(segment, pool)?,
-                markers::DRI => self.parse_dri(segment)?,
-                markers::SOS => {
-                    self.parse_sos(segment)?;
-                    self.sos_position = pos;
-                    return Ok(());
-                }
-                markers::EOI => return Err(Error::FormatError),
-                _ if (marker & 0xFF) as u8 >= 0xC0 && (marker & 0xFF) as u8 <= 0xCF => {
-                    return Err(Error::UnsupportedStandard);
-                }
-                _ => {}
+
+            if self.parse_segment((marker & 0xFF) as u8, segment, pool)? {
+                self.sos_position = u32::try_from(pos).map_err(|_| Error::Input)?;
+                return Ok(());
             }
 
             pos = seg_start + seg_len;
         }
     }
 
+    /// Forget the prepared image so the decoder can be reused
+    ///
+    /// Clears the dimensions, the component and scan state and the
+    /// references to the Huffman and quantization tables, leaving the
+    /// decoder as if `prepare()` had never been called. Settings such as the
+    /// output format, rotation and limits are kept.
+    ///
+    /// To decode a stream of images (e.g. MJPEG frames) with one decoder
+    /// and one pool, reset the pool, reset the decoder, then prepare again:
+    ///
+    /// ```rust,no_run
+    /// # use tjpgdec_rs::{JpegDecoder, MemoryPool, RECOMMENDED_POOL_SIZE};
+    /// # let frames: &[&[u8]] = &[];
+    /// let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+    /// let mut pool = MemoryPool::new(&mut pool_buffer);
+    /// let mut decoder = JpegDecoder::new();
+    ///
+    /// for frame in frames {
+    ///     pool.reset();
+    ///     decoder.reset();
+    ///     decoder.prepare(frame, &mut pool)?;
+    ///     // decoder.decompress(frame, ...)
+    /// }
+    /// # Ok::<(), tjpgdec_rs::Error>(())
+    /// ```
+    pub fn reset(&mut self) {
+        self.reset_header_state();
+        self.width = 0;
+        self.height = 0;
+        self.sampling = SamplingFactor::Yuv444;
+        self.components = [ComponentInfo::default(); MAX_COMPONENTS];
+        self.dc_values = [0; MAX_COMPONENTS];
+        self.scan_tables = 0;
+        self.scale = 0;
+        self.sos_position = 0;
+    }
+
+    /// 清除上一幅图像留下的头部信息
+    /// 
+    /// 表也要清除，否则缺少 DQT/DHT 的文件会沿用上一幅图像的表通过检查。
+    fn reset_header_state(&mut self) {
+        self.num_components = 0;
+        self.huff_dc = [None; 2];
+        self.huff_ac = [None; 2];
+        self.qtables = [None; 4];
+        self.warning_count = 0;
+        self.orientation = 1;
+        self.density = None;
+        self.restart_interval = 0;
+        self.scan_end = 0;
+        self.set_flag(FLAG_PROGRESSIVE, false);
+        self.set_flag(FLAG_TRUNCATED, false);
+        self.set_flag(FLAG_HAVE_SOF, false);
+        #[cfg(feature = "cmyk")]
+        {
+            self.adobe_transform = NO_ADOBE;
+        }
+    }
+
+    #[inline]
+    fn flag(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+
+    fn set_flag(&mut self, flag: u8, on: bool) {
+        if on {
+            self.flags |= flag;
+        } else {
+            self.flags &= !flag;
+        }
+    }
+
+    /// 处理一个标记段，读到 SOS 时返回 true
+    fn parse_segment<A: Allocator<'a>>(&mut self, marker: u8, segment: &[u8], pool: &mut A) -> Result<bool> {
+        match marker {
+            markers::SOF0 => self.parse_sof(segment)?,
+            markers::DHT => self.parse_dht(segment, pool)?,
+            markers::DQT => self.parse_dqt(segment, pool)?,
+            markers::DRI => self.parse_dri(segment)?,
+            markers::SOS => {
+                self.parse_sos(segment)?;
+                return Ok(true);
+            }
+            markers::EOI => return Err(Error::FormatError),
+            0xE0 if segment.starts_with(b"JFIF\0") => self.density = jfif_density(segment),
+            0xE1 if segment.starts_with(b"Exif\0") => {
+                if let Some(orientation) = exif::orientation(segment) {
+                    self.orientation = orientation;
+                }
+            }
+            // "Adobe" + 版本、两个标志字 + 颜色变换
+            #[cfg(feature = "cmyk")]
+            0xEE if segment.starts_with(b"Adobe") && segment.len() >= 12 => self.adobe_transform = segment[11],
+            0xC2 | 0xC6 | 0xCA | 0xCE => {
+                self.set_flag(FLAG_PROGRESSIVE, true);
+                return Err(Error::ProgressiveUnsupported);
+            }
+            _ if (0xC0..=0xCF).contains(&marker) => return Err(Error::UnsupportedStandard),
+            0xE0..=0xEF if !is_known_app_segment(segment) => self.warn(Warning::UnknownAppSegment),
+            _ => {}
+        }
+        Ok(false)
+    }
+
+    /// Prepare decoder by reading JPEG headers from a stream
+    /// 
+    /// Streaming counterpart of `prepare()` for files that don't fit in RAM
+    /// (e.g. on a flash filesystem). Reads exactly up to the end of the SOS
+    /// header, leaving `reader` at the start of the scan data for
+    /// `decompress_reader()`. Segments the decoder needs (SOF, DHT, DQT,
+    /// DRI, SOS) are read into `buffer` and must fit in it; others are
+    /// skipped. `BUFFER_SIZE` bytes is enough for typical files.
+    /// 
+    /// # Parameters
+    /// 
+    /// * `reader` - JPEG data source, positioned at SOI
+    /// * `buffer` - Input buffer
+    /// * `pool` - Workspace memory pool
+    /// 
+    /// # Returns
+    /// 
+    /// `Error::InsufficientBuffer` if a needed segment is larger than
+    /// `buffer`, `Error::Input` if the stream ends early.
+    pub fn prepare_reader<R: JpegReader + ?Sized, A: Allocator<'a>>(
+        &mut self,
+        reader: &mut R,
+        buffer: &mut [u8],
+        pool: &mut A,
+    ) -> Result<()> {
+        match self.pool_limit {
+            u32::MAX => self.parse_headers_from(reader, buffer, pool),
+            limit => self.parse_headers_from(reader, buffer, &mut Budget::new(pool, limit as usize)),
+        }
+    }
+
+    fn parse_headers_from<R: JpegReader + ?Sized, A: Allocator<'a>>(
+        &mut self,
+        reader: &mut R,
+        buffer: &mut [u8],
+        pool: &mut A,
+    ) -> Result<()> {
+        if buffer.len() < 4 {
+            return Err(Error::InsufficientBuffer);
+        }
+
+        reader::read_exact(reader, &mut buffer[..2])?;
+        if u16::from_be_bytes([buffer[0], buffer[1]]) != markers::SOI {
+            return Err(Error::FormatError);
+        }
+
+        self.reset_header_state();
+        self.sos_position = 0;
+
+        loop {
+            reader::read_exact(reader, &mut buffer[..4])?;
+            let length = u16::from_be_bytes([buffer[2], buffer[3]]) as usize;
+            if buffer[0] != 0xFF || length < 2 {
+                return Err(Error::FormatError);
+            }
+
+            let marker = buffer[1];
+            let seg_len = length - 2;
+            // 只读入需要解析的段（APPn 读入开头用于识别），其余跳过
+            let needed = match marker {
+                markers::SOF0 | markers::DHT | markers::DQT | markers::DRI | markers::SOS => seg_len,
+                // Orientation 在 IFD0 中，通常位于 APP1 开头
+                0xE1 => seg_len.min(buffer.len()),
+                0xE0..=0xEF => seg_len.min(32).min(buffer.len()),
+                _ => 0,
+            };
+            if needed > buffer.len() {
+                return Err(Error::InsufficientBuffer);
+            }
+
+            reader::read_exact(reader, &mut buffer[..needed])?;
+            let (segment, scratch) = buffer.split_at_mut(needed);
+            if self.parse_segment(marker, segment, pool)? {
+                return Ok(());
+            }
+            if scratch.is_empty() && needed < seg_len {
+                reader::skip(reader, seg_len - needed, segment)?;
+            } else {
+                reader::skip(reader, seg_len - needed, scratch)?;
+            }
+        }
+    }
+
+    /// Parse JPEG headers of an image embedded in a larger buffer
+    /// 
+    /// Finds the top-level SOI at or after `offset` with `find_soi()` and
+    /// parses the image from there. Pass the same complete `data` to the
+    /// decompress functions afterwards.
+    /// 
+    /// Returns `Error::FormatError` if no valid image is found.
+    pub fn prepare_at<A: Allocator<'a>>(&mut self, data: &[u8], offset: usize, pool: &mut A) -> Result<usize> {
+        let soi = find_soi(data, offset).ok_or(Error::FormatError)?;
+        self.prepare(&data[soi..], pool)?;
+        self.sos_position = u32::try_from(soi as u64 + self.sos_position as u64).map_err(|_| Error::Input)?;
+        Ok(soi)
+    }
+
     fn parse_sof(&mut self, data: &[u8]) -> Result<()> {
-        if data.len() < 6 {
+        // 只允许一个帧头，第二个 SOF 会覆盖已按第一个校验过的状态
+        if data.len() < 6 || self.flag(FLAG_HAVE_SOF) {
             return Err(Error::FormatError);
         }
+        self.set_flag(FLAG_HAVE_SOF, true);
 
         if data[0] != 8 {
             return Err(Error::UnsupportedFormat);
@@ -211,7 +944,13 @@ impl<'a> JpegDecoder<'a> {
         self.width = u16::from_be_bytes([data[3], data[4]]);
         self.num_components = data[5];
 
-        if self.num_components != 1 && self.num_components != 3 {
+        if self.expected_dimensions != (0, 0) && self.expected_dimensions != (self.width, self.height) {
+            return Err(Error::FormatError);
+        }
+
+        // 四分量（CMYK/YCCK）需要 cmyk 特性
+        let supported = matches!(self.num_components, 1 | 3) || (cfg!(feature = "cmyk") && self.num_components == 4);
+        if !supported {
             return Err(Error::UnsupportedStandard);
         }
 
@@ -225,19 +964,33 @@ impl<'a> JpegDecoder<'a> {
             let sampling_factor = data[comp_start + 1];
             let qtable_id = data[comp_start + 2];
 
+            self.components[i] = ComponentInfo {
+                id: data[comp_start],
+                h_sampling: sampling_factor >> 4,
+                v_sampling: sampling_factor & 0x0F,
+                quant_table_id: qtable_id,
+            };
+
             if i == 0 {
+                // 四分量只支持不采样
+                if self.num_components == 4 && sampling_factor != 0x11 {
+                    return Err(Error::UnsupportedFormat);
+                }
                 let h = sampling_factor >> 4;
                 let v = sampling_factor & 0x0F;
-                self.sampling = SamplingFactor::from_factor(h, v)
-                    .ok_or(Error::UnsupportedFormat)?;
+                self.sampling = if self.num_components == 1 {
+                    // 单分量扫描不交错，MCU 总是一个 8x8 块，采样因子不影响块顺序（T.81 A.2.2）
+                    if !(1..=4).contains(&h) || !(1..=4).contains(&v) {
+                        return Err(Error::FormatError);
+                    }
+                    SamplingFactor::Yuv444
+                } else {
+                    SamplingFactor::from_factor(h, v).ok_or(Error::UnsupportedFormat)?
+                };
             } else if sampling_factor != 0x11 {
                 return Err(Error::UnsupportedFormat);
             }
 
-            if i < 3 {
-                self.qtable_ids[i] = qtable_id;
-            }
-
             if qtable_id > 3 {
                 return Err(Error::FormatError);
             }
@@ -246,98 +999,86 @@ impl<'a> JpegDecoder<'a> {
         Ok(())
     }
 
-    fn parse_dht(&mut self, mut data: &[u8], pool: &mut MemoryPool<'a>) -> Result<()> {
-        while !data.is_empty() {
-            if data.len() < 17 {
+    fn parse_dht<A: Allocator<'a>>(&mut self, mut data: &[u8], pool: &mut A) -> Result<()> {
+        // 先检查段内每张表都完整，残缺的段不会先耗尽内存池
+        let mut rest = data;
+        while let Some((&table_info, tail)) = rest.split_first() {
+            if table_info & 0x0F > 1 || tail.len() < 16 {
                 return Err(Error::FormatError);
             }
+            let num_codes: usize = tail[..16].iter().map(|&b| b as usize).sum();
+            rest = tail.get(16 + num_codes..).ok_or(Error::FormatError)?;
+        }
 
-            let table_info = data[0];
+        while let Some((&table_info, rest)) = data.split_first() {
             let class = (table_info >> 4) & 0x01;
             let id = table_info & 0x0F;
 
-            if id > 1 {
-                return Err(Error::FormatError);
-            }
-
-            let bits = &data[1..17];
+            let (bits, rest) = rest.split_at(16);
             let num_codes: usize = bits.iter().map(|&b| b as usize).sum();
-
-            if data.len() < 17 + num_codes {
-                return Err(Error::FormatError);
-            }
-
-            let values = &data[17..17 + num_codes];
+            let (values, rest) = rest.split_at(num_codes);
 
             // 从池中创建Huffman表
             let table = HuffmanTable::create_in_pool(pool, bits, values)?;
             
             // 分配结构体存储空间
             let table_size = core::mem::size_of::<HuffmanTable>();
-            let table_mem = pool.alloc(table_size).ok_or(Error::InsufficientMemory)?;
+            let table_align = core::mem::align_of::<HuffmanTable>();
+            let table_mem = pool.alloc(table_size, table_align).ok_or(Error::InsufficientMemory)?;
             
-            unsafe {
+            let table: &'a HuffmanTable<'a> = unsafe {
                 let table_ptr = table_mem.as_mut_ptr() as *mut HuffmanTable<'a>;
                 core::ptr::write(table_ptr, table);
-                
-                if class == 0 {
-                    self.huff_dc[id as usize] = table_ptr;
-                } else {
-                    self.huff_ac[id as usize] = table_ptr;
-                }
+                &*table_ptr
+            };
+
+            if class == 0 {
+                self.huff_dc[id as usize] = Some(table);
+            } else {
+                self.huff_ac[id as usize] = Some(table);
             }
 
-            data = &data[17 + num_codes..];
+            data = rest;
         }
 
         Ok(())
     }
 
-    fn parse_dqt(&mut self, mut data: &[u8], pool: &mut MemoryPool<'a>) -> Result<()> {
-        use crate::tables::{ZIGZAG, ARAI_SCALE_FACTOR};
-        
-        while !data.is_empty() {
-            let table_info = data[0];
-            let precision = (table_info >> 4) & 0x0F;
-            let id = table_info & 0x0F;
-
-            if id > 3 {
+    fn parse_dqt<A: Allocator<'a>>(&mut self, mut data: &[u8], pool: &mut A) -> Result<()> {
+        // 先检查段内每张表都完整，再占用池空间
+        let mut rest = data;
+        while let Some((&table_info, tail)) = rest.split_first() {
+            if table_info & 0x0F > 3 || table_info >> 4 > 1 {
                 return Err(Error::FormatError);
             }
+            rest = tail.get(64 << (table_info >> 4)..).ok_or(Error::FormatError)?;
+        }
 
-            // 分配量化表存储空间
-            let qtable_mem = pool.alloc(64 * 4).ok_or(Error::InsufficientMemory)?;
-            let qtable_ptr = qtable_mem.as_mut_ptr() as *mut i32;
-            
-            unsafe {
-                let qtable = core::slice::from_raw_parts_mut(qtable_ptr, 64);
-                
-                if precision == 0 {
-                    if data.len() < 65 {
-                        return Err(Error::FormatError);
-                    }
-                    for i in 0..64 {
-                        let zi = ZIGZAG[i] as usize;
-                        let q_value = data[1 + i] as u32;
-                        let ipsf = ARAI_SCALE_FACTOR[zi] as u32;
-                        qtable[zi] = (q_value * ipsf) as i32;
-                    }
-                    data = &data[65..];
-                } else {
-                    if data.len() < 129 {
-                        return Err(Error::FormatError);
-                    }
-                    for i in 0..64 {
-                        let zi = ZIGZAG[i] as usize;
-                        let q_value = u16::from_be_bytes([data[1 + i * 2], data[2 + i * 2]]) as u32;
-                        let ipsf = ARAI_SCALE_FACTOR[zi] as u32;
-                        qtable[zi] = (q_value * ipsf) as i32;
-                    }
-                    data = &data[129..];
+        while let Some((&table_info, rest)) = data.split_first() {
+            let precision = table_info >> 4;
+            let id = table_info & 0x0F;
+            let size = if precision == 0 { 64 } else { 128 };
+            let mut raw = [0u16; 64];
+            if precision == 0 {
+                for (q, &v) in raw.iter_mut().zip(&rest[..64]) {
+                    *q = v as u16;
+                }
+            } else {
+                for (q, v) in raw.iter_mut().zip(rest[..128].chunks_exact(2)) {
+                    *q = u16::from_be_bytes([v[0], v[1]]);
                 }
-                
-                self.qtables[id as usize] = qtable_ptr as *const [i32; 64];
             }
+
+            // 分配量化表存储空间
+            let qtable: &'a mut [i32; 64] = pool
+                .alloc_array::<i32>(64)
+                .ok_or(Error::InsufficientMemory)?
+                .try_into()
+                .map_err(|_| Error::InsufficientMemory)?;
+            *qtable = scale_qtable(&raw);
+
+            self.qtables[id as usize] = Some(qtable);
+            data = &rest[size..];
         }
 
         Ok(())
@@ -351,8 +1092,9 @@ impl<'a> JpegDecoder<'a> {
         Ok(())
     }
 
-    fn parse_sos(&self, data: &[u8]) -> Result<()> {
-        if data.is_empty() {
+    fn parse_sos(&mut self, data: &[u8]) -> Result<()> {
+        // SOF 必须在 SOS 之前，否则分量数为 0 的 SOS 会通过下面的检查
+        if data.is_empty() || !self.flag(FLAG_HAVE_SOF) {
             return Err(Error::FormatError);
         }
 
@@ -361,14 +1103,40 @@ impl<'a> JpegDecoder<'a> {
             return Err(Error::FormatError);
         }
 
+        // 分量选择符 (Cs, Td/Ta) 各 2 字节，之后是 Ss, Se, Ah/Al
+        if data.len() < 1 + num_components as usize * 2 + 3 {
+            return Err(Error::FormatError);
+        }
+
+        // 每个分量的 (Td, Ta) 按分量 ID 对应到 SOF 中的分量
+        self.scan_tables = 0;
+        for selector in data[1..1 + num_components as usize * 2].chunks_exact(2) {
+            let i = self.components[..self.num_components as usize]
+                .iter()
+                .position(|c| c.id == selector[0])
+                .ok_or(Error::FormatError)?;
+            let (dc, ac) = (selector[1] >> 4, selector[1] & 0x0F);
+            if dc > 1 || ac > 1 {
+                return Err(Error::FormatError);
+            }
+            self.scan_tables |= (dc << i) | (ac << (i + 4));
+        }
+
+        self.validate_tables()
+    }
+
+    /// 头部结束时检查扫描用到的 Huffman 表和量化表都已定义
+    /// 
+    /// 在分配 MCU 缓冲区之前拒绝不完整的文件，而不是解码到一半才失败。
+    fn validate_tables(&self) -> Result<()> {
         for i in 0..self.num_components as usize {
-            let table_id = if i == 0 { 0 } else { 1 };
+            let (dc, ac) = self.huffman_tables(i);
             
-            if self.huff_dc[table_id].is_null() || self.huff_ac[table_id].is_null() {
+            if self.huff_dc[dc].is_none() || self.huff_ac[ac].is_none() {
                 return Err(Error::FormatError);
             }
 
-            if self.qtables[self.qtable_ids[i] as usize].is_null() {
+            if self.qtables[self.components[i].quant_table_id as usize].is_none() {
                 return Err(Error::FormatError);
             }
         }
@@ -383,12 +1151,19 @@ impl<'a> JpegDecoder<'a> {
     /// # Parameters
     /// 
     /// * `data` - Complete JPEG file data
-    /// * `scale` - Scale factor (0=1/1, 1=1/2, 2=1/4, 3=1/8)
+    /// * `scale` - Scale factor (0=1/1, 1=1/2, 2=1/4, 3=1/8, 4=1/16, 5=1/32)
     /// * `mcu_buffer` - MCU work buffer (provided by user)
     /// * `work_buffer` - RGB conversion work buffer (provided by user)
     /// * `callback` - Output callback function
     /// 
-    /// Use `mcu_buffer_size()` and `work_buffer_size()` to get required buffer sizes.
+    /// Use `mcu_buffer_size()` and `work_buffer_size_for_scale()` to get required buffer sizes.
+    /// Returns `Error::Parameter` if `prepare()` hasn't been called successfully,
+    /// `Error::InsufficientMemory` if `mcu_buffer` is too small and
+    /// `Error::InsufficientBuffer` if `work_buffer` is too small.
+    /// 
+    /// Scaled output pixels are box averages of the full-resolution pixels.
+    /// From 1/16 on, an output pixel can cover several MCUs; it is then
+    /// delivered as a 1x1 rectangle once its last MCU has been decoded.
     /// 
     /// # Example
     /// 
@@ -424,351 +1199,6159 @@ impl<'a> JpegDecoder<'a> {
         work_buffer: &mut [u8],
         callback: OutputCallback,
     ) -> Result<()> {
-        if scale > 3 {
-            return Err(Error::Parameter);
-        }
-
-        // 验证缓冲区大小
-        let mcu_size = self.mcu_buffer_size();
-        let work_size = self.work_buffer_size();
-        
-        if mcu_buffer.len() < mcu_size {
-            return Err(Error::InsufficientMemory);
-        }
-        if work_buffer.len() < work_size {
-            return Err(Error::InsufficientMemory);
-        }
+        self.check_scale(scale, MAX_SCALE)?;
+        self.check_buffers(scale, mcu_buffer, work_buffer)?;
 
         self.scale = scale;
-        self.dc_values = [0; 3];
 
         let mcu_width = self.sampling.mcu_width() as usize;
         let mcu_height = self.sampling.mcu_height() as usize;
-        let mcu_pixel_width = mcu_width * 8;
-        let mcu_pixel_height = mcu_height * 8;
 
-        let scan_data = self.find_scan_data(data)?;
-        let mut bitstream = BitStream::new(scan_data);
+        let scan = self.start_scan(data)?;
+        let (work_buffer, sums) = self.split_sums(work_buffer, scale);
 
+        let mut emitted = 0;
+        self.decode_scan(BitStream::new(scan), mcu_buffer, None, None, ScanMode::Full, &mut |decoder, mcu, mcu_x, mcu_y, _| {
+            decoder.output_scaled(mcu, work_buffer, sums, mcu_x, mcu_y, mcu_width, mcu_height, &mut emitted, callback)
+        })?;
+        Ok(())
+    }
+
+    /// Decompress only the part of the image inside `region`
+    /// 
+    /// Like `decompress()`, but the callback only sees MCUs overlapping
+    /// `region` (in output coordinates at the given scale, inclusive), and
+    /// each `Rectangle` and bitmap is clipped to it. JPEG entropy coding is
+    /// sequential, so MCUs before the region still have to be Huffman
+    /// decoded, but their IDCT and color conversion are skipped, and
+    /// decoding stops after the last MCU row of the region. With restart
+    /// intervals (DRI), whole intervals outside the region are skipped by
+    /// scanning for the next `RSTn` marker without decoding them, which
+    /// makes crops of large images much cheaper. Not available for the
+    /// packed formats (Mono1/Gray4).
+    /// 
+    /// # Parameters
+    /// 
+    /// * `data` - Complete JPEG file data
+    /// * `scale` - Scale factor (0=1/1, 1=1/2, 2=1/4, 3=1/8); 1/16 and 1/32 are not supported
+    /// * `region` - Requested area of the scaled image
+    /// * `mcu_buffer` - MCU work buffer (provided by user)
+    /// * `work_buffer` - RGB conversion work buffer (provided by user)
+    /// * `callback` - Output callback function
+    /// 
+    /// # Returns
+    /// 
+    /// `Error::Parameter` if `region` is empty, the format is packed or
+    /// `scale` is above 3: from 1/16 on an output pixel spans several MCUs,
+    /// which can't be combined when MCUs outside the region are skipped.
+    #[allow(clippy::too_many_arguments)]
+    pub fn decompress_region(
+        &mut self,
+        data: &[u8],
+        scale: u8,
+        region: Rectangle,
+        mcu_buffer: &mut [i16],
+        work_buffer: &mut [u8],
+        callback: OutputCallback,
+    ) -> Result<()> {
+        self.check_scale(scale, MAX_MCU_SCALE)?;
+        if self.output_bpp() == 0 || region.left > region.right || region.top > region.bottom {
+            return Err(Error::Parameter);
+        }
+        self.check_buffers(scale, mcu_buffer, work_buffer)?;
+
+        self.scale = scale;
+
+        let mcu_width = self.sampling.mcu_width() as usize;
+        let mcu_height = self.sampling.mcu_height() as usize;
+        let scan = self.start_scan(data)?;
+
+        // 换算到原始分辨率，用于判断 MCU 是否相交
+        let first = |v: u16| ((v as u32) << scale).min(u16::MAX as u32) as u16;
+        let last = |v: u16| (((v as u32 + 1) << scale) - 1).min(u16::MAX as u32) as u16;
+        let full_region = Rectangle::new(first(region.left), last(region.right), first(region.top), last(region.bottom));
+
+        let mut emitted = 0;
+        self.decode_scan(BitStream::new(scan), mcu_buffer, None, None, ScanMode::Region(full_region), &mut |decoder, mcu, mcu_x, mcu_y, _| {
+            decoder.output_mcu(mcu, work_buffer, mcu_x, mcu_y, mcu_width, mcu_height, Some(&region), &mut emitted, callback)
+        })?;
+        Ok(())
+    }
+
+    /// Decompress JPEG image from a stream
+    /// 
+    /// Streaming counterpart of `decompress()`: call `prepare_reader()`
+    /// first, then pass the same reader, now positioned at the scan data.
+    /// The entropy-coded data is pulled through `buffer` as needed, so the
+    /// file never has to be in memory as a whole.
+    /// 
+    /// # Parameters
+    /// 
+    /// * `reader` - JPEG data source, positioned after the SOS header
+    /// * `buffer` - Input buffer (`BUFFER_SIZE` bytes is a good default)
+    /// * `scale` - Scale factor (0=1/1, 1=1/2, 2=1/4, 3=1/8, 4=1/16, 5=1/32)
+    /// * `mcu_buffer` - MCU work buffer (provided by user)
+    /// * `work_buffer` - RGB conversion work buffer (provided by user)
+    /// * `callback` - Output callback function
+    pub fn decompress_reader(
+        &mut self,
+        reader: &mut dyn JpegReader,
+        buffer: &mut [u8],
+        scale: u8,
+        mcu_buffer: &mut [i16],
+        work_buffer: &mut [u8],
+        callback: OutputCallback,
+    ) -> Result<()> {
+        self.check_scale(scale, MAX_SCALE)?;
+        self.check_buffers(scale, mcu_buffer, work_buffer)?;
+        if buffer.is_empty() {
+            return Err(Error::InsufficientBuffer);
+        }
+
+        self.scale = scale;
+
+        let mcu_width = self.sampling.mcu_width() as usize;
+        let mcu_height = self.sampling.mcu_height() as usize;
+
+        let bitstream = BitStream::from_reader(reader, buffer);
+        self.scan_end = 0;
+        let (work_buffer, sums) = self.split_sums(work_buffer, scale);
+        let mut emitted = 0;
+        self.decode_scan(bitstream, mcu_buffer, None, None, ScanMode::Full, &mut |decoder, mcu, mcu_x, mcu_y, _| {
+            decoder.output_scaled(mcu, work_buffer, sums, mcu_x, mcu_y, mcu_width, mcu_height, &mut emitted, callback)
+        })?;
+        Ok(())
+    }
+
+    /// Decompress raw scan data using the tables from a previous `prepare()`
+    /// 
+    /// Intended for fixed-format streams (e.g. MJPEG from a camera) where
+    /// every frame carries byte-identical headers: call `prepare()` once on
+    /// the first frame, then pass only the entropy-coded data of each frame
+    /// followed by its EOI marker (`&frame[start..end + 2]` from
+    /// `scan_range()`). No headers are parsed and nothing is validated per
+    /// frame. Stuffed bytes and `RSTn` markers are still handled.
+    /// 
+    /// The caller is responsible for the headers actually matching. A frame
+    /// with different dimensions, sampling, Huffman or quantization tables
+    /// decodes to garbage or fails with `Error::FormatError`; it cannot
+    /// cause undefined behavior.
+    /// 
+    /// # Parameters
+    /// 
+    /// * `scan` - Entropy-coded data, from after the SOS header through EOI
+    /// * `scale` - Scale factor (0-5)
+    /// * `mcu_buffer` - MCU work buffer (provided by user)
+    /// * `work_buffer` - RGB conversion work buffer (provided by user)
+    /// * `callback` - Output callback function
+    pub fn decompress_fast(
+        &mut self,
+        scan: &[u8],
+        scale: u8,
+        mcu_buffer: &mut [i16],
+        work_buffer: &mut [u8],
+        callback: OutputCallback,
+    ) -> Result<()> {
+        self.check_scale(scale, MAX_SCALE)?;
+        self.check_buffers(scale, mcu_buffer, work_buffer)?;
+        if scan.is_empty() {
+            return Err(Error::Input);
+        }
+
+        self.scale = scale;
+        self.scan_end = 0;
+
+        let mcu_width = self.sampling.mcu_width() as usize;
+        let mcu_height = self.sampling.mcu_height() as usize;
+
+        let (work_buffer, sums) = self.split_sums(work_buffer, scale);
+        let mut emitted = 0;
+        self.decode_scan(BitStream::new(scan), mcu_buffer, None, None, ScanMode::Full, &mut |decoder, mcu, mcu_x, mcu_y, _| {
+            decoder.output_scaled(mcu, work_buffer, sums, mcu_x, mcu_y, mcu_width, mcu_height, &mut emitted, callback)
+        })?;
+        Ok(())
+    }
+
+    /// Decompress a complete frame using the tables from a previous `prepare()`
+    ///
+    /// For Motion JPEG streams, where every frame repeats the same DQT/DHT
+    /// segments: call `prepare()` once on the first frame, then pass each
+    /// whole frame here. The marker segments are only walked to find the
+    /// scan; DQT and DHT are skipped rather than rebuilt, so no pool memory
+    /// is taken per frame. The frame size in the SOF segment is checked
+    /// against the prepared image, everything else is trusted as in
+    /// `decompress_fast()`.
+    ///
+    /// # Parameters
+    ///
+    /// * `frame` - Complete JPEG data of one frame
+    /// * `scale` - Scale factor (0-5)
+    /// * `mcu_buffer` - MCU work buffer (provided by user)
+    /// * `work_buffer` - RGB conversion work buffer (provided by user)
+    /// * `callback` - Output callback function
+    ///
+    /// Returns `Error::Parameter` before `prepare()` and
+    /// `Error::FormatError` if the frame size differs from the prepared one.
+    pub fn decompress_scan(
+        &mut self,
+        frame: &[u8],
+        scale: u8,
+        mcu_buffer: &mut [i16],
+        work_buffer: &mut [u8],
+        callback: OutputCallback,
+    ) -> Result<()> {
+        self.check_scale(scale, MAX_SCALE)?;
+        let mut segments = MarkerIter::new(frame);
+        loop {
+            match segments.next().ok_or(Error::Input)?? {
+                (markers::SOF0, sof) => {
+                    // SOF: 精度、高度、宽度
+                    let size = sof.get(1..5).map(|s| (u16::from_be_bytes([s[0], s[1]]), u16::from_be_bytes([s[2], s[3]])));
+                    if size != Some((self.height, self.width)) {
+                        return Err(Error::FormatError);
+                    }
+                }
+                (markers::SOS, _) => break,
+                (markers::EOI, _) => return Err(Error::FormatError),
+                _ => {}
+            }
+        }
+
+        let scan = &frame[segments.position()..];
+        self.decompress_fast(scan, scale, mcu_buffer, work_buffer, callback)
+    }
+
+    /// Decompress JPEG image and report how detailed each MCU is
+    /// 
+    /// Same as `decompress()`, but `complexity` is called before each MCU is
+    /// output with the number of nonzero AC coefficients it contained, which
+    /// tracks visual detail: flat areas report (close to) zero, textured
+    /// areas many. The count falls out of entropy decoding at no extra cost.
+    /// 
+    /// # Parameters
+    /// 
+    /// * `data` - Complete JPEG file data
+    /// * `scale` - Scale factor (0=1/1, 1=1/2, 2=1/4, 3=1/8, 4=1/16, 5=1/32)
+    /// * `mcu_buffer` - MCU work buffer (provided by user)
+    /// * `work_buffer` - RGB conversion work buffer (provided by user)
+    /// * `callback` - Output callback function
+    /// * `complexity` - Per-MCU complexity callback
+    pub fn decompress_with_complexity(
+        &mut self,
+        data: &[u8],
+        scale: u8,
+        mcu_buffer: &mut [i16],
+        work_buffer: &mut [u8],
+        callback: OutputCallback,
+        complexity: ComplexityCallback,
+    ) -> Result<()> {
+        self.check_scale(scale, MAX_SCALE)?;
+        self.check_buffers(scale, mcu_buffer, work_buffer)?;
+
+        self.scale = scale;
+
+        let mcu_width = self.sampling.mcu_width() as usize;
+        let mcu_height = self.sampling.mcu_height() as usize;
+
+        let scan = self.start_scan(data)?;
+        let (work_buffer, sums) = self.split_sums(work_buffer, scale);
+
+        let mut emitted = 0;
+        self.decode_scan(BitStream::new(scan), mcu_buffer, None, None, ScanMode::Full, &mut |decoder, mcu, mcu_x, mcu_y, nonzero_ac| {
+            let right = (mcu_x as usize + mcu_width * 8).min(decoder.width as usize) as u16 - 1;
+            let bottom = (mcu_y as usize + mcu_height * 8).min(decoder.height as usize) as u16 - 1;
+            complexity(&Rectangle::new(mcu_x, right, mcu_y, bottom), nonzero_ac);
+            decoder.output_scaled(mcu, work_buffer, sums, mcu_x, mcu_y, mcu_width, mcu_height, &mut emitted, callback)
+        })?;
+        Ok(())
+    }
+
+    /// Decompress JPEG image, telling the callback which MCU it receives
+    /// 
+    /// Same as `decompress()`, but the callback also gets an `McuInfo` with
+    /// the MCU's column, row and raster index and the total MCU count, e.g.
+    /// for keying a tile cache or driving a progress bar. At 1/16 and 1/32 an
+    /// output pixel can cover several MCUs; the info then describes the MCU
+    /// that completed it.
+    /// 
+    /// # Parameters
+    /// 
+    /// * `data` - Complete JPEG file data
+    /// * `scale` - Scale factor (0=1/1, 1=1/2, 2=1/4, 3=1/8, 4=1/16, 5=1/32)
+    /// * `mcu_buffer` - MCU work buffer (provided by user)
+    /// * `work_buffer` - RGB conversion work buffer (provided by user)
+    /// * `callback` - Output callback function
+    pub fn decompress_with_info(
+        &mut self,
+        data: &[u8],
+        scale: u8,
+        mcu_buffer: &mut [i16],
+        work_buffer: &mut [u8],
+        callback: McuInfoCallback,
+    ) -> Result<()> {
+        self.check_scale(scale, MAX_SCALE)?;
+        self.check_buffers(scale, mcu_buffer, work_buffer)?;
+
+        self.scale = scale;
+
+        let mcu_width = self.sampling.mcu_width() as usize;
+        let mcu_height = self.sampling.mcu_height() as usize;
+        let mcus_across = self.mcus_across();
+        let total_mcus = self.total_mcus();
+
+        let scan = self.start_scan(data)?;
+        let (work_buffer, sums) = self.split_sums(work_buffer, scale);
+
+        let mut emitted = 0;
+        self.decode_scan(BitStream::new(scan), mcu_buffer, None, None, ScanMode::Full, &mut |decoder, mcu, mcu_x, mcu_y, _| {
+            let mcu_col = mcu_x as u32 / (mcu_width as u32 * 8);
+            let mcu_row = mcu_y as u32 / (mcu_height as u32 * 8);
+            let info = McuInfo { mcu_col, mcu_row, mcu_index: mcu_row * mcus_across + mcu_col, total_mcus };
+            decoder.output_scaled(mcu, work_buffer, sums, mcu_x, mcu_y, mcu_width, mcu_height, &mut emitted, &mut |decoder, bitmap, rect| {
+                callback(decoder, bitmap, rect, &info)
+            })
+        })?;
+        Ok(())
+    }
+
+    /// Decompress JPEG image, yielding between MCU rows on request
+    /// 
+    /// Same as `decompress()`, but polls `yield_check` before each MCU row.
+    /// When it returns `true` the decode stops cleanly at the start of that
+    /// row. Typical checks compare a deadline timer, count down an MCU row
+    /// budget or read a preemption flag. Start with `state = None`; when the
+    /// check fires, `Ok(Some(state))` is returned and the decode is
+    /// continued by calling again with `Some(state)`. Returns `Ok(None)` once
+    /// the whole image has been output. At least one MCU row is decoded per
+    /// call, so repeated calls always make progress.
+    /// 
+    /// The callback can pause the decode as well: returning `Ok(false)` or
+    /// `Err(Error::Interrupted)` returns `Ok(Some(state))` right after the
+    /// current MCU instead of failing, so time-sliced callers can decode a
+    /// few MCUs per tick. The MCU handed to that callback counts as output.
+    /// 
+    /// `scale` is limited to 0..=3 (`Error::Parameter` otherwise): from 1/16
+    /// on an output pixel is summed across MCU rows, and those partial sums
+    /// are not part of `DecodeState`.
+    /// 
+    /// # Parameters
+    /// 
+    /// * `data` - Complete JPEG file data (the same slice for every call)
+    /// * `scale` - Scale factor (0-3), must match the one the state was created with
+    /// * `mcu_buffer` - MCU work buffer (provided by user)
+    /// * `work_buffer` - RGB conversion work buffer (provided by user)
+    /// * `state` - `None` to start, or the state returned by the previous call
+    /// * `yield_check` - Polled before each MCU row, `None` to never yield
+    /// * `callback` - Output callback function
+    #[allow(clippy::too_many_arguments)]
+    pub fn decompress_resumable(
+        &mut self,
+        data: &[u8],
+        scale: u8,
+        mcu_buffer: &mut [i16],
+        work_buffer: &mut [u8],
+        state: Option<DecodeState>,
+        yield_check: Option<YieldCheck>,
+        callback: OutputCallback,
+    ) -> Result<Option<DecodeState>> {
+        self.check_scale(scale, MAX_MCU_SCALE)?;
+        if state.is_some_and(|st| st.scale != scale) {
+            return Err(Error::Parameter);
+        }
+        self.check_buffers(scale, mcu_buffer, work_buffer)?;
+
+        self.scale = scale;
+
+        let mcu_width = self.sampling.mcu_width() as usize;
+        let mcu_height = self.sampling.mcu_height() as usize;
+
+        let scan = self.start_scan(data)?;
+
+        let mut never = || false;
+        let yield_check = yield_check.unwrap_or(&mut never);
+        let mut emitted = 0;
+        self.decode_scan(BitStream::new(scan), mcu_buffer, state.as_ref(), Some(yield_check), ScanMode::Full, &mut |decoder, mcu, mcu_x, mcu_y, _| {
+            decoder.output_mcu(mcu, work_buffer, mcu_x, mcu_y, mcu_width, mcu_height, None, &mut emitted, callback)
+        })
+    }
+
+    /// Continue a decode paused by `decompress_resumable()`
+    /// 
+    /// Same as calling `decompress_resumable()` with `Some(state)` and the
+    /// scale the state was created with.
+    /// 
+    /// # Parameters
+    /// 
+    /// * `data` - Complete JPEG file data (the same slice as before)
+    /// * `state` - The state returned by the previous call
+    /// * `mcu_buffer` - MCU work buffer (provided by user)
+    /// * `work_buffer` - RGB conversion work buffer (provided by user)
+    /// * `yield_check` - Polled before each MCU row, `None` to never yield
+    /// * `callback` - Output callback function
+    /// 
+    /// # Returns
+    /// 
+    /// `Ok(Some(state))` if paused again, `Ok(None)` once the image is done.
+    pub fn resume(
+        &mut self,
+        data: &[u8],
+        state: DecodeState,
+        mcu_buffer: &mut [i16],
+        work_buffer: &mut [u8],
+        yield_check: Option<YieldCheck>,
+        callback: OutputCallback,
+    ) -> Result<Option<DecodeState>> {
+        self.decompress_resumable(data, state.scale, mcu_buffer, work_buffer, Some(state), yield_check, callback)
+    }
+
+    /// Decode the image one MCU at a time
+    /// 
+    /// Returns an `McuIter` whose `next()` yields each MCU's rectangle and
+    /// pixels in turn, an alternative to the callback of `decompress()` for
+    /// callers that would rather write a plain loop. It is built on the same
+    /// pausing decode as `decompress_resumable()` (so `scale` is 0..=3, with
+    /// no yield check), and argument errors are reported by the
+    /// first `next()` call. `decompress()` remains the faster choice when a
+    /// callback is convenient.
+    /// 
+    /// # Parameters
+    /// 
+    /// * `data` - Complete JPEG file data
+    /// * `scale` - Scale factor (0=1/1, 1=1/2, 2=1/4, 3=1/8)
+    /// * `mcu_buffer` - MCU work buffer (provided by user)
+    /// * `work_buffer` - RGB conversion work buffer (provided by user)
+    pub fn mcus<'d>(
+        &'d mut self,
+        data: &'d [u8],
+        scale: u8,
+        mcu_buffer: &'d mut [i16],
+        work_buffer: &'d mut [u8],
+    ) -> McuIter<'d, 'a> {
+        McuIter { decoder: self, data, scale, mcu_buffer, work_buffer, state: None, done: false }
+    }
+
+    /// Decompress JPEG image as separate Y, Cb and Cr planes
+    /// 
+    /// Skips color conversion and delivers the raw component samples, one
+    /// callback per plane per MCU (plane 0 = Y, 1 = Cb, 2 = Cr; grayscale
+    /// images and `OutputFormat::Grayscale` only have plane 0). `bitmap` holds 1 byte per sample and `rect`
+    /// is in the coordinates of that plane, so chroma rectangles of 4:2:2 and
+    /// 4:2:0 images are subsampled. Use `plane_dimensions()` to size the
+    /// destination of each plane.
+    /// 
+    /// # Parameters
+    /// 
+    /// * `data` - Complete JPEG file data
+    /// * `mcu_buffer` - MCU work buffer (provided by user)
+    /// * `work_buffer` - Sample work buffer, at least `work_buffer_size()` bytes
+    /// * `callback` - Plane output callback, return `Ok(false)` to abort
+    pub fn decompress_planes(
+        &mut self,
+        data: &[u8],
+        mcu_buffer: &mut [i16],
+        work_buffer: &mut [u8],
+        callback: PlaneCallback,
+    ) -> Result<()> {
+        self.require_prepared()?;
+        self.require_ycc()?;
+        if mcu_buffer.len() < self.mcu_buffer_size() {
+            return Err(Error::InsufficientMemory);
+        }
+        if work_buffer.len() < self.work_buffer_size() {
+            return Err(Error::InsufficientBuffer);
+        }
+
+        let mcu_width = self.sampling.mcu_width() as usize;
+        let mcu_height = self.sampling.mcu_height() as usize;
+
+        let scan = self.start_scan(data)?;
+
+        self.decode_scan(BitStream::new(scan), mcu_buffer, None, None, ScanMode::Full, &mut |decoder, mcu, mcu_x, mcu_y, _| {
+            decoder.output_planes(mcu, work_buffer, mcu_x, mcu_y, mcu_width, mcu_height, callback)
+        })?;
+        Ok(())
+    }
+
+    /// Decompress JPEG image to raw YCbCr MCUs
+    /// 
+    /// Hands the IDCT output of every MCU to the callback as is: no color
+    /// conversion, clipping or chroma upsampling runs, and no work buffer is
+    /// needed. See `McuLayout` for the block-interleaved layout. Grayscale
+    /// images and `OutputFormat::Grayscale` deliver the luma blocks only
+    /// (the Cb/Cr IDCT is skipped for the latter). The rectangle is in
+    /// full-resolution image coordinates; samples of edge MCUs past the
+    /// rectangle are padding.
+    /// 
+    /// # Parameters
+    /// 
+    /// * `data` - Complete JPEG file data
+    /// * `mcu_buffer` - MCU work buffer (provided by user)
+    /// * `callback` - Raw MCU callback, return `Ok(false)` to abort
+    pub fn decompress_raw_ycbcr(
+        &mut self,
+        data: &[u8],
+        mcu_buffer: &mut [i16],
+        callback: RawMcuCallback,
+    ) -> Result<()> {
+        self.require_prepared()?;
+        self.require_ycc()?;
+        if mcu_buffer.len() < self.mcu_buffer_size() {
+            return Err(Error::InsufficientMemory);
+        }
+
+        let mcu_width = self.sampling.mcu_width() as usize;
+        let mcu_height = self.sampling.mcu_height() as usize;
+        let layout = McuLayout {
+            h_blocks: mcu_width as u8,
+            v_blocks: mcu_height as u8,
+            chroma_blocks: if self.luma_only() { 0 } else { 2 },
+        };
+
+        let scan = self.start_scan(data)?;
+
+        self.decode_scan(BitStream::new(scan), mcu_buffer, None, None, ScanMode::Full, &mut |decoder, mcu, mcu_x, mcu_y, _| {
+            let right = (mcu_x as usize + mcu_width * 8).min(decoder.width as usize) as u16 - 1;
+            let bottom = (mcu_y as usize + mcu_height * 8).min(decoder.height as usize) as u16 - 1;
+            let rect = Rectangle::new(mcu_x, right, mcu_y, bottom);
+            if !callback(decoder, &mcu[..layout.sample_count()], &rect, &layout)? {
+                return Err(Error::Interrupted);
+            }
+            Ok(())
+        })?;
+        Ok(())
+    }
+
+    /// Get the size of a component plane in samples
+    /// 
+    /// Returns `None` if the image has no such plane.
+    pub fn plane_dimensions(&self, plane: u8) -> Option<(u16, u16)> {
+        match plane {
+            0 if self.num_components > 0 => Some((self.width, self.height)),
+            1 | 2 if self.num_components == 3 => Some((
+                self.width.div_ceil(self.sampling.mcu_width() as u16),
+                self.height.div_ceil(self.sampling.mcu_height() as u16),
+            )),
+            _ => None,
+        }
+    }
+
+    /// Decompress JPEG image into separate R, G and B plane buffers
+    /// 
+    /// Planar (CHW) layout for consumers such as tensor inputs: pixel `(x, y)`
+    /// of channel `c` is stored at `y * stride + x` of the matching plane.
+    /// Grayscale images write the same value to all three planes.
+    /// 
+    /// # Parameters
+    /// 
+    /// * `data` - Complete JPEG file data
+    /// * `scale` - Scale factor (0=1/1, 1=1/2, 2=1/4, 3=1/8, 4=1/16, 5=1/32)
+    /// * `mcu_buffer` - MCU work buffer (provided by user)
+    /// * `work_buffer` - RGB conversion work buffer (provided by user)
+    /// * `r` / `g` / `b` - Destination planes
+    /// * `stride` - Bytes per row of each plane
+    /// 
+    /// The planes hold the rotated image when `set_rotation()` is in effect,
+    /// so `stride` must cover the rotated width.
+    /// 
+    /// Returns `Error::Parameter` if `stride` is smaller than the scaled width
+    /// or the output format is not RGB888, RGB666 or grayscale, and
+    /// `Error::InsufficientMemory` if a plane is too short.
+    #[allow(clippy::too_many_arguments)]
+    pub fn decompress_to_planes(
+        &mut self,
+        data: &[u8],
+        scale: u8,
+        mcu_buffer: &mut [i16],
+        work_buffer: &mut [u8],
+        r: &mut [u8],
+        g: &mut [u8],
+        b: &mut [u8],
+        stride: usize,
+    ) -> Result<()> {
+        self.check_scale(scale, MAX_SCALE)?;
+
+        let bpp = self.output_bpp();
+        if !matches!(bpp, 1 | 3) {
+            return Err(Error::Parameter);
+        }
+        let (width, height) = self.rotated_size(scale);
+        let (width, height) = (width as usize, height as usize);
+
+        if stride < width {
+            return Err(Error::Parameter);
+        }
+        let plane_size = if height > 0 { (height - 1) * stride + width } else { 0 };
+        if r.len() < plane_size || g.len() < plane_size || b.len() < plane_size {
+            return Err(Error::InsufficientMemory);
+        }
+
+        self.decompress(data, scale, mcu_buffer, work_buffer, &mut |_decoder, bitmap, rect| {
+            let mut src = 0;
+            for y in rect.top..=rect.bottom {
+                let row = y as usize * stride;
+                for x in rect.left..=rect.right {
+                    let dst = row + x as usize;
+                    if bpp == 1 {
+                        r[dst] = bitmap[src];
+                        g[dst] = bitmap[src];
+                        b[dst] = bitmap[src];
+                    } else {
+                        r[dst] = bitmap[src];
+                        g[dst] = bitmap[src + 1];
+                        b[dst] = bitmap[src + 2];
+                    }
+                    src += bpp;
+                }
+            }
+            Ok(true)
+        })
+    }
+
+    /// Get the buffer size needed by `decompress_mcu_rows()`
+    /// 
+    /// One full-width MCU row at the given scale in the current output
+    /// format, in bytes. Returns 0 for packed formats (Mono1/Gray4).
+    pub fn mcu_row_buffer_size(&self, scale: u8) -> usize {
+        let scale = scale.min(MAX_SCALE);
+        let row_height = ((self.sampling.mcu_height() as usize * 8) >> scale).max(1);
+        (self.width >> scale) as usize * row_height * self.output_bpp()
+    }
+
+    /// Decompress JPEG image one full MCU row at a time
+    /// 
+    /// Collects the blocks of each MCU row into `row_buffer` and calls
+    /// `callback` once per row with a contiguous `width x row_height` bitmap,
+    /// so display drivers can issue one large DMA transfer per row instead of
+    /// one per MCU. The rectangle always spans the full (scaled) image width;
+    /// the last row may be shorter.
+    /// 
+    /// # Parameters
+    /// 
+    /// * `data` - Complete JPEG file data
+    /// * `scale` - Scale factor (0=1/1, 1=1/2, 2=1/4, 3=1/8, 4=1/16, 5=1/32)
+    /// * `mcu_buffer` - MCU work buffer (provided by user)
+    /// * `work_buffer` - RGB conversion work buffer (provided by user)
+    /// * `row_buffer` - Row buffer, at least `mcu_row_buffer_size(scale)` bytes
+    /// * `callback` - Output callback, called once per MCU row
+    /// 
+    /// Returns `Error::Parameter` for packed output formats (Mono1/Gray4) and
+    /// when `set_rotation()` is in effect (rotated MCUs don't arrive row by
+    /// row).
+    pub fn decompress_mcu_rows(
+        &mut self,
+        data: &[u8],
+        scale: u8,
+        mcu_buffer: &mut [i16],
+        work_buffer: &mut [u8],
+        row_buffer: &mut [u8],
+        callback: OutputCallback,
+    ) -> Result<()> {
+        self.check_scale(scale, MAX_SCALE)?;
+        if self.rotation != Rotation::None {
+            return Err(Error::Parameter);
+        }
+        let bpp = self.output_bpp();
+        if bpp == 0 {
+            return Err(Error::Parameter);
+        }
+        if row_buffer.len() < self.mcu_row_buffer_size(scale) {
+            return Err(Error::InsufficientMemory);
+        }
+
+        let stride = (self.width >> scale) as usize * bpp;
+        self.collect_mcu_rows(data, scale, mcu_buffer, work_buffer, row_buffer, stride, callback)
+    }
+
+    /// Get the buffer size needed by `decompress_scanlines()`
+    /// 
+    /// One MCU row at the given scale with every row padded to
+    /// `row_alignment` bytes. Returns 0 for packed formats (Mono1/Gray4) or
+    /// an alignment of 0.
+    pub fn scanline_buffer_size(&self, scale: u8, row_alignment: usize) -> usize {
+        if row_alignment == 0 {
+            return 0;
+        }
+        let scale = scale.min(MAX_SCALE);
+        let row_height = ((self.sampling.mcu_height() as usize * 8) >> scale).max(1);
+        let row = (self.width >> scale) as usize * self.output_bpp();
+        row.next_multiple_of(row_alignment) * row_height
+    }
+
+    /// Decompress JPEG image one scanline at a time
+    /// 
+    /// Calls `callback` once per output row, top to bottom, with the pixels
+    /// of the whole row followed by zero padding up to a multiple of
+    /// `row_alignment` bytes (e.g. 4 for BMP rows or word-aligned DMA; 1 for
+    /// packed rows).
+    /// 
+    /// # Parameters
+    /// 
+    /// * `data` - Complete JPEG file data
+    /// * `scale` - Scale factor (0=1/1, 1=1/2, 2=1/4, 3=1/8, 4=1/16, 5=1/32)
+    /// * `mcu_buffer` - MCU work buffer (provided by user)
+    /// * `work_buffer` - RGB conversion work buffer (provided by user)
+    /// * `row_buffer` - At least `scanline_buffer_size(scale, row_alignment)` bytes
+    /// * `row_alignment` - Row length alignment in bytes (non-zero)
+    /// * `callback` - Scanline callback function
+    /// 
+    /// Returns `Error::Parameter` for packed output formats (Mono1/Gray4), a
+    /// zero alignment or when `set_rotation()` is in effect.
+    #[allow(clippy::too_many_arguments)]
+    pub fn decompress_scanlines(
+        &mut self,
+        data: &[u8],
+        scale: u8,
+        mcu_buffer: &mut [i16],
+        work_buffer: &mut [u8],
+        row_buffer: &mut [u8],
+        row_alignment: usize,
+        callback: ScanlineCallback,
+    ) -> Result<()> {
+        self.check_scale(scale, MAX_SCALE)?;
+        if row_alignment == 0 || self.rotation != Rotation::None {
+            return Err(Error::Parameter);
+        }
+        let bpp = self.output_bpp();
+        if bpp == 0 {
+            return Err(Error::Parameter);
+        }
+        let size = self.scanline_buffer_size(scale, row_alignment);
+        if row_buffer.len() < size {
+            return Err(Error::InsufficientMemory);
+        }
+
+        // 填充字节从不被写入，预先清零即可
+        row_buffer[..size].fill(0);
+        let stride = ((self.width >> scale) as usize * bpp).next_multiple_of(row_alignment);
+
+        self.collect_mcu_rows(data, scale, mcu_buffer, work_buffer, row_buffer, stride, &mut |decoder, rows, rect| {
+            for (i, line) in rows.chunks_exact(stride).enumerate() {
+                if !callback(decoder, line, rect.top + i as u16)? {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        })
+    }
+
+    /// 把每个 MCU 行的块拼接到 row_buffer（行距为 stride），每行完成后调用 callback
+    #[allow(clippy::too_many_arguments)]
+    fn collect_mcu_rows(
+        &mut self,
+        data: &[u8],
+        scale: u8,
+        mcu_buffer: &mut [i16],
+        work_buffer: &mut [u8],
+        row_buffer: &mut [u8],
+        stride: usize,
+        callback: OutputCallback,
+    ) -> Result<()> {
+        let bpp = self.output_bpp();
+        let width = (self.width >> scale) as usize;
+
+        self.decompress(data, scale, mcu_buffer, work_buffer, &mut |decoder, bitmap, rect| {
+            // 行内的所有 MCU 共享 top/bottom
+            let line = rect.width() as usize * bpp;
+            for (row, src) in bitmap.chunks_exact(line).take(rect.height() as usize).enumerate() {
+                let dst = row * stride + rect.left as usize * bpp;
+                row_buffer[dst..dst + line].copy_from_slice(src);
+            }
+
+            if rect.right as usize + 1 < width {
+                return Ok(true);
+            }
+            let full = Rectangle::new(0, width as u16 - 1, rect.top, rect.bottom);
+            callback(decoder, &row_buffer[..stride * rect.height() as usize], &full)
+        })
+    }
+
+    /// Decode a 1/8 scale preview from the DC coefficients only
+    /// 
+    /// Produces one RGB888 pixel per 8x8 block, i.e. a
+    /// `ceil(width / 8)` x `ceil(height / 8)` image, from the block averages.
+    /// AC coefficients are entropy-decoded but discarded, and no IDCT or
+    /// per-pixel color conversion runs, making this the cheapest usable
+    /// preview. Chroma is taken per MCU, so 4:2:2/4:2:0 previews share one
+    /// chroma value between the luma blocks of an MCU. Grayscale images
+    /// produce gray RGB triples.
+    /// 
+    /// # Parameters
+    /// 
+    /// * `data` - Complete JPEG file data
+    /// * `mcu_buffer` - MCU work buffer (provided by user)
+    /// * `output` - Destination for the packed RGB888 preview
+    /// 
+    /// # Returns
+    /// 
+    /// The preview `(width, height)`. `Error::InsufficientMemory` if a
+    /// buffer is too small.
+    pub fn decompress_dc(&mut self, data: &[u8], mcu_buffer: &mut [i16], output: &mut [u8]) -> Result<(u16, u16)> {
+        self.require_prepared()?;
+        self.require_ycc()?;
+        let width = self.width.div_ceil(8) as usize;
+        let height = self.height.div_ceil(8) as usize;
+        if mcu_buffer.len() < self.mcu_buffer_size() || output.len() < width * height * 3 {
+            return Err(Error::InsufficientMemory);
+        }
+
+        let mcu_width = self.sampling.mcu_width() as usize;
+        let mcu_height = self.sampling.mcu_height() as usize;
+        let scan = self.start_scan(data)?;
+
+        self.decode_scan(BitStream::new(scan), mcu_buffer, None, None, ScanMode::DcOnly, &mut |decoder, mcu, mcu_x, mcu_y, _| {
+            let num_y_blocks = mcu_width * mcu_height;
+            let (bx0, by0) = (mcu_x as usize / 8, mcu_y as usize / 8);
+            for by in 0..mcu_height {
+                for bx in 0..mcu_width {
+                    let (px, py) = (bx0 + bx, by0 + by);
+                    if px >= width || py >= height {
+                        continue;
+                    }
+                    let y = mcu[(by * mcu_width + bx) * 64] as i32;
+                    let rgb = if decoder.num_components == 3 {
+                        let cb = mcu[num_y_blocks * 64] as i32 - 128;
+                        let cr = mcu[(num_y_blocks + 1) * 64] as i32 - 128;
+                        color::ycbcr_to_rgb(y, cb, cr, decoder.color_matrix)
+                    } else {
+                        let v = byte_clip(y);
+                        [v, v, v]
+                    };
+                    let dst = (py * width + px) * 3;
+                    output[dst..dst + 3].copy_from_slice(&rgb);
+                }
+            }
+            Ok(())
+        })?;
+        Ok((width as u16, height as u16))
+    }
+
+    /// Compute the average color of the image
+    /// 
+    /// Decodes only the DC term of every block (no IDCT, no work buffer),
+    /// averages the blocks inside the image and converts the result to RGB
+    /// once. The result matches the mean color of a 1/8 scale decode, except
+    /// that a subsampled chroma block of a partial edge MCU counts as a whole
+    /// (including the encoder's padding). Handy as a placeholder color for
+    /// lazy-loading UIs. Grayscale images, and
+    /// color images decoded with `OutputFormat::Grayscale`, return a gray
+    /// triple.
+    /// 
+    /// # Parameters
+    /// 
+    /// * `data` - Complete JPEG file data
+    /// * `mcu_buffer` - MCU work buffer (provided by user)
+    pub fn average_color(&mut self, data: &[u8], mcu_buffer: &mut [i16]) -> Result<[u8; 3]> {
+        self.require_prepared()?;
+        self.require_ycc()?;
+        if mcu_buffer.len() < self.mcu_buffer_size() {
+            return Err(Error::InsufficientMemory);
+        }
+
+        let mcu_width = self.sampling.mcu_width() as usize;
+        let mcu_height = self.sampling.mcu_height() as usize;
+        let (width, height) = (self.width.div_ceil(8) as usize, self.height.div_ceil(8) as usize);
+        let mut sums = [0i64; 3];
+        let mut blocks = 0i64;
+
+        let scan = self.start_scan(data)?;
+        self.decode_scan(BitStream::new(scan), mcu_buffer, None, None, ScanMode::DcOnly, &mut |decoder, mcu, mcu_x, mcu_y, _| {
+            // 每个块的平均值在第一个样本；色度按 MCU 内位于图像中的亮度块数加权
+            let (bx0, by0) = (mcu_x as usize / 8, mcu_y as usize / 8);
+            let w = mcu_width.min(width - bx0);
+            let h = mcu_height.min(height - by0);
+            for by in 0..h {
+                for bx in 0..w {
+                    sums[0] += mcu[(by * mcu_width + bx) * 64] as i64;
+                }
+            }
+            let n = (w * h) as i64;
+            if !decoder.luma_only() {
+                let num_y_blocks = mcu_width * mcu_height;
+                sums[1] += mcu[num_y_blocks * 64] as i64 * n;
+                sums[2] += mcu[(num_y_blocks + 1) * 64] as i64 * n;
+            }
+            blocks += n;
+            Ok(())
+        })?;
+
+        let blocks = blocks.max(1);
+        let y = (sums[0] / blocks) as i32;
+        if self.luma_only() {
+            let v = byte_clip(y);
+            return Ok([v, v, v]);
+        }
+        let cb = (sums[1] / blocks) as i32 - 128;
+        let cr = (sums[2] / blocks) as i32 - 128;
+        Ok(color::ycbcr_to_rgb(y, cb, cr, self.color_matrix))
+    }
+
+    /// Decompress JPEG image as runs of identical pixels
+    /// 
+    /// Each scanline of every output block is split into spans of identical
+    /// pixels, which are passed to `callback` left to right. Flat regions
+    /// such as graphics and illustrations collapse into a few spans, reducing
+    /// traffic for sinks that support RLE (e.g. a framebuffer over SPI).
+    /// Spans never cross block boundaries, so they arrive in MCU order, not
+    /// raster order.
+    /// 
+    /// # Parameters
+    /// 
+    /// * `data` - Complete JPEG file data
+    /// * `scale` - Scale factor (0=1/1, 1=1/2, 2=1/4, 3=1/8, 4=1/16, 5=1/32)
+    /// * `mcu_buffer` - MCU work buffer (provided by user)
+    /// * `work_buffer` - RGB conversion work buffer (provided by user)
+    /// * `callback` - Span callback function
+    /// 
+    /// Returns `Error::Parameter` for packed output formats (Mono1/Gray4).
+    pub fn decompress_rle(
+        &mut self,
+        data: &[u8],
+        scale: u8,
+        mcu_buffer: &mut [i16],
+        work_buffer: &mut [u8],
+        callback: SpanCallback,
+    ) -> Result<()> {
+        self.check_scale(scale, MAX_SCALE)?;
+        let bpp = self.output_bpp();
+        if bpp == 0 {
+            return Err(Error::Parameter);
+        }
+
+        self.decompress(data, scale, mcu_buffer, work_buffer, &mut |decoder, bitmap, rect| {
+            let width = rect.width() as usize;
+            for (row, line) in bitmap.chunks_exact(width * bpp).take(rect.height() as usize).enumerate() {
+                let y = rect.top + row as u16;
+                let mut start = 0;
+                while start < width {
+                    let color = &line[start * bpp..(start + 1) * bpp];
+                    let mut end = start + 1;
+                    while end < width && &line[end * bpp..(end + 1) * bpp] == color {
+                        end += 1;
+                    }
+                    if !callback(decoder, color, rect.left + start as u16, y, (end - start) as u16)? {
+                        return Ok(false);
+                    }
+                    start = end;
+                }
+            }
+            Ok(true)
+        })
+    }
+
+    /// Decompress JPEG image as 4x4 RGB888 tiles for block compressors
+    /// 
+    /// Each output block is cut into 4x4 tiles, delivered in row-major order
+    /// within the block, which is the unit BC1/DXT1 encoders work on. Tiles
+    /// are aligned to multiples of 4 in image coordinates. Tiles on the right
+    /// and bottom edges are padded by repeating the last column/row so the
+    /// encoder always sees 16 pixels. Grayscale images are expanded to gray
+    /// RGB.
+    /// 
+    /// # Parameters
+    /// 
+    /// * `data` - Complete JPEG file data
+    /// * `scale` - Scale factor (0=1/1, 1=1/2, 2=1/4, 3=1/8, 4=1/16, 5=1/32)
+    /// * `mcu_buffer` - MCU work buffer (provided by user)
+    /// * `work_buffer` - RGB conversion work buffer (provided by user)
+    /// * `callback` - Tile callback function
+    /// 
+    /// Returns `Error::Parameter` for packed, 16-bit and 32-bit output formats.
+    pub fn decompress_4x4_tiles(
+        &mut self,
+        data: &[u8],
+        scale: u8,
+        mcu_buffer: &mut [i16],
+        work_buffer: &mut [u8],
+        callback: TileCallback,
+    ) -> Result<()> {
+        self.check_scale(scale, MAX_SCALE)?;
+        let bpp = self.output_bpp();
+        if !matches!(bpp, 1 | 3) {
+            return Err(Error::Parameter);
+        }
+
+        self.decompress(data, scale, mcu_buffer, work_buffer, &mut |decoder, bitmap, rect| {
+            let (width, height) = (rect.width() as usize, rect.height() as usize);
+            let mut tile = [0u8; 48];
+
+            for ty in (0..height).step_by(4) {
+                for tx in (0..width).step_by(4) {
+                    for i in 0..16 {
+                        // 超出边缘时重复最后一行/列
+                        let x = (tx + (i & 3)).min(width - 1);
+                        let y = (ty + (i >> 2)).min(height - 1);
+                        let src = (y * width + x) * bpp;
+                        if bpp == 1 {
+                            tile[i * 3..i * 3 + 3].fill(bitmap[src]);
+                        } else {
+                            tile[i * 3..i * 3 + 3].copy_from_slice(&bitmap[src..src + 3]);
+                        }
+                    }
+                    if !callback(decoder, &tile, rect.left + tx as u16, rect.top + ty as u16)? {
+                        return Ok(false);
+                    }
+                }
+            }
+            Ok(true)
+        })
+    }
+
+    /// Decompress JPEG image straight into an RGB565 display framebuffer
+    /// 
+    /// Places the decoded image with its top-left corner at (`dst_x`, `dst_y`)
+    /// of a `fb_width` x `fb_height` framebuffer. The offset may be negative,
+    /// and pixels falling outside the framebuffer are clipped. Pixels are
+    /// written as native `u16` values (red in the high bits); grayscale
+    /// images are expanded to gray RGB565. With `OutputFormat::Bgr565`
+    /// selected the pixels are written in that order instead, and with
+    /// `set_swap_rgb565(true)` each value is byte-swapped, so a framebuffer
+    /// filled on a little-endian MCU can be sent to a high-byte-first panel
+    /// as is. Grayscale and RGB888 sources are swapped the same way.
+    /// 
+    /// # Parameters
+    /// 
+    /// * `data` - Complete JPEG file data
+    /// * `scale` - Scale factor (0=1/1, 1=1/2, 2=1/4, 3=1/8, 4=1/16, 5=1/32)
+    /// * `mcu_buffer` - MCU work buffer (provided by user)
+    /// * `work_buffer` - RGB conversion work buffer (provided by user)
+    /// * `fb` - Framebuffer, at least `fb_width * fb_height` pixels
+    /// * `fb_width` / `fb_height` - Framebuffer size in pixels
+    /// * `dst_x` / `dst_y` - Position of the image in the framebuffer
+    /// 
+    /// Returns `Error::Parameter` for packed and 32-bit output formats.
+    #[allow(clippy::too_many_arguments)]
+    pub fn blit_to_display(
+        &mut self,
+        data: &[u8],
+        scale: u8,
+        mcu_buffer: &mut [i16],
+        work_buffer: &mut [u8],
+        fb: &mut [u16],
+        fb_width: u16,
+        fb_height: u16,
+        dst_x: i32,
+        dst_y: i32,
+    ) -> Result<()> {
+        self.check_scale(scale, MAX_SCALE)?;
+        if fb.len() < fb_width as usize * fb_height as usize {
+            return Err(Error::InsufficientMemory);
+        }
+
+        let bpp = self.output_bpp();
+        if bpp == 0 || bpp == 4 {
+            return Err(Error::Parameter);
+        }
+        let fb_w = fb_width as i32;
+        let fb_h = fb_height as i32;
+
+        self.decompress(data, scale, mcu_buffer, work_buffer, &mut |decoder, bitmap, rect| {
+            let rect_width = rect.width() as usize;
+
+            // 裁剪到帧缓冲范围
+            let left = dst_x + rect.left as i32;
+            let top = dst_y + rect.top as i32;
+            let x0 = left.max(0);
+            let y0 = top.max(0);
+            let x1 = (left + rect_width as i32).min(fb_w);
+            let y1 = (top + rect.height() as i32).min(fb_h);
+
+            for fy in y0..y1 {
+                let src_row = (fy - top) as usize * rect_width;
+                let dst_row = fy as usize * fb_width as usize;
+                for fx in x0..x1 {
+                    let src = (src_row + (fx - left) as usize) * bpp;
+                    // 已打包的 16 位格式（含 Bgr565 和字节交换）原样写入，其余按同样规则打包
+                    let bytes = match bpp {
+                        1 => decoder.encode_565([bitmap[src]; 3]),
+                        2 => [bitmap[src], bitmap[src + 1]],
+                        _ => decoder.encode_565([bitmap[src], bitmap[src + 1], bitmap[src + 2]]),
+                    };
+                    let pixel = u16::from_ne_bytes(bytes);
+                    fb[dst_row + fx as usize] = pixel;
+                }
+            }
+            Ok(true)
+        })
+    }
+
+    /// Set the pixel format delivered to the output callback
+    /// 
+    /// Default is `OutputFormat::Rgb888`. `OutputFormat::Rgb666` keeps the
+    /// 3 bytes/pixel layout but clears the low 2 bits of every channel, which
+    /// is what 18-bit display controllers expect. Grayscale images are
+    /// delivered as 1 byte/pixel in these formats. Call `work_buffer_size()` after changing the
+    /// format.
+    /// 
+    /// `OutputFormat::Mono1` and `OutputFormat::Gray4` pack several pixels per
+    /// byte from the luma (color and grayscale images alike). Each rectangle
+    /// row starts on a byte boundary, so the bitmap holds
+    /// `ceil(width * bits / 8) * height` bytes. See `set_mono_mode()` for
+    /// the 1-bit conversion.
+    /// 
+    /// `OutputFormat::Rgb565` and `OutputFormat::Bgr565` deliver 2 bytes/pixel
+    /// (a native-endian `u16` per pixel, see `set_swap_rgb565()`), packed
+    /// straight from the color conversion so the work buffer shrinks to
+    /// 2 bytes/pixel as well. Bgr565 puts blue in the high bits, the color
+    /// order many SPI TFT panels (ILI9341 and similar) are configured for.
+    /// Grayscale images are expanded to gray pixels in these formats.
+    /// 
+    /// `OutputFormat::Rgba8888` and `OutputFormat::Bgra8888` deliver
+    /// 4 bytes/pixel with a constant 0xFF alpha, ready for GPU texture
+    /// uploads; the work buffer grows to 4 bytes/pixel. Grayscale images are
+    /// expanded to gray pixels in these formats.
+    /// 
+    /// `OutputFormat::PlanarRgb` delivers the red, green and blue planes of
+    /// each rectangle one after another, `rect.width() * rect.height()` bytes
+    /// each with a row stride of `rect.width()`, for tensor-style consumers.
+    /// Grayscale images replicate the luma into all three planes. The work
+    /// buffer grows to 6 bytes/pixel (the planes are split out of the
+    /// interleaved conversion result). Only the callback APIs accept it:
+    /// rotation, region decoding and the framebuffer/row/tile helpers return
+    /// `Error::Parameter`, like they do for the packed formats.
+    /// 
+    /// `OutputFormat::Grayscale` delivers the luma only, 1 byte/pixel. For
+    /// color images the Cb/Cr blocks are still entropy-decoded (the
+    /// bitstream requires it) but their IDCT and the color conversion are
+    /// skipped, and the work buffer shrinks to 1 byte/pixel. See also
+    /// `force_grayscale()`.
+    /// 
+    /// Every format is currently supported; the `Result` is reserved for
+    /// formats that need decoder support not yet implemented.
+    pub fn set_output_format(&mut self, format: OutputFormat) -> Result<()> {
+        match format {
+            OutputFormat::Rgb888
+            | OutputFormat::Rgb565
+            | OutputFormat::Grayscale
+            | OutputFormat::Rgb666
+            | OutputFormat::Mono1
+            | OutputFormat::Gray4
+            | OutputFormat::Bgr565
+            | OutputFormat::Rgba8888
+            | OutputFormat::Bgra8888
+            | OutputFormat::PlanarRgb => {
+                self.output_format = format;
+                Ok(())
+            }
+        }
+    }
+
+    /// Decode color images to luma only
+    /// 
+    /// Shorthand for `set_output_format(OutputFormat::Grayscale)`; disabling
+    /// it goes back to `OutputFormat::Rgb888`. The callback then receives
+    /// 1 byte/pixel while `components()` still reports the source component
+    /// count.
+    pub fn force_grayscale(&mut self, enable: bool) {
+        if enable {
+            self.output_format = OutputFormat::Grayscale;
+        } else if self.output_format == OutputFormat::Grayscale {
+            self.output_format = OutputFormat::Rgb888;
+        }
+    }
+
+    /// Byte-swap 16-bit pixels in `OutputFormat::Rgb565` / `OutputFormat::Bgr565`
+    /// 
+    /// Default is `false` (native-endian `u16`). On little-endian MCUs,
+    /// enabling this yields the high-byte-first order that ILI9341-class
+    /// SPI controllers expect, so the bitmap can be sent to the panel as is.
+    pub fn set_swap_rgb565(&mut self, swap: bool) {
+        self.set_flag(FLAG_SWAP_565, swap);
+    }
+
+    /// Rotate the output of the callback-based decode functions
+    /// 
+    /// Every MCU is rotated before it reaches the `OutputCallback`, and its
+    /// `Rectangle` is given in the coordinates of the rotated image (width
+    /// and height swap for `Cw90`/`Cw270`). The bitmap stays contiguous and
+    /// row-major for the rotated rect. Rotating doubles `work_buffer_size()`
+    /// and isn't supported for the packed formats (Mono1/Gray4), where
+    /// decoding fails with `Error::Parameter`. Use
+    /// `Rotation::from_orientation(decoder.orientation())` to honor EXIF.
+    pub fn set_rotation(&mut self, rotation: Rotation) {
+        self.rotation = rotation;
+    }
+
+    /// Set how luma is reduced to 1 bit for `OutputFormat::Mono1`
+    /// 
+    /// Default is `MonoMode::Threshold(128)`. `MonoMode::OrderedDither`
+    /// keys the dither matrix on image coordinates, so it needs no state
+    /// between MCUs.
+    pub fn set_mono_mode(&mut self, mode: MonoMode) {
+        self.mono_mode = mode;
+    }
+
+    /// Require the image to have exactly the given dimensions
+    /// 
+    /// When set, `prepare()` fails with `Error::FormatError` as soon as it
+    /// reads an SOF with a different width or height, without parsing the
+    /// remaining segments. Meant for fixed-resolution pipelines
+    /// (e.g. camera frames) to catch corrupt headers early. Pass `None` to
+    /// accept any size (the default); `Some((0, 0))` is the same as `None`.
+    pub fn set_expected_dimensions(&mut self, dimensions: Option<(u16, u16)>) {
+        self.expected_dimensions = dimensions.unwrap_or((0, 0));
+    }
+
+    /// Set how out-of-range RGB values are brought into `0..=255`
+    /// 
+    /// Default is `GamutMode::Clamp`, which clips each channel and can
+    /// desaturate bright colors. `GamutMode::Scale` scales the channels
+    /// together to preserve hue at a small per-pixel cost.
+    pub fn set_gamut_mode(&mut self, mode: GamutMode) {
+        self.gamut_mode = mode;
+    }
+
+    /// Set the YCbCr to RGB conversion matrix
+    /// 
+    /// Default is `ColorMatrix::Bt601`, TJpgDec's fast fixed-point JFIF
+    /// conversion. `ColorMatrix::Full601` rounds like libjpeg, for matching
+    /// a reference decoder; `ColorMatrix::Bt709` suits HD video sources.
+    /// Grayscale pixels (Cb = Cr = 128) come out the same under every
+    /// matrix.
+    pub fn set_color_matrix(&mut self, matrix: ColorMatrix) {
+        self.color_matrix = matrix;
+    }
+
+    /// Set where subsampled chroma samples are assumed to sit
+    /// 
+    /// Default is `ChromaSiting::Centered`, the JFIF convention. Use
+    /// `ChromaSiting::Cosited` for streams produced by video pipelines
+    /// (e.g. MJPEG from BT.601 sources), which otherwise show a half-pixel
+    /// color shift on sharp color edges. Has no effect on 4:4:4 or
+    /// grayscale images.
+    pub fn set_chroma_siting(&mut self, siting: ChromaSiting) {
+        self.chroma_siting = siting;
+    }
+
+    /// Set how subsampled chroma is expanded to full resolution
+    /// 
+    /// Default is `ChromaUpsample::Nearest`, which repeats each chroma
+    /// sample and shows blocky color on smooth gradients.
+    /// `ChromaUpsample::Bilinear` interpolates between neighboring chroma
+    /// samples like libjpeg's fancy upsampling, at a small per-pixel cost
+    /// and no extra memory. Applies to `ChromaSiting::Centered` only
+    /// (`Cosited` already interpolates) and has no effect on 4:4:4 or
+    /// grayscale images.
+    pub fn set_chroma_upsample(&mut self, upsample: ChromaUpsample) {
+        self.chroma_upsample = upsample;
+    }
+
+    /// Enable or disable strict restart marker checking
+    /// 
+    /// In strict mode, every `RSTn` marker must carry the expected sequence
+    /// number (cycling 0..7). A dropped or duplicated restart interval then
+    /// fails with `Error::FormatError` instead of silently corrupting the
+    /// MCUs that follow. Disabled by default.
+    pub fn set_strict_restart(&mut self, strict: bool) {
+        self.set_flag(FLAG_STRICT_RESTART, strict);
+    }
+
+    /// Decode only the DC coefficient of every block
+    /// 
+    /// Each 8x8 block then comes out as a single flat color, the block
+    /// average, which is good enough for blurry previews and thumbnails and
+    /// much faster than a full decode: AC coefficients are still read from
+    /// the bitstream to stay in step, but never dequantized or transformed.
+    /// Applies to every decode function; the nonzero AC count reported by
+    /// `decompress_with_complexity()` is then always `0`. Disabled by
+    /// default.
+    pub fn set_dc_only(&mut self, dc_only: bool) {
+        self.set_flag(FLAG_DC_ONLY, dc_only);
+    }
+
+    /// Recover partial images from truncated scan data
+    /// 
+    /// When enabled, running out of entropy-coded data stops decoding
+    /// gracefully instead of failing with `Error::Input`: the MCUs decoded so
+    /// far are output as usual, every remaining MCU is output filled with
+    /// the color set by `set_truncated_fill()`, and the decode returns `Ok`.
+    /// `was_truncated()` then reports that this happened. The MCU that was
+    /// cut off is filled as well. Disabled by default.
+    pub fn set_allow_truncated(&mut self, allow: bool) {
+        self.set_flag(FLAG_ALLOW_TRUNCATED, allow);
+    }
+
+    /// Set the RGB color for MCUs missing from truncated scan data
+    /// 
+    /// Used with `set_allow_truncated()`. Grayscale images use its luma. The
+    /// color goes through the normal color conversion, so it may come out
+    /// off by one. Defaults to mid gray (128, 128, 128).
+    pub fn set_truncated_fill(&mut self, rgb: [u8; 3]) {
+        self.truncated_fill = rgb;
+    }
+
+    /// Check whether the last decode ran out of scan data
+    /// 
+    /// Only ever `true` with `set_allow_truncated()` enabled; without it a
+    /// truncated scan fails with `Error::Input`.
+    pub fn was_truncated(&self) -> bool {
+        self.flag(FLAG_TRUNCATED)
+    }
+
+    /// Decompress JPEG image into a caller-provided framebuffer
+    /// 
+    /// Same as `decompress()`, but copies every MCU into `output` instead of
+    /// handing it to a callback. Rows are `stride` bytes apart, so the image can
+    /// be placed at (`dst_x`, `dst_y`) inside a larger canvas (e.g. a sprite
+    /// sheet). Pixel `(x, y)` of the decoded image lands at byte
+    /// `(dst_y + y) * stride + (dst_x + x) * bpp`, where `bpp` is 3 for color
+    /// and 1 for grayscale images.
+    /// 
+    /// # Parameters
+    /// 
+    /// * `data` - Complete JPEG file data
+    /// * `scale` - Scale factor (0=1/1, 1=1/2, 2=1/4, 3=1/8, 4=1/16, 5=1/32)
+    /// * `mcu_buffer` - MCU work buffer (provided by user)
+    /// * `work_buffer` - RGB conversion work buffer (provided by user)
+    /// * `output` - Destination framebuffer
+    /// * `stride` - Bytes per row of `output`
+    /// * `dst_x` / `dst_y` - Destination offset of the image in pixels
+    /// 
+    /// # Returns
+    /// 
+    /// The length of the valid region of `output`: the offset just past the
+    /// last byte written, `(dst_y + height - 1) * stride + (dst_x + width) * bpp`.
+    /// With no offset and a packed stride this equals `output_byte_size()`.
+    /// With `set_rotation()`, `width` and `height` are those of the rotated
+    /// image.
+    /// 
+    /// `Error::Parameter` if a row doesn't fit in `stride` and
+    /// `Error::InsufficientMemory` if `output` is too short.
+    #[allow(clippy::too_many_arguments)]
+    pub fn decompress_to_buffer(
+        &mut self,
+        data: &[u8],
+        scale: u8,
+        mcu_buffer: &mut [i16],
+        work_buffer: &mut [u8],
+        output: &mut [u8],
+        stride: usize,
+        dst_x: u16,
+        dst_y: u16,
+    ) -> Result<usize> {
+        self.decompress_placed(data, scale, mcu_buffer, work_buffer, output, stride, (dst_x, dst_y), None)
+    }
+
+    /// Decompress JPEG image directly into a strided destination buffer
+    /// 
+    /// The decoder places every MCU at byte `y * stride + x * bpp` of
+    /// `dest` itself (`bpp` is 3 for color and 1 for grayscale images in
+    /// RGB888, 2 for RGB565), so no offset arithmetic is needed in user
+    /// code. `notify` is called after each rectangle has been written, e.g.
+    /// to flush that part of a display; return `Ok(false)` to stop.
+    /// 
+    /// # Parameters
+    /// 
+    /// * `data` - Complete JPEG file data
+    /// * `scale` - Scale factor (0=1/1, 1=1/2, 2=1/4, 3=1/8, 4=1/16, 5=1/32)
+    /// * `mcu_buffer` - MCU work buffer (provided by user)
+    /// * `work_buffer` - RGB conversion work buffer (provided by user)
+    /// * `dest` - Destination framebuffer
+    /// * `stride` - Bytes per row of `dest`
+    /// * `notify` - Completion callback
+    /// 
+    /// # Returns
+    /// 
+    /// `Error::Parameter` if a row doesn't fit in `stride` or the format is
+    /// packed (Mono1/Gray4), `Error::InsufficientMemory` if `dest` is too
+    /// short.
+    #[allow(clippy::too_many_arguments)]
+    pub fn decompress_into(
+        &mut self,
+        data: &[u8],
+        scale: u8,
+        mcu_buffer: &mut [i16],
+        work_buffer: &mut [u8],
+        dest: &mut [u8],
+        stride: usize,
+        notify: NotifyCallback,
+    ) -> Result<()> {
+        self.decompress_placed(data, scale, mcu_buffer, work_buffer, dest, stride, (0, 0), Some(notify))?;
+        Ok(())
+    }
+
+    /// 把每个 MCU 写入 `output` 的 (`dst_x`, `dst_y`) 处，返回写入范围的长度
+    #[allow(clippy::too_many_arguments)]
+    fn decompress_placed(
+        &mut self,
+        data: &[u8],
+        scale: u8,
+        mcu_buffer: &mut [i16],
+        work_buffer: &mut [u8],
+        output: &mut [u8],
+        stride: usize,
+        (dst_x, dst_y): (u16, u16),
+        mut notify: Option<NotifyCallback>,
+    ) -> Result<usize> {
+        self.check_scale(scale, MAX_SCALE)?;
+
+        let bpp = self.output_bpp();
+        if bpp == 0 {
+            return Err(Error::Parameter);
+        }
+        let (width, height) = self.rotated_size(scale);
+        let (width, height) = (width as usize, height as usize);
+        let row_end = (dst_x as usize + width) * bpp;
+
+        if stride < row_end {
+            return Err(Error::Parameter);
+        }
+        let written = if height > 0 { (dst_y as usize + height - 1) * stride + row_end } else { 0 };
+        if output.len() < written {
+            return Err(Error::InsufficientMemory);
+        }
+
+        self.decompress(data, scale, mcu_buffer, work_buffer, &mut |decoder, bitmap, rect| {
+            let line = rect.width() as usize * bpp;
+            for row in 0..rect.height() as usize {
+                let dst = (dst_y as usize + rect.top as usize + row) * stride
+                    + (dst_x as usize + rect.left as usize) * bpp;
+                output[dst..dst + line].copy_from_slice(&bitmap[row * line..(row + 1) * line]);
+            }
+            match notify.as_mut() {
+                Some(notify) => notify(decoder, rect),
+                None => Ok(true),
+            }
+        })?;
+        Ok(written)
+    }
+
+    /// Decompress the prepared image into a new RGB888 buffer
+    /// 
+    /// One-call alternative to `decompress()` for desktop tooling: allocates
+    /// the MCU and work buffers (sized by `mcu_buffer_size()` /
+    /// `work_buffer_size()`) and the framebuffer, and copies every MCU into
+    /// place. Grayscale images are expanded to gray RGB triples. Requires
+    /// `OutputFormat::Rgb888` (the default).
+    /// 
+    /// # Parameters
+    /// 
+    /// * `data` - Complete JPEG file data
+    /// * `scale` - Scale factor (0=1/1, 1=1/2, 2=1/4, 3=1/8, 4=1/16, 5=1/32)
+    /// 
+    /// # Returns
+    /// 
+    /// `(pixels, width, height)` with `width * height * 3` bytes of pixels.
+    #[cfg(feature = "alloc")]
+    pub fn decode_to_vec(&mut self, data: &[u8], scale: u8) -> Result<(Vec<u8>, u16, u16)> {
+        self.check_scale(scale, MAX_SCALE)?;
+        if self.output_format != OutputFormat::Rgb888 {
+            return Err(Error::Parameter);
+        }
+
+        let mut mcu_buffer = vec![0i16; self.mcu_buffer_size()];
+        let mut work_buffer = vec![0u8; self.work_buffer_size_for_scale(scale)];
+        let (width, height) = self.rotated_size(scale);
+        let stride = width as usize * 3;
+        let mut pixels = vec![0u8; stride * height as usize];
+
+        self.decompress(data, scale, &mut mcu_buffer, &mut work_buffer, &mut |decoder, bitmap, rect| {
+            let bpp = decoder.output_bpp();
+            let w = rect.width() as usize;
+            for row in 0..rect.height() as usize {
+                let dst = (rect.top as usize + row) * stride + rect.left as usize * 3;
+                let src = &bitmap[row * w * bpp..(row + 1) * w * bpp];
+                let dst = &mut pixels[dst..dst + w * 3];
+                if bpp == 3 {
+                    dst.copy_from_slice(src);
+                } else {
+                    for (px, &v) in dst.chunks_exact_mut(3).zip(src) {
+                        px.fill(v);
+                    }
+                }
+            }
+            Ok(true)
+        })?;
+        Ok((pixels, width, height))
+    }
+
+    /// Decompress JPEG image into a caller-provided RGBA framebuffer
+    /// 
+    /// Meant for reusing one oversized staging buffer (e.g. a texture atlas
+    /// upload) across images of different sizes. Pixel `(x, y)` lands at
+    /// byte `y * fb_stride + x * 4` as `R, G, B, 0xFF`; grayscale images are
+    /// expanded to gray RGB. Bytes outside the decoded image are left
+    /// untouched. Requires `OutputFormat::Rgb888` (the default).
+    /// 
+    /// # Parameters
+    /// 
+    /// * `data` - Complete JPEG file data
+    /// * `scale` - Scale factor (0=1/1, 1=1/2, 2=1/4, 3=1/8, 4=1/16, 5=1/32)
+    /// * `mcu_buffer` - MCU work buffer (provided by user)
+    /// * `work_buffer` - RGB conversion work buffer (provided by user)
+    /// * `fb` - Destination RGBA framebuffer
+    /// * `fb_stride` - Bytes per row of `fb`
+    /// * `out_dims` - Receives the scaled `(width, height)` of the image
+    /// 
+    /// # Returns
+    /// 
+    /// `Error::Parameter` if the output format isn't RGB888 or a row doesn't
+    /// fit in `fb_stride`, `Error::InsufficientMemory` if `fb` is too short.
+    /// `out_dims` is only written once the buffer has been checked.
+    #[allow(clippy::too_many_arguments)]
+    pub fn decompress_to_rgba(
+        &mut self,
+        data: &[u8],
+        scale: u8,
+        mcu_buffer: &mut [i16],
+        work_buffer: &mut [u8],
+        fb: &mut [u8],
+        fb_stride: usize,
+        out_dims: &mut (u16, u16),
+    ) -> Result<()> {
+        self.check_scale(scale, MAX_SCALE)?;
+        if self.output_format != OutputFormat::Rgb888 {
+            return Err(Error::Parameter);
+        }
+
+        let (width, height) = self.rotated_size(scale);
+        if fb_stride < width as usize * 4 {
+            return Err(Error::Parameter);
+        }
+        if height > 0 && fb.len() < (height as usize - 1) * fb_stride + width as usize * 4 {
+            return Err(Error::InsufficientMemory);
+        }
+        *out_dims = (width, height);
+
+        let bpp = self.output_bpp();
+        self.decompress(data, scale, mcu_buffer, work_buffer, &mut |_decoder, bitmap, rect| {
+            let w = rect.width() as usize;
+            for row in 0..rect.height() as usize {
+                let src = &bitmap[row * w * bpp..(row + 1) * w * bpp];
+                let dst = (rect.top as usize + row) * fb_stride + rect.left as usize * 4;
+                for (px, out) in src.chunks_exact(bpp).zip(fb[dst..dst + w * 4].chunks_exact_mut(4)) {
+                    // 灰度图 bpp 为 1，三个通道取同一个值
+                    out[0] = px[0];
+                    out[1] = px[(bpp - 1).min(1)];
+                    out[2] = px[bpp - 1];
+                    out[3] = 0xFF;
+                }
+            }
+            Ok(true)
+        })
+    }
+
+    /// Get the byte range of the entropy-coded scan data
+    /// 
+    /// Returns `(start, end)` offsets into `data`: `start` is the first byte
+    /// after the SOS header and `end` is the offset of the EOI marker, so
+    /// `&data[start..end]` is the complete scan including stuffed `0xFF 0x00`
+    /// bytes and `RSTn` markers. Must be called after `prepare()` on the same
+    /// data.
+    /// 
+    /// Returns `Error::Input` if the data ends before EOI and
+    /// `Error::FormatError` if another marker interrupts the scan.
+    pub fn scan_range(&self, data: &[u8]) -> Result<(usize, usize)> {
+        let scan = self.find_scan_data(data)?;
+        let start = data.len() - scan.len();
+
+        let mut i = 0;
+        while i + 1 < scan.len() {
+            if scan[i] != 0xFF {
+                i += 1;
+                continue;
+            }
+
+            // 跳过填充的 0xFF
+            let mut j = i + 1;
+            while j < scan.len() && scan[j] == 0xFF {
+                j += 1;
+            }
+            if j >= scan.len() {
+                break;
+            }
+
+            match scan[j] {
+                0x00 | 0xD0..=0xD7 => i = j + 1,
+                markers::EOI => return Ok((start, start + i)),
+                _ => return Err(Error::FormatError),
+            }
+        }
+
+        Err(Error::Input)
+    }
+
+    /// Get the input offset just past the decoded JPEG
+    /// 
+    /// After a decode that reached the last MCU, this is the offset in
+    /// `data` just past the EOI marker, so a container parser can continue
+    /// with `&data[decoder.bytes_consumed()..]`. If the scan ends without an
+    /// EOI (truncated data), it is where the entropy-coded data stopped.
+    /// `decompress_fast()` counts from the start of its `scan` argument.
+    /// 
+    /// Before the first decode and for `decompress_reader()` it is 0; when a
+    /// decode stops before the last MCU (callback abort, region decode,
+    /// yield) it is the offset of the first scan byte.
+    pub fn bytes_consumed(&self) -> usize {
+        self.scan_end as usize
+    }
+
+    /// Get a CRC32 of the entropy-coded scan data
+    /// 
+    /// Covers exactly the bytes returned by `scan_range()`, so files that only
+    /// differ in metadata (APPn, comments, marker order) give the same value.
+    /// Useful as a decode cache key. Must be called after `prepare()` on the
+    /// same data.
+    pub fn scan_checksum(&self, data: &[u8]) -> Result<u32> {
+        let (start, end) = self.scan_range(data)?;
+        Ok(crc32(&data[start..end]))
+    }
+
+    /// Get the compressed scan size per pixel
+    /// 
+    /// Bytes of entropy-coded data (`scan_range()`) divided by
+    /// `width * height`; multiply by 8 for bits per pixel. A cheap proxy for
+    /// the encoding quality that needs no decoding. Returns `None` before
+    /// `prepare()` or if the scan cannot be located.
+    pub fn compression_ratio(&self, data: &[u8]) -> Option<f32> {
+        let pixels = self.width as u32 * self.height as u32;
+        if pixels == 0 {
+            return None;
+        }
+        let (start, end) = self.scan_range(data).ok()?;
+        Some((end - start) as f32 / pixels as f32)
+    }
+
+    /// Get required MCU buffer size
+    /// 
+    /// Returns the number of i16 elements needed for MCU buffer.
+    pub fn mcu_buffer_size(&self) -> usize {
+        let mcu_width = self.sampling.mcu_width() as usize;
+        let mcu_height = self.sampling.mcu_height() as usize;
+        // 亮度块之后至少留两个色度块，CMYK 还需要 K 块
+        (mcu_width * mcu_height + (self.num_components as usize).max(3) - 1) * 64
+    }
+
+    /// Get required work buffer size
+    /// 
+    /// Returns the number of u8 bytes needed for work buffer.
+    pub fn work_buffer_size(&self) -> usize {
+        let mcu_width = self.sampling.mcu_width() as usize;
+        let mcu_height = self.sampling.mcu_height() as usize;
+        let bpp = match self.output_format {
+            // 交错结果与拆分出的平面各占一份
+            OutputFormat::PlanarRgb => 6,
+            _ => self.output_bpp().max(self.source_bpp()),
+        };
+        let size = mcu_width * 8 * mcu_height * 8 * bpp;
+        // 旋转需要另一半作为目标区域
+        if self.rotation == Rotation::None { size } else { size * 2 }
+    }
+
+    /// Get the work buffer size `decompress()` needs at `scale`
+    /// 
+    /// Same as `work_buffer_size()` up to 1/8. At 1/16 and 1/32 an output
+    /// pixel can span several MCUs, in which case `decompress()` also keeps
+    /// running sums for one output row at the end of the work buffer.
+    /// 
+    /// # Parameters
+    /// 
+    /// * `scale` - Scale factor (0=1/1, 1=1/2, 2=1/4, 3=1/8, 4=1/16, 5=1/32)
+    pub fn work_buffer_size_for_scale(&self, scale: u8) -> usize {
+        let scale = scale.min(MAX_SCALE);
+        let sums = if self.mcu_shift(scale) == (scale, scale) { 0 } else { (self.width >> scale) as usize * 6 };
+        self.work_buffer_size() + sums
+    }
+
+    /// Get the size of the whole decoded image in bytes
+    /// 
+    /// Tightly packed rows at the given scale in the current output format.
+    /// Packed formats (Mono1/Gray4) round every row up to whole bytes.
+    pub fn output_byte_size(&self, scale: u8) -> usize {
+        let width = (self.width >> scale.min(MAX_SCALE)) as usize;
+        let height = (self.height >> scale.min(MAX_SCALE)) as usize;
+        self.row_bytes(width) * height
+    }
+
+    /// 缩放并旋转后的输出图像尺寸
+    fn rotated_size(&self, scale: u8) -> (u16, u16) {
+        let (width, height) = (self.width >> scale, self.height >> scale);
+        if self.rotation.swaps_axes() { (height, width) } else { (width, height) }
+    }
+
+    /// 当前输出格式下 `width` 个像素一行的字节数（打包格式按字节对齐）
+    fn row_bytes(&self, width: usize) -> usize {
+        match self.output_format {
+            OutputFormat::Mono1 => width.div_ceil(8),
+            OutputFormat::Gray4 => width.div_ceil(2),
+            OutputFormat::PlanarRgb => width * 3,
+            _ => width * self.output_bpp(),
+        }
+    }
+
+    /// Get total scratch memory needed by `decompress()` in bytes
+    /// 
+    /// Sum of the MCU buffer (`mcu_buffer_size()` i16 elements) and the work
+    /// buffer (`work_buffer_size()` bytes) for the current image and output
+    /// format. Does not include the memory pool.
+    pub fn external_memory_bytes(&self) -> usize {
+        self.mcu_buffer_size() * core::mem::size_of::<i16>() + self.work_buffer_size()
+    }
+
+    /// Reset the DC predictors of all components to zero
+    /// 
+    /// This is what the built-in scan loop does at every restart boundary.
+    /// Call it when driving `decode_single_mcu()` or a custom loop yourself:
+    /// once before the first MCU of the scan and again after each `RSTn`
+    /// marker, then pass `dc_predictors()` as the predictors of the next MCU.
+    pub fn reset_dc_predictors(&mut self) {
+        self.dc_values = [0; MAX_COMPONENTS];
+    }
+
+    /// Get the current DC predictors of all components
+    /// 
+    /// Ordered as in the frame header: (Y, Cb, Cr) for color images, only the
+    /// first entry for grayscale and (C, M, Y, K) for CMYK/YCCK with the `cmyk`
+    /// feature. Entries past the image's component count are 0. After
+    /// `decode_single_mcu()` these equal the returned `dc_out`.
+    pub fn dc_predictors(&self) -> [i16; 4] {
+        let mut dc = [0; 4];
+        dc[..MAX_COMPONENTS].copy_from_slice(&self.dc_values);
+        dc
+    }
+
+    /// Decode a single MCU at an explicit bit position
+    /// 
+    /// Low-level entry point for error-concealment experiments. Decodes one MCU
+    /// from the entropy-coded segment with caller-supplied DC predictors and
+    /// converts it into `work_buffer` at 1:1 scale, in the same layout the
+    /// output callback of `decompress()` receives.
+    /// 
+    /// `bit_offset` counts bits of the de-stuffed entropy stream: every stuffed
+    /// `0xFF 0x00` pair contributes 8 bits. The first MCU of the scan is at
+    /// offset 0. Restart markers are not interpreted; for the first MCU after an
+    /// `RSTn`, pass the data following the marker with offset 0 and zeroed
+    /// predictors.
+    /// 
+    /// # Parameters
+    /// 
+    /// * `scan` - Entropy-coded data, starting right after the SOS segment
+    /// * `bit_offset` - Position of the MCU within `scan` (see above)
+    /// * `mcu_index` - Raster-order index of the MCU, used for the output region
+    /// * `dc_in` - DC predictors in effect before this MCU, laid out as
+    ///   `dc_predictors()` returns them (the fourth entry is ignored)
+    /// * `mcu_buffer` - MCU work buffer (`mcu_buffer_size()` elements)
+    /// * `work_buffer` - Pixel work buffer (`work_buffer_size()` bytes)
+    /// 
+    /// # Returns
+    /// 
+    /// `(dc_out, rect, next_bit_offset)`: the predictors after this MCU, the
+    /// region the pixels in `work_buffer` belong to, and the bit offset of the
+    /// following MCU.
+    #[allow(clippy::too_many_arguments)]
+    pub fn decode_single_mcu(
+        &mut self,
+        scan: &[u8],
+        bit_offset: usize,
+        mcu_index: u32,
+        dc_in: [i16; 4],
+        mcu_buffer: &mut [i16],
+        work_buffer: &mut [u8],
+    ) -> Result<([i16; 4], Rectangle, usize)> {
+        self.require_prepared()?;
+        self.require_ycc()?;
+        if mcu_buffer.len() < self.mcu_buffer_size() {
+            return Err(Error::InsufficientMemory);
+        }
+        if work_buffer.len() < self.work_buffer_size() {
+            return Err(Error::InsufficientBuffer);
+        }
+
+        let mcu_width = self.sampling.mcu_width() as usize;
+        let mcu_height = self.sampling.mcu_height() as usize;
+        let mcus_across = self.mcus_across();
+
+        if mcu_index >= self.total_mcus() {
+            return Err(Error::Parameter);
+        }
+
+        let mut bitstream = BitStream::new(scan);
+        let mut remaining = bit_offset;
+        while remaining > 0 {
+            let n = remaining.min(16);
+            bitstream.read_bits(n)?;
+            remaining -= n;
+        }
+
+        self.dc_values[..3].copy_from_slice(&dc_in[..3]);
+        self.decode_mcu(&mut bitstream, mcu_buffer, mcu_width, mcu_height, false)?;
+
+        let x = ((mcu_index % mcus_across) * mcu_width as u32 * 8) as u16;
+        let y = ((mcu_index / mcus_across) * mcu_height as u32 * 8) as u16;
+        let rect = self
+            .render_mcu(mcu_buffer, work_buffer, x, y, mcu_width, mcu_height, 0)
+            .ok_or(Error::Parameter)?;
+
+        Ok((self.dc_predictors(), rect, bitstream.bit_position()))
+    }
+
+    /// 解码熵编码数据，每个 MCU 解码后调用 `emit`（处理复位间隔）
+    /// 
+    /// `resume` 给出时从保存的位置继续；给出 `yield_check` 时为可恢复解码，
+    /// 每行开始前检查它，需要让出时返回当前状态。`mode` 决定哪些 MCU 需要重建
+    /// 和输出。
+    #[allow(clippy::too_many_arguments)]
+    fn decode_scan(
+        &mut self,
+        mut bitstream: BitStream,
+        mcu_buffer: &mut [i16],
+        resume: Option<&DecodeState>,
+        mut yield_check: Option<YieldCheck>,
+        mode: ScanMode,
+        emit: McuSink<'_, 'a>,
+    ) -> Result<Option<DecodeState>> {
+        let mcu_width = self.sampling.mcu_width() as usize;
+        let mcu_height = self.sampling.mcu_height() as usize;
+        let mcus_across = self.mcus_across();
+        let total_mcus = self.total_mcus();
+
+        let mut index = 0u32;
         let mut restart_counter = 0u16;
         let mut restart_marker = 0u8;
+        self.dc_values = [0; MAX_COMPONENTS];
+        self.set_flag(FLAG_TRUNCATED, false);
+
+        if let Some(state) = resume {
+            bitstream.restore(&state.bits);
+            self.dc_values = state.dc_values;
+            index = state.next_mcu;
+            restart_counter = state.restart_counter;
+            restart_marker = state.restart_marker;
+        }
+        let first = index;
+        let yielding = yield_check.is_some();
+
+        while index < total_mcus {
+            if index != first && index.is_multiple_of(mcus_across) {
+                if let Some(check) = yield_check.as_mut() {
+                    if check() {
+                        return Ok(Some(self.save_state(&bitstream, index, restart_counter, restart_marker)));
+                    }
+                }
+            }
+
+            if self.restart_interval > 0 && restart_counter >= self.restart_interval && !self.flag(FLAG_TRUNCATED) {
+                match bitstream.restart() {
+                    Err(Error::Input) if self.flag(FLAG_ALLOW_TRUNCATED) => self.set_flag(FLAG_TRUNCATED, true),
+                    marker => {
+                        // 严格模式：RSTn 必须按 0..7 顺序循环
+                        if marker? - 0xD0 != restart_marker {
+                            if self.flag(FLAG_STRICT_RESTART) {
+                                return Err(Error::FormatError);
+                            }
+                            self.warn(Warning::RestartOutOfSequence);
+                        }
+                        self.reset_dc_predictors();
+                        restart_counter = 0;
+                        restart_marker = (restart_marker + 1) & 0x07;
+                    }
+                }
+            }
+
+            let (dc_only, wanted) = match mode {
+                ScanMode::Full => (false, true),
+                ScanMode::DcOnly => (true, true),
+                ScanMode::Region(region) => {
+                    // 区域下方的 MCU 不再需要
+                    if self.mcu_rect(index).top > region.bottom {
+                        return Ok(None);
+                    }
+                    // 整个重启间隔都在区域外时，直接扫描到下一个 RSTn
+                    let interval = self.restart_interval as u32;
+                    if restart_counter == 0
+                        && interval > 0
+                        && index + interval < total_mcus
+                        && (index..index + interval).all(|i| !self.mcu_rect(i).overlaps(&region))
+                    {
+                        index += interval;
+                        restart_counter = self.restart_interval;
+                        continue;
+                    }
+                    // 区域外的 MCU 仍需熵解码以跟上码流，但跳过 IDCT
+                    let hit = self.mcu_rect(index).overlaps(&region);
+                    (!hit, hit)
+                }
+            };
+
+            let nonzero_ac = if self.flag(FLAG_TRUNCATED) {
+                self.fill_mcu(mcu_buffer);
+                0
+            } else {
+                match self.decode_mcu(&mut bitstream, mcu_buffer, mcu_width, mcu_height, dc_only) {
+                    // 数据提前结束：当前及之后的 MCU 都用填充色输出
+                    Err(Error::Input) if self.flag(FLAG_ALLOW_TRUNCATED) => {
+                        self.set_flag(FLAG_TRUNCATED, true);
+                        self.fill_mcu(mcu_buffer);
+                        0
+                    }
+                    result => result?,
+                }
+            };
+
+            if wanted {
+                let mcu_x = ((index % mcus_across) as usize * mcu_width * 8) as u16;
+                let mcu_y = ((index / mcus_across) as usize * mcu_height * 8) as u16;
+                match emit(self, mcu_buffer, mcu_x, mcu_y, nonzero_ac) {
+                    // 可恢复解码中回调中止视为暂停，从下一个 MCU 继续
+                    Err(Error::Interrupted) if yielding => {
+                        return Ok(Some(self.save_state(&bitstream, index + 1, restart_counter + 1, restart_marker)));
+                    }
+                    result => result?,
+                }
+            }
+
+            index += 1;
+            restart_counter += 1;
+        }
+
+        // EOI 之后还有数据（流式输入时不检查）
+        let tail_start = bitstream.pos.saturating_sub(2).min(bitstream.data.len());
+        let tail = &bitstream.data[tail_start..];
+        let end = match tail.windows(2).position(|w| w == [0xFF, markers::EOI]) {
+            Some(eoi) => {
+                if eoi + 2 < tail.len() {
+                    self.warn(Warning::TrailingData);
+                }
+                tail_start + eoi + 2
+            }
+            // 没有 EOI（截断的数据）：以读到的位置为结束
+            None => bitstream.pos.min(bitstream.data.len()),
+        };
+        self.scan_end = self.scan_end.saturating_add(u32::try_from(end).unwrap_or(u32::MAX));
+
+        Ok(None)
+    }
+
+    /// 保存继续解码所需的状态（码流位置、DC 预测值和重启间隔进度）
+    fn save_state(&self, bitstream: &BitStream, next_mcu: u32, restart_counter: u16, restart_marker: u8) -> DecodeState {
+        DecodeState {
+            bits: bitstream.save(),
+            dc_values: self.dc_values,
+            next_mcu,
+            restart_counter,
+            restart_marker,
+            scale: self.scale,
+        }
+    }
+
+    /// 第 `index` 个 MCU 在原始分辨率下的区域（未裁剪到图像）
+    fn mcu_rect(&self, index: u32) -> Rectangle {
+        let mcus_across = self.mcus_across();
+        let w = self.sampling.mcu_width() as u32 * 8;
+        let h = self.sampling.mcu_height() as u32 * 8;
+        let x = (index % mcus_across) * w;
+        let y = (index / mcus_across) * h;
+        let clamp = |v: u32| v.min(u16::MAX as u32) as u16;
+        Rectangle::new(clamp(x), clamp(x + w - 1), clamp(y), clamp(y + h - 1))
+    }
+
+    /// 记录警告（同类只记录一次）
+    fn warn(&mut self, warning: Warning) {
+        let count = self.warning_count as usize;
+        if !self.warnings[..count].contains(&warning) && count < Warning::COUNT {
+            self.warnings[count] = warning;
+            self.warning_count += 1;
+        }
+    }
+
+    /// Get the non-fatal warnings collected so far
+    /// 
+    /// Cleared by `prepare()`; decoding adds to the list. Each kind of
+    /// warning is recorded once, in the order first seen.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings[..self.warning_count as usize]
+    }
+
+    /// 回调中每个像素的字节数
+    /// 打包格式（Mono1/Gray4）和平面格式返回 0
+    fn output_bpp(&self) -> usize {
+        match self.output_format {
+            OutputFormat::Mono1 | OutputFormat::Gray4 | OutputFormat::PlanarRgb => 0,
+            OutputFormat::Rgb565 | OutputFormat::Bgr565 => 2,
+            OutputFormat::Rgba8888 | OutputFormat::Bgra8888 => 4,
+            _ if self.num_components == 1 => 1,
+            OutputFormat::Grayscale => 1,
+            OutputFormat::Rgb888 | OutputFormat::Rgb666 => 3,
+        }
+    }
+
+    /// 颜色转换后（打包前）work_buffer 中每个像素的字节数
+    /// 彩色图的 565 格式在颜色转换时直接打包
+    fn source_bpp(&self) -> usize {
+        match self.output_format {
+            _ if self.luma_only() => 1,
+            OutputFormat::Rgb565 | OutputFormat::Bgr565 => 2,
+            _ => 3,
+        }
+    }
+
+    /// 是否只输出亮度（灰度图，或强制灰度输出的彩色图）
+    #[inline]
+    fn luma_only(&self) -> bool {
+        self.num_components == 1 || self.output_format == OutputFormat::Grayscale
+    }
+
+    /// 定位扫描数据，并把 `scan_end` 设为其起点（解码完成时再加上扫描长度）
+    fn start_scan<'b>(&mut self, data: &'b [u8]) -> Result<&'b [u8]> {
+        let scan = self.find_scan_data(data)?;
+        self.scan_end = u32::try_from(data.len() - scan.len()).map_err(|_| Error::Input)?;
+        Ok(scan)
+    }
+
+    fn find_scan_data<'b>(&self, data: &'b [u8]) -> Result<&'b [u8]> {
+        // 所有下标都相对 SOS 标记，不做可能溢出的加法
+        let i = usize::try_from(self.sos_position).map_err(|_| Error::Input)?;
+        let sos = data.get(i..).ok_or(Error::Input)?;
+        
+        if sos.len() < 4 {
+            return Err(Error::Input);
+        }
+        
+        if sos[0] != 0xFF || sos[1] != markers::SOS {
+            return Err(Error::FormatError);
+        }
+        
+        // Ls 包含自身 2 字节，之后是 Ns、每个分量 2 字节和 Ss/Se/Ah/Al 共 3 字节
+        let seg_len = u16::from_be_bytes([sos[2], sos[3]]) as usize;
+        if seg_len < 8 || sos.get(4).is_some_and(|&ns| seg_len < 6 + 2 * ns as usize) {
+            return Err(Error::FormatError);
+        }
+        
+        match sos.get(2 + seg_len..) {
+            Some(scan) if !scan.is_empty() => Ok(scan),
+            _ => Err(Error::Input),
+        }
+    }
+
+    /// 分量使用的 (DC, AC) Huffman 表
+    #[inline]
+    fn huffman_tables(&self, component: usize) -> (usize, usize) {
+        let tables = self.scan_tables as usize;
+        ((tables >> component) & 1, (tables >> (component + 4)) & 1)
+    }
+
+    /// 所有解码入口共用：未调用 prepare() 时没有分量也没有表
+    fn require_prepared(&self) -> Result<()> {
+        if self.num_components == 0 {
+            return Err(Error::Parameter);
+        }
+        Ok(())
+    }
+
+    /// 解码入口共用的参数检查：已 prepare() 且缩放不超过该入口支持的 `max_scale`
+    /// （`MAX_SCALE`，或不能跨 MCU 累加的入口用 `MAX_MCU_SCALE`）
+    fn check_scale(&self, scale: u8, max_scale: u8) -> Result<()> {
+        self.require_prepared()?;
+        if scale > max_scale {
+            return Err(Error::Parameter);
+        }
+        Ok(())
+    }
+
+    /// 检查调用者提供的 MCU 缓冲区和工作缓冲区（含 1/16 以下的行累加和）
+    fn check_buffers(&self, scale: u8, mcu_buffer: &[i16], work_buffer: &[u8]) -> Result<()> {
+        if mcu_buffer.len() < self.mcu_buffer_size() {
+            return Err(Error::InsufficientMemory);
+        }
+        if work_buffer.len() < self.work_buffer_size_for_scale(scale) {
+            return Err(Error::InsufficientBuffer);
+        }
+        Ok(())
+    }
+
+    /// 行累加和放在 work_buffer 末尾，不缩到 MCU 以下时为空
+    fn split_sums<'b>(&self, work_buffer: &'b mut [u8], scale: u8) -> (&'b mut [u8], &'b mut [u8]) {
+        let extra = self.work_buffer_size_for_scale(scale) - self.work_buffer_size();
+        let split = work_buffer.len() - extra;
+        work_buffer.split_at_mut(split)
+    }
+
+    /// 直接读取 Y/Cb/Cr 块的接口不支持四分量图像
+    fn require_ycc(&self) -> Result<()> {
+        if self.num_components > 3 {
+            return Err(Error::UnsupportedFormat);
+        }
+        Ok(())
+    }
+
+    fn decode_mcu(
+        &mut self,
+        bitstream: &mut BitStream,
+        buffer: &mut [i16],
+        mcu_width: usize,
+        mcu_height: usize,
+        dc_only: bool,
+    ) -> Result<u16> {
+        let num_y_blocks = mcu_width * mcu_height;
+        let mut tmp = [0i32; 64];
+        // 非零 AC 系数总数
+        let mut nonzero_ac = 0u16;
+
+        // 解码Y blocks
+        for i in 0..num_y_blocks {
+            let block_slice = &mut buffer[i * 64..(i + 1) * 64];
+            let block: &mut [i16; 64] = block_slice.try_into().map_err(|_| Error::FormatError)?;
+            let qtable_id = self.components[0].quant_table_id;
+            
+            let nonzero = self.decode_and_dequantize_block(bitstream, &mut tmp, qtable_id, 0)?;
+            Self::reconstruct(&mut tmp, block, nonzero, dc_only);
+            nonzero_ac += nonzero as u16;
+        }
+
+        // 只输出亮度时跳过色度 IDCT（仍需熵解码以推进码流）；CMYK 的每个分量都参与颜色转换
+        let chroma_idct = !self.luma_only() || self.num_components == 4;
+
+        // Cb/Cr（CMYK 为 M/Y/K）blocks
+        for component in 1..self.num_components as usize {
+            let offset = (num_y_blocks + component - 1) * 64;
+            let slice = &mut buffer[offset..offset + 64];
+            let block: &mut [i16; 64] = slice.try_into().map_err(|_| Error::FormatError)?;
+            let qtable_id = self.components[component].quant_table_id;
+            let nonzero = self.decode_and_dequantize_block(bitstream, &mut tmp, qtable_id, component)?;
+            if chroma_idct {
+                Self::reconstruct(&mut tmp, block, nonzero, dc_only);
+            }
+            nonzero_ac += nonzero as u16;
+        }
+
+        Ok(nonzero_ac)
+    }
+
+    /// 用截断填充色的各分量样本值填满一个 MCU
+    fn fill_mcu(&self, mcu_buffer: &mut [i16]) {
+        let [r, g, b] = self.truncated_fill.map(|v| v as i32);
+        // JFIF RGB -> YCbCr
+        let ycc = |r: i32, g: i32, b: i32| {
+            [
+                (77 * r + 150 * g + 29 * b + 128) >> 8,
+                ((-43 * r - 85 * g + 128 * b + 128) >> 8) + 128,
+                ((128 * r - 107 * g - 21 * b + 128) >> 8) + 128,
+            ]
+        };
+        let [y, cb, cr] = ycc(r, g, b);
+        #[allow(unused_mut)]
+        let mut samples = [y, cb, cr, 0];
+        #[cfg(feature = "cmyk")]
+        if self.num_components == 4 {
+            // 与 cmyk_pixels() 的转换相反
+            samples = if self.adobe_transform == ADOBE_YCCK {
+                let [y, cb, cr] = ycc(255 - r, 255 - g, 255 - b);
+                [y, cb, cr, 255]
+            } else if self.adobe_transform != NO_ADOBE {
+                [r, g, b, 255]
+            } else {
+                [255 - r, 255 - g, 255 - b, 0]
+            };
+        }
+
+        // 亮度块之后每个分量一个块
+        let num_y_blocks = self.sampling.mcu_width() as usize * self.sampling.mcu_height() as usize;
+        let blocks = num_y_blocks + self.num_components as usize - 1;
+        for (block, samples_out) in mcu_buffer[..blocks * 64].chunks_exact_mut(64).enumerate() {
+            samples_out.fill(samples[block.saturating_sub(num_y_blocks - 1)] as i16);
+        }
+    }
+
+    /// 只需要 DC 时把块的平均值写入第一个样本，否则做 IDCT
+    #[inline]
+    fn reconstruct(tmp: &mut [i32; 64], block: &mut [i16; 64], nonzero: u8, dc_only: bool) {
+        if dc_only {
+            block[0] = ((tmp[0] + (128 << 8)) >> 8) as i16;
+        } else {
+            Self::idct(tmp, block, nonzero == 0);
+        }
+    }
+
+    /// 反量化后的块做 IDCT；没有 AC 系数时直接填充 DC 值
+    #[inline]
+    fn idct(tmp: &mut [i32; 64], block: &mut [i16; 64], dc_only: bool) {
+        if dc_only {
+            block_idct_dc(tmp[0], block);
+        } else {
+            block_idct(tmp, block);
+        }
+    }
+
+    /// 解码并反量化一个块，返回非零 AC 系数个数
+    fn decode_and_dequantize_block(
+        &mut self,
+        bitstream: &mut BitStream,
+        tmp: &mut [i32; 64],
+        qtable_id: u8,
+        component: usize,
+    ) -> Result<u8> {
+        let qtable = self.qtables[qtable_id as usize].ok_or(Error::FormatError)?;
+        
+        let (dc_id, ac_id) = self.huffman_tables(component);
+
+        let dc_table = self.huff_dc[dc_id].ok_or(Error::FormatError)?;
+        let ac_table = self.huff_ac[ac_id].ok_or(Error::FormatError)?;
+
+        if self.flag(FLAG_DC_ONLY) {
+            return dequantize_dc(bitstream, dc_table, ac_table, qtable, &mut self.dc_values[component], tmp);
+        }
+        dequantize_block(bitstream, dc_table, ac_table, qtable, &mut self.dc_values[component], tmp)
+    }
+
+    /// 把 `t` 位的附加位还原为有符号值（T.81 F.2.2.1 的 EXTEND，即 C 版本的 JD_EXTEND）
+    /// 
+    /// 用 i32 计算，`t` 为 16 时结果超出 i16。
+    #[inline]
+    fn extend(v: u16, t: usize) -> i32 {
+        let t = t.clamp(1, 16);
+        let v = v as i32;
+        if v & (1 << (t - 1)) == 0 {
+            v - ((1 << t) - 1)
+        } else {
+            v
+        }
+    }
+
+    /// 按当前缩放输出一个 MCU：缩到 MCU 以下时先跨 MCU 累加，像素完成后再输出
+    /// （`sums` 来自 `split_sums()`）
+    #[allow(clippy::too_many_arguments)]
+    fn output_scaled(
+        &self,
+        mcu_buffer: &[i16],
+        work_buffer: &mut [u8],
+        sums: &mut [u8],
+        x: u16,
+        y: u16,
+        mcu_width: usize,
+        mcu_height: usize,
+        emitted: &mut usize,
+        callback: OutputCallback,
+    ) -> Result<()> {
+        if self.mcu_shift(self.scale) == (self.scale, self.scale) {
+            return self.output_mcu(mcu_buffer, work_buffer, x, y, mcu_width, mcu_height, None, emitted, callback);
+        }
+        match self.accumulate_mcu(mcu_buffer, work_buffer, sums, x, y, mcu_width, mcu_height) {
+            Some(rect) => self.emit_rect(work_buffer, rect, None, emitted, callback),
+            None => Ok(()),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn output_mcu(
+        &self,
+        mcu_buffer: &[i16],
+        work_buffer: &mut [u8],
+        x: u16,
+        y: u16,
+        mcu_width: usize,
+        mcu_height: usize,
+        clip: Option<&Rectangle>,
+        emitted: &mut usize,
+        callback: OutputCallback,
+    ) -> Result<()> {
+        match self.render_mcu(mcu_buffer, work_buffer, x, y, mcu_width, mcu_height, self.scale) {
+            Some(rect) => self.emit_rect(work_buffer, rect, clip, emitted, callback),
+            None => Ok(()),
+        }
+    }
+
+    /// 裁剪、计数并旋转 work_buffer 中 `rect` 的像素，然后交给回调
+    fn emit_rect(
+        &self,
+        work_buffer: &mut [u8],
+        mut rect: Rectangle,
+        clip: Option<&Rectangle>,
+        emitted: &mut usize,
+        callback: OutputCallback,
+    ) -> Result<()> {
+        if let Some(clip) = clip {
+            rect = match self.crop_mcu(work_buffer, &rect, clip)? {
+                Some(rect) => rect,
+                None => return Ok(()),
+            };
+        }
+
+        // 累计输出字节数，超过上限时在交给回调前中止
+        *emitted += self.row_bytes(rect.width() as usize) * rect.height() as usize;
+        if self.max_output != u32::MAX && *emitted > self.max_output as usize {
+            return Err(Error::InsufficientMemory);
+        }
+
+        let continue_processing = if self.rotation == Rotation::None {
+            callback(self, work_buffer, &rect)?
+        } else {
+            let (src, dst) = work_buffer.split_at_mut(work_buffer.len() / 2);
+            let rect = self.rotate_mcu(src, dst, &rect)?;
+            callback(self, dst, &rect)?
+        };
+        
+        if !continue_processing {
+            return Err(Error::Interrupted);
+        }
+
+        Ok(())
+    }
+
+    /// 把 work_buffer 中 `rect` 的像素就地裁剪到与 `clip` 的交集，不相交时返回 None
+    fn crop_mcu(&self, work_buffer: &mut [u8], rect: &Rectangle, clip: &Rectangle) -> Result<Option<Rectangle>> {
+        let bpp = self.output_bpp();
+        if bpp == 0 {
+            return Err(Error::Parameter);
+        }
+        let cropped = Rectangle::new(
+            rect.left.max(clip.left),
+            rect.right.min(clip.right),
+            rect.top.max(clip.top),
+            rect.bottom.min(clip.bottom),
+        );
+        if cropped.left > cropped.right || cropped.top > cropped.bottom {
+            return Ok(None);
+        }
+
+        // 目标位置不会超过源位置，逐行前移即可
+        let src_row = rect.width() as usize * bpp;
+        let dst_row = cropped.width() as usize * bpp;
+        let dx = (cropped.left - rect.left) as usize * bpp;
+        for y in 0..cropped.height() as usize {
+            let src = (y + (cropped.top - rect.top) as usize) * src_row + dx;
+            work_buffer.copy_within(src..src + dst_row, y * dst_row);
+        }
+        Ok(Some(cropped))
+    }
+
+    /// 把 `src` 中 `rect` 的像素旋转后写入 `dst`，返回旋转后图像中的区域
+    fn rotate_mcu(&self, src: &[u8], dst: &mut [u8], rect: &Rectangle) -> Result<Rectangle> {
+        let bpp = self.output_bpp();
+        if bpp == 0 {
+            return Err(Error::Parameter);
+        }
+        let (w, h) = (rect.width() as usize, rect.height() as usize);
+        // 旋转前的输出图像尺寸
+        let iw = (self.width >> self.scale) - 1;
+        let ih = (self.height >> self.scale) - 1;
+
+        for y in 0..h {
+            for x in 0..w {
+                let d = match self.rotation {
+                    Rotation::None => y * w + x,
+                    Rotation::Cw90 => x * h + (h - 1 - y),
+                    Rotation::Cw180 => (h - 1 - y) * w + (w - 1 - x),
+                    Rotation::Cw270 => (w - 1 - x) * h + y,
+                };
+                let s = (y * w + x) * bpp;
+                dst[d * bpp..(d + 1) * bpp].copy_from_slice(&src[s..s + bpp]);
+            }
+        }
+
+        let r = rect;
+        Ok(match self.rotation {
+            Rotation::None => *r,
+            Rotation::Cw90 => Rectangle::new(ih - r.bottom, ih - r.top, r.left, r.right),
+            Rotation::Cw180 => Rectangle::new(iw - r.right, iw - r.left, ih - r.bottom, ih - r.top),
+            Rotation::Cw270 => Rectangle::new(r.top, r.bottom, iw - r.right, iw - r.left),
+        })
+    }
+
+    /// 把 work_buffer 中的像素就地打包为 Mono1/Gray4（每行按字节对齐）
+    fn pack_luma(&self, work_buffer: &mut [u8], rx: usize, ry: usize, rect: &Rectangle) {
+        // 4x4 Bayer 矩阵
+        const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+        let src_bpp = self.source_bpp();
+        let bits = if self.output_format == OutputFormat::Mono1 { 1 } else { 4 };
+        let row_bytes = (rx * bits).div_ceil(8);
+
+        // 写入位置永远不超过读取位置，可以就地处理
+        let mut src = 0;
+        for y in 0..ry {
+            let mut dst = y * row_bytes;
+            let mut acc = 0u8;
+            let mut filled = 0;
+            for x in 0..rx {
+                let luma = if src_bpp == 1 {
+                    work_buffer[src]
+                } else {
+                    let (r, g, b) = (work_buffer[src] as u32, work_buffer[src + 1] as u32, work_buffer[src + 2] as u32);
+                    ((r * 77 + g * 150 + b * 29) >> 8) as u8
+                };
+                src += src_bpp;
+
+                let value = if bits == 1 {
+                    let threshold = match self.mono_mode {
+                        MonoMode::Threshold(t) => t,
+                        MonoMode::OrderedDither => {
+                            let (ax, ay) = (rect.left as usize + x, rect.top as usize + y);
+                            BAYER[ay & 3][ax & 3] * 16 + 8
+                        }
+                    };
+                    (luma >= threshold) as u8
+                } else {
+                    luma >> 4
+                };
+
+                acc = (acc << bits) | value;
+                filled += bits;
+                if filled == 8 {
+                    work_buffer[dst] = acc;
+                    dst += 1;
+                    acc = 0;
+                    filled = 0;
+                }
+            }
+            if filled > 0 {
+                work_buffer[dst] = acc << (8 - filled);
+            }
+        }
+    }
+
+    /// 逐像素转换一个四分量 MCU（总是 1x1 采样）左上角 `out_width` x `out_height` 的可见部分
+    #[cfg(feature = "cmyk")]
+    fn cmyk_pixels(&self, mcu_buffer: &[i16], out_width: usize, out_height: usize, mut put: impl FnMut(usize, [u8; 3])) {
+        // 有 APP14 段的文件（Photoshop 等）存放反相值
+        let inverted = self.adobe_transform != NO_ADOBE;
+        let ycck = self.adobe_transform == ADOBE_YCCK;
+
+        for (n, i) in (0..out_height).flat_map(|y| y * 8..y * 8 + out_width).enumerate() {
+            let [c0, c1, c2, k] = [0, 1, 2, 3].map(|block| mcu_buffer[block * 64 + i] as i32);
+            let k = byte_clip(k);
+            put(n, if ycck {
+                color::ycck_to_rgb(c0, c1 - 128, c2 - 128, k, inverted, self.color_matrix)
+            } else {
+                color::cmyk_to_rgb(byte_clip(c0), byte_clip(c1), byte_clip(c2), k, inverted)
+            });
+        }
+    }
+
+    /// 把一个像素按 `source_bpp()` 格式写入 `out`
+    #[inline]
+    fn put_source_pixel(&self, out: &mut [u8], rgb: [u8; 3]) {
+        match out.len() {
+            1 => out[0] = ((rgb[0] as u32 * 77 + rgb[1] as u32 * 150 + rgb[2] as u32 * 29) >> 8) as u8,
+            2 => out.copy_from_slice(&self.encode_565(rgb)),
+            _ => out.copy_from_slice(&rgb),
+        }
+    }
+
+    /// MCU 内每个方向最多能缩小的位数（不超过 MCU 本身的尺寸）
+    fn mcu_shift(&self, scale: u8) -> (u8, u8) {
+        let bits = |blocks: u8| 3 + (blocks > 1) as u8;
+        (scale.min(bits(self.sampling.mcu_width())), scale.min(bits(self.sampling.mcu_height())))
+    }
+
+    /// 把 MCU 的像素按 `2^sx x 2^sy` 的方块累加到 `sums`（按行排列）
+    #[allow(clippy::too_many_arguments)]
+    fn sum_pixels(
+        &self,
+        mcu_buffer: &[i16],
+        work_buffer: &mut [u8],
+        mcu_width: usize,
+        mcu_height: usize,
+        (sx, sy): (u8, u8),
+        sums: &mut [[u16; 3]; 64],
+    ) {
+        let mcu_pixel_width = mcu_width * 8;
+        let mx = mcu_pixel_width >> sx;
+        // 每个方块最多 16x16 个像素，u16 不会溢出
+        let mut add = |i: usize, rgb: [u8; 3]| {
+            let sum = &mut sums[((i / mcu_pixel_width) >> sy) * mx + ((i % mcu_pixel_width) >> sx)];
+            for (s, v) in sum.iter_mut().zip(rgb) {
+                *s += v as u16;
+            }
+        };
+
+        if self.num_components == 4 {
+            #[cfg(feature = "cmyk")]
+            self.cmyk_pixels(mcu_buffer, 8, 8, add);
+        } else if !self.luma_only() {
+            let num_y_blocks = mcu_width * mcu_height;
+            color::mcu_for_each_rgb(
+                &mcu_buffer[0..num_y_blocks * 64],
+                &mcu_buffer[num_y_blocks * 64..(num_y_blocks + 1) * 64],
+                &mcu_buffer[(num_y_blocks + 1) * 64..(num_y_blocks + 2) * 64],
+                mcu_width,
+                mcu_height,
+                mcu_pixel_width,
+                mcu_height * 8,
+                self.sampling.mcu_width() as usize,
+                self.sampling.mcu_height() as usize,
+                self.gamut_mode,
+                self.color_matrix,
+                self.chroma_siting,
+                self.chroma_upsample,
+                add,
+            );
+        } else {
+            color::mcu_to_grayscale(mcu_buffer, work_buffer, mcu_width, mcu_height, mcu_pixel_width, mcu_height * 8);
+            for (i, &v) in work_buffer[..mcu_pixel_width * mcu_height * 8].iter().enumerate() {
+                add(i, [v, v, v]);
+            }
+        }
+    }
+
+    /// 方块累加和的四舍五入平均值
+    #[inline]
+    fn average(sum: [u16; 3], shift: u8) -> [u8; 3] {
+        sum.map(|s| ((s as u32 + ((1 << shift) >> 1)) >> shift) as u8)
+    }
+
+    /// 按当前格式与字节序把一个像素编码为 16 位
+    #[inline]
+    fn encode_565(&self, rgb: [u8; 3]) -> [u8; 2] {
+        let [r, g, b] = rgb;
+        let pixel = if self.output_format == OutputFormat::Bgr565 {
+            color::rgb888_to_bgr565(r, g, b)
+        } else {
+            color::rgb888_to_rgb565(r, g, b)
+        };
+        let pixel = if self.flag(FLAG_SWAP_565) { color::swap_rgb565(pixel) } else { pixel };
+        pixel.to_ne_bytes()
+    }
+
+    /// 把 work_buffer 中的 count 个灰度像素就地扩展为 16 位
+    fn pack_565(&self, work_buffer: &mut [u8], count: usize) {
+        // 写入位置在读取位置之后，需倒序处理
+        for i in (0..count).rev() {
+            let v = work_buffer[i];
+            work_buffer[i * 2..i * 2 + 2].copy_from_slice(&self.encode_565([v, v, v]));
+        }
+    }
+
+    /// 把 work_buffer 中的 count 个 `source_bpp()` 格式像素就地扩展为 32 位（alpha 为 0xFF）
+    fn pack_8888(&self, work_buffer: &mut [u8], count: usize) {
+        let src_bpp = self.source_bpp();
+        let bgr = self.output_format == OutputFormat::Bgra8888;
+        // 写入位置在读取位置之后，需倒序处理
+        for i in (0..count).rev() {
+            let [r, g, b] = if src_bpp == 1 {
+                [work_buffer[i]; 3]
+            } else {
+                [work_buffer[i * 3], work_buffer[i * 3 + 1], work_buffer[i * 3 + 2]]
+            };
+            let pixel = if bgr { [b, g, r, 0xFF] } else { [r, g, b, 0xFF] };
+            work_buffer[i * 4..i * 4 + 4].copy_from_slice(&pixel);
+        }
+    }
+
+    /// 输出一个 MCU 的 Y/Cb/Cr 平面
+    #[allow(clippy::too_many_arguments)]
+    fn output_planes(
+        &self,
+        mcu_buffer: &[i16],
+        work_buffer: &mut [u8],
+        x: u16,
+        y: u16,
+        mcu_width: usize,
+        mcu_height: usize,
+        callback: PlaneCallback,
+    ) -> Result<()> {
+        let mcu_pixel_width = mcu_width * 8;
+        let out_width = (mcu_pixel_width as u16).min(self.width - x) as usize;
+        let out_height = ((mcu_height * 8) as u16).min(self.height - y) as usize;
+
+        // Y 平面
+        color::mcu_to_grayscale(mcu_buffer, work_buffer, mcu_width, mcu_height, out_width, out_height);
+        let rect = Rectangle::new(x, x + out_width as u16 - 1, y, y + out_height as u16 - 1);
+        if !callback(self, 0, &work_buffer[..out_width * out_height], &rect)? {
+            return Err(Error::Interrupted);
+        }
+
+        if self.luma_only() {
+            return Ok(());
+        }
+
+        // Cb/Cr 平面（按采样因子缩小）
+        let num_y_blocks = mcu_width * mcu_height;
+        let cw = out_width.div_ceil(mcu_width);
+        let ch = out_height.div_ceil(mcu_height);
+        let cx = x / mcu_width as u16;
+        let cy = y / mcu_height as u16;
+        let rect = Rectangle::new(cx, cx + cw as u16 - 1, cy, cy + ch as u16 - 1);
+
+        for plane in 1..3u8 {
+            let block = &mcu_buffer[(num_y_blocks + plane as usize - 1) * 64..][..64];
+            for row in 0..ch {
+                for col in 0..cw {
+                    work_buffer[row * cw + col] = byte_clip(block[row * 8 + col] as i32);
+                }
+            }
+            if !callback(self, plane, &work_buffer[..cw * ch], &rect)? {
+                return Err(Error::Interrupted);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 将MCU转换为像素数据写入work_buffer，返回输出区域（全部被缩放舍去时返回None）
+    #[allow(clippy::too_many_arguments)]
+    fn render_mcu(
+        &self,
+        mcu_buffer: &[i16],
+        work_buffer: &mut [u8],
+        x: u16,
+        y: u16,
+        mcu_width: usize,
+        mcu_height: usize,
+        scale: u8,
+    ) -> Option<Rectangle> {
+        let mcu_pixel_width = (mcu_width * 8) as u16;
+        let mcu_pixel_height = (mcu_height * 8) as u16;
+
+        let out_width = mcu_pixel_width.min(self.width - x);
+        let out_height = mcu_pixel_height.min(self.height - y);
+
+        // 超出 MCU 尺寸的缩放由 accumulate_mcu() 跨 MCU 完成
+        debug_assert_eq!(self.mcu_shift(scale), (scale, scale));
+        let scaled_width = out_width >> scale;
+        let scaled_height = out_height >> scale;
+
+        if scaled_width == 0 || scaled_height == 0 {
+            return None;
+        }
+
+        let rect = Rectangle::new(
+            x >> scale,
+            (x >> scale) + scaled_width - 1,
+            y >> scale,
+            (y >> scale) + scaled_height - 1,
+        );
+
+        // 全尺寸时颜色转换只处理边缘 MCU 的可见部分，直接按 rx 宽的行排列
+        debug_assert!(work_buffer.len() >= mcu_width * mcu_height * 64 * self.source_bpp());
+
+        let bpp = self.source_bpp();
+        let rx = scaled_width as usize;
+        let ry = scaled_height as usize;
+        if scale > 0 {
+            // 缩放输出每个像素是对应方块的平均值，结果按缩放后的 MCU 宽度排列
+            let mut sums = [[0u16; 3]; 64];
+            self.sum_pixels(mcu_buffer, work_buffer, mcu_width, mcu_height, (scale, scale), &mut sums);
+            let mx = (mcu_pixel_width >> scale) as usize;
+            let count = mx * (mcu_pixel_height >> scale) as usize;
+            for (i, &sum) in sums[..count].iter().enumerate() {
+                self.put_source_pixel(&mut work_buffer[i * bpp..(i + 1) * bpp], Self::average(sum, scale * 2));
+            }
+            if rx < mx {
+                // 灰度图每像素 1 字节，彩色 3 字节（565 为 2 字节）
+                for row in 1..ry {
+                    work_buffer.copy_within(row * mx * bpp..(row * mx + rx) * bpp, row * rx * bpp);
+                }
+            }
+        } else if self.num_components == 4 {
+            #[cfg(feature = "cmyk")]
+            self.cmyk_pixels(mcu_buffer, rx, ry, |i, rgb| self.put_source_pixel(&mut work_buffer[i * bpp..(i + 1) * bpp], rgb));
+        } else if !self.luma_only() {
+            let num_y_blocks = mcu_width * mcu_height;
+            let y_data = &mcu_buffer[0..num_y_blocks * 64];
+            let cb_data = &mcu_buffer[num_y_blocks * 64..(num_y_blocks + 1) * 64];
+            let cr_data = &mcu_buffer[(num_y_blocks + 1) * 64..(num_y_blocks + 2) * 64];
+
+            if bpp == 2 {
+                color::mcu_for_each_rgb(
+                    y_data,
+                    cb_data,
+                    cr_data,
+                    mcu_width,
+                    mcu_height,
+                    rx,
+                    ry,
+                    self.sampling.mcu_width() as usize,
+                    self.sampling.mcu_height() as usize,
+                    self.gamut_mode,
+                    self.color_matrix,
+                    self.chroma_siting,
+                    self.chroma_upsample,
+                    |i, rgb| work_buffer[i * 2..i * 2 + 2].copy_from_slice(&self.encode_565(rgb)),
+                );
+            } else {
+                color::mcu_to_rgb(
+                    y_data,
+                    cb_data,
+                    cr_data,
+                    work_buffer,
+                    mcu_width,
+                    mcu_height,
+                    rx,
+                    ry,
+                    self.sampling.mcu_width() as usize,
+                    self.sampling.mcu_height() as usize,
+                    self.gamut_mode,
+                    self.color_matrix,
+                    self.chroma_siting,
+                    self.chroma_upsample,
+                );
+            }
+        } else {
+            color::mcu_to_grayscale(mcu_buffer, work_buffer, mcu_width, mcu_height, rx, ry);
+        }
+
+        self.finish_pixels(work_buffer, rx, ry, &rect);
+        Some(rect)
+    }
+
+    /// 把 work_buffer 中 `rx` x `ry` 个 `source_bpp()` 格式的像素就地转换为输出格式
+    fn finish_pixels(&self, work_buffer: &mut [u8], rx: usize, ry: usize, rect: &Rectangle) {
+        match self.output_format {
+            OutputFormat::Rgb666 if self.num_components > 1 => {
+                // 每通道保留高 6 位
+                for v in &mut work_buffer[..rx * ry * 3] {
+                    *v &= 0xFC;
+                }
+            }
+            OutputFormat::Mono1 | OutputFormat::Gray4 => self.pack_luma(work_buffer, rx, ry, rect),
+            OutputFormat::Rgb565 | OutputFormat::Bgr565 if self.num_components == 1 => {
+                self.pack_565(work_buffer, rx * ry)
+            }
+            OutputFormat::Rgba8888 | OutputFormat::Bgra8888 => self.pack_8888(work_buffer, rx * ry),
+            OutputFormat::PlanarRgb => self.split_planes(work_buffer, rx * ry),
+            _ => {}
+        }
+    }
+
+    /// 把 `count` 个交错像素拆成 R、G、B 三个连续平面
+    /// 
+    /// 平面先写到交错数据之后，再整体移回 work_buffer 开头。
+    fn split_planes(&self, work_buffer: &mut [u8], count: usize) {
+        if self.source_bpp() == 1 {
+            // 亮度直接复制到 G、B 平面
+            work_buffer.copy_within(0..count, count);
+            work_buffer.copy_within(0..count, count * 2);
+            return;
+        }
+        let (src, planes) = work_buffer.split_at_mut(count * 3);
+        let (r, rest) = planes.split_at_mut(count);
+        let (g, b) = rest.split_at_mut(count);
+        for (i, px) in src.chunks_exact(3).enumerate() {
+            r[i] = px[0];
+            g[i] = px[1];
+            b[i] = px[2];
+        }
+        work_buffer.copy_within(count * 3..count * 6, 0);
+    }
+
+    /// 缩放超过 MCU 尺寸时，把一个 MCU 的平均值累加到 `sums` 的输出像素中
+    /// 
+    /// `sums` 保存一行输出像素的 RGB 累加和。方块的最后一个 MCU 把平均值
+    /// 写入 work_buffer 并返回其区域；不完整的方块（图像右、下边缘）被舍去。
+    #[allow(clippy::too_many_arguments)]
+    fn accumulate_mcu(
+        &self,
+        mcu_buffer: &[i16],
+        work_buffer: &mut [u8],
+        sums: &mut [u8],
+        x: u16,
+        y: u16,
+        mcu_width: usize,
+        mcu_height: usize,
+    ) -> Option<Rectangle> {
+        let scale = self.scale;
+        let (ox, oy) = (x >> scale, y >> scale);
+        if ox >= self.width >> scale || oy >= self.height >> scale {
+            return None;
+        }
+
+        // 此时每个 MCU 只缩成一个像素
+        let (sx, sy) = self.mcu_shift(scale);
+        let mut mcu_sums = [[0u16; 3]; 64];
+        self.sum_pixels(mcu_buffer, work_buffer, mcu_width, mcu_height, (sx, sy), &mut mcu_sums);
+        let pixel = Self::average(mcu_sums[0], sx + sy);
+
+        // 每个方块最多 4x4 个 MCU，u16 不会溢出
+        let slot = &mut sums[ox as usize * 6..(ox as usize + 1) * 6];
+        let mask = (1u16 << scale) - 1;
+        let first = x & mask == 0 && y & mask == 0;
+        for (c, v) in pixel.into_iter().enumerate() {
+            let old = if first { 0 } else { u16::from_ne_bytes([slot[c * 2], slot[c * 2 + 1]]) };
+            slot[c * 2..c * 2 + 2].copy_from_slice(&(old + v as u16).to_ne_bytes());
+        }
+
+        // 最后一列/行的 MCU 末端可能超过 u16（接近 65535 像素的图像）
+        let mask = mask as u32;
+        let last = (x as u32 + mcu_width as u32 * 8) & mask == 0 && (y as u32 + mcu_height as u32 * 8) & mask == 0;
+        if !last {
+            return None;
+        }
+        let sum = [0, 1, 2].map(|c| u16::from_ne_bytes([slot[c * 2], slot[c * 2 + 1]]));
+        let bpp = self.source_bpp();
+        self.put_source_pixel(&mut work_buffer[..bpp], Self::average(sum, 2 * scale - sx - sy));
+
+        let rect = Rectangle::new(ox, ox, oy, oy);
+        self.finish_pixels(work_buffer, 1, 1, &rect);
+        Some(rect)
+    }
+
+    /// Get output width (with scaling and rotation applied)
+    pub fn width(&self) -> u16 {
+        self.rotated_size(self.scale).0
+    }
+
+    /// Get output height (with scaling and rotation applied)
+    pub fn height(&self) -> u16 {
+        self.rotated_size(self.scale).1
+    }
+
+    /// Get original image width (without scaling)
+    pub fn raw_width(&self) -> u16 {
+        self.width
+    }
+
+    /// Get original image height (without scaling)
+    pub fn raw_height(&self) -> u16 {
+        self.height
+    }
+
+    /// Get the EXIF orientation of the image
+    /// 
+    /// The Orientation tag (1..=8) from the APP1 EXIF segment, or `1` (no
+    /// transform) when the image has none or it is malformed. The decoder
+    /// never rotates pixels itself; apply the transform in the callback.
+    pub fn orientation(&self) -> u8 {
+        self.orientation
+    }
+
+    /// Get the pixel density from the JFIF APP0 segment
+    /// 
+    /// `(unit, x_density, y_density)`, or `None` when the image has no
+    /// valid JFIF header (other APP0 segments don't count). Metadata only;
+    /// it doesn't affect the decoded pixels.
+    pub fn density(&self) -> Option<(DensityUnit, u16, u16)> {
+        self.density
+    }
+
+    /// Get the chroma subsampling of the image
+    /// 
+    /// Grayscale images always report `SamplingFactor::Yuv444`.
+    pub fn sampling_factor(&self) -> SamplingFactor {
+        self.sampling
+    }
+
+    /// Get the restart interval in MCUs (`0` if the image has no DRI)
+    pub fn restart_interval(&self) -> u16 {
+        self.restart_interval
+    }
+
+    /// Check whether the last `prepare()` hit a progressive SOF
+    /// 
+    /// Progressive images aren't supported, so `prepare()` fails on them
+    /// with `Error::ProgressiveUnsupported`. Always `false` after
+    /// `prepare()` succeeded.
+    pub fn is_progressive(&self) -> bool {
+        self.flag(FLAG_PROGRESSIVE)
+    }
+
+    /// Get number of color components
+    /// 
+    /// Returns 1 for grayscale, 3 for color images and 4 for CMYK/YCCK
+    /// images (`cmyk` feature).
+    pub fn components(&self) -> u8 {
+        self.num_components
+    }
+
+    /// Check whether the callback bitmap carries luma only
+    ///
+    /// `true` for grayscale images in any byte-wide format (the decoder
+    /// doesn't expand them to RGB), for `OutputFormat::Grayscale` and for
+    /// the packed `Mono1`/`Gray4` formats. RGB565, RGBA/BGRA and planar RGB
+    /// outputs always carry color, even for grayscale images.
+    pub fn is_grayscale(&self) -> bool {
+        matches!(self.output_format, OutputFormat::Mono1 | OutputFormat::Gray4) || self.output_bpp() == 1
+    }
+
+    /// Get the bytes per pixel of the callback bitmap
+    ///
+    /// Follows both the component count and the output format: 1 for
+    /// grayscale output, 2 for RGB565, 3 for RGB888/RGB666 and 4 for
+    /// RGBA/BGRA. Returns 0 for the packed `Mono1`/`Gray4` formats, whose
+    /// rows are padded to whole bytes (see `output_byte_size()`), and for
+    /// `PlanarRgb`, which isn't interleaved (3 bytes per pixel in total).
+    pub fn bytes_per_pixel(&self) -> usize {
+        self.output_bpp()
+    }
+
+    /// Get the per-component table of the SOF header
+    /// 
+    /// One entry per component (empty before `prepare()`), exactly as read
+    /// from the frame header. Useful for debugging subsampling and
+    /// quantization table mapping.
+    pub fn components_info(&self) -> &[ComponentInfo] {
+        &self.components[..self.num_components as usize]
+    }
+
+    /// Get number of MCU columns
+    pub fn mcus_across(&self) -> u32 {
+        (self.width as u32).div_ceil(self.sampling.mcu_width() as u32 * 8)
+    }
+
+    /// Get number of MCU rows
+    pub fn mcus_down(&self) -> u32 {
+        (self.height as u32).div_ceil(self.sampling.mcu_height() as u32 * 8)
+    }
+
+    /// Get total number of MCUs in the image
+    /// 
+    /// Available after `prepare()`, e.g. as a progress bar denominator.
+    /// Computed in `u32`, so it cannot overflow even for 65535x65535 images.
+    pub fn total_mcus(&self) -> u32 {
+        self.mcus_across() * self.mcus_down()
+    }
+
+    /// Whether the Huffman fast lookup table is in use for this image
+    /// 
+    /// `fastdecode_level()` only reports what was compiled in; this reports
+    /// whether any Huffman table parsed by `prepare()` actually has a LUT.
+    /// Always `false` before `prepare()` or without `fast-decode-2`.
+    pub fn using_lut(&self) -> bool {
+        self.huff_dc
+            .iter()
+            .chain(self.huff_ac.iter())
+            .flatten()
+            .any(|table| table.has_lut())
+    }
+}
+
+impl Default for JpegDecoder<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Build a dequantization table from raw DQT values
+/// 
+/// Takes the 64 quantizer values in zigzag order (as stored in a DQT segment)
+/// and returns them in raster order, pre-multiplied by the Arai IDCT scale
+/// factors. This is the `qtable` format expected by `decode_block()`.
+pub fn scale_qtable(zigzag: &[u16; 64]) -> [i32; 64] {
+    use crate::tables::{ZIGZAG, ARAI_SCALE_FACTOR};
+
+    let mut qtable = [0i32; 64];
+    for (i, &q) in zigzag.iter().enumerate() {
+        let zi = ZIGZAG[i] as usize;
+        qtable[zi] = (q as u32 * ARAI_SCALE_FACTOR[zi] as u32) as i32;
+    }
+    qtable
+}
+
+/// Decode a single 8x8 block
+/// 
+/// Runs Huffman decoding, dequantization and the IDCT for one block with
+/// explicitly supplied tables, without a `JpegDecoder`. Useful for testing
+/// the pipeline in isolation or building non-standard decoders.
+/// 
+/// # Parameters
+/// 
+/// * `bits` - Entropy-coded data positioned at the start of the block
+/// * `dc_table` / `ac_table` - Huffman tables of the component
+/// * `qtable` - Dequantization table, see `scale_qtable()`
+/// * `dc_pred` - DC predictor of the component, updated in place
+/// 
+/// # Returns
+/// 
+/// The 64 output samples in raster order, level-shifted to 0..255
+/// (values may fall slightly outside that range before clipping).
+pub fn decode_block(
+    bits: &mut BitStream,
+    dc_table: &HuffmanTable,
+    ac_table: &HuffmanTable,
+    qtable: &[i32; 64],
+    dc_pred: &mut i16,
+) -> Result<[i16; 64]> {
+    // 就地变换，不需要单独的输出块
+    let mut tmp = [0i32; 64];
+    let nonzero = dequantize_block(bits, dc_table, ac_table, qtable, dc_pred, &mut tmp)?;
+    if nonzero == 0 {
+        let dc = (tmp[0] + (128 << 8)) >> 8;
+        tmp.fill(dc);
+    } else {
+        block_idct_in_place(&mut tmp);
+    }
+    Ok(tmp.map(|v| v as i16))
+}
+
+/// 解码 DC 差值并更新预测值，反量化后的 DC 写入 `tmp[0]`，其余系数清零
+#[inline]
+fn decode_dc(
+    bitstream: &mut BitStream,
+    dc_table: &HuffmanTable,
+    qtable: &[i32; 64],
+    dc_pred: &mut i16,
+    tmp: &mut [i32; 64],
+) -> Result<()> {
+    let dc_len = dc_table.decode(bitstream)? as usize;
+    
+    let dc_diff = if dc_len > 0 {
+        let bits = bitstream.read_bits(dc_len)?;
+        JpegDecoder::extend(bits, dc_len)
+    } else {
+        0
+    };
+
+    *dc_pred = dc_pred.wrapping_add(dc_diff as i16);
+    let dc = *dc_pred as i32;
+    
+    tmp[0] = (dc * qtable[0]) >> 8;
+    tmp[1..].fill(0);
+    Ok(())
+}
+
+/// 只反量化 DC 系数；AC 符号和附加位照常读取后丢弃，返回 0
+fn dequantize_dc(
+    bitstream: &mut BitStream,
+    dc_table: &HuffmanTable,
+    ac_table: &HuffmanTable,
+    qtable: &[i32; 64],
+    dc_pred: &mut i16,
+    tmp: &mut [i32; 64],
+) -> Result<u8> {
+    decode_dc(bitstream, dc_table, qtable, dc_pred, tmp)?;
+
+    let mut z = 1;
+    while z < 64 {
+        let symbol = ac_table.decode(bitstream)?;
+        if symbol == 0 {
+            break;
+        }
+        z += (symbol >> 4) as usize;
+        if z >= 64 {
+            return Err(Error::FormatError);
+        }
+        let ac_len = (symbol & 0x0F) as usize;
+        if ac_len > 0 {
+            bitstream.read_bits(ac_len)?;
+        }
+        z += 1;
+    }
+    Ok(0)
+}
+
+/// 解码一个块的 Huffman 数据并反量化到 `tmp`，返回是否所有 AC 系数为零
+fn dequantize_block(
+    bitstream: &mut BitStream,
+    dc_table: &HuffmanTable,
+    ac_table: &HuffmanTable,
+    qtable: &[i32; 64],
+    dc_pred: &mut i16,
+    tmp: &mut [i32; 64],
+) -> Result<u8> {
+    use crate::tables::ZIGZAG;
+
+    decode_dc(bitstream, dc_table, qtable, dc_pred, tmp)?;
+
+    let mut z = 1;
+    let mut nonzero = 0u8;
+
+    loop {
+        let symbol = ac_table.decode(bitstream)?;
+        
+        if symbol == 0 {
+            break;
+        }
+
+        let zero_run = (symbol >> 4) as usize;
+        let ac_len = (symbol & 0x0F) as usize;
+
+        z += zero_run;
+        
+        if z >= 64 {
+            return Err(Error::FormatError);
+        }
+
+        if ac_len > 0 {
+            let bits = bitstream.read_bits(ac_len)?;
+            let ac_value = JpegDecoder::extend(bits, ac_len);
+            let i = ZIGZAG[z] as usize;
+            tmp[i] = (ac_value * qtable[i]) >> 8;
+            nonzero += 1;
+        }
+
+        z += 1;
+        
+        if z >= 64 {
+            break;
+        }
+    }
+    
+    Ok(nonzero)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{blit_rect, decode, encode, max_diff, pattern, prepared, EncodeOptions};
+    use crate::{MemoryPool, BUFFER_SIZE, RECOMMENDED_POOL_SIZE};
+
+    fn assert_send<T: Send>() {}
+
+    /// Upper bound for `size_of::<JpegDecoder>()` on 64-bit targets
+    /// 
+    /// The crate documents this bound for the decoder struct. New fields must
+    /// fit in this budget; raising it is a deliberate decision, not a side effect.
+    /// The `cmyk` feature adds the state of a fourth component on top. Raised
+    /// by 8 for the truncated-scan fill color and by 8 for the JFIF density.
+    const MAX_DECODER_SIZE: usize = if cfg!(feature = "cmyk") { 152 } else { 144 };
+
+    #[test]
+    fn test_decoder_size() {
+        const _: () = assert!(core::mem::size_of::<JpegDecoder>() <= MAX_DECODER_SIZE);
+    }
+
+    #[test]
+    fn test_decoder_is_send() {
+        assert_send::<JpegDecoder<'static>>();
+        assert_send::<MemoryPool<'static>>();
+    }
+
+    #[test]
+    fn test_decode_single_mcu_matches_full_decode() {
+        for &sampling in &[(1, 1), (2, 1), (2, 2)] {
+            let opts = EncodeOptions { sampling, ..Default::default() };
+            let jpeg = encode(40, 40, &pattern(40, 40, 3), &opts);
+
+            let (mut decoder, mut mcu_buffer, mut work_buffer) = prepared(&jpeg);
+
+            let mut expected = Vec::new();
+            decoder
+                .decompress(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut |_, bitmap, rect| {
+                    let n = rect.width() as usize * rect.height() as usize * 3;
+                    expected.push((*rect, bitmap[..n].to_vec()));
+                    Ok(true)
+                })
+                .unwrap();
+
+            let scan = decoder.find_scan_data(&jpeg).unwrap();
+            let mut dc = [0i16; 4];
+            let mut offset = 0;
+            for (index, (rect, pixels)) in expected.iter().enumerate() {
+                let (dc_out, got_rect, next) = decoder
+                    .decode_single_mcu(scan, offset, index as u32, dc, &mut mcu_buffer, &mut work_buffer)
+                    .unwrap();
+                assert_eq!(got_rect, *rect);
+                assert_eq!(&work_buffer[..pixels.len()], &pixels[..]);
+                assert!(next > offset);
+                dc = dc_out;
+                offset = next;
+            }
+        }
+    }
+
+    #[test]
+    fn test_decompress_to_buffer_with_stride_and_offset() {
+        let jpeg = encode(24, 16, &pattern(24, 16, 3), &EncodeOptions::default());
+        let (_, _, packed) = decode(&jpeg, 0, 3);
+
+        let (mut decoder, mut mcu_buffer, mut work_buffer) = prepared(&jpeg);
+
+        // 64x32 canvas, image placed at (5, 7)
+        let stride = 64 * 3;
+        let mut canvas = vec![0xAAu8; stride * 32];
+        let written = decoder
+            .decompress_to_buffer(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut canvas, stride, 5, 7)
+            .unwrap();
+        assert_eq!(written, 22 * stride + 29 * 3);
+
+        for y in 0..32 {
+            for x in 0..64 {
+                let px = &canvas[y * stride + x * 3..y * stride + x * 3 + 3];
+                if (5..29).contains(&x) && (7..23).contains(&y) {
+                    let src = ((y - 7) * 24 + (x - 5)) * 3;
+                    assert_eq!(px, &packed[src..src + 3]);
+                } else {
+                    assert_eq!(px, &[0xAA; 3]);
+                }
+            }
+        }
+
+        // Stride too small for the offset row, and canvas too short
+        assert_eq!(
+            decoder.decompress_to_buffer(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut canvas, 28 * 3, 5, 0),
+            Err(Error::Parameter)
+        );
+        assert_eq!(
+            decoder.decompress_to_buffer(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut canvas, stride, 0, 20),
+            Err(Error::InsufficientMemory)
+        );
+    }
+
+    fn decode_with(jpeg: &[u8], strict: bool) -> Result<()> {
+        let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+        let mut pool = MemoryPool::new(&mut pool_buffer);
+        let mut decoder = JpegDecoder::new();
+        decoder.prepare(jpeg, &mut pool)?;
+        decoder.set_strict_restart(strict);
+
+        let mut mcu_buffer = vec![0i16; decoder.mcu_buffer_size()];
+        let mut work_buffer = vec![0u8; decoder.work_buffer_size()];
+        decoder.decompress(jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut |_, _, _| Ok(true))
+    }
+
+    #[test]
+    fn test_restart_markers() {
+        let pixels = pattern(64, 32, 3);
+        let plain = encode(64, 32, &pixels, &EncodeOptions::default());
+        let opts = EncodeOptions { restart_interval: 2, ..EncodeOptions::default() };
+        let jpeg = encode(64, 32, &pixels, &opts);
+
+        let (_, _, expected) = decode(&plain, 0, 3);
+        let (_, _, actual) = decode(&jpeg, 0, 3);
+        assert_eq!(actual, expected);
+        assert_eq!(decode_with(&jpeg, true), Ok(()));
+
+        // 把 RST1 改成 RST2：宽松模式继续解码，严格模式报错
+        let sos = jpeg.windows(2).position(|w| w == [0xFF, 0xDA]).unwrap();
+        let rst1 = sos + jpeg[sos..].windows(2).position(|w| w == [0xFF, 0xD1]).unwrap();
+        let mut corrupt = jpeg.clone();
+        corrupt[rst1 + 1] = 0xD2;
+        assert_eq!(decode_with(&corrupt, false), Ok(()));
+        assert_eq!(decode_with(&corrupt, true), Err(Error::FormatError));
+    }
+
+    #[test]
+    fn test_rgb666_output() {
+        let jpeg = encode(24, 16, &pattern(24, 16, 3), &EncodeOptions::default());
+        let (_, _, rgb888) = decode(&jpeg, 0, 3);
+
+        let (mut decoder, mut mcu_buffer, _) = prepared(&jpeg);
+        decoder.set_output_format(OutputFormat::Rgb666).unwrap();
+
+        let mut work_buffer = vec![0u8; decoder.work_buffer_size()];
+        let mut rgb666 = vec![0u8; 24 * 16 * 3];
+        decoder
+            .decompress_to_buffer(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut rgb666, 24 * 3, 0, 0)
+            .unwrap();
+
+        for (q, v) in rgb666.iter().zip(rgb888.iter()) {
+            assert_eq!(*q, *v & 0xFC);
+        }
+    }
+
+    #[test]
+    fn test_using_lut() {
+        let jpeg = encode(16, 16, &pattern(16, 16, 3), &EncodeOptions::default());
+        let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+        let mut pool = MemoryPool::new(&mut pool_buffer);
+        let mut decoder = JpegDecoder::new();
+        assert!(!decoder.using_lut());
+
+        decoder.prepare(&jpeg, &mut pool).unwrap();
+        assert_eq!(decoder.using_lut(), crate::fastdecode_level() == 2);
+    }
+
+    #[test]
+    fn test_decompress_planes() {
+        for &(sampling, chroma) in &[((1, 1), (24, 20)), ((2, 1), (12, 20)), ((2, 2), (12, 10))] {
+            let opts = EncodeOptions { sampling, ..EncodeOptions::default() };
+            let jpeg = encode(24, 20, &pattern(24, 20, 3), &opts);
+
+            let (mut decoder, mut mcu_buffer, mut work_buffer) = prepared(&jpeg);
+            assert_eq!(decoder.plane_dimensions(0), Some((24, 20)));
+            assert_eq!(decoder.plane_dimensions(1), Some(chroma));
+            assert_eq!(decoder.plane_dimensions(3), None);
+
+            let mut coverage = [vec![0u8; 24 * 20], vec![0u8; 24 * 20], vec![0u8; 24 * 20]];
+            let mut expected_plane = 0u8;
+            let mut calls = 0;
+
+            decoder
+                .decompress_planes(&jpeg, &mut mcu_buffer, &mut work_buffer, &mut |d, plane, samples, rect| {
+                    assert_eq!(plane, expected_plane);
+                    expected_plane = (plane + 1) % 3;
+                    calls += 1;
+
+                    let (pw, ph) = d.plane_dimensions(plane).unwrap();
+                    assert!(rect.right < pw && rect.bottom < ph);
+                    assert_eq!(samples.len(), rect.width() as usize * rect.height() as usize);
+                    for y in rect.top..=rect.bottom {
+                        for x in rect.left..=rect.right {
+                            coverage[plane as usize][y as usize * pw as usize + x as usize] += 1;
+                        }
+                    }
+                    Ok(true)
+                })
+                .unwrap();
+
+            let mcus = 24usize.div_ceil(sampling.0 as usize * 8) * 20usize.div_ceil(sampling.1 as usize * 8);
+            assert_eq!(calls, mcus * 3);
+            assert!(coverage[0].iter().all(|&c| c == 1));
+            let n = chroma.0 as usize * chroma.1 as usize;
+            for plane in &coverage[1..] {
+                assert!(plane[..n].iter().all(|&c| c == 1));
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_block_hand_crafted() {
+        let mut pool_buffer = vec![0u8; 8192];
+        let mut pool = MemoryPool::new(&mut pool_buffer);
+
+        // DC: '0' -> 0, '1' -> 2; AC: '0' -> EOB, '1' -> 0x01 (run 0, 1 bit)
+        let mut bits = [0u8; 16];
+        bits[0] = 2;
+        let dc_table = HuffmanTable::create_in_pool(&mut pool, &bits, &[0x00, 0x02]).unwrap();
+        let ac_table = HuffmanTable::create_in_pool(&mut pool, &bits, &[0x00, 0x01]).unwrap();
+        let qtable = scale_qtable(&[32; 64]);
+
+        // DC '1' '11' (+3), AC '1' '1' (+1 at zigzag 1), EOB '0', pad '11'
+        let data = [0b1111_1011, 0xFF, 0xD9];
+        let mut stream = BitStream::new(&data);
+        let mut dc_pred = 10i16;
+        let block = decode_block(&mut stream, &dc_table, &ac_table, &qtable, &mut dc_pred).unwrap();
+        assert_eq!(dc_pred, 13);
+
+        let mut tmp = [0i32; 64];
+        tmp[0] = (13 * qtable[0]) >> 8;
+        tmp[1] = qtable[1] >> 8;
+        let mut expected = [0i16; 64];
+        block_idct(&mut tmp, &mut expected);
+        assert_eq!(block, expected);
+
+        // 只有水平频率：各行相同，且左右有差异
+        for row in 1..8 {
+            assert_eq!(block[row * 8..row * 8 + 8], block[..8]);
+        }
+        assert!(block[0] > block[7]);
+    }
+
+    #[test]
+    fn test_prepare_with_custom_allocator() {
+        struct Counting<'a> {
+            pool: MemoryPool<'a>,
+            calls: usize,
+        }
+
+        impl<'a> Allocator<'a> for Counting<'a> {
+            fn alloc(&mut self, size: usize, align: usize) -> Option<&'a mut [u8]> {
+                self.calls += 1;
+                self.pool.alloc_aligned(size, align)
+            }
+        }
+
+        let jpeg = encode(16, 16, &pattern(16, 16, 3), &EncodeOptions::default());
+        let (_, _, expected) = decode(&jpeg, 0, 3);
+
+        let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+        let mut alloc = Counting { pool: MemoryPool::new(&mut pool_buffer), calls: 0 };
+        let mut decoder = JpegDecoder::new();
+        decoder.prepare(&jpeg, &mut alloc).unwrap();
+        assert!(alloc.calls > 0);
+
+        let mut mcu_buffer = vec![0i16; decoder.mcu_buffer_size()];
+        let mut work_buffer = vec![0u8; decoder.work_buffer_size()];
+        let mut output = vec![0u8; 16 * 16 * 3];
+        decoder
+            .decompress_to_buffer(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut output, 16 * 3, 0, 0)
+            .unwrap();
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_total_mcus() {
+        for &(w, h) in &[(8u16, 8u16), (17, 9), (24, 20), (33, 47)] {
+            for &sampling in &[(1, 1), (2, 1), (2, 2)] {
+                let opts = EncodeOptions { sampling, ..EncodeOptions::default() };
+                let jpeg = encode(w, h, &pattern(w, h, 3), &opts);
+
+                let (mut decoder, mut mcu_buffer, mut work_buffer) = prepared(&jpeg);
+                let mut calls = 0u32;
+                decoder
+                    .decompress(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut |_, _, _| {
+                        calls += 1;
+                        Ok(true)
+                    })
+                    .unwrap();
+
+                assert_eq!(decoder.total_mcus(), calls, "{}x{} {:?}", w, h, sampling);
+                assert_eq!(decoder.total_mcus(), decoder.mcus_across() * decoder.mcus_down());
+            }
+        }
+
+        // 最大尺寸不会溢出
+        let mut decoder = JpegDecoder::new();
+        decoder.width = u16::MAX;
+        decoder.height = u16::MAX;
+        assert_eq!(decoder.total_mcus(), 8192 * 8192);
+    }
+
+    #[test]
+    fn test_blit_to_display_clipping() {
+        let jpeg = encode(16, 16, &pattern(16, 16, 3), &EncodeOptions::default());
+        let (_, _, rgb) = decode(&jpeg, 0, 3);
+        let expected = |x: i32, y: i32| {
+            let i = (y as usize * 16 + x as usize) * 3;
+            color::rgb888_to_rgb565(rgb[i], rgb[i + 1], rgb[i + 2])
+        };
+
+        let (mut decoder, mut mcu_buffer, mut work_buffer) = prepared(&jpeg);
+
+        // 左上角超出 (-4, -3) 与右下角超出 (10, 6)
+        for &(dx, dy) in &[(-4, -3), (10, 6)] {
+            let mut fb = vec![0xAAAAu16; 20 * 12];
+            decoder
+                .blit_to_display(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut fb, 20, 12, dx, dy)
+                .unwrap();
+
+            for y in 0..12 {
+                for x in 0..20 {
+                    let (sx, sy) = (x - dx, y - dy);
+                    let want = if (0..16).contains(&sx) && (0..16).contains(&sy) {
+                        expected(sx, sy)
+                    } else {
+                        0xAAAA
+                    };
+                    assert_eq!(fb[(y * 20 + x) as usize], want, "({}, {}) at offset ({}, {})", x, y, dx, dy);
+                }
+            }
+        }
+
+        // 完全在帧缓冲之外
+        let mut fb = vec![0xAAAAu16; 20 * 12];
+        decoder
+            .blit_to_display(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut fb, 20, 12, 40, -40)
+            .unwrap();
+        assert!(fb.iter().all(|&p| p == 0xAAAA));
+    }
+
+    #[test]
+    fn test_blit_to_display_swap_rgb565() {
+        // 开启字节交换后，无论输出格式如何，帧缓冲都收到交换后的值
+        for components in [1u8, 3] {
+            let opts = EncodeOptions { components, ..EncodeOptions::default() };
+            let jpeg = encode(16, 16, &pattern(16, 16, components), &opts);
+            let bpp = components as usize;
+            let (_, _, pixels) = decode(&jpeg, 0, bpp);
+            let expected: Vec<u16> = pixels
+                .chunks(bpp)
+                .map(|p| color::swap_rgb565(color::rgb888_to_rgb565(p[0], p[bpp / 2], p[bpp - 1])))
+                .collect();
+
+            for format in [OutputFormat::Rgb888, OutputFormat::Rgb565] {
+                let (mut decoder, mut mcu_buffer, _) = prepared(&jpeg);
+                decoder.set_output_format(format).unwrap();
+                decoder.set_swap_rgb565(true);
+
+                let mut work_buffer = vec![0u8; decoder.work_buffer_size()];
+                let mut fb = vec![0u16; 16 * 16];
+                decoder
+                    .blit_to_display(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut fb, 16, 16, 0, 0)
+                    .unwrap();
+                assert_eq!(fb, expected, "components {} format {:?}", components, format);
+            }
+        }
+    }
+
+    #[test]
+    fn test_truncated_sos_rejected() {
+        for components in [1u8, 3] {
+            let opts = EncodeOptions { components, ..EncodeOptions::default() };
+            let jpeg = encode(8, 8, &pattern(8, 8, components), &opts);
+            let sos = jpeg.windows(2).position(|w| w == [0xFF, 0xDA]).unwrap();
+
+            let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+            let mut pool = MemoryPool::new(&mut pool_buffer);
+            assert_eq!(JpegDecoder::new().prepare(&jpeg, &mut pool), Ok(()));
+
+            // 声明长度减 1：Ah/Al 字节缺失
+            let mut short = jpeg.clone();
+            short[sos + 3] -= 1;
+            let mut pool = MemoryPool::new(&mut pool_buffer);
+            assert_eq!(JpegDecoder::new().prepare(&short, &mut pool), Err(Error::FormatError));
+        }
+    }
+
+    #[test]
+    fn test_oversubscribed_dht_rejected() {
+        let jpeg = encode(8, 8, &pattern(8, 8, 3), &EncodeOptions::default());
+        let dht = jpeg.windows(2).position(|w| w == [0xFF, 0xC4]).unwrap();
+
+        // 码字总数不变，但 3 个 1 位码字超出编码空间
+        let mut bad = jpeg.clone();
+        let counts = &mut bad[dht + 5..dht + 21];
+        let mut moved = 3;
+        for count in counts.iter_mut().rev() {
+            let take = (*count).min(moved);
+            *count -= take;
+            moved -= take;
+        }
+        assert_eq!(moved, 0);
+        counts[0] = 3;
+
+        let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+        let mut pool = MemoryPool::new(&mut pool_buffer);
+        assert_eq!(JpegDecoder::new().prepare(&bad, &mut pool), Err(Error::FormatError));
+    }
+
+    #[test]
+    fn test_short_table_segments_rejected() {
+        let jpeg = encode(16, 16, &pattern(16, 16, 3), &EncodeOptions::default());
+        // 把段内容替换为 `body`，并相应修正段长度
+        let with_body = |pos: usize, body: &[u8]| {
+            let len = u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]) as usize;
+            let mut out = jpeg[..pos + 2].to_vec();
+            out.extend_from_slice(&(body.len() as u16 + 2).to_be_bytes());
+            out.extend_from_slice(body);
+            out.extend_from_slice(&jpeg[pos + 2 + len..]);
+            out
+        };
+
+        for marker in [0xDB, 0xC4] {
+            let pos = jpeg.windows(2).position(|w| w == [0xFF, marker]).unwrap();
+            let len = u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]) as usize;
+            let body = &jpeg[pos + 4..pos + 2 + len];
+
+            let mut cases = vec![
+                // 最后一张表少一个字节
+                body[..body.len() - 1].to_vec(),
+                // 完整的表之后跟一张少一个字节的表
+                [body, &body[..body.len() - 1]].concat(),
+                // 完整的表之后只剩表头字节
+                [body, &body[..1]].concat(),
+            ];
+            if marker == 0xC4 {
+                // 码字长度计数不完整
+                cases.push([body, &body[..16]].concat());
+            }
+            // 残缺的段在分配任何表之前就被拒绝，池用量与段内完整表的数量无关
+            let mut used = None;
+            for (i, case) in cases.iter().enumerate() {
+                // fast-decode-2 的 LUT 需要额外空间
+                let mut pool_buffer = vec![0u8; 4 * RECOMMENDED_POOL_SIZE];
+                let mut pool = MemoryPool::new(&mut pool_buffer);
+                let result = JpegDecoder::new().prepare(&with_body(pos, case), &mut pool);
+                assert_eq!(result, Err(Error::FormatError), "marker {:02X} case {}", marker, i);
+                assert_eq!(*used.get_or_insert(pool.used()), pool.used(), "marker {:02X} case {}", marker, i);
+            }
+        }
+
+        // 未定义的量化表精度
+        let pos = jpeg.windows(2).position(|w| w == [0xFF, 0xDB]).unwrap();
+        let mut bad = jpeg.clone();
+        bad[pos + 4] |= 0x20;
+        let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+        let mut pool = MemoryPool::new(&mut pool_buffer);
+        assert_eq!(JpegDecoder::new().prepare(&bad, &mut pool), Err(Error::FormatError));
+    }
+
+    #[test]
+    fn test_allow_truncated() {
+        let cases = [(3u8, (2, 2), 0u16, [200u8, 30, 90]), (3, (1, 1), 1, [0, 0, 0]), (1, (1, 1), 0, [128, 128, 128])];
+        for (components, sampling, restart_interval, fill) in cases {
+            let opts = EncodeOptions { components, sampling, restart_interval, ..EncodeOptions::default() };
+            let jpeg = encode(64, 32, &pattern(64, 32, components), &opts);
+            let bpp = components as usize;
+            let (_, _, full) = decode(&jpeg, 0, bpp);
+
+            let (mut decoder, mut mcu_buffer, mut work_buffer) = prepared(&jpeg);
+            // 扫描数据只保留一半
+            let (start, end) = decoder.scan_range(&jpeg).unwrap();
+            let cut = &jpeg[..(start + end) / 2];
+            let mut image = vec![0u8; 64 * 32 * bpp];
+            let mut run = |decoder: &mut JpegDecoder, data: &[u8], image: &mut Vec<u8>| {
+                let mut mcus = 0;
+                let result = decoder.decompress(data, 0, &mut mcu_buffer, &mut work_buffer, &mut |_, bitmap, rect| {
+                    blit_rect(image, 64, bpp, bitmap, rect);
+                    mcus += 1;
+                    Ok(true)
+                });
+                result.map(|_| mcus)
+            };
+
+            // 默认严格：截断是错误
+            assert_eq!(run(&mut decoder, cut, &mut image), Err(Error::Input));
+            assert!(!decoder.was_truncated());
+
+            decoder.set_allow_truncated(true);
+            decoder.set_truncated_fill(fill);
+            assert_eq!(run(&mut decoder, &jpeg, &mut image), Ok(decoder.total_mcus()));
+            assert!(!decoder.was_truncated());
+            assert_eq!(image, full);
+
+            image.fill(0x55);
+            assert_eq!(run(&mut decoder, cut, &mut image), Ok(decoder.total_mcus()));
+            assert!(decoder.was_truncated());
+            // 开头的 MCU 正常解码，最后一个 MCU 是填充色
+            let mcu = decoder.sampling.mcu_width() as usize * 8;
+            assert_eq!(image[..mcu * bpp], full[..mcu * bpp]);
+            let luma = ((fill[0] as u32 * 77 + fill[1] as u32 * 150 + fill[2] as u32 * 29) >> 8) as u8;
+            let expected: &[u8] = if bpp == 1 { &[luma] } else { &fill };
+            for px in image[(64 * 32 - 1) * bpp..].chunks_exact(bpp) {
+                assert!(max_diff(px, expected) <= 2, "{:?} vs {:?}", px, fill);
+            }
+        }
+    }
+
+    #[test]
+    fn test_decompress_raw_ycbcr() {
+        for (components, sampling, gray_out) in
+            [(3u8, (1, 1), false), (3, (2, 1), false), (3, (2, 2), false), (3, (2, 2), true), (1, (1, 1), false)]
+        {
+            let opts = EncodeOptions { components, sampling, ..EncodeOptions::default() };
+            let jpeg = encode(40, 24, &pattern(40, 24, components), &opts);
+
+            let (mut decoder, mut mcu_buffer, mut work_buffer) = prepared(&jpeg);
+            decoder.force_grayscale(gray_out);
+
+            // 参考平面来自 decompress_planes()
+            let mut planes = [vec![0u8; 40 * 24], vec![0u8; 40 * 24], vec![0u8; 40 * 24]];
+            decoder
+                .decompress_planes(&jpeg, &mut mcu_buffer, &mut work_buffer, &mut |d, plane, samples, rect| {
+                    let pw = d.plane_dimensions(plane).unwrap().0 as usize;
+                    for (row, line) in samples.chunks_exact(rect.width() as usize).enumerate() {
+                        let dst = (rect.top as usize + row) * pw + rect.left as usize;
+                        planes[plane as usize][dst..dst + line.len()].copy_from_slice(line);
+                    }
+                    Ok(true)
+                })
+                .unwrap();
+
+            let clip = |v: i16| v.clamp(0, 255) as u8;
+            let (h, v) = (sampling.0 as usize, sampling.1 as usize);
+            let chroma_width = decoder.plane_dimensions(1).map_or(0, |(w, _)| w as usize);
+            let mut covered = vec![0u8; 40 * 24];
+            decoder
+                .decompress_raw_ycbcr(&jpeg, &mut mcu_buffer, &mut |_, mcu, rect, layout| {
+                    assert_eq!((layout.h_blocks as usize, layout.v_blocks as usize), (h, v));
+                    let chroma_blocks = if components == 3 && !gray_out { 2 } else { 0 };
+                    assert_eq!(layout.chroma_blocks, chroma_blocks);
+                    assert_eq!(mcu.len(), (h * v + chroma_blocks as usize) * 64);
+
+                    for y in rect.top as usize..=rect.bottom as usize {
+                        for x in rect.left as usize..=rect.right as usize {
+                            let raw = mcu[layout.luma_index(x - rect.left as usize, y - rect.top as usize)];
+                            assert_eq!(clip(raw), planes[0][y * 40 + x], "Y ({}, {})", x, y);
+                            covered[y * 40 + x] += 1;
+                        }
+                    }
+                    for plane in 0..layout.chroma_blocks as usize {
+                        let (cx, cy) = (rect.left as usize / h, rect.top as usize / v);
+                        for row in 0..(rect.height() as usize).div_ceil(v) {
+                            for col in 0..(rect.width() as usize).div_ceil(h) {
+                                let raw = mcu[layout.chroma_offset(plane) + row * 8 + col];
+                                let expected = planes[plane + 1][(cy + row) * chroma_width + cx + col];
+                                assert_eq!(clip(raw), expected, "C{} ({}, {})", plane, cx + col, cy + row);
+                            }
+                        }
+                    }
+                    Ok(true)
+                })
+                .unwrap();
+            assert!(covered.iter().all(|&c| c == 1));
+        }
+    }
+
+    #[test]
+    fn test_decompress_to_planes() {
+        let jpeg = encode(24, 16, &pattern(24, 16, 3), &EncodeOptions::default());
+        let (_, _, rgb) = decode(&jpeg, 0, 3);
+
+        let (mut decoder, mut mcu_buffer, mut work_buffer) = prepared(&jpeg);
+        let stride = 27;
+        let mut planes = [vec![0u8; stride * 16], vec![0u8; stride * 16], vec![0u8; stride * 16]];
+        let [r, g, b] = &mut planes;
+        decoder
+            .decompress_to_planes(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, r, g, b, stride)
+            .unwrap();
+
+        for y in 0..16 {
+            for x in 0..24 {
+                let i = (y * 24 + x) * 3;
+                for (c, plane) in planes.iter().enumerate() {
+                    assert_eq!(plane[y * stride + x], rgb[i + c]);
+                }
+            }
+        }
+
+        let [r, g, b] = &mut planes;
+        assert_eq!(
+            decoder.decompress_to_planes(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, r, g, b, 23),
+            Err(Error::Parameter)
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_mcu_iter() {
+        let opts = EncodeOptions { sampling: (2, 1), restart_interval: 2, ..EncodeOptions::default() };
+        let jpeg = encode(40, 24, &pattern(40, 24, 3), &opts);
+
+        for (scale, rotation) in [(0u8, Rotation::None), (1, Rotation::None), (0, Rotation::Cw90)] {
+            let (mut decoder, mut mcu_buffer, _) = prepared(&jpeg);
+            decoder.set_rotation(rotation);
+            let total = decoder.total_mcus() as usize;
+            let (expected, w, h) = decoder.decode_to_vec(&jpeg, scale).unwrap();
+            let width = w as usize;
+
+            let mut work_buffer = vec![0u8; decoder.work_buffer_size()];
+            let mut image = vec![0u8; width * h as usize * 3];
+            let mut count = 0;
+            let mut mcus = decoder.mcus(&jpeg, scale, &mut mcu_buffer, &mut work_buffer);
+            while let Some(mcu) = mcus.next() {
+                let (rect, pixels) = mcu.unwrap();
+                let line = rect.width() as usize * 3;
+                assert_eq!(pixels.len(), line * rect.height() as usize);
+                for (row, src) in pixels.chunks_exact(line).enumerate() {
+                    let dst = ((rect.top as usize + row) * width + rect.left as usize) * 3;
+                    image[dst..dst + line].copy_from_slice(src);
+                }
+                count += 1;
+            }
+            assert!(mcus.next().is_none());
+            assert_eq!(count, total);
+            assert_eq!(image, expected);
+        }
+
+        // 参数错误由第一次 next() 报告，之后结束
+        let (mut decoder, mut mcu_buffer, _) = prepared(&jpeg);
+        let mut mcus = decoder.mcus(&jpeg, 0, &mut mcu_buffer, &mut []);
+        assert_eq!(mcus.next().map(|r| r.err()), Some(Some(Error::InsufficientBuffer)));
+        assert!(mcus.next().is_none());
+    }
+
+    #[test]
+    fn test_decompress_resumable_yields_per_row() {
+        for &(sampling, restart_interval) in &[((1, 1), 0), ((2, 2), 0), ((2, 1), 3)] {
+            let opts = EncodeOptions { sampling, restart_interval, ..EncodeOptions::default() };
+            let jpeg = encode(40, 48, &pattern(40, 48, 3), &opts);
+            let (_, _, expected) = decode(&jpeg, 0, 3);
+
+            let (mut decoder, mut mcu_buffer, mut work_buffer) = prepared(&jpeg);
+            let mut framebuffer = vec![0u8; 40 * 48 * 3];
+            let mut state = None;
+            let mut yields = 0;
+
+            loop {
+                state = decoder
+                    .decompress_resumable(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, state, Some(&mut || true), &mut |_, bitmap, rect| {
+                        blit_rect(&mut framebuffer, 40, 3, bitmap, rect);
+                        Ok(true)
+                    })
+                    .unwrap();
+
+                match state {
+                    Some(st) => {
+                        yields += 1;
+                        assert_eq!(st.next_mcu(), yields * decoder.mcus_across());
+                    }
+                    None => break,
+                }
+            }
+
+            assert_eq!(yields, decoder.mcus_down() - 1);
+            assert_eq!(framebuffer, expected);
+        }
+    }
+
+    #[test]
+    fn test_yield_check_closure_budget() {
+        use core::cell::Cell;
+
+        let jpeg = encode(40, 48, &pattern(40, 48, 3), &EncodeOptions::default());
+        let (_, _, expected) = decode(&jpeg, 0, 3);
+
+        let (mut decoder, mut mcu_buffer, mut work_buffer) = prepared(&jpeg);
+        let mut framebuffer = vec![0u8; 40 * 48 * 3];
+
+        // 闭包捕获 MCU 预算：输出回调计数，累计 7 个 MCU 后在下一行开始前让出
+        let decoded = Cell::new(0u32);
+        let mut budget = || {
+            if decoded.get() >= 7 {
+                decoded.set(0);
+                true
+            } else {
+                false
+            }
+        };
+        let mut callback = |_: &JpegDecoder, bitmap: &[u8], rect: &Rectangle| {
+            decoded.set(decoded.get() + 1);
+            blit_rect(&mut framebuffer, 40, 3, bitmap, rect);
+            Ok(true)
+        };
+
+        let mut state = decoder
+            .decompress_resumable(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, None, Some(&mut budget), &mut callback)
+            .unwrap();
+        let mut yields = 0;
+        while let Some(st) = state {
+            yields += 1;
+            // 每行 5 个 MCU，预算 7 个意味着每两行让出一次
+            assert_eq!(st.next_mcu(), yields * 2 * decoder.mcus_across());
+            state = decoder.resume(&jpeg, st, &mut mcu_buffer, &mut work_buffer, Some(&mut budget), &mut callback).unwrap();
+        }
+
+        assert_eq!(yields, 2);
+        assert_eq!(framebuffer, expected);
+    }
+
+    #[test]
+    fn test_callback_pauses_resumable_decode() {
+        for &(sampling, restart_interval) in &[((1, 1), 0), ((2, 2), 2)] {
+            let opts = EncodeOptions { sampling, restart_interval, ..EncodeOptions::default() };
+            let jpeg = encode(40, 48, &pattern(40, 48, 3), &opts);
+            let (_, _, expected) = decode(&jpeg, 1, 3);
+
+            let (mut decoder, mut mcu_buffer, mut work_buffer) = prepared(&jpeg);
+            let mut framebuffer = vec![0u8; 20 * 24 * 3];
+            let mut mcus = 0u32;
+            let mut callback = |_: &JpegDecoder, bitmap: &[u8], rect: &Rectangle| {
+                blit_rect(&mut framebuffer, 20, 3, bitmap, rect);
+                // 每三个 MCU 暂停一次，两种中止方式交替
+                mcus += 1;
+                match mcus % 6 {
+                    0 => Err(Error::Interrupted),
+                    3 => Ok(false),
+                    _ => Ok(true),
+                }
+            };
+
+            let mut state = decoder
+                .decompress_resumable(&jpeg, 1, &mut mcu_buffer, &mut work_buffer, None, None, &mut callback)
+                .unwrap();
+            let mut pauses = 0;
+            while let Some(st) = state {
+                pauses += 1;
+                assert_eq!(st.next_mcu(), pauses * 3);
+                state = decoder.resume(&jpeg, st, &mut mcu_buffer, &mut work_buffer, None, &mut callback).unwrap();
+            }
+
+            assert_eq!(pauses, decoder.total_mcus() / 3);
+            assert_eq!(framebuffer, expected);
+
+            // 普通解码中回调中止仍然是错误
+            let result = decoder.decompress(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut |_, _, _| Ok(false));
+            assert_eq!(result, Err(Error::Interrupted));
+        }
+    }
+
+    #[test]
+    fn test_422_chroma_edge() {
+        // 红蓝竖直边缘分别落在 MCU 内的块边界（x=8）和 MCU 边界（x=16、x=24）上
+        let (width, height) = (32u16, 16u16);
+        let red = |x: usize| x < 8 || (16..24).contains(&x);
+        let mut pixels = Vec::new();
+        for _ in 0..height {
+            for x in 0..width as usize {
+                pixels.extend_from_slice(if red(x) { &[200, 40, 40] } else { &[40, 40, 200] });
+            }
+        }
+        let opts = EncodeOptions { sampling: (2, 1), ..EncodeOptions::default() };
+        let jpeg = encode(width, height, &pixels, &opts);
+        let (_, _, rgb) = decode(&jpeg, 0, 3);
+
+        for (i, px) in rgb.chunks_exact(3).enumerate() {
+            let x = i % width as usize;
+            let (r, b) = (px[0] as i32, px[2] as i32);
+            if red(x) {
+                assert!(r - b > 100, "blue bled into red at x={} ({:?})", x, px);
+            } else {
+                assert!(b - r > 100, "red bled into blue at x={} ({:?})", x, px);
+            }
+        }
+    }
+
+    #[test]
+    fn test_decompress_with_info() {
+        let opts = EncodeOptions { sampling: (2, 1), ..EncodeOptions::default() };
+        let jpeg = encode(40, 24, &pattern(40, 24, 3), &opts);
+        let (_, _, expected) = decode(&jpeg, 0, 3);
+
+        let (mut decoder, mut mcu_buffer, mut work_buffer) = prepared(&jpeg);
+        let mut framebuffer = vec![0u8; 40 * 24 * 3];
+        let mut infos = Vec::new();
+        decoder
+            .decompress_with_info(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut |_, bitmap, rect, info| {
+                assert_eq!((rect.left as u32 / 16, rect.top as u32 / 8), (info.mcu_col, info.mcu_row));
+                blit_rect(&mut framebuffer, 40, 3, bitmap, rect);
+                infos.push(*info);
+                Ok(true)
+            })
+            .unwrap();
+
+        // 16x8 的 MCU，3 列 3 行，最右一列只有 8 像素宽
+        assert_eq!(infos.len(), 9);
+        for (i, info) in infos.iter().enumerate() {
+            assert_eq!(*info, McuInfo { mcu_col: i as u32 % 3, mcu_row: i as u32 / 3, mcu_index: i as u32, total_mcus: 9 });
+        }
+        assert_eq!(framebuffer, expected);
+    }
+
+    #[test]
+    fn test_find_scan_data_bounds() {
+        let jpeg = encode(16, 16, &pattern(16, 16, 3), &EncodeOptions::default());
+        let (mut decoder, _, _) = prepared(&jpeg);
+        let sos = decoder.sos_position as usize;
+        assert_eq!(decoder.find_scan_data(&jpeg).unwrap().len(), jpeg.len() - sos - 14);
+
+        // 各种 SOS 长度：过短、与分量数不符、超出数据都必须返回错误而不是 panic
+        for len in (0..=64u16).chain([0x100, 0x7FFF, 0xFFFE, 0xFFFF]) {
+            let mut data = jpeg.clone();
+            data[sos + 2..sos + 4].copy_from_slice(&len.to_be_bytes());
+            let result = decoder.find_scan_data(&data);
+            match len {
+                0..=11 => assert_eq!(result, Err(Error::FormatError), "len = {}", len),
+                12 => assert!(result.is_ok()),
+                _ if (len as usize) < jpeg.len() - sos - 2 => assert!(result.is_ok(), "len = {}", len),
+                _ => assert_eq!(result, Err(Error::Input), "len = {}", len),
+            }
+        }
+
+        // 截断在 SOS 头之内或紧接其后
+        for end in 0..=sos + 14 {
+            assert_eq!(decoder.find_scan_data(&jpeg[..end]), Err(Error::Input), "end = {}", end);
+        }
+        assert_eq!(decoder.find_scan_data(&jpeg[..sos + 15]).unwrap().len(), 1);
+
+        // 位置超出数据
+        decoder.sos_position = u32::MAX;
+        assert_eq!(decoder.find_scan_data(&jpeg), Err(Error::Input));
+    }
+
+    fn decode_with_format(jpeg: &[u8], format: OutputFormat, mode: MonoMode) -> Vec<(Rectangle, Vec<u8>)> {
+        let (mut decoder, mut mcu_buffer, _) = prepared(jpeg);
+        decoder.set_output_format(format).unwrap();
+        decoder.set_mono_mode(mode);
+
+        let mut work_buffer = vec![0u8; decoder.work_buffer_size()];
+        let bits = if format == OutputFormat::Mono1 { 1 } else { 4 };
+        let mut blocks = Vec::new();
+        decoder
+            .decompress(jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut |_, bitmap, rect| {
+                let len = (rect.width() as usize * bits).div_ceil(8) * rect.height() as usize;
+                blocks.push((*rect, bitmap[..len].to_vec()));
+                Ok(true)
+            })
+            .unwrap();
+        blocks
+    }
+
+    #[test]
+    fn test_mono1_and_gray4_packing() {
+        // 12x8 灰度：左 6 列暗、右 6 列亮
+        let pixels: Vec<u8> = (0..12 * 8).map(|i| if i % 12 < 6 { 20 } else { 230 }).collect();
+        let opts = EncodeOptions { components: 1, ..EncodeOptions::default() };
+        let jpeg = encode(12, 8, &pixels, &opts);
+        let (_, _, gray) = decode(&jpeg, 0, 1);
+
+        let mono = decode_with_format(&jpeg, OutputFormat::Mono1, MonoMode::Threshold(128));
+        // MCU 0: 8 列 -> 0b0000_0011；MCU 1: 4 列 -> 0b1111_0000
+        assert_eq!(mono.len(), 2);
+        assert!(mono[0].1.iter().all(|&b| b == 0x03));
+        assert!(mono[1].1.iter().all(|&b| b == 0xF0));
+
+        let gray4 = decode_with_format(&jpeg, OutputFormat::Gray4, MonoMode::default());
+        for (rect, bytes) in &gray4 {
+            let row_bytes = (rect.width() as usize).div_ceil(2);
+            for y in 0..8 {
+                for x in 0..rect.width() as usize {
+                    let nibble = (bytes[y * row_bytes + x / 2] >> (4 - (x & 1) * 4)) & 0x0F;
+                    assert_eq!(nibble, gray[y * 12 + rect.left as usize + x] >> 4);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_mono1_ordered_dither() {
+        let pixels = vec![128u8; 16 * 16 * 3];
+        let jpeg = encode(16, 16, &pixels, &EncodeOptions::default());
+        let mono = decode_with_format(&jpeg, OutputFormat::Mono1, MonoMode::OrderedDither);
+
+        // 中灰：每个 4x4 区域恰好一半像素为白
+        for (_, bytes) in &mono {
+            for ty in 0..2 {
+                for tx in 0..2 {
+                    let white: u32 = (0..4)
+                        .map(|y| ((bytes[ty * 4 + y] >> (4 - tx * 4)) & 0x0F).count_ones())
+                        .sum();
+                    assert_eq!(white, 8);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_external_memory_bytes() {
+        // (采样, 分量数, 格式, MCU 像素数 * 每像素字节)
+        let cases = [
+            ((1, 1), 3, OutputFormat::Rgb888, 64 * 3),
+            ((2, 2), 3, OutputFormat::Rgb888, 256 * 3),
+            ((2, 1), 3, OutputFormat::Rgb666, 128 * 3),
+            ((2, 2), 3, OutputFormat::Mono1, 256 * 3),
+            ((1, 1), 1, OutputFormat::Rgb888, 64),
+            ((1, 1), 1, OutputFormat::Gray4, 64),
+        ];
+
+        for &(sampling, components, format, work) in &cases {
+            let opts = EncodeOptions { sampling, components, ..EncodeOptions::default() };
+            let jpeg = encode(16, 16, &pattern(16, 16, components), &opts);
+
+            let (mut decoder, _, _) = prepared(&jpeg);
+            decoder.set_output_format(format).unwrap();
+
+            let blocks = if components == 1 { 1 } else { sampling.0 as usize * sampling.1 as usize };
+            let mcu = (blocks + 2) * 64 * 2;
+            assert_eq!(decoder.work_buffer_size(), work, "{:?} {:?}", sampling, format);
+            assert_eq!(decoder.external_memory_bytes(), mcu + work, "{:?} {:?}", sampling, format);
+        }
+    }
+
+    #[test]
+    fn test_edge_mcu_converts_visible_pixels() {
+        let decode_as = |jpeg: &[u8], format: OutputFormat, w: usize, h: usize| {
+            let (mut decoder, mut mcu_buffer, _) = prepared(jpeg);
+            decoder.set_output_format(format).unwrap();
+            let bpp = decoder.bytes_per_pixel();
+            let mut work_buffer = vec![0u8; decoder.work_buffer_size()];
+            let mut fb = vec![0u8; w * h * bpp];
+            decoder.decompress_to_buffer(jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut fb, w * bpp, 0, 0).unwrap();
+            (fb, bpp)
+        };
+
+        #[allow(unused_mut)]
+        let mut cases = vec![((1, 1), 3u8), ((2, 1), 3), ((2, 2), 3), ((1, 1), 1)];
+        #[cfg(feature = "cmyk")]
+        cases.push(((1, 1), 4));
+        for (sampling, components) in cases {
+            let opts = EncodeOptions { sampling, components, ..EncodeOptions::default() };
+            let pixels = match components {
+                4 => pattern(17, 17, 3).chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 64]).collect(),
+                _ => pattern(17, 17, components),
+            };
+            let jpeg = encode(17, 17, &pixels, &opts);
+
+            // 把 SOF 中的尺寸改成整数个 MCU：扫描数据相同，右下的填充像素也被输出
+            let (pw, ph) = (17usize.next_multiple_of(sampling.0 as usize * 8), 17usize.next_multiple_of(sampling.1 as usize * 8));
+            let sof = jpeg.windows(2).position(|w| w == [0xFF, markers::SOF0]).unwrap();
+            let mut padded = jpeg.clone();
+            padded[sof + 5..sof + 7].copy_from_slice(&(ph as u16).to_be_bytes());
+            padded[sof + 7..sof + 9].copy_from_slice(&(pw as u16).to_be_bytes());
+
+            for format in [OutputFormat::Rgb888, OutputFormat::Rgb565, OutputFormat::Grayscale] {
+                let (edge, bpp) = decode_as(&jpeg, format, 17, 17);
+                let (full, _) = decode_as(&padded, format, pw, ph);
+                let cropped: Vec<u8> = full.chunks_exact(pw * bpp).take(17).flat_map(|row| &row[..17 * bpp]).copied().collect();
+                assert_eq!(edge, cropped, "{:?} {} {:?}", sampling, components, format);
+            }
+        }
+
+        // 只给可见部分大小的输出缓冲区：任何越界读写都会 panic
+        let y = [100i16; 4 * 64];
+        let c = [128i16; 64];
+        for (out_width, out_height) in [(1, 1), (1, 16), (16, 1), (3, 5)] {
+            let mut rgb = vec![0u8; out_width * out_height * 3];
+            color::mcu_to_rgb(
+                &y, &c, &c, &mut rgb, 2, 2, out_width, out_height, 2, 2,
+                GamutMode::Clamp, ColorMatrix::Bt601, ChromaSiting::Centered, ChromaUpsample::Bilinear,
+            );
+            assert!(rgb.iter().all(|&v| v == 100));
+            let mut gray = vec![0u8; out_width * out_height];
+            color::mcu_to_grayscale(&y, &mut gray, 2, 2, out_width, out_height);
+            assert!(gray.iter().all(|&v| v == 100));
+        }
+    }
+
+    #[test]
+    fn test_restart_every_mcu() {
+        for &(sampling, components) in &[((1, 1), 3), ((2, 1), 3), ((2, 2), 3), ((1, 1), 1)] {
+            let pixels = pattern(40, 24, components);
+            let plain = encode(40, 24, &pixels, &EncodeOptions { sampling, components, ..EncodeOptions::default() });
+            let opts = EncodeOptions { sampling, components, restart_interval: 1, ..EncodeOptions::default() };
+            let jpeg = encode(40, 24, &pixels, &opts);
+
+            let bpp = components as usize;
+            assert_eq!(decode(&jpeg, 0, bpp), decode(&plain, 0, bpp), "{:?} {}", sampling, components);
+            assert_eq!(decode_with(&jpeg, true), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_restart_no_drift() {
+        // 256 个 MCU：间隔为 1 时 RSTn 编号循环 32 次
+        let pixels = pattern(256, 64, 3);
+        let (_, _, expected) = decode(&encode(256, 64, &pixels, &EncodeOptions::default()), 0, 3);
+        for interval in [1u16, 3, 7] {
+            let jpeg = encode(256, 64, &pixels, &EncodeOptions { restart_interval: interval, ..EncodeOptions::default() });
+            assert_eq!(decode(&jpeg, 0, 3).2, expected, "interval {}", interval);
+            assert_eq!(decode_with(&jpeg, true), Ok(()));
+        }
+
+        // 删掉最后一轮循环中的一个完整间隔：后面的标记都比预期早一个
+        let jpeg = encode(256, 64, &pixels, &EncodeOptions { restart_interval: 1, ..EncodeOptions::default() });
+        let sos = jpeg.windows(2).position(|w| w == [0xFF, 0xDA]).unwrap();
+        let rst: Vec<usize> = (sos..jpeg.len() - 1).filter(|&i| jpeg[i] == 0xFF && (0xD0..=0xD7).contains(&jpeg[i + 1])).collect();
+        assert_eq!(rst.len(), 255);
+        let mut dropped = jpeg[..rst[250]].to_vec();
+        dropped.extend_from_slice(&jpeg[rst[251]..]);
+        assert_eq!(decode_with(&dropped, true), Err(Error::FormatError));
+    }
+
+    #[test]
+    fn test_restart_resync_after_fill_bytes() {
+        // 每个 RSTn 前插入 0xFF 填充字节（标准允许任意个），预读可能停在填充字节和 marker 代码之间
+        let pixels = pattern(64, 48, 3);
+        let opts = EncodeOptions { sampling: (2, 1), ..EncodeOptions::default() };
+        let (_, _, expected) = decode(&encode(64, 48, &pixels, &opts), 0, 3);
+        for (interval, fill) in [(1u16, 1usize), (2, 1), (3, 3), (5, 2)] {
+            let jpeg = encode(64, 48, &pixels, &EncodeOptions { restart_interval: interval, ..opts });
+            let sos = jpeg.windows(2).position(|w| w == [0xFF, 0xDA]).unwrap();
+            let mut padded = jpeg[..sos].to_vec();
+            for (i, &byte) in jpeg.iter().enumerate().skip(sos) {
+                if byte == 0xFF && (0xD0..=0xD7).contains(&jpeg[i + 1]) {
+                    padded.extend(core::iter::repeat_n(0xFF, fill));
+                }
+                padded.push(byte);
+            }
+            assert!(padded.len() > jpeg.len());
+
+            assert_eq!(decode(&padded, 0, 3).2, expected, "interval {} fill {}", interval, fill);
+            assert_eq!(decode_with(&padded, true), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_scan_range() {
+        let opts = EncodeOptions { restart_interval: 2, ..EncodeOptions::default() };
+        let jpeg = encode(32, 16, &pattern(32, 16, 3), &opts);
+
+        let (decoder, _, _) = prepared(&jpeg);
+
+        let (start, end) = decoder.scan_range(&jpeg).unwrap();
+        let sos = jpeg.windows(2).position(|w| w == [0xFF, 0xDA]).unwrap();
+        assert_eq!(start, sos + 2 + u16::from_be_bytes([jpeg[sos + 2], jpeg[sos + 3]]) as usize);
+        assert_eq!(end, jpeg.len() - 2);
+
+        // 结尾附近的填充字节、RST 与 fill bytes
+        let mut tail = jpeg[..start].to_vec();
+        tail.extend_from_slice(&[0x12, 0xFF, 0x00, 0xFF, 0xD3, 0x34, 0xFF, 0x00, 0xFF, 0xFF, 0xD9]);
+        assert_eq!(decoder.scan_range(&tail), Ok((start, start + 8)));
+
+        // 缺少 EOI / 扫描中出现其它 marker
+        assert_eq!(decoder.scan_range(&tail[..tail.len() - 1]), Err(Error::Input));
+        tail[start + 4] = 0xC4;
+        assert_eq!(decoder.scan_range(&tail), Err(Error::FormatError));
+    }
+
+    #[test]
+    fn test_bytes_consumed() {
+        let opts = EncodeOptions { restart_interval: 2, ..EncodeOptions::default() };
+        let jpeg = encode(32, 16, &pattern(32, 16, 3), &opts);
+        // 容器中 JPEG 之后还有其它数据
+        let mut stream = jpeg.clone();
+        stream.extend_from_slice(&[0xFF, 0xD8, 0x00, 0x42, 0xFF, 0xD9, 0x17]);
+
+        let (mut decoder, mut mcu_buffer, mut work_buffer) = prepared(&stream);
+        assert_eq!(decoder.bytes_consumed(), 0);
+
+        decoder.decompress(&stream, 0, &mut mcu_buffer, &mut work_buffer, &mut |_, _, _| Ok(true)).unwrap();
+        assert_eq!(decoder.bytes_consumed(), jpeg.len());
+        assert_eq!(&stream[decoder.bytes_consumed() - 2..decoder.bytes_consumed()], [0xFF, 0xD9]);
+        assert_eq!(decoder.warnings(), [Warning::TrailingData]);
+
+        // 中止的解码停在扫描数据起点
+        let (start, end) = decoder.scan_range(&stream).unwrap();
+        let result = decoder.decompress(&stream, 0, &mut mcu_buffer, &mut work_buffer, &mut |_, _, _| Ok(false));
+        assert_eq!(result, Err(Error::Interrupted));
+        assert_eq!(decoder.bytes_consumed(), start);
+
+        // 再次完整解码得到相同结果；decompress_fast() 从扫描数据起算
+        decoder.decompress(&stream, 0, &mut mcu_buffer, &mut work_buffer, &mut |_, _, _| Ok(true)).unwrap();
+        assert_eq!(decoder.bytes_consumed(), jpeg.len());
+        decoder
+            .decompress_fast(&stream[start..], 0, &mut mcu_buffer, &mut work_buffer, &mut |_, _, _| Ok(true))
+            .unwrap();
+        assert_eq!(decoder.bytes_consumed(), end + 2 - start);
+    }
+
+    #[test]
+    fn test_decompress_fast() {
+        let opts = EncodeOptions { sampling: (2, 2), restart_interval: 3, ..EncodeOptions::default() };
+        let first = encode(32, 32, &pattern(32, 32, 3), &opts);
+        let second = encode(32, 32, &pattern(32, 32, 3).iter().map(|v| 255 - v).collect::<Vec<_>>(), &opts);
+
+        let (mut decoder, mut mcu_buffer, mut work_buffer) = prepared(&first);
+
+        // 两帧头部相同：只传扫描数据，结果与完整解码一致
+        for frame in [&first, &second] {
+            let (start, end) = decoder.scan_range(frame).unwrap();
+            let mut image = vec![0u8; 32 * 32 * 3];
+            decoder
+                .decompress_fast(&frame[start..end + 2], 0, &mut mcu_buffer, &mut work_buffer, &mut |_, bitmap, rect| {
+                    let w = rect.width() as usize * 3;
+                    for (row, line) in bitmap.chunks(w).enumerate() {
+                        let offset = ((rect.top as usize + row) * 32 + rect.left as usize) * 3;
+                        image[offset..offset + w].copy_from_slice(line);
+                    }
+                    Ok(true)
+                })
+                .unwrap();
+            assert_eq!(image, decode(frame, 0, 3).2);
+        }
+
+        assert_eq!(
+            decoder.decompress_fast(&[], 0, &mut mcu_buffer, &mut work_buffer, &mut |_, _, _| Ok(true)),
+            Err(Error::Input)
+        );
+    }
+
+    #[test]
+    fn test_decompress_scan() {
+        let opts = EncodeOptions { sampling: (2, 1), restart_interval: 2, ..EncodeOptions::default() };
+        let first = encode(40, 24, &pattern(40, 24, 3), &opts);
+        let second = encode(40, 24, &pattern(40, 24, 3).iter().map(|v| v / 2 + 60).collect::<Vec<_>>(), &opts);
+
+        let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+        let mut pool = MemoryPool::new(&mut pool_buffer);
+        let mut decoder = JpegDecoder::new();
+        let mut mcu_buffer = vec![0i16; 6 * 64];
+        let mut work_buffer = vec![0u8; 16 * 16 * 3];
+        assert_eq!(
+            decoder.decompress_scan(&first, 0, &mut mcu_buffer, &mut work_buffer, &mut |_, _, _| Ok(true)),
+            Err(Error::Parameter)
+        );
+
+        decoder.prepare(&first, &mut pool).unwrap();
+        let used = pool.used();
+
+        // 整帧传入，表沿用 prepare() 的结果，不再占用内存池
+        for frame in [&first, &second, &first] {
+            let mut image = vec![0u8; 40 * 24 * 3];
+            decoder
+                .decompress_scan(frame, 0, &mut mcu_buffer, &mut work_buffer, &mut |_, bitmap, rect| {
+                    let w = rect.width() as usize * 3;
+                    for (row, line) in bitmap.chunks(w).take(rect.height() as usize).enumerate() {
+                        let offset = ((rect.top as usize + row) * 40 + rect.left as usize) * 3;
+                        image[offset..offset + w].copy_from_slice(line);
+                    }
+                    Ok(true)
+                })
+                .unwrap();
+            assert_eq!(image, decode(frame, 0, 3).2);
+        }
+        assert_eq!(pool.used(), used);
+
+        let other_size = encode(24, 24, &pattern(24, 24, 3), &opts);
+        assert_eq!(
+            decoder.decompress_scan(&other_size, 0, &mut mcu_buffer, &mut work_buffer, &mut |_, _, _| Ok(true)),
+            Err(Error::FormatError)
+        );
+        assert_eq!(
+            decoder.decompress_scan(&first[..first.len() / 8], 0, &mut mcu_buffer, &mut work_buffer, &mut |_, _, _| Ok(true)),
+            Err(Error::Input)
+        );
+    }
+
+    #[test]
+    fn test_components_info() {
+        let mut decoder = JpegDecoder::new();
+        assert!(decoder.components_info().is_empty());
+
+        let opts = EncodeOptions { sampling: (2, 1), ..EncodeOptions::default() };
+        let jpeg = encode(16, 8, &pattern(16, 8, 3), &opts);
+        let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+        let mut pool = MemoryPool::new(&mut pool_buffer);
+        decoder.prepare(&jpeg, &mut pool).unwrap();
+
+        let info = |id, h, v, q| ComponentInfo { id, h_sampling: h, v_sampling: v, quant_table_id: q };
+        assert_eq!(decoder.components_info(), &[info(1, 2, 1, 0), info(2, 1, 1, 1), info(3, 1, 1, 1)]);
+
+        let gray = encode(8, 8, &pattern(8, 8, 1), &EncodeOptions { components: 1, ..EncodeOptions::default() });
+        let mut gray_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+        let mut pool = MemoryPool::new(&mut gray_buffer);
+        decoder.prepare(&gray, &mut pool).unwrap();
+        assert_eq!(decoder.components_info(), &[info(1, 1, 1, 0)]);
+    }
+
+    #[test]
+    fn test_edge_mcus_exact_work_buffer() {
+        const SENTINEL: u8 = 0xA5;
+
+        for &(w, h) in &[(1, 1), (7, 9), (17, 15), (23, 31), (33, 1)] {
+            for components in [1, 3] {
+                for sampling in [(1, 1), (2, 1), (2, 2)] {
+                    let opts = EncodeOptions { components, sampling, ..EncodeOptions::default() };
+                    let jpeg = encode(w, h, &pattern(w, h, components), &opts);
+
+                    for format in [OutputFormat::Rgb888, OutputFormat::Rgb666, OutputFormat::Mono1, OutputFormat::Gray4] {
+                        for scale in 0..4 {
+                            let (mut decoder, mut mcu_buffer, _) = prepared(&jpeg);
+                            decoder.set_output_format(format).unwrap();
+
+                            // 工作缓冲区恰好为最小尺寸，后面跟哨兵字节
+                            let size = decoder.work_buffer_size();
+                            let mut work = vec![SENTINEL; size + 64];
+                            let (work_buffer, guard) = work.split_at_mut(size);
+
+                            let (out_w, out_h) = (decoder.raw_width() >> scale, decoder.raw_height() >> scale);
+                            decoder
+                                .decompress(&jpeg, scale, &mut mcu_buffer, work_buffer, &mut |_, bitmap, rect| {
+                                    assert!(rect.right < out_w && rect.bottom < out_h);
+                                    assert_eq!(bitmap.len(), size);
+                                    Ok(true)
+                                })
+                                .unwrap();
+                            assert!(guard.iter().all(|&b| b == SENTINEL));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_scan_checksum() {
+        let jpeg = encode(24, 16, &pattern(24, 16, 3), &EncodeOptions::default());
+
+        // SOI 之后插入一个 APP0 注释，扫描数据不变
+        let mut tagged = jpeg[..2].to_vec();
+        tagged.extend_from_slice(&[0xFF, 0xE0, 0x00, 0x07, b'n', b'o', b't', b'e', 0]);
+        tagged.extend_from_slice(&jpeg[2..]);
+
+        let checksum = |data: &[u8]| {
+            let (decoder, _, _) = prepared(data);
+            let (start, end) = decoder.scan_range(data).unwrap();
+            (decoder.scan_checksum(data).unwrap(), crc32(&data[start..end]))
+        };
+
+        let (a, expected) = checksum(&jpeg);
+        assert_eq!(a, expected);
+        assert_eq!(checksum(&tagged).0, a);
+
+        let other = encode(24, 16, &pattern(24, 16, 3).iter().map(|v| v / 2).collect::<Vec<_>>(), &EncodeOptions::default());
+        assert_ne!(checksum(&other).0, a);
+    }
+
+    #[test]
+    fn test_reset_dc_predictors_matches_restart() {
+        let opts = EncodeOptions { restart_interval: 1, ..EncodeOptions::default() };
+        let jpeg = encode(16, 8, &pattern(16, 8, 3), &opts);
+
+        let (mut decoder, mut mcu_buffer, mut work_buffer) = prepared(&jpeg);
+
+        let mut expected = Vec::new();
+        decoder
+            .decompress(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut |_, bitmap, _| {
+                expected.push(bitmap[..8 * 8 * 3].to_vec());
+                Ok(true)
+            })
+            .unwrap();
+
+        let scan = decoder.find_scan_data(&jpeg).unwrap();
+        decoder.reset_dc_predictors();
+        let (dc_out, _, _) = decoder
+            .decode_single_mcu(scan, 0, 0, decoder.dc_predictors(), &mut mcu_buffer, &mut work_buffer)
+            .unwrap();
+        assert_eq!(decoder.dc_predictors(), dc_out);
+        assert_ne!(dc_out, [0; 4]);
+
+        // RST0 之后：手动复位预测值，结果与自动复位路径一致
+        let rst = scan.windows(2).position(|w| w == [0xFF, 0xD0]).unwrap();
+        decoder.reset_dc_predictors();
+        assert_eq!(decoder.dc_predictors(), [0; 4]);
+        decoder
+            .decode_single_mcu(&scan[rst + 2..], 0, 1, decoder.dc_predictors(), &mut mcu_buffer, &mut work_buffer)
+            .unwrap();
+        assert_eq!(&work_buffer[..8 * 8 * 3], &expected[1][..]);
+    }
+
+    #[test]
+    fn test_decompress_rle() {
+        for components in [1u8, 3] {
+            let opts = EncodeOptions { components, ..EncodeOptions::default() };
+            let jpeg = encode(24, 16, &pattern(24, 16, components), &opts);
+            let (width, height, expected) = decode(&jpeg, 0, components as usize);
+            let bpp = components as usize;
+
+            let (mut decoder, mut mcu_buffer, mut work_buffer) = prepared(&jpeg);
+
+            // 用 span 重建图像
+            let mut image = vec![0u8; width as usize * height as usize * bpp];
+            decoder
+                .decompress_rle(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut |_, color, x, y, count| {
+                    for i in 0..count as usize {
+                        let dst = (y as usize * width as usize + x as usize + i) * bpp;
+                        image[dst..dst + bpp].copy_from_slice(color);
+                    }
+                    Ok(true)
+                })
+                .unwrap();
+            assert_eq!(image, expected);
+        }
+
+        // 纯色图像：每个块的每行只有一个 span
+        let flat = encode(16, 8, &[200u8; 16 * 8 * 3], &EncodeOptions::default());
+        let (mut decoder, mut mcu_buffer, mut work_buffer) = prepared(&flat);
+        let mut spans = 0;
+        decoder
+            .decompress_rle(&flat, 0, &mut mcu_buffer, &mut work_buffer, &mut |_, _, _, _, count| {
+                assert_eq!(count, 8);
+                spans += 1;
+                Ok(true)
+            })
+            .unwrap();
+        assert_eq!(spans, 2 * 8);
+
+        decoder.set_output_format(OutputFormat::Mono1).unwrap();
+        assert_eq!(
+            decoder.decompress_rle(&flat, 0, &mut mcu_buffer, &mut work_buffer, &mut |_, _, _, _, _| Ok(true)),
+            Err(Error::Parameter)
+        );
+    }
+
+    #[test]
+    fn test_decompress_4x4_tiles() {
+        for &(w, h, sampling) in &[(16u16, 16u16, (2, 2)), (10, 6, (1, 1))] {
+            let opts = EncodeOptions { sampling, ..EncodeOptions::default() };
+            let jpeg = encode(w, h, &pattern(w, h, 3), &opts);
+            let (_, _, expected) = decode(&jpeg, 0, 3);
+
+            let (mut decoder, mut mcu_buffer, mut work_buffer) = prepared(&jpeg);
+
+            let mut tiles = Vec::new();
+            decoder
+                .decompress_4x4_tiles(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut |_, tile, x, y| {
+                    for i in 0..16 {
+                        // 边缘外的像素取最近的边缘像素
+                        let px = (x as usize + (i & 3)).min(w as usize - 1);
+                        let py = (y as usize + (i >> 2)).min(h as usize - 1);
+                        let src = (py * w as usize + px) * 3;
+                        assert_eq!(&tile[i * 3..i * 3 + 3], &expected[src..src + 3]);
+                    }
+                    tiles.push((x, y));
+                    Ok(true)
+                })
+                .unwrap();
+
+            if w == 16 {
+                // 单个 16x16 MCU：行优先的 4x4 顺序
+                let order: Vec<_> = (0..16).map(|i| ((i % 4) * 4, (i / 4) * 4)).collect();
+                assert_eq!(tiles, order);
+            } else {
+                // 两个 8x8 MCU，右侧 MCU 只有 2 列、底部只有 2 行
+                assert_eq!(tiles, [(0, 0), (4, 0), (0, 4), (4, 4), (8, 0), (8, 4)]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_find_soi_skips_embedded_thumbnail() {
+        let main = encode(32, 16, &pattern(32, 16, 3), &EncodeOptions::default());
+        let thumb = encode(8, 8, &pattern(8, 8, 3), &EncodeOptions::default());
+
+        // 前导垃圾（含一个假 SOI）+ 主图，主图 APP1 中嵌入缩略图
+        let mut data = vec![0x00, 0xFF, 0xD8, 0x12, 0x34];
+        let soi = data.len();
+        data.extend_from_slice(&main[..2]);
+        data.extend_from_slice(&[0xFF, 0xE1]);
+        data.extend_from_slice(&((2 + 6 + thumb.len()) as u16).to_be_bytes());
+        data.extend_from_slice(b"Exif\0\0");
+        data.extend_from_slice(&thumb);
+        data.extend_from_slice(&main[2..]);
+
+        assert_eq!(find_soi(&data, 0), Some(soi));
+        assert_eq!(find_soi(&data, soi + 1), Some(soi + 2 + 4 + 6));
+        assert_eq!(find_soi(&data[..soi + 20], 0), None);
+
+        let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+        let mut pool = MemoryPool::new(&mut pool_buffer);
+        let mut decoder = JpegDecoder::new();
+        assert_eq!(decoder.prepare_at(&data, 0, &mut pool), Ok(soi));
+        assert_eq!((decoder.width(), decoder.height()), (32, 16));
+
+        let mut mcu_buffer = vec![0i16; decoder.mcu_buffer_size()];
+        let mut work_buffer = vec![0u8; decoder.work_buffer_size()];
+        let mut image = vec![0u8; 32 * 16 * 3];
+        decoder
+            .decompress(&data, 0, &mut mcu_buffer, &mut work_buffer, &mut |_, bitmap, rect| {
+                let w = rect.width() as usize * 3;
+                for (row, line) in bitmap.chunks(w).take(rect.height() as usize).enumerate() {
+                    let offset = ((rect.top as usize + row) * 32 + rect.left as usize) * 3;
+                    image[offset..offset + w].copy_from_slice(line);
+                }
+                Ok(true)
+            })
+            .unwrap();
+        assert_eq!(image, decode(&main, 0, 3).2);
+    }
+
+    #[test]
+    fn test_decompress_mcu_rows() {
+        for &(w, h, sampling) in &[(40u16, 40u16, (2, 2)), (20, 13, (1, 1))] {
+            let opts = EncodeOptions { sampling, ..EncodeOptions::default() };
+            let jpeg = encode(w, h, &pattern(w, h, 3), &opts);
+            let (_, _, expected) = decode(&jpeg, 0, 3);
+
+            let (mut decoder, mut mcu_buffer, mut work_buffer) = prepared(&jpeg);
+            let mut row_buffer = vec![0u8; decoder.mcu_row_buffer_size(0)];
+            assert_eq!(row_buffer.len(), w as usize * sampling.1 as usize * 8 * 3);
+
+            // 按顺序拼接各行即得到完整图像
+            let mut image = Vec::new();
+            let mut rows = 0;
+            decoder
+                .decompress_mcu_rows(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut row_buffer, &mut |_, bitmap, rect| {
+                    assert_eq!((rect.left, rect.right), (0, w - 1));
+                    assert_eq!(rect.top as usize, image.len() / (w as usize * 3));
+                    assert_eq!(bitmap.len(), rect.height() as usize * w as usize * 3);
+                    image.extend_from_slice(bitmap);
+                    rows += 1;
+                    Ok(true)
+                })
+                .unwrap();
+            assert_eq!(rows, decoder.mcus_down());
+            assert_eq!(image, expected);
+
+            assert_eq!(
+                decoder.decompress_mcu_rows(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut row_buffer[..10], &mut |_, _, _| Ok(true)),
+                Err(Error::InsufficientMemory)
+            );
+        }
+    }
+
+    #[test]
+    fn test_is_jpeg() {
+        let jpeg = encode(8, 8, &pattern(8, 8, 3), &EncodeOptions::default());
+        assert!(is_jpeg(&jpeg));
+        assert!(is_jpeg(&[0xFF, 0xD8, 0xFF, 0xFF, 0xE0]));
+
+        assert!(!is_jpeg(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"));
+        assert!(!is_jpeg(&[0xFF]));
+        assert!(!is_jpeg(&[]));
+        assert!(!is_jpeg(&[0xFF, 0xD8, 0x00, 0x10]));
+        assert!(!is_jpeg(&[0xFF, 0xD8, 0xFF, 0x00]));
+        assert!(!is_jpeg(&[0xFF, 0xD8, 0xFF, 0xFF]));
+    }
+
+    #[test]
+    fn test_decompress_scanlines_alignment() {
+        let (w, h) = (13u16, 11u16);
+        let jpeg = encode(w, h, &pattern(w, h, 3), &EncodeOptions { sampling: (2, 2), ..EncodeOptions::default() });
+        let (_, _, expected) = decode(&jpeg, 0, 3);
+
+        let (mut decoder, mut mcu_buffer, mut work_buffer) = prepared(&jpeg);
+
+        for alignment in [1, 4] {
+            let row = w as usize * 3;
+            let padded = row.next_multiple_of(alignment);
+            let mut row_buffer = vec![0xEEu8; decoder.scanline_buffer_size(0, alignment)];
+            assert_eq!(row_buffer.len(), padded * 16);
+
+            let mut next_y = 0;
+            decoder
+                .decompress_scanlines(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut row_buffer, alignment, &mut |_, line, y| {
+                    assert_eq!(y, next_y);
+                    assert_eq!(line.len(), padded);
+                    assert_eq!(&line[..row], &expected[y as usize * row..(y as usize + 1) * row]);
+                    assert!(line[row..].iter().all(|&b| b == 0));
+                    next_y += 1;
+                    Ok(true)
+                })
+                .unwrap();
+            assert_eq!(next_y, h);
+        }
+
+        let mut row_buffer = vec![0u8; 1024];
+        assert_eq!(
+            decoder.decompress_scanlines(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut row_buffer, 0, &mut |_, _, _| Ok(true)),
+            Err(Error::Parameter)
+        );
+    }
+
+    #[test]
+    fn test_compression_ratio() {
+        let pixels = pattern(64, 64, 3);
+        let ratio = |quality| {
+            let jpeg = encode(64, 64, &pixels, &EncodeOptions { quality, ..EncodeOptions::default() });
+            let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+            let mut pool = MemoryPool::new(&mut pool_buffer);
+            let mut decoder = JpegDecoder::new();
+            assert_eq!(decoder.compression_ratio(&jpeg), None);
+            decoder.prepare(&jpeg, &mut pool).unwrap();
+            decoder.compression_ratio(&jpeg).unwrap()
+        };
+
+        let high = ratio(95);
+        let low = ratio(10);
+        assert!(low > 0.0 && low < high, "low {} high {}", low, high);
+        assert!(high < 3.0);
+    }
+
+    #[test]
+    fn test_decompress_to_buffer_returns_length() {
+        let mut reused = vec![0u8; 64 * 64 * 3];
+
+        for &(w, h, components) in &[(24u16, 16u16, 3u8), (10, 7, 1)] {
+            let opts = EncodeOptions { components, ..EncodeOptions::default() };
+            let jpeg = encode(w, h, &pattern(w, h, components), &opts);
+
+            let (mut decoder, mut mcu_buffer, mut work_buffer) = prepared(&jpeg);
+
+            // 复用过大的缓冲区：返回值标出有效部分
+            let stride = w as usize * components as usize;
+            let written = decoder
+                .decompress_to_buffer(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut reused, stride, 0, 0)
+                .unwrap();
+            assert_eq!(written, decoder.output_byte_size(0));
+            assert_eq!(&reused[..written], &decode(&jpeg, 0, components as usize).2[..]);
+        }
+
+        let jpeg = encode(13, 5, &pattern(13, 5, 3), &EncodeOptions::default());
+        let (mut decoder, _, _) = prepared(&jpeg);
+        assert_eq!(decoder.output_byte_size(1), 6 * 2 * 3);
+        decoder.set_output_format(OutputFormat::Mono1).unwrap();
+        assert_eq!(decoder.output_byte_size(0), 2 * 5);
+        decoder.set_output_format(OutputFormat::Gray4).unwrap();
+        assert_eq!(decoder.output_byte_size(0), 7 * 5);
+    }
+
+    #[test]
+    fn test_expected_dimensions() {
+        let jpeg = encode(24, 16, &pattern(24, 16, 3), &EncodeOptions::default());
+        let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+
+        let mut pool = MemoryPool::new(&mut pool_buffer);
+        let mut decoder = JpegDecoder::new();
+        decoder.set_expected_dimensions(Some((16, 24)));
+        assert_eq!(decoder.prepare(&jpeg, &mut pool), Err(Error::FormatError));
+
+        let mut pool = MemoryPool::new(&mut pool_buffer);
+        let mut decoder = JpegDecoder::new();
+        decoder.set_expected_dimensions(Some((24, 16)));
+        decoder.prepare(&jpeg, &mut pool).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (24, 16));
+    }
+
+    #[test]
+    fn test_bgr565_output() {
+        for components in [3u8, 1] {
+            let opts = EncodeOptions { components, ..EncodeOptions::default() };
+            let jpeg = encode(16, 8, &pattern(16, 8, components), &opts);
+            let (_, _, rgb) = decode(&jpeg, 0, components as usize);
+
+            let (mut decoder, mut mcu_buffer, _) = prepared(&jpeg);
+            decoder.set_output_format(OutputFormat::Bgr565).unwrap();
+
+            let mut work_buffer = vec![0u8; decoder.work_buffer_size()];
+            let mut out = vec![0u8; decoder.output_byte_size(0)];
+            assert_eq!(out.len(), 16 * 8 * 2);
+            decoder
+                .decompress_to_buffer(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut out, 16 * 2, 0, 0)
+                .unwrap();
+
+            for (i, px) in out.chunks_exact(2).enumerate() {
+                let c = components as usize;
+                let (r, g, b) = (rgb[i * c], rgb[i * c + (c - 1) / 2], rgb[i * c + c - 1]);
+                let value = u16::from_ne_bytes([px[0], px[1]]);
+                assert_eq!(value, color::rgb888_to_bgr565(r, g, b));
+                assert_eq!(value, color::rgb888_to_rgb565(b, g, r));
+            }
+        }
+    }
+
+    #[test]
+    fn test_rgba8888_output() {
+        for components in [3u8, 1] {
+            let opts = EncodeOptions { components, ..EncodeOptions::default() };
+            let jpeg = encode(24, 16, &pattern(24, 16, components), &opts);
+            let (_, _, rgb) = decode(&jpeg, 0, components as usize);
+
+            for format in [OutputFormat::Rgba8888, OutputFormat::Bgra8888] {
+                let (mut decoder, mut mcu_buffer, _) = prepared(&jpeg);
+                let mcu_pixels = decoder.sampling.mcu_width() as usize * 8 * decoder.sampling.mcu_height() as usize * 8;
+                decoder.set_output_format(format).unwrap();
+                assert_eq!(decoder.work_buffer_size(), mcu_pixels * 4);
+
+                let mut work_buffer = vec![0u8; decoder.work_buffer_size()];
+                let mut out = vec![0u8; decoder.output_byte_size(0)];
+                assert_eq!(out.len(), 24 * 16 * 4);
+                decoder
+                    .decompress_to_buffer(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut out, 24 * 4, 0, 0)
+                    .unwrap();
+
+                for (i, px) in out.chunks_exact(4).enumerate() {
+                    let c = components as usize;
+                    let (r, g, b) = (rgb[i * c], rgb[i * c + (c - 1) / 2], rgb[i * c + c - 1]);
+                    let expected = if format == OutputFormat::Bgra8888 { [b, g, r, 0xFF] } else { [r, g, b, 0xFF] };
+                    assert_eq!(px, expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_bytes_per_pixel() {
+        for components in [3u8, 1] {
+            let opts = EncodeOptions { components, ..EncodeOptions::default() };
+            let jpeg = encode(16, 16, &pattern(16, 16, components), &opts);
+            let (mut decoder, _, _) = prepared(&jpeg);
+
+            let gray = components == 1;
+            for (format, bpp, is_gray) in [
+                (OutputFormat::Rgb888, if gray { 1 } else { 3 }, gray),
+                (OutputFormat::Rgb666, if gray { 1 } else { 3 }, gray),
+                (OutputFormat::Grayscale, 1, true),
+                (OutputFormat::Rgb565, 2, false),
+                (OutputFormat::Bgra8888, 4, false),
+                (OutputFormat::Mono1, 0, true),
+                (OutputFormat::Gray4, 0, true),
+            ] {
+                decoder.set_output_format(format).unwrap();
+                assert_eq!(decoder.bytes_per_pixel(), bpp, "{:?}", format);
+                assert_eq!(decoder.is_grayscale(), is_gray, "{:?}", format);
+                if bpp > 0 {
+                    assert_eq!(decoder.output_byte_size(0), 16 * 16 * bpp);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_rgb565_output() {
+        for (components, sampling) in [(3u8, (2, 2)), (3, (1, 1)), (1, (1, 1))] {
+            let opts = EncodeOptions { components, sampling, ..EncodeOptions::default() };
+            let jpeg = encode(40, 24, &pattern(40, 24, components), &opts);
+            let (_, _, rgb) = decode(&jpeg, 0, components as usize);
+
+            for swap in [false, true] {
+                let (mut decoder, mut mcu_buffer, _) = prepared(&jpeg);
+                let rgb888_work = decoder.work_buffer_size();
+                decoder.set_output_format(OutputFormat::Rgb565).unwrap();
+                decoder.set_swap_rgb565(swap);
+
+                // 彩色图直接打包为 565，work_buffer 随之缩小
+                let work_size = decoder.work_buffer_size();
+                if components == 3 {
+                    assert_eq!(work_size * 3, rgb888_work * 2);
+                }
+
+                let mut work_buffer = vec![0u8; work_size];
+                let mut out = vec![0u8; decoder.output_byte_size(0)];
+                assert_eq!(out.len(), 40 * 24 * 2);
+                decoder
+                    .decompress_to_buffer(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut out, 40 * 2, 0, 0)
+                    .unwrap();
+
+                for (i, px) in out.chunks_exact(2).enumerate() {
+                    let c = components as usize;
+                    let expected = color::rgb888_to_rgb565(rgb[i * c], rgb[i * c + (c - 1) / 2], rgb[i * c + c - 1]);
+                    let expected = if swap { expected.swap_bytes() } else { expected };
+                    assert_eq!(px, expected.to_ne_bytes());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_planar_rgb_output() {
+        for (components, sampling) in [(3u8, (2, 2)), (3, (1, 1)), (1, (1, 1))] {
+            let opts = EncodeOptions { components, sampling, ..EncodeOptions::default() };
+            let jpeg = encode(40, 24, &pattern(40, 24, components), &opts);
+            for scale in [0u8, 1] {
+                let (width, _, rgb) = decode(&jpeg, scale, components as usize);
+
+                let (mut decoder, mut mcu_buffer, _) = prepared(&jpeg);
+                decoder.set_output_format(OutputFormat::PlanarRgb).unwrap();
+                assert_eq!(decoder.bytes_per_pixel(), 0);
+                assert!(!decoder.is_grayscale());
+                assert_eq!(decoder.output_byte_size(scale), (40 >> scale) * (24 >> scale) * 3);
+
+                let mut work_buffer = vec![0u8; decoder.work_buffer_size_for_scale(scale)];
+                let mut pixels = 0;
+                decoder
+                    .decompress(&jpeg, scale, &mut mcu_buffer, &mut work_buffer, &mut |_, bitmap, rect| {
+                        let (w, h) = (rect.width() as usize, rect.height() as usize);
+                        let plane = w * h;
+                        assert!(bitmap.len() >= plane * 3);
+                        // 每个像素的 R/G/B 分别位于三个平面的同一位置
+                        for y in 0..h {
+                            for x in 0..w {
+                                let src = ((rect.top as usize + y) * width as usize + rect.left as usize + x)
+                                    * components as usize;
+                                for c in 0..3 {
+                                    let expected = rgb[src + if components == 1 { 0 } else { c }];
+                                    assert_eq!(bitmap[c * plane + y * w + x], expected, "{:?} ({}, {})", rect, x, y);
+                                }
+                            }
+                        }
+                        pixels += plane;
+                        Ok(true)
+                    })
+                    .unwrap();
+                assert_eq!(pixels, (40 >> scale) * (24 >> scale));
+            }
+
+            // 需要交错像素的接口不接受平面格式
+            let (mut decoder, mut mcu_buffer, _) = prepared(&jpeg);
+            decoder.set_output_format(OutputFormat::PlanarRgb).unwrap();
+            let mut work_buffer = vec![0u8; decoder.work_buffer_size()];
+            let mut out = vec![0u8; decoder.output_byte_size(0)];
+            assert_eq!(
+                decoder.decompress_to_buffer(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut out, 40 * 3, 0, 0),
+                Err(Error::Parameter)
+            );
+            decoder.set_rotation(Rotation::Cw90);
+            let mut work_buffer = vec![0u8; decoder.work_buffer_size()];
+            assert_eq!(
+                decoder.decompress(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut |_, _, _| Ok(true)),
+                Err(Error::Parameter)
+            );
+        }
+    }
+
+    #[test]
+    fn test_pool_limit() {
+        let jpeg = encode(16, 16, &pattern(16, 16, 3), &EncodeOptions::default());
+
+        let used = {
+            let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+            let mut pool = MemoryPool::new(&mut pool_buffer);
+            JpegDecoder::new().prepare(&jpeg, &mut pool).unwrap();
+            pool.used()
+        };
+
+        // 池中空间充足，但超出上限时停止分配
+        let mut pool_buffer = vec![0u8; 4 * RECOMMENDED_POOL_SIZE];
+        let mut pool = MemoryPool::new(&mut pool_buffer);
+        let mut decoder = JpegDecoder::new();
+        decoder.set_pool_limit(Some(used / 2));
+        assert_eq!(decoder.prepare(&jpeg, &mut pool), Err(Error::InsufficientMemory));
+        assert!(pool.used() <= used / 2);
+        assert!(pool.remaining() > used);
+
+        let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+        let mut pool = MemoryPool::new(&mut pool_buffer);
+        let mut decoder = JpegDecoder::new();
+        decoder.set_pool_limit(Some(used));
+        decoder.prepare(&jpeg, &mut pool).unwrap();
+    }
+
+    #[test]
+    fn test_warnings() {
+        let jpeg = encode(16, 16, &pattern(16, 16, 3), &EncodeOptions { restart_interval: 1, ..EncodeOptions::default() });
+        let (_, _, expected) = decode(&jpeg, 0, 3);
+
+        let decode_warnings = |data: &[u8]| {
+            let (mut decoder, mut mcu_buffer, mut work_buffer) = prepared(data);
+            let mut out = vec![0u8; decoder.output_byte_size(0)];
+            decoder
+                .decompress_to_buffer(data, 0, &mut mcu_buffer, &mut work_buffer, &mut out, 16 * 3, 0, 0)
+                .unwrap();
+            (decoder.warnings().to_vec(), out)
+        };
+
+        assert_eq!(decode_warnings(&jpeg), (vec![], expected.clone()));
+
+        // EOI 之后的垃圾数据
+        let mut trailing = jpeg.clone();
+        trailing.extend_from_slice(b"garbage");
+        assert_eq!(decode_warnings(&trailing), (vec![Warning::TrailingData], expected.clone()));
+
+        // 未知 APP 段 + 乱序的 RST
+        let mut odd = jpeg[..2].to_vec();
+        odd.extend_from_slice(&[0xFF, 0xE7, 0x00, 0x05, b'x', b'y', b'z']);
+        odd.extend_from_slice(&jpeg[2..]);
+        let rst = odd.windows(2).position(|w| w == [0xFF, 0xD1]).unwrap();
+        odd[rst + 1] = 0xD5;
+        let (warnings, pixels) = decode_warnings(&odd);
+        assert_eq!(warnings, [Warning::UnknownAppSegment, Warning::RestartOutOfSequence]);
+        assert_eq!(pixels, expected);
+    }
+
+    #[test]
+    fn test_average_color() {
+        let average = |w: u16, h: u16, pixels: &[u8], opts: &EncodeOptions| {
+            let jpeg = encode(w, h, pixels, opts);
+            let (mut decoder, mut mcu_buffer, _) = prepared(&jpeg);
+            decoder.average_color(&jpeg, &mut mcu_buffer).unwrap()
+        };
+
+        for sampling in [(1, 1), (2, 1), (2, 2)] {
+            let opts = EncodeOptions { sampling, quality: 95, ..EncodeOptions::default() };
+            let solid: Vec<u8> = [200u8, 100, 50].repeat(21 * 13);
+            let avg = average(21, 13, &solid, &opts);
+            for (got, want) in avg.iter().zip([200u8, 100, 50]) {
+                assert!(got.abs_diff(want) <= 3, "{:?} for {:?}", avg, sampling);
+            }
+        }
+
+        let gray = EncodeOptions { components: 1, ..EncodeOptions::default() };
+        let avg = average(9, 9, &[77u8; 81], &gray);
+        assert!(avg[0].abs_diff(77) <= 1 && avg[0] == avg[1] && avg[1] == avg[2]);
+
+        // 与 1/8 缩放完整解码结果的平均值一致（尺寸按 MCU 对齐，边缘色度不含填充）
+        for sampling in [(1, 1), (2, 1), (2, 2)] {
+            let opts = EncodeOptions { sampling, ..EncodeOptions::default() };
+            let (w, h) = (48u16, 32u16);
+            let jpeg = encode(w, h, &pattern(w, h, 3), &opts);
+            let (_, _, pixels) = decode(&jpeg, 3, 3);
+            let n = (pixels.len() / 3) as u32;
+            let avg = average(w, h, &pattern(w, h, 3), &opts);
+            for c in 0..3 {
+                let mean = pixels.iter().skip(c).step_by(3).map(|&v| v as u32).sum::<u32>() / n;
+                assert!((avg[c] as u32).abs_diff(mean) <= 2, "{:?} vs {} for {:?}", avg, mean, sampling);
+            }
+        }
+    }
+
+    #[test]
+    fn test_chroma_siting() {
+        // 4:2:0，40x24 → 2.5 x 1.5 个 MCU；色条边界位于偶数列（色度样本对齐）
+        let bars = [[200u8, 40, 40], [40, 200, 40], [40, 40, 200], [200, 200, 40], [40, 200, 200]];
+        let (w, h) = (40u16, 24u16);
+        let mut pixels = Vec::new();
+        for _ in 0..h {
+            for x in 0..w as usize {
+                pixels.extend_from_slice(&bars[x / 8]);
+            }
+        }
+        let opts = EncodeOptions { sampling: (2, 2), quality: 100, ..EncodeOptions::default() };
+        let jpeg = encode(w, h, &pixels, &opts);
+
+        let decode_sited = |siting: ChromaSiting| {
+            let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+            let mut pool = MemoryPool::new(&mut pool_buffer);
+            let mut decoder = JpegDecoder::new();
+            decoder.set_chroma_siting(siting);
+            decoder.prepare(&jpeg, &mut pool).unwrap();
+            let mut mcu_buffer = vec![0i16; decoder.mcu_buffer_size()];
+            let mut work_buffer = vec![0u8; decoder.work_buffer_size()];
+            let mut fb = vec![0u8; w as usize * h as usize * 3];
+            decoder.decompress_to_buffer(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut fb, w as usize * 3, 0, 0).unwrap();
+            fb
+        };
+
+        let centered = decode_sited(ChromaSiting::Centered);
+        assert_eq!(centered, decode(&jpeg, 0, 3).2);
+        let cosited = decode_sited(ChromaSiting::Cosited);
+
+        // 按列比较与原图的最大误差
+        let column_error = |fb: &[u8], x: usize| {
+            (0..h as usize)
+                .map(|y| {
+                    let i = (y * w as usize + x) * 3;
+                    max_diff(&fb[i..i + 3], &pixels[i..i + 3])
+                })
+                .max()
+                .unwrap()
+        };
+        for edge in [8, 16, 24, 32] {
+            // 居中采样与编码端一致：边界左侧像素保持原色；共点采样会混入右侧颜色，
+            // 但在 MCU 边界处钳位到本 MCU 的最后一个色度样本
+            let left = edge - 1;
+            assert!(column_error(&centered, left) <= 12, "centered x={}", left);
+            if edge % 16 == 0 {
+                assert_eq!(column_error(&cosited, left), column_error(&centered, left));
+            } else {
+                assert!(column_error(&cosited, left) >= 40, "cosited x={}", left);
+            }
+            assert_eq!(column_error(&centered, edge), column_error(&cosited, edge));
+        }
+        // 远离边界的列两种方式一致
+        for x in [2, 4, 12, 20, 28, 36] {
+            assert_eq!(column_error(&centered, x), column_error(&cosited, x));
+        }
+    }
+
+    #[test]
+    fn test_color_matrix() {
+        let decode_with_matrix = |jpeg: &[u8], w: usize, h: usize, matrix: ColorMatrix| {
+            let (mut decoder, mut mcu_buffer, _) = prepared(jpeg);
+            decoder.set_color_matrix(matrix);
+            let mut work_buffer = vec![0u8; decoder.work_buffer_size()];
+            let mut fb = vec![0u8; w * h * 3];
+            decoder.decompress_to_buffer(jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut fb, w * 3, 0, 0).unwrap();
+            fb
+        };
+        let matrices = [ColorMatrix::Bt601, ColorMatrix::Bt709, ColorMatrix::Full601];
+
+        // 灰度渐变：Cb = Cr = 128，三种矩阵输出相同的灰色
+        let ramp: Vec<u8> = (0..64 * 8).flat_map(|i| [(i % 64 * 4) as u8; 3]).collect();
+        let jpeg = encode(64, 8, &ramp, &EncodeOptions { sampling: (2, 2), ..EncodeOptions::default() });
+        let default = decode(&jpeg, 0, 3).2;
+        assert!(default.chunks_exact(3).all(|px| px[0] == px[1] && px[1] == px[2]));
+        for matrix in matrices {
+            assert_eq!(decode_with_matrix(&jpeg, 64, 8, matrix), default, "{:?}", matrix);
+        }
+
+        // 彩色：默认矩阵不变，Full601 只差舍入，BT.709 的红色明显不同
+        let jpeg = encode(16, 16, &pattern(16, 16, 3), &EncodeOptions::default());
+        let [bt601, bt709, full601] = matrices.map(|matrix| decode_with_matrix(&jpeg, 16, 16, matrix));
+        assert_eq!(bt601, decode(&jpeg, 0, 3).2);
+        assert!(bt601.iter().zip(&full601).all(|(a, b)| a.abs_diff(*b) <= 2));
+        assert!(bt601.iter().zip(&bt709).any(|(a, b)| a.abs_diff(*b) > 8));
+
+        // R = Y + 1.5748 Cr, G = Y - 0.1873 Cb - 0.4681 Cr, B = Y + 1.8556 Cb
+        assert_eq!(color::ycbcr_to_rgb(128, -40, 60, ColorMatrix::Bt709), [222, 107, 54]);
+        assert_eq!(color::ycbcr_to_rgb(128, -40, 60, ColorMatrix::Full601), [212, 99, 57]);
+    }
+
+    #[test]
+    fn test_chroma_upsample() {
+        // 色度沿两个方向平滑变化的渐变，亮度基本不变
+        let (w, h) = (48u16, 32u16);
+        let mut pixels = Vec::new();
+        for y in 0..h as usize {
+            for x in 0..w as usize {
+                let r = (40 + x * 160 / 47) as u8;
+                let b = (200 - y * 160 / 31) as u8;
+                pixels.extend_from_slice(&[r, 120, b]);
+            }
+        }
+
+        let decode_upsampled = |jpeg: &[u8], upsample: ChromaUpsample| {
+            let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+            let mut pool = MemoryPool::new(&mut pool_buffer);
+            let mut decoder = JpegDecoder::new();
+            decoder.set_chroma_upsample(upsample);
+            decoder.prepare(jpeg, &mut pool).unwrap();
+            let mut mcu_buffer = vec![0i16; decoder.mcu_buffer_size()];
+            let mut work_buffer = vec![0u8; decoder.work_buffer_size()];
+            let mut fb = vec![0u8; w as usize * h as usize * 3];
+            decoder.decompress_to_buffer(jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut fb, w as usize * 3, 0, 0).unwrap();
+            fb
+        };
+        let total_error = |fb: &[u8]| fb.iter().zip(&pixels).map(|(&a, &b)| a.abs_diff(b) as u32).sum::<u32>();
+        // 相邻像素的色差跳变：最近邻在每两个像素处出现台阶
+        let roughness = |fb: &[u8]| {
+            let mut sum = 0u32;
+            for y in 0..h as usize {
+                for x in 1..w as usize {
+                    let i = (y * w as usize + x) * 3;
+                    sum += fb[i].abs_diff(fb[i - 3]).saturating_sub(3) as u32;
+                }
+            }
+            sum
+        };
+
+        for sampling in [(2, 2), (2, 1)] {
+            let jpeg = encode(w, h, &pixels, &EncodeOptions { sampling, quality: 100, ..EncodeOptions::default() });
+            let nearest = decode_upsampled(&jpeg, ChromaUpsample::Nearest);
+            assert_eq!(nearest, decode(&jpeg, 0, 3).2);
+
+            let bilinear = decode_upsampled(&jpeg, ChromaUpsample::Bilinear);
+            assert!(max_diff(&bilinear, &nearest) <= 8, "{:?}", sampling);
+            assert!(total_error(&bilinear) < total_error(&nearest), "{:?}", sampling);
+            assert!(roughness(&bilinear) < roughness(&nearest), "{:?}", sampling);
+        }
+
+        // 4:4:4 没有可插值的色度
+        let jpeg = encode(w, h, &pixels, &EncodeOptions::default());
+        assert_eq!(decode_upsampled(&jpeg, ChromaUpsample::Bilinear), decode(&jpeg, 0, 3).2);
+    }
+
+    #[test]
+    fn test_decompress_to_rgba_reuse() {
+        // 同一块 64x64 的 RGBA 缓冲区依次解码不同尺寸/格式的图像
+        let stride = 64 * 4;
+        let mut fb = vec![0u8; stride * 64];
+        let images = [
+            (encode(33, 17, &pattern(33, 17, 3), &EncodeOptions::default()), 0u8),
+            (encode(64, 64, &pattern(64, 64, 3), &EncodeOptions { sampling: (2, 2), ..EncodeOptions::default() }), 1),
+            (encode(21, 40, &pattern(21, 40, 1), &EncodeOptions { components: 1, ..EncodeOptions::default() }), 0),
+        ];
+
+        for (jpeg, scale) in &images {
+            let (mut decoder, mut mcu_buffer, mut work_buffer) = prepared(jpeg);
+
+            fb.fill(0);
+            let mut dims = (0, 0);
+            decoder
+                .decompress_to_rgba(jpeg, *scale, &mut mcu_buffer, &mut work_buffer, &mut fb, stride, &mut dims)
+                .unwrap();
+
+            let bpp = decoder.output_bpp();
+            let (w, h, expected) = decode(jpeg, *scale, bpp);
+            assert_eq!(dims, (w, h));
+            for y in 0..h as usize {
+                for x in 0..w as usize {
+                    let src = &expected[(y * w as usize + x) * bpp..][..bpp];
+                    let rgba = &fb[y * stride + x * 4..][..4];
+                    assert_eq!(&rgba[..3], &[src[0], src[(bpp - 1).min(1)], src[bpp - 1]]);
+                    assert_eq!(rgba[3], 0xFF);
+                }
+                // 图像右侧不写
+                assert!(fb[y * stride + w as usize * 4..(y + 1) * stride].iter().all(|&b| b == 0));
+            }
+        }
+
+        // 缓冲区放不下时报错且不写 out_dims
+        let jpeg = &images[1].0;
+        let (mut decoder, mut mcu_buffer, mut work_buffer) = prepared(jpeg);
+        let mut dims = (0, 0);
+        assert_eq!(
+            decoder.decompress_to_rgba(jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut fb, 32 * 4, &mut dims),
+            Err(Error::Parameter)
+        );
+        assert_eq!(
+            decoder.decompress_to_rgba(jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut fb[..stride * 32], stride, &mut dims),
+            Err(Error::InsufficientMemory)
+        );
+        assert_eq!(dims, (0, 0));
+    }
+
+    #[test]
+    fn test_decompress_without_prepare() {
+        let jpeg = encode(16, 16, &pattern(16, 16, 3), &EncodeOptions::default());
+        let mut decoder = JpegDecoder::new();
+        let mut mcu_buffer = vec![0i16; 6 * 64];
+        let mut work_buffer = vec![0u8; 16 * 16 * 3];
+        let result = decoder.decompress(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut |_, _, _| {
+            panic!("no output expected")
+        });
+        assert_eq!(result, Err(Error::Parameter));
+
+        // 其他入口共用同一检查，不会用空表解码
+        let result = decoder.decompress_resumable(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, None, None, &mut |_, _, _| {
+            panic!("no output expected")
+        });
+        assert_eq!(result, Err(Error::Parameter));
+        let result = decoder.decompress_fast(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut |_, _, _| {
+            panic!("no output expected")
+        });
+        assert_eq!(result, Err(Error::Parameter));
+        assert_eq!(decoder.average_color(&jpeg, &mut mcu_buffer), Err(Error::Parameter));
+        assert_eq!(
+            decoder.decompress_raw_ycbcr(&jpeg, &mut mcu_buffer, &mut |_, _, _, _| panic!("no output expected")),
+            Err(Error::Parameter)
+        );
+        let mut fb = vec![0u8; 16 * 16 * 3];
+        assert_eq!(
+            decoder.decompress_to_buffer(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut fb, 16 * 3, 0, 0),
+            Err(Error::Parameter)
+        );
+    }
+
+    #[test]
+    fn test_complexity_callback() {
+        // 左半平坦，右半为高频噪声
+        let (w, h) = (32u16, 16u16);
+        let mut pixels = Vec::new();
+        let mut seed = 0x1234_5678u32;
+        for _ in 0..h {
+            for x in 0..w {
+                if x < 16 {
+                    pixels.extend_from_slice(&[90, 120, 150]);
+                } else {
+                    for _ in 0..3 {
+                        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                        pixels.push((seed >> 24) as u8);
+                    }
+                }
+            }
+        }
+        let jpeg = encode(w, h, &pixels, &EncodeOptions::default());
+
+        let (mut decoder, mut mcu_buffer, mut work_buffer) = prepared(&jpeg);
+
+        let mut reports = Vec::new();
+        let mut outputs = 0;
+        decoder
+            .decompress_with_complexity(
+                &jpeg,
+                0,
+                &mut mcu_buffer,
+                &mut work_buffer,
+                &mut |_, _, _| {
+                    outputs += 1;
+                    Ok(true)
+                },
+                &mut |rect, nonzero_ac| reports.push((*rect, nonzero_ac)),
+            )
+            .unwrap();
+
+        assert_eq!(reports.len(), 8);
+        assert_eq!(outputs, 8);
+        for (rect, nonzero_ac) in reports {
+            assert_eq!((rect.width(), rect.height()), (8, 8));
+            if rect.left < 16 {
+                assert!(nonzero_ac <= 2, "flat MCU at {:?}: {}", rect, nonzero_ac);
+            } else {
+                assert!(nonzero_ac >= 60, "detailed MCU at {:?}: {}", rect, nonzero_ac);
+            }
+        }
+    }
 
-        for mcu_y in (0..self.height).step_by(mcu_pixel_height) {
-            for mcu_x in (0..self.width).step_by(mcu_pixel_width) {
-                if self.restart_interval > 0 && restart_counter >= self.restart_interval {
-                    bitstream.reset_for_restart();
-                    self.dc_values = [0; 3];
-                    restart_counter = 0;
-                    restart_marker = (restart_marker + 1) & 0x07;
-                }
+    #[test]
+    fn test_force_grayscale() {
+        for sampling in [(1, 1), (2, 1), (2, 2)] {
+            let opts = EncodeOptions { sampling, ..EncodeOptions::default() };
+            let jpeg = encode(40, 24, &pattern(40, 24, 3), &opts);
+            let (_, _, rgb) = decode(&jpeg, 0, 3);
 
-                self.decode_mcu(&mut bitstream, mcu_buffer, mcu_width, mcu_height)?;
+            let (mut decoder, mut mcu_buffer, _) = prepared(&jpeg);
+            let rgb_work = decoder.work_buffer_size();
+            decoder.force_grayscale(true);
+            assert_eq!(decoder.components(), 3);
+            assert_eq!(decoder.work_buffer_size() * 3, rgb_work);
 
-                if let Some(marker) = bitstream.get_marker() {
-                    if marker >= 0xD0 && marker <= 0xD7 {
-                        bitstream.reset_for_restart();
-                        self.dc_values = [0; 3];
-                        restart_marker = ((marker - 0xD0) + 1) & 0x07;
-                    }
+            let mut work_buffer = vec![0u8; decoder.work_buffer_size()];
+            let mut gray = vec![0u8; decoder.output_byte_size(0)];
+            assert_eq!(gray.len(), 40 * 24);
+            decoder
+                .decompress_to_buffer(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut gray, 40, 0, 0)
+                .unwrap();
+
+            // 亮度应与 RGB 结果换算出的亮度接近
+            for (luma, px) in gray.iter().zip(rgb.chunks_exact(3)) {
+                let (r, g, b) = (px[0] as i32, px[1] as i32, px[2] as i32);
+                let expected = (r * 299 + g * 587 + b * 114) / 1000;
+                assert!((*luma as i32 - expected).abs() <= 4, "{} vs {} ({:?})", luma, expected, sampling);
+            }
+
+            decoder.force_grayscale(false);
+            assert_eq!(decoder.work_buffer_size(), rgb_work);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_decode_dc_preview() {
+        // 平滑渐变，块平均值与预览像素应很接近
+        let gradient = |w: u16, h: u16| {
+            let mut px = Vec::new();
+            for y in 0..h as usize {
+                for x in 0..w as usize {
+                    px.extend_from_slice(&[(x * 4) as u8, (y * 5) as u8, 200 - (x + y) as u8]);
                 }
+            }
+            px
+        };
 
-                self.output_mcu(
-                    mcu_buffer,
-                    work_buffer,
-                    mcu_x,
-                    mcu_y,
-                    mcu_width,
-                    mcu_height,
-                    callback,
-                )?;
+        for (w, h, sampling) in [(40u16, 24u16, (1, 1)), (37, 29, (2, 1)), (50, 21, (2, 2))] {
+            let opts = EncodeOptions { sampling, quality: 95, ..EncodeOptions::default() };
+            let jpeg = encode(w, h, &gradient(w, h), &opts);
+            let (pw, ph, preview) = decode_dc_preview(&jpeg).unwrap();
+            assert_eq!((pw, ph), (w.div_ceil(8), h.div_ceil(8)));
+            assert_eq!(preview.len(), pw as usize * ph as usize * 3);
 
-                restart_counter += 1;
+            // 与全尺寸解码的 8x8 块平均值比较（色度按 MCU 共享，容差放宽）
+            let (_, _, full) = decode(&jpeg, 0, 3);
+            for by in 0..ph as usize {
+                for bx in 0..pw as usize {
+                    let mut sum = [0u32; 3];
+                    let mut n = 0;
+                    for y in by * 8..(by * 8 + 8).min(h as usize) {
+                        for x in bx * 8..(bx * 8 + 8).min(w as usize) {
+                            for c in 0..3 {
+                                sum[c] += full[(y * w as usize + x) * 3 + c] as u32;
+                            }
+                            n += 1;
+                        }
+                    }
+                    for c in 0..3 {
+                        let got = preview[(by * pw as usize + bx) * 3 + c];
+                        let avg = (sum[c] / n) as u8;
+                        assert!(got.abs_diff(avg) <= 24, "{:?} block ({}, {}) channel {}: {} vs {}", sampling, bx, by, c, got, avg);
+                    }
+                }
             }
         }
 
-        Ok(())
+        // 灰度图输出灰色三元组
+        let gray = EncodeOptions { components: 1, ..EncodeOptions::default() };
+        let (_, _, preview) = decode_dc_preview(&encode(16, 16, &[99u8; 256], &gray)).unwrap();
+        for px in preview.chunks_exact(3) {
+            assert!(px[0].abs_diff(99) <= 1 && px[0] == px[1] && px[1] == px[2]);
+        }
     }
 
-    /// Get required MCU buffer size
-    /// 
-    /// Returns the number of i16 elements needed for MCU buffer.
-    pub fn mcu_buffer_size(&self) -> usize {
-        let mcu_width = self.sampling.mcu_width() as usize;
-        let mcu_height = self.sampling.mcu_height() as usize;
-        (mcu_width * mcu_height + 2) * 64
+    #[test]
+    fn test_grayscale_2x2_sampling() {
+        // 单分量图像声明 2x2 采样时仍按 8x8 块光栅顺序编码
+        let (w, h) = (40u16, 24u16);
+        let pixels = pattern(w, h, 1);
+        let plain = encode(w, h, &pixels, &EncodeOptions { components: 1, ..EncodeOptions::default() });
+        let sampled = encode(w, h, &pixels, &EncodeOptions { components: 1, sampling: (2, 2), ..EncodeOptions::default() });
+        assert_ne!(plain, sampled);
+
+        let (decoder, _, _) = prepared(&sampled);
+        let info = decoder.components_info()[0];
+        assert_eq!((info.h_sampling, info.v_sampling), (2, 2));
+        assert_eq!(decoder.work_buffer_size(), 64);
+
+        let (_, _, expected) = decode(&plain, 0, 1);
+        let (dw, dh, gray) = decode(&sampled, 0, 1);
+        assert_eq!((dw, dh), (w, h));
+        assert_eq!(gray, expected);
+        assert!(max_diff(&gray, &pixels) <= 24);
     }
 
-    /// Get required work buffer size
-    /// 
-    /// Returns the number of u8 bytes needed for work buffer.
-    pub fn work_buffer_size(&self) -> usize {
-        let mcu_width = self.sampling.mcu_width() as usize;
-        let mcu_height = self.sampling.mcu_height() as usize;
-        mcu_width * 8 * mcu_height * 8 * 3
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_decode_frame() {
+        let (w, h) = (24u16, 16u16);
+        let color = encode(w, h, &pattern(w, h, 3), &EncodeOptions::default());
+        let (_, _, expected) = decode(&color, 1, 3);
+        let frame = decode_frame(&color, 1).unwrap();
+        assert_eq!((frame.width(), frame.height(), frame.format()), (w / 2, h / 2, OutputFormat::Rgb888));
+        assert_eq!(frame.as_bytes(), &expected[..]);
+        assert_eq!(frame.row(7).unwrap(), &expected[7 * 36..8 * 36]);
+        assert_eq!(frame.pixel(11, 7).unwrap(), &expected[7 * 36 + 33..]);
+        assert!(frame.row(8).is_none() && frame.pixel(12, 0).is_none());
+
+        let gray = encode(w, h, &pattern(w, h, 1), &EncodeOptions { components: 1, ..EncodeOptions::default() });
+        let frame = decode_frame(&gray, 0).unwrap();
+        assert_eq!((frame.format(), frame.bytes_per_pixel()), (OutputFormat::Grayscale, 1));
+        assert_eq!(frame.into_bytes(), decode(&gray, 0, 1).2);
+
+        assert_eq!(decode_frame(&color, 6), Err(Error::Parameter));
     }
 
-    fn find_scan_data<'b>(&self, data: &'b [u8]) -> Result<&'b [u8]> {
-        let i = self.sos_position;
-        
-        if i + 4 > data.len() {
-            return Err(Error::Input);
-        }
-        
-        if data[i] != 0xFF || data[i + 1] != markers::SOS {
-            return Err(Error::FormatError);
+    #[test]
+    fn test_insufficient_buffer() {
+        let jpeg = encode(32, 16, &pattern(32, 16, 3), &EncodeOptions { sampling: (2, 2), ..EncodeOptions::default() });
+        let (mut decoder, mut mcu_buffer, mut work_buffer) = prepared(&jpeg);
+
+        // MCU 缓冲区不足是 InsufficientMemory，work 缓冲区不足是 InsufficientBuffer
+        let mut noop = |_: &JpegDecoder, _: &[u8], _: &Rectangle| Ok(true);
+        let (mcu_size, work_size) = (mcu_buffer.len(), work_buffer.len());
+        assert_eq!(
+            decoder.decompress(&jpeg, 0, &mut mcu_buffer[..mcu_size - 1], &mut work_buffer, &mut noop),
+            Err(Error::InsufficientMemory)
+        );
+        assert_eq!(
+            decoder.decompress(&jpeg, 0, &mut mcu_buffer, &mut work_buffer[..work_size - 1], &mut noop),
+            Err(Error::InsufficientBuffer)
+        );
+        assert_eq!(
+            decoder.decompress_fast(&jpeg, 0, &mut mcu_buffer, &mut work_buffer[..work_size - 1], &mut noop),
+            Err(Error::InsufficientBuffer)
+        );
+        assert_eq!(decoder.decompress(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut noop), Ok(()));
+    }
+
+    #[test]
+    fn test_workspace_size_for() {
+        let level = crate::fastdecode_level();
+        const STATIC_POOL: usize = workspace_size_for(SamplingFactor::Yuv420, 2);
+        assert_eq!(STATIC_POOL, workspace_size_for(SamplingFactor::Yuv444, 2));
+        assert!(workspace_size_for(SamplingFactor::Yuv420, 0) <= 3100);
+        assert!(workspace_size_for(SamplingFactor::Yuv420, 1) <= 3500);
+        if level < 2 {
+            assert!(workspace_size_for(SamplingFactor::Yuv420, level) <= crate::MIN_WORKSPACE_SIZE);
         }
-        
-        let seg_len = u16::from_be_bytes([data[i + 2], data[i + 3]]) as usize;
-        let scan_start = i + 2 + seg_len;
-        
-        if scan_start < data.len() {
-            Ok(&data[scan_start..])
-        } else {
-            Err(Error::Input)
+
+        // 标准表（DC 12 个码字，AC 162 个）正好放得下，且不超过上限
+        for sampling in [(1, 1), (2, 1), (2, 2)] {
+            let jpeg = encode(32, 16, &pattern(32, 16, 3), &EncodeOptions { sampling, ..EncodeOptions::default() });
+            let size = workspace_size_for(SamplingFactor::Yuv420, level);
+            let mut pool_buffer = vec![0u8; size];
+            let mut pool = MemoryPool::new(&mut pool_buffer);
+            JpegDecoder::new().prepare(&jpeg, &mut pool).unwrap();
+            assert!(pool.alloc_stats().high_water_mark <= size);
         }
     }
 
-    fn decode_mcu(
-        &mut self,
-        bitstream: &mut BitStream,
-        buffer: &mut [i16],
-        mcu_width: usize,
-        mcu_height: usize,
-    ) -> Result<()> {
-        let num_y_blocks = mcu_width * mcu_height;
-        let mut tmp = [0i32; 64];
+    #[test]
+    fn test_reset_reuse() {
+        let first = encode(40, 24, &pattern(40, 24, 3), &EncodeOptions { sampling: (2, 2), ..EncodeOptions::default() });
+        let gray = EncodeOptions { components: 1, ..EncodeOptions::default() };
+        let second = encode(16, 32, &pattern(16, 32, 1), &gray);
 
-        // 解码Y blocks
-        for i in 0..num_y_blocks {
-            let block_slice = &mut buffer[i * 64..(i + 1) * 64];
-            let block: &mut [i16; 64] = block_slice.try_into().map_err(|_| Error::FormatError)?;
-            let qtable_id = self.qtable_ids[0];
-            
-            self.decode_and_dequantize_block(bitstream, &mut tmp, qtable_id, 0)?;
-            block_idct(&mut tmp, block);
+        let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+        let mut pool = MemoryPool::new(&mut pool_buffer);
+        let mut decoder = JpegDecoder::new();
+        for (jpeg, bpp) in [(&first, 3), (&second, 1), (&first, 3)] {
+            pool.reset();
+            decoder.reset();
+            decoder.prepare(jpeg, &mut pool).unwrap();
+
+            let (w, h, expected) = decode(jpeg, 0, bpp);
+            assert_eq!((decoder.width(), decoder.height()), (w, h));
+            let mut mcu_buffer = vec![0i16; decoder.mcu_buffer_size()];
+            let mut work_buffer = vec![0u8; decoder.work_buffer_size()];
+            let mut out = vec![0u8; decoder.output_byte_size(0)];
+            decoder
+                .decompress_to_buffer(jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut out, w as usize * bpp, 0, 0)
+                .unwrap();
+            assert_eq!(out, expected);
         }
 
-        if self.num_components == 3 {
-            // Cb block
-            let cb_offset = num_y_blocks * 64;
-            let cb_slice = &mut buffer[cb_offset..cb_offset + 64];
-            let cb_block: &mut [i16; 64] = cb_slice.try_into().map_err(|_| Error::FormatError)?;
-            self.decode_and_dequantize_block(bitstream, &mut tmp, self.qtable_ids[1], 1)?;
-            block_idct(&mut tmp, cb_block);
+        // reset 之后与新建的解码器一样，不能直接解码
+        decoder.reset();
+        assert_eq!((decoder.width(), decoder.height()), (0, 0));
+        let mut mcu_buffer = vec![0i16; 6 * 64];
+        let mut work_buffer = vec![0u8; 16 * 16 * 3];
+        let result = decoder.decompress(&first, 0, &mut mcu_buffer, &mut work_buffer, &mut |_, _, _| Ok(true));
+        assert_eq!(result, Err(Error::Parameter));
+    }
 
-            // Cr block
-            let cr_offset = cb_offset + 64;
-            let cr_slice = &mut buffer[cr_offset..cr_offset + 64];
-            let cr_block: &mut [i16; 64] = cr_slice.try_into().map_err(|_| Error::FormatError)?;
-            self.decode_and_dequantize_block(bitstream, &mut tmp, self.qtable_ids[2], 2)?;
-            block_idct(&mut tmp, cr_block);
+    #[test]
+    #[cfg(feature = "testing")]
+    fn test_decode_and_hash() {
+        let jpeg = encode(24, 16, &pattern(24, 16, 3), &EncodeOptions::default());
+        for scale in [0u8, 1, 4] {
+            let (mut decoder, _, _) = prepared(&jpeg);
+            let (pixels, _, _) = decoder.decode_to_vec(&jpeg, scale).unwrap();
+            assert_eq!(decode_and_hash(&jpeg, scale), Ok(crc32(&pixels)));
         }
-
-        Ok(())
+        assert_ne!(decode_and_hash(&jpeg, 0), decode_and_hash(&jpeg, 1));
+        assert_eq!(decode_and_hash(&jpeg, 6), Err(Error::Parameter));
+        assert!(decode_and_hash(&jpeg[..10], 0).is_err());
     }
 
-    fn decode_and_dequantize_block(
-        &mut self,
-        bitstream: &mut BitStream,
-        tmp: &mut [i32; 64],
-        qtable_id: u8,
-        component: usize,
-    ) -> Result<()> {
-        use crate::tables::ZIGZAG;
-        
-        let qtable = unsafe {
-            let ptr = self.qtables[qtable_id as usize];
-            if ptr.is_null() {
-                return Err(Error::FormatError);
-            }
-            &*ptr
-        };
-        
-        let table_id = if component == 0 { 0 } else { 1 };
+    #[test]
+    fn test_max_output_bytes() {
+        // 4:4:4 图像每个 MCU 输出 8x8x3 = 192 字节
+        let (w, h) = (32u16, 16u16);
+        let jpeg = encode(w, h, &pattern(w, h, 3), &EncodeOptions::default());
+        let (mut decoder, mut mcu_buffer, mut work_buffer) = prepared(&jpeg);
 
-        let dc_table = unsafe {
-            let ptr = self.huff_dc[table_id];
-            if ptr.is_null() {
-                return Err(Error::FormatError);
-            }
-            &*ptr
+        let mut run = |decoder: &mut JpegDecoder| {
+            let mut rects = Vec::new();
+            let result = decoder.decompress(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut |_, _, rect| {
+                rects.push(*rect);
+                Ok(true)
+            });
+            (result, rects)
         };
-        
-        let dc_len = dc_table.decode(bitstream)? as usize;
-        
-        let dc_diff = if dc_len > 0 {
-            let bits = bitstream.read_bits(dc_len)?;
-            Self::extend(bits, dc_len) as i32
-        } else {
-            0
+
+        decoder.set_max_output_bytes(Some(192 * 3 + 100));
+        let (result, rects) = run(&mut decoder);
+        assert_eq!(result, Err(Error::InsufficientMemory));
+        assert_eq!(rects.len(), 3);
+        assert_eq!(rects[2], Rectangle::new(16, 23, 0, 7));
+
+        // 上限恰好等于整幅图像
+        decoder.set_max_output_bytes(Some(decoder.output_byte_size(0)));
+        let (result, rects) = run(&mut decoder);
+        assert_eq!((result, rects.len()), (Ok(()), 8));
+
+        decoder.set_max_output_bytes(None);
+        assert_eq!(run(&mut decoder).0, Ok(()));
+    }
+
+    #[test]
+    fn test_exif_orientation() {
+        let main = encode(16, 8, &pattern(16, 8, 3), &EncodeOptions::default());
+        // 大端 TIFF，IFD0 只有 Orientation = 6
+        let with_exif = |tiff: &[u8]| {
+            let mut data = main[..2].to_vec();
+            data.extend_from_slice(&[0xFF, 0xE1]);
+            data.extend_from_slice(&((2 + 6 + tiff.len()) as u16).to_be_bytes());
+            data.extend_from_slice(b"Exif\0\0");
+            data.extend_from_slice(tiff);
+            data.extend_from_slice(&main[2..]);
+            data
         };
+        let tiff = [b'M', b'M', 0, 42, 0, 0, 0, 8, 0, 1, 0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, 6, 0, 0, 0, 0, 0, 0];
 
-        self.dc_values[component] = self.dc_values[component].wrapping_add(dc_diff as i16);
-        let dc = self.dc_values[component] as i32;
-        
-        tmp[0] = (dc * qtable[0]) >> 8;
-        tmp[1..].fill(0);
+        let orientation = |data: &[u8]| prepared(data).0.orientation();
+        assert_eq!(orientation(&with_exif(&tiff)), 6);
+        assert_eq!(orientation(&main), 1);
+        // IFD 偏移越界时忽略
+        let mut corrupt = tiff;
+        corrupt[4] = 0x7F;
+        assert_eq!(orientation(&with_exif(&corrupt)), 1);
 
-        let ac_table = unsafe {
-            let ptr = self.huff_ac[table_id];
-            if ptr.is_null() {
-                return Err(Error::FormatError);
-            }
-            &*ptr
+        let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+        let mut pool = MemoryPool::new(&mut pool_buffer);
+        let mut buffer = [0u8; BUFFER_SIZE];
+        let mut decoder = JpegDecoder::new();
+        decoder.prepare_reader(&mut &with_exif(&tiff)[..], &mut buffer, &mut pool).unwrap();
+        assert_eq!(decoder.orientation(), 6);
+    }
+
+    #[test]
+    fn test_jfif_density() {
+        // 测试编码器写入单位 0、密度 1:1 的 JFIF APP0
+        let main = encode(16, 8, &pattern(16, 8, 3), &EncodeOptions::default());
+        let app0 = main.windows(2).position(|w| w == [0xFF, 0xE0]).unwrap();
+        let with_app0 = |body: &[u8]| {
+            let mut data = main[..app0].to_vec();
+            data.extend_from_slice(&[0xFF, 0xE0]);
+            data.extend_from_slice(&((2 + body.len()) as u16).to_be_bytes());
+            data.extend_from_slice(body);
+            data.extend_from_slice(&main[app0 + 2 + 16..]);
+            data
         };
-        
-        let mut z = 1;
 
-        loop {
-            let symbol = ac_table.decode(bitstream)?;
-            
-            if symbol == 0 {
-                break;
+        let density = |data: &[u8]| prepared(data).0.density();
+        assert_eq!(density(&main), Some((DensityUnit::AspectRatio, 1, 1)));
+
+        // 300 x 150 dpi
+        let dpi = *b"JFIF\0\x01\x02\x01\x01\x2C\x00\x96\x00\x00";
+        assert_eq!(density(&with_app0(&dpi)), Some((DensityUnit::PerInch, 300, 150)));
+        let mut per_cm = dpi;
+        per_cm[7] = 2;
+        assert_eq!(density(&with_app0(&per_cm)), Some((DensityUnit::PerCm, 300, 150)));
+
+        // 标识不符、单位非法、密度为 0 或段太短时为 None
+        for (offset, value) in [(1, b'X'), (4, 1), (7, 3), (10, 0)] {
+            let mut bad = dpi;
+            bad[offset..offset + 2].copy_from_slice(&[value, 0]);
+            assert_eq!(density(&with_app0(&bad)), None, "{:?}", bad);
+        }
+        assert_eq!(density(&with_app0(&dpi[..12])), None);
+
+        // 流式解析同样读取
+        let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+        let mut pool = MemoryPool::new(&mut pool_buffer);
+        let mut buffer = [0u8; BUFFER_SIZE];
+        let mut decoder = JpegDecoder::new();
+        decoder.prepare_reader(&mut &with_app0(&dpi)[..], &mut buffer, &mut pool).unwrap();
+        assert_eq!(decoder.density(), Some((DensityUnit::PerInch, 300, 150)));
+    }
+
+    #[test]
+    fn test_rotation() {
+        // 4:2:0，右侧 MCU 只有 8 像素宽
+        let (w, h) = (24usize, 16usize);
+        let jpeg = encode(w as u16, h as u16, &pattern(w as u16, h as u16, 3), &EncodeOptions { sampling: (2, 2), ..EncodeOptions::default() });
+        let (_, _, plain) = decode(&jpeg, 0, 3);
+        let px = |x: usize, y: usize| &plain[(y * w + x) * 3..(y * w + x + 1) * 3];
+
+        let (mut decoder, mut mcu_buffer, _) = prepared(&jpeg);
+
+        for rotation in [Rotation::Cw90, Rotation::Cw180, Rotation::Cw270] {
+            decoder.set_rotation(rotation);
+            let (rw, rh) = if rotation.swaps_axes() { (h, w) } else { (w, h) };
+            let mut work_buffer = vec![0u8; decoder.work_buffer_size()];
+            let mut fb = vec![0u8; w * h * 3];
+            decoder.decompress_to_buffer(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut fb, rw * 3, 0, 0).unwrap();
+
+            // 旋转后 (x, y) 对应原图的位置
+            let src = |x: usize, y: usize| match rotation {
+                Rotation::Cw90 => (y, h - 1 - x),
+                Rotation::Cw180 => (w - 1 - x, h - 1 - y),
+                _ => (w - 1 - y, x),
+            };
+            for y in 0..rh {
+                for x in 0..rw {
+                    let (sx, sy) = src(x, y);
+                    assert_eq!(&fb[(y * rw + x) * 3..(y * rw + x + 1) * 3], px(sx, sy), "{rotation:?} ({x}, {y})");
+                }
             }
+            // 原图左上角
+            let top_left = match rotation {
+                Rotation::Cw90 => (rw - 1, 0),
+                Rotation::Cw180 => (rw - 1, rh - 1),
+                _ => (0, rh - 1),
+            };
+            assert_eq!(&fb[(top_left.1 * rw + top_left.0) * 3..][..3], px(0, 0));
+        }
 
-            let zero_run = (symbol >> 4) as usize;
-            let ac_len = (symbol & 0x0F) as usize;
+        decoder.set_output_format(OutputFormat::Mono1).unwrap();
+        let mut work_buffer = vec![0u8; decoder.work_buffer_size()];
+        assert_eq!(
+            decoder.decompress(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut |_, _, _| Ok(true)),
+            Err(Error::Parameter)
+        );
+        assert_eq!(Rotation::from_orientation(6), Rotation::Cw90);
+        assert_eq!(Rotation::from_orientation(2), Rotation::None);
+    }
 
-            z += zero_run;
-            
-            if z >= 64 {
-                return Err(Error::FormatError);
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_rotation_row_helpers() {
+        let (w, h) = (40usize, 16usize);
+        let jpeg = encode(w as u16, h as u16, &pattern(w as u16, h as u16, 3), &EncodeOptions { sampling: (2, 2), ..EncodeOptions::default() });
+
+        let (mut decoder, mut mcu_buffer, _) = prepared(&jpeg);
+
+        for rotation in [Rotation::Cw90, Rotation::Cw270] {
+            decoder.set_rotation(rotation);
+            assert_eq!((decoder.width(), decoder.height()), (h as u16, w as u16));
+            let (rotated, rw, rh) = decoder.decode_to_vec(&jpeg, 0).unwrap();
+            assert_eq!((rw as usize, rh as usize), (h, w));
+
+            // 平面按旋转后的尺寸写入
+            let mut work_buffer = vec![0u8; decoder.work_buffer_size()];
+            let mut planes = [vec![0u8; h * w], vec![0u8; h * w], vec![0u8; h * w]];
+            let [r, g, b] = &mut planes;
+            decoder.decompress_to_planes(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, r, g, b, h).unwrap();
+            for (i, px) in rotated.chunks_exact(3).enumerate() {
+                assert_eq!([planes[0][i], planes[1][i], planes[2][i]], px, "{rotation:?} pixel {i}");
             }
+            let [r, g, b] = &mut planes;
+            assert_eq!(
+                decoder.decompress_to_planes(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, r, g, b, w),
+                Err(Error::InsufficientMemory)
+            );
+
+            // 旋转后的 MCU 不按行到达，整行接口拒绝
+            let mut row_buffer = vec![0u8; decoder.scanline_buffer_size(0, 4).max(w * h * 3)];
+            assert_eq!(
+                decoder.decompress_mcu_rows(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut row_buffer, &mut |_, _, _| Ok(true)),
+                Err(Error::Parameter)
+            );
+            assert_eq!(
+                decoder.decompress_scanlines(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut row_buffer, 4, &mut |_, _, _| Ok(true)),
+                Err(Error::Parameter)
+            );
+        }
+    }
+
+    #[test]
+    fn test_header_info() {
+        let opts = EncodeOptions { sampling: (2, 1), restart_interval: 3, ..EncodeOptions::default() };
+        let jpeg = encode(32, 16, &pattern(32, 16, 3), &opts);
+        let sof = jpeg.windows(2).position(|w| w == [0xFF, 0xC0]).unwrap();
+        let mut progressive = jpeg.clone();
+        progressive[sof + 1] = 0xC2;
+
+        let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+        let mut pool = MemoryPool::new(&mut pool_buffer);
+        let mut decoder = JpegDecoder::new();
+        assert_eq!(decoder.prepare(&progressive, &mut pool), Err(Error::ProgressiveUnsupported));
+        assert!(decoder.is_progressive());
 
-            if ac_len > 0 {
-                let bits = bitstream.read_bits(ac_len)?;
-                let ac_value = Self::extend(bits, ac_len) as i32;
-                let i = ZIGZAG[z] as usize;
-                tmp[i] = (ac_value * qtable[i]) >> 8;
+        let mut pool = MemoryPool::new(&mut pool_buffer);
+        let mut decoder = JpegDecoder::new();
+        decoder.prepare(&jpeg, &mut pool).unwrap();
+        assert_eq!(decoder.sampling_factor(), SamplingFactor::Yuv422);
+        assert_eq!(decoder.restart_interval(), 3);
+        assert!(!decoder.is_progressive());
+
+        // 重新 prepare 不带 DRI 的图像时清除重启间隔
+        let plain = encode(8, 8, &pattern(8, 8, 3), &EncodeOptions::default());
+        let mut pool = MemoryPool::new(&mut pool_buffer);
+        let mut decoder = JpegDecoder::new();
+        decoder.prepare(&jpeg, &mut pool).unwrap();
+        pool.reset();
+        decoder.prepare(&plain, &mut pool).unwrap();
+        assert_eq!((decoder.sampling_factor(), decoder.restart_interval()), (SamplingFactor::Yuv444, 0));
+    }
+
+    #[test]
+    fn test_progressive_error() {
+        let jpeg = encode(16, 8, &pattern(16, 8, 3), &EncodeOptions::default());
+        let sof = jpeg.windows(2).position(|w| w == [0xFF, 0xC0]).unwrap();
+        let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+        for (marker, expected) in [
+            (0xC2, Error::ProgressiveUnsupported),
+            (0xC1, Error::UnsupportedStandard),
+            (0xC3, Error::UnsupportedStandard),
+        ] {
+            let mut data = jpeg.clone();
+            data[sof + 1] = marker;
+            let mut pool = MemoryPool::new(&mut pool_buffer);
+            let mut decoder = JpegDecoder::new();
+            assert_eq!(decoder.prepare(&data, &mut pool), Err(expected));
+        }
+        assert!(Error::ProgressiveUnsupported.as_str().contains("baseline"));
+    }
+
+    #[test]
+    fn test_decompress_region() {
+        // 4:2:0，64x48 = 4x3 个 16x16 的 MCU
+        let (w, h) = (64u16, 48u16);
+        let pixels = pattern(w, h, 3);
+        for restart_interval in [0u16, 1] {
+            let opts = EncodeOptions { sampling: (2, 2), restart_interval, ..EncodeOptions::default() };
+            let mut jpeg = encode(w, h, &pixels, &opts);
+            if restart_interval > 0 {
+                // 破坏第一个重启间隔（MCU 0，区域外），跳过时不应读取它
+                let sos = jpeg.windows(2).position(|m| m == [0xFF, markers::SOS]).unwrap();
+                let start = sos + 2 + u16::from_be_bytes([jpeg[sos + 2], jpeg[sos + 3]]) as usize;
+                let rst0 = start + jpeg[start..].windows(2).position(|m| m == [0xFF, 0xD0]).unwrap();
+                jpeg[start..rst0].fill(0x55);
             }
 
-            z += 1;
-            
-            if z >= 64 {
-                break;
+            for (scale, region) in [(0u8, Rectangle::new(20, 40, 18, 30)), (1, Rectangle::new(5, 20, 9, 15))] {
+                let sw = (w >> scale) as usize;
+                let (_, _, full) = decode(&encode(w, h, &pixels, &opts), scale, 3);
+
+                let (mut decoder, mut mcu_buffer, mut work_buffer) = prepared(&jpeg);
+
+                let mut covered = 0;
+                decoder
+                    .decompress_region(&jpeg, scale, region, &mut mcu_buffer, &mut work_buffer, &mut |_, bitmap, rect| {
+                        assert!(rect.left >= region.left && rect.right <= region.right);
+                        assert!(rect.top >= region.top && rect.bottom <= region.bottom);
+                        let row = rect.width() as usize * 3;
+                        for y in 0..rect.height() as usize {
+                            let src = ((rect.top as usize + y) * sw + rect.left as usize) * 3;
+                            assert_eq!(&bitmap[y * row..(y + 1) * row], &full[src..src + row]);
+                        }
+                        covered += rect.width() as usize * rect.height() as usize;
+                        Ok(true)
+                    })
+                    .unwrap();
+                assert_eq!(covered, region.width() as usize * region.height() as usize);
             }
         }
-        
-        Ok(())
     }
 
-    fn extend(v: u16, t: usize) -> i16 {
-        let vt = 1 << (t - 1);
-        if (v as i16) < vt {
-            v as i16 + ((-1i16) << t) + 1
-        } else {
-            v as i16
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_decode_to_vec() {
+        let (w, h) = (40u16, 24u16);
+        for components in [3u8, 1] {
+            let jpeg = encode(w, h, &pattern(w, h, components), &EncodeOptions { components, ..EncodeOptions::default() });
+            let (mut decoder, _, _) = prepared(&jpeg);
+            let (pixels, dw, dh) = decoder.decode_to_vec(&jpeg, 1).unwrap();
+            assert_eq!((dw, dh), (w / 2, h / 2));
+            // 自带内存池的便捷函数结果相同
+            assert_eq!(decode_to_vec(&jpeg, 1).unwrap(), (pixels.clone(), dw, dh));
+
+            let (_, _, expected) = decode(&jpeg, 1, components as usize);
+            if components == 3 {
+                assert_eq!(pixels, expected);
+            } else {
+                assert!(pixels.chunks_exact(3).zip(&expected).all(|(px, &v)| px == [v, v, v]));
+            }
         }
+        assert_eq!(JpegDecoder::new().decode_to_vec(&[], 0), Err(Error::Parameter));
+        assert!(decode_to_vec(&[], 0).is_err());
     }
 
-    fn output_mcu(
-        &self,
-        mcu_buffer: &[i16],
-        work_buffer: &mut [u8],
-        x: u16,
-        y: u16,
-        mcu_width: usize,
-        mcu_height: usize,
-        callback: OutputCallback,
-    ) -> Result<()> {
-        let mcu_pixel_width = (mcu_width * 8) as u16;
-        let mcu_pixel_height = (mcu_height * 8) as u16;
+    #[test]
+    fn test_decompress_into() {
+        let (w, h) = (40u16, 24u16);
+        let jpeg = encode(w, h, &pattern(w, h, 3), &EncodeOptions { sampling: (2, 2), ..EncodeOptions::default() });
+        let (_, _, expected) = decode(&jpeg, 0, 3);
 
-        let out_width = mcu_pixel_width.min(self.width - x);
-        let out_height = mcu_pixel_height.min(self.height - y);
+        let (mut decoder, mut mcu_buffer, mut work_buffer) = prepared(&jpeg);
 
-        let scaled_width = out_width >> self.scale;
-        let scaled_height = out_height >> self.scale;
+        // 行间距大于图像宽度，多余的字节保持不变
+        let stride = w as usize * 3 + 7;
+        let mut dest = vec![0xAAu8; stride * h as usize];
+        let mut rects = 0;
+        decoder
+            .decompress_into(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut dest, stride, &mut |_, _| {
+                rects += 1;
+                Ok(true)
+            })
+            .unwrap();
+        assert_eq!(rects, 6);
+        for (row, line) in dest.chunks_exact(stride).enumerate() {
+            assert_eq!(&line[..w as usize * 3], &expected[row * w as usize * 3..(row + 1) * w as usize * 3]);
+            assert!(line[w as usize * 3..].iter().all(|&b| b == 0xAA));
+        }
 
-        if scaled_width == 0 || scaled_height == 0 {
-            return Ok(());
+        let result = decoder.decompress_into(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut dest, stride, &mut |_, _| Ok(false));
+        assert_eq!(result, Err(Error::Interrupted));
+        let result = decoder.decompress_into(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut dest, 10, &mut |_, _| Ok(true));
+        assert_eq!(result, Err(Error::Parameter));
+    }
+
+    #[test]
+    fn test_cmyk() {
+        // 三个纯色 8x8 块：青、50% 灰、品红（Adobe 反相存储，255 为无墨）
+        let colors: [([u8; 4], [u8; 3]); 3] = [
+            ([0, 255, 255, 255], [0, 255, 255]),
+            ([255, 255, 255, 128], [128, 128, 128]),
+            ([255, 0, 255, 255], [255, 0, 255]),
+        ];
+        let (w, h) = (24u16, 8u16);
+        let mut inverted = Vec::new();
+        let mut expected = Vec::new();
+        for _ in 0..h {
+            for x in 0..w as usize {
+                inverted.extend_from_slice(&colors[x / 8].0);
+                expected.extend_from_slice(&colors[x / 8].1);
+            }
         }
+        let plain: Vec<u8> = inverted.iter().map(|&v| 255 - v).collect();
 
-        let rect = Rectangle::new(
-            x >> self.scale,
-            (x >> self.scale) + scaled_width - 1,
-            y >> self.scale,
-            (y >> self.scale) + scaled_height - 1,
-        );
+        let cmyk = EncodeOptions { components: 4, quality: 100, ..EncodeOptions::default() };
+        let cases = [
+            encode(w, h, &inverted, &EncodeOptions { adobe_transform: Some(0), ..cmyk.clone() }),
+            encode(w, h, &inverted, &EncodeOptions { adobe_transform: Some(2), ..cmyk.clone() }),
+            // 没有 APP14 段时按未反相处理
+            encode(w, h, &plain, &cmyk),
+        ];
 
-        if self.num_components == 3 {
-            let num_y_blocks = mcu_width * mcu_height;
-            let y_data = &mcu_buffer[0..num_y_blocks * 64];
-            let cb_data = &mcu_buffer[num_y_blocks * 64..(num_y_blocks + 1) * 64];
-            let cr_data = &mcu_buffer[(num_y_blocks + 1) * 64..(num_y_blocks + 2) * 64];
+        let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+        if !cfg!(feature = "cmyk") {
+            let mut pool = MemoryPool::new(&mut pool_buffer);
+            assert_eq!(JpegDecoder::new().prepare(&cases[0], &mut pool), Err(Error::UnsupportedStandard));
+            return;
+        }
 
-            color::mcu_to_rgb(
-                y_data,
-                cb_data,
-                cr_data,
-                work_buffer,
-                mcu_width,
-                mcu_height,
-                self.sampling.mcu_width() as usize,
-                self.sampling.mcu_height() as usize,
-            );
-        } else {
-            color::mcu_to_grayscale(mcu_buffer, work_buffer, mcu_width, mcu_height);
+        for jpeg in &cases {
+            let (_, _, rgb) = decode(jpeg, 0, 3);
+            assert!(max_diff(&rgb, &expected) <= 2, "{}", max_diff(&rgb, &expected));
+
+            let mut pool = MemoryPool::new(&mut pool_buffer);
+            let mut decoder = JpegDecoder::new();
+            decoder.prepare(jpeg, &mut pool).unwrap();
+            assert_eq!(decoder.components(), 4);
+            assert_eq!(decoder.components_info().len(), 4);
+            assert_eq!(decoder.mcu_buffer_size(), 4 * 64);
+
+            // 灰度输出由转换后的 RGB 计算亮度
+            decoder.set_output_format(OutputFormat::Grayscale).unwrap();
+            let mut mcu_buffer = vec![0i16; decoder.mcu_buffer_size()];
+            let mut work_buffer = vec![0u8; decoder.work_buffer_size()];
+            let mut lumas = Vec::new();
+            decoder
+                .decompress(jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut |_, bitmap, _| {
+                    lumas.push(bitmap[0]);
+                    Ok(true)
+                })
+                .unwrap();
+            assert_eq!(lumas.len(), 3);
+            assert!(lumas[0].abs_diff(179) <= 2 && lumas[1].abs_diff(128) <= 2 && lumas[2].abs_diff(105) <= 2, "{:?}", lumas);
+
+            // 四个分量的 DC 预测值都可读取（K 分量最后一块不为 0），复位后全部清零
+            assert_ne!(decoder.dc_predictors()[3], 0);
+            decoder.reset_dc_predictors();
+            assert_eq!(decoder.dc_predictors(), [0; 4]);
+
+            // 直接读取 Y/Cb/Cr 块的接口不支持
+            let mut output = vec![0u8; 3 * 3];
+            assert_eq!(decoder.decompress_dc(jpeg, &mut mcu_buffer, &mut output), Err(Error::UnsupportedFormat));
         }
 
-        let rx = scaled_width as usize;
-        let ry = scaled_height as usize;
-        let mx = (mcu_pixel_width >> self.scale) as usize;
-        
-        if rx < mx {
-            let mut s = 0usize;
-            let mut d = 0usize;
-            for _y in 0..ry {
-                for _x in 0..rx {
-                    work_buffer[d] = work_buffer[s];
-                    work_buffer[d + 1] = work_buffer[s + 1];
-                    work_buffer[d + 2] = work_buffer[s + 2];
-                    s += 3;
-                    d += 3;
-                }
-                s += (mx - rx) * 3;
+        // 四分量只支持 1x1 采样
+        let mut data = cases[0].clone();
+        let sof = data.windows(2).position(|m| m == [0xFF, markers::SOF0]).unwrap();
+        data[sof + 11] = 0x22;
+        let mut pool = MemoryPool::new(&mut pool_buffer);
+        assert_eq!(JpegDecoder::new().prepare(&data, &mut pool), Err(Error::UnsupportedFormat));
+    }
+
+    #[test]
+    fn test_extend() {
+        // C 版本：if (!(d & (1 << (dl - 1)))) d -= (1 << dl) - 1;
+        let reference = |v: u16, t: usize| {
+            let d = v as i64;
+            if d & (1 << (t - 1)) == 0 { d - ((1 << t) - 1) } else { d }
+        };
+        for t in 1..=16usize {
+            let half = 1u32 << (t - 1);
+            let max = (1u32 << t) - 1;
+            for v in [0, 1, half - 1, half, half + 1, max - 1, max] {
+                let v = v.min(max) as u16;
+                assert_eq!(JpegDecoder::extend(v, t) as i64, reference(v, t), "t = {}, v = {:#x}", t, v);
             }
         }
 
-        let continue_processing = callback(self, work_buffer, &rect)?;
-        
-        if !continue_processing {
-            return Err(Error::Interrupted);
+        // 边界幅值
+        for (v, t, expected) in [
+            (0u16, 1, -1),
+            (1, 1, 1),
+            (0, 11, -2047),
+            (0x7FF, 11, 2047),
+            (0, 15, -32767),
+            (0x3FFF, 15, -16384),
+            (0x4000, 15, 16384),
+            (0x7FFF, 15, 32767),
+            (0, 16, -65535),
+            (0xFFFF, 16, 65535),
+        ] {
+            assert_eq!(JpegDecoder::extend(v, t), expected, "t = {}, v = {:#x}", t, v);
+        }
+        // 超出范围的位数被钳位
+        assert_eq!(JpegDecoder::extend(0, 0), -1);
+        assert_eq!(JpegDecoder::extend(0, 20), -65535);
+    }
+
+    #[test]
+    fn test_scan_table_selectors() {
+        let jpeg = encode(24, 16, &pattern(24, 16, 3), &EncodeOptions { sampling: (2, 1), ..EncodeOptions::default() });
+        let segment_at = |data: &[u8], marker: u8| {
+            let pos = data.windows(2).position(|m| m == [0xFF, marker]).unwrap();
+            (pos + 4, pos + 2 + u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize)
+        };
+
+        // 交换 Huffman 表 0/1：亮度使用表 1，色度使用表 0
+        let mut swapped = jpeg.clone();
+        let (mut p, end) = segment_at(&swapped, markers::DHT);
+        while p < end {
+            let count: usize = swapped[p + 1..p + 17].iter().map(|&n| n as usize).sum();
+            swapped[p] ^= 0x01;
+            p += 17 + count;
         }
+        let (sos, _) = segment_at(&swapped, markers::SOS);
+        for i in 0..3 {
+            swapped[sos + 2 + i * 2] ^= 0x11;
+        }
+        assert_eq!(decode(&swapped, 0, 3), decode(&jpeg, 0, 3));
 
-        Ok(())
+        // SOS 按分量 ID 对应，ID 不存在或表号超出范围时报错
+        let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+        for (offset, value) in [(1, 9), (2, 0x20), (4, 0x02)] {
+            let mut data = jpeg.clone();
+            data[sos + offset] = value;
+            let mut pool = MemoryPool::new(&mut pool_buffer);
+            assert_eq!(JpegDecoder::new().prepare(&data, &mut pool), Err(Error::FormatError));
+        }
     }
 
-    /// Get output width (with scaling applied)
-    pub fn width(&self) -> u16 {
-        self.width >> self.scale
+    #[test]
+    fn test_missing_tables_rejected_by_prepare() {
+        let jpeg = encode(16, 16, &pattern(16, 16, 3), &EncodeOptions::default());
+        let without = |marker: u8| {
+            let pos = jpeg.windows(2).position(|m| m == [0xFF, marker]).unwrap();
+            let len = u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]) as usize;
+            [&jpeg[..pos], &jpeg[pos + 2 + len..]].concat()
+        };
+
+        // 同一个解码器先解析完整的文件，上一幅图像的表和 SOF 不能被沿用（池只增不减，留足六次 prepare 的空间）
+        let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE * 8];
+        let mut pool = MemoryPool::new(&mut pool_buffer);
+        let mut decoder = JpegDecoder::new();
+        for marker in [markers::DQT, markers::DHT, markers::SOF0] {
+            decoder.prepare(&jpeg, &mut pool).unwrap();
+            assert_eq!(decoder.prepare(&without(marker), &mut pool), Err(Error::FormatError), "{:#x}", marker);
+        }
     }
 
-    /// Get output height (with scaling applied)
-    pub fn height(&self) -> u16 {
-        self.height >> self.scale
+    #[test]
+    fn test_segment_order() {
+        let jpeg = encode(16, 16, &pattern(16, 16, 3), &EncodeOptions::default());
+        let segment = |marker: u8| {
+            let pos = jpeg.windows(2).position(|m| m == [0xFF, marker]).unwrap();
+            pos..pos + 2 + u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]) as usize
+        };
+        let (sof, dht, sos) = (segment(markers::SOF0), segment(markers::DHT), segment(markers::SOS));
+        assert!(sof.end <= dht.start && dht.end <= sos.start);
+        let prepare = |data: &[u8]| {
+            let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+            let mut pool = MemoryPool::new(&mut pool_buffer);
+            JpegDecoder::new().prepare(data, &mut pool)
+        };
+
+        // SOF 移到 DHT 之后：表与帧头无关，解码结果不变
+        let reordered = [&jpeg[..sof.start], &jpeg[sof.end..sos.start], &jpeg[sof.clone()], &jpeg[sos.start..]].concat();
+        assert_eq!(decode(&reordered, 0, 3), decode(&jpeg, 0, 3));
+
+        // 没有 SOF：分量数为 0 的 SOS 也不能通过
+        let mut zero = [&jpeg[..sof.start], &jpeg[sof.end..sos.start], &[0xFF, 0xDA, 0x00, 0x06, 0x00, 0x00, 0x3F, 0x00][..]].concat();
+        zero.extend_from_slice(&jpeg[sos.end..]);
+        assert_eq!(prepare(&zero), Err(Error::FormatError));
+
+        // 重复的 SOF
+        let twice = [&jpeg[..sos.start], &jpeg[sof.clone()], &jpeg[sos.start..]].concat();
+        assert_eq!(prepare(&twice), Err(Error::FormatError));
     }
 
-    /// Get original image width (without scaling)
-    pub fn raw_width(&self) -> u16 {
-        self.width
+    #[test]
+    fn test_scaled_output_is_box_average() {
+        let (w, h) = (512usize, 128usize);
+        for sampling in [(1, 1), (2, 2)] {
+            let jpeg = encode(w as u16, h as u16, &pattern(w as u16, h as u16, 3), &EncodeOptions { sampling, ..EncodeOptions::default() });
+            let (_, _, full) = decode(&jpeg, 0, 3);
+
+            for scale in 1..=5u8 {
+                let (sw, sh, scaled) = decode(&jpeg, scale, 3);
+                assert_eq!((sw as usize, sh as usize), (w >> scale, h >> scale));
+
+                // 对全分辨率结果按方块求平均
+                let n = 1usize << scale;
+                let mut expected = vec![0u8; scaled.len()];
+                for (i, out) in expected.iter_mut().enumerate() {
+                    let (x, y, c) = (i / 3 % sw as usize, i / 3 / sw as usize, i % 3);
+                    let sum: usize = (0..n * n).map(|j| full[((y * n + j / n) * w + x * n + j % n) * 3 + c] as usize).sum();
+                    *out = ((sum + n * n / 2) / (n * n)) as u8;
+                }
+                assert!(max_diff(&scaled, &expected) <= 1, "{:?} scale {}", sampling, scale);
+            }
+        }
+
+        // 1/16 的 4:4:4 图像中一个像素跨 2x2 个 MCU，需要额外的行累加和
+        let jpeg = encode(512, 16, &pattern(512, 16, 3), &EncodeOptions::default());
+        let (mut decoder, mut mcu_buffer, _) = prepared(&jpeg);
+        assert_eq!(decoder.work_buffer_size_for_scale(3), decoder.work_buffer_size());
+        assert_eq!(decoder.work_buffer_size_for_scale(4), decoder.work_buffer_size() + 32 * 6);
+
+        let mut work_buffer = vec![0u8; decoder.work_buffer_size()];
+        let mut noop = |_: &JpegDecoder, _: &[u8], _: &Rectangle| Ok(true);
+        assert_eq!(decoder.decompress(&jpeg, 4, &mut mcu_buffer, &mut work_buffer, &mut noop), Err(Error::InsufficientBuffer));
+        assert_eq!(decoder.decompress(&jpeg, 6, &mut mcu_buffer, &mut work_buffer, &mut noop), Err(Error::Parameter));
+
+        let mut work_buffer = vec![0u8; decoder.work_buffer_size_for_scale(4)];
+        let mut rects = Vec::new();
+        decoder
+            .decompress(&jpeg, 4, &mut mcu_buffer, &mut work_buffer, &mut |decoder, _, rect| {
+                assert_eq!((decoder.width(), decoder.height()), (32, 1));
+                rects.push(*rect);
+                Ok(true)
+            })
+            .unwrap();
+        assert_eq!(rects, (0..32).map(|x| Rectangle::new(x, x, 0, 0)).collect::<Vec<_>>());
     }
 
-    /// Get original image height (without scaling)
-    pub fn raw_height(&self) -> u16 {
-        self.height
+    #[test]
+    fn test_small_scales_on_every_entry_point() {
+        // 4:4:4 的 1/16 和两种采样的 1/32 都需要跨 MCU 累加
+        for sampling in [(1, 1), (2, 2)] {
+            let jpeg = encode(128, 64, &pattern(128, 64, 3), &EncodeOptions { sampling, ..EncodeOptions::default() });
+            for scale in 4..=5u8 {
+                let (w, h, expected) = decode(&jpeg, scale, 3);
+                let (w, h) = (w as usize, h as usize);
+                let (mut decoder, mut mcu_buffer, _) = prepared(&jpeg);
+                let mut work_buffer = vec![0u8; decoder.work_buffer_size_for_scale(scale)];
+                let mut fb = vec![0u8; w * h * 3];
+
+                let (start, end) = decoder.scan_range(&jpeg).unwrap();
+                decoder
+                    .decompress_fast(&jpeg[start..end + 2], scale, &mut mcu_buffer, &mut work_buffer, &mut |_, bitmap, rect| {
+                        blit_rect(&mut fb, w, 3, bitmap, rect);
+                        Ok(true)
+                    })
+                    .unwrap();
+                assert_eq!(fb, expected, "fast {:?} scale {}", sampling, scale);
+
+                fb.fill(0);
+                decoder
+                    .decompress_scan(&jpeg, scale, &mut mcu_buffer, &mut work_buffer, &mut |_, bitmap, rect| {
+                        blit_rect(&mut fb, w, 3, bitmap, rect);
+                        Ok(true)
+                    })
+                    .unwrap();
+                assert_eq!(fb, expected, "scan {:?} scale {}", sampling, scale);
+
+                fb.fill(0);
+                let mut mcus = 0;
+                decoder
+                    .decompress_with_complexity(
+                        &jpeg,
+                        scale,
+                        &mut mcu_buffer,
+                        &mut work_buffer,
+                        &mut |_, bitmap, rect| {
+                            blit_rect(&mut fb, w, 3, bitmap, rect);
+                            Ok(true)
+                        },
+                        &mut |_, _| mcus += 1,
+                    )
+                    .unwrap();
+                assert_eq!(fb, expected, "complexity {:?} scale {}", sampling, scale);
+                assert_eq!(mcus, decoder.total_mcus());
+
+                fb.fill(0);
+                decoder
+                    .decompress_with_info(&jpeg, scale, &mut mcu_buffer, &mut work_buffer, &mut |_, bitmap, rect, info| {
+                        assert!(info.mcu_index < info.total_mcus);
+                        blit_rect(&mut fb, w, 3, bitmap, rect);
+                        Ok(true)
+                    })
+                    .unwrap();
+                assert_eq!(fb, expected, "info {:?} scale {}", sampling, scale);
+
+                let mut rows = Vec::new();
+                let mut row_buffer = vec![0u8; decoder.scanline_buffer_size(scale, 1)];
+                decoder
+                    .decompress_scanlines(&jpeg, scale, &mut mcu_buffer, &mut work_buffer, &mut row_buffer, 1, &mut |_, line, _| {
+                        rows.extend_from_slice(&line[..w * 3]);
+                        Ok(true)
+                    })
+                    .unwrap();
+                assert_eq!(rows, expected, "scanlines {:?} scale {}", sampling, scale);
+
+                let mut planes = [vec![0u8; w * h], vec![0u8; w * h], vec![0u8; w * h]];
+                let [r, g, b] = &mut planes;
+                decoder.decompress_to_planes(&jpeg, scale, &mut mcu_buffer, &mut work_buffer, r, g, b, w).unwrap();
+                let interleaved: Vec<u8> = (0..w * h).flat_map(|i| [planes[0][i], planes[1][i], planes[2][i]]).collect();
+                assert_eq!(interleaved, expected, "planes {:?} scale {}", sampling, scale);
+
+                // 区域解码和可恢复解码无法保存跨 MCU 的累加和
+                let region = Rectangle::new(0, 0, 0, 0);
+                let mut noop = |_: &JpegDecoder, _: &[u8], _: &Rectangle| Ok(true);
+                assert_eq!(
+                    decoder.decompress_region(&jpeg, scale, region, &mut mcu_buffer, &mut work_buffer, &mut noop),
+                    Err(Error::Parameter)
+                );
+                assert_eq!(
+                    decoder.decompress_resumable(&jpeg, scale, &mut mcu_buffer, &mut work_buffer, None, None, &mut noop),
+                    Err(Error::Parameter)
+                );
+            }
+
+            // 流式输入同样支持 1/32
+            let (w, _, expected) = decode(&jpeg, 5, 3);
+            let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+            let mut pool = MemoryPool::new(&mut pool_buffer);
+            let mut decoder = JpegDecoder::new();
+            let mut buffer = [0u8; BUFFER_SIZE];
+            let mut reader = Trickle(&jpeg);
+            decoder.prepare_reader(&mut reader, &mut buffer, &mut pool).unwrap();
+            let mut mcu_buffer = vec![0i16; decoder.mcu_buffer_size()];
+            let mut work_buffer = vec![0u8; decoder.work_buffer_size_for_scale(5)];
+            let mut fb = vec![0u8; expected.len()];
+            decoder
+                .decompress_reader(&mut reader, &mut buffer, 5, &mut mcu_buffer, &mut work_buffer, &mut |_, bitmap, rect| {
+                    blit_rect(&mut fb, w as usize, 3, bitmap, rect);
+                    Ok(true)
+                })
+                .unwrap();
+            assert_eq!(fb, expected, "reader {:?}", sampling);
+        }
     }
 
-    /// Get number of color components
-    /// 
-    /// Returns 1 for grayscale, 3 for color images.
-    pub fn components(&self) -> u8 {
-        self.num_components
+    #[test]
+    fn test_dc_only() {
+        for (sampling, restart_interval) in [((1, 1), 0), ((2, 2), 3)] {
+            let opts = EncodeOptions { sampling, restart_interval, ..EncodeOptions::default() };
+            let jpeg = encode(48, 32, &pattern(48, 32, 3), &opts);
+            let (mut decoder, mut mcu_buffer, _) = prepared(&jpeg);
+            decoder.set_dc_only(true);
+
+            let mut work_buffer = vec![0u8; decoder.work_buffer_size()];
+            let mut fb = vec![0u8; 48 * 32 * 3];
+            decoder
+                .decompress(&jpeg, 0, &mut mcu_buffer, &mut work_buffer, &mut |_, bitmap, rect| {
+                    blit_rect(&mut fb, 48, 3, bitmap, rect);
+                    Ok(true)
+                })
+                .unwrap();
+
+            // 每个块的颜色与 DC 预览一致（码流没有错位），4:4:4 中整个 8x8 块都是单一颜色
+            let mut preview = vec![0u8; 6 * 4 * 3];
+            assert_eq!(decoder.decompress_dc(&jpeg, &mut mcu_buffer, &mut preview), Ok((6, 4)));
+            let mut block_colors = vec![0u8; preview.len()];
+            for (i, v) in block_colors.iter_mut().enumerate() {
+                let (bx, by, c) = (i / 3 % 6, i / 3 / 6, i % 3);
+                *v = fb[((by * 8) * 48 + bx * 8) * 3 + c];
+            }
+            assert_eq!(block_colors, preview, "{:?}", sampling);
+            if sampling == (1, 1) {
+                for (i, px) in fb.chunks_exact(3).enumerate() {
+                    let (x, y) = (i % 48, i / 48);
+                    assert_eq!(px, &fb[((y & !7) * 48 + (x & !7)) * 3..][..3]);
+                }
+            }
+        }
     }
-}
 
-impl Default for JpegDecoder<'_> {
-    fn default() -> Self {
-        Self::new()
+    /// 每次最多返回 7 字节的读取器
+    struct Trickle<'a>(&'a [u8]);
+
+    impl JpegReader for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            let n = buf.len().min(self.0.len()).min(7);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_decompress_reader() {
+        let (w, h) = (40u16, 24u16);
+        let pixels = pattern(w, h, 3);
+        let mut jpeg = encode(w, h, &pixels, &EncodeOptions { sampling: (2, 2), restart_interval: 2, ..EncodeOptions::default() });
+        // 插入比输入缓冲区大的 COM 段，应被跳过
+        let mut comment = vec![0xFF, 0xFE, 0x03, 0x02];
+        comment.resize(0x302 + 2, b'x');
+        jpeg.splice(2..2, comment);
+        let (_, _, expected) = decode(&jpeg, 0, 3);
+
+        let mut pool_buffer = vec![0u8; RECOMMENDED_POOL_SIZE];
+        let mut pool = MemoryPool::new(&mut pool_buffer);
+        let mut decoder = JpegDecoder::new();
+        let mut buffer = [0u8; BUFFER_SIZE];
+        let mut reader = Trickle(&jpeg);
+        decoder.prepare_reader(&mut reader, &mut buffer, &mut pool).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (w, h));
+
+        let mut mcu_buffer = vec![0i16; decoder.mcu_buffer_size()];
+        let mut work_buffer = vec![0u8; decoder.work_buffer_size()];
+        let mut fb = vec![0u8; expected.len()];
+        decoder
+            .decompress_reader(&mut reader, &mut buffer, 0, &mut mcu_buffer, &mut work_buffer, &mut |_, bitmap, rect| {
+                blit_rect(&mut fb, w as usize, 3, bitmap, rect);
+                Ok(true)
+            })
+            .unwrap();
+        assert_eq!(fb, expected);
+
+        // 需要解析的段放不进缓冲区
+        let mut decoder = JpegDecoder::new();
+        let mut small = [0u8; 32];
+        let mut pool = MemoryPool::new(&mut pool_buffer);
+        assert_eq!(
+            decoder.prepare_reader(&mut &jpeg[..], &mut small, &mut pool),
+            Err(Error::InsufficientBuffer)
+        );
+
+        // 数据提前结束
+        let mut decoder = JpegDecoder::new();
+        let mut pool = MemoryPool::new(&mut pool_buffer);
+        assert_eq!(decoder.prepare_reader(&mut &jpeg[..40], &mut buffer, &mut pool), Err(Error::Input));
     }
 }
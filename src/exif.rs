@@ -0,0 +1,97 @@
+//! EXIF orientation
+//!
+//! Cameras and phones store the sensor orientation as tag 0x0112 of the
+//! first IFD in the APP1 `Exif\0\0` segment. Only that tag is read; every
+//! offset is checked against the segment, so a corrupt segment simply
+//! yields no orientation.
+
+/// APP1 标识
+const EXIF_TAG: &[u8; 6] = b"Exif\0\0";
+
+/// Orientation 标签
+const ORIENTATION: u16 = 0x0112;
+
+/// 从 APP1 段中读取 Orientation（1..=8），不存在或损坏时返回 None
+pub(crate) fn orientation(segment: &[u8]) -> Option<u8> {
+    let tiff = segment.strip_prefix(EXIF_TAG)?;
+    let big_endian = match tiff.get(..4)? {
+        [b'I', b'I', 42, 0] => false,
+        [b'M', b'M', 0, 42] => true,
+        _ => return None,
+    };
+
+    let read_u16 = |pos: usize| {
+        let bytes = [*tiff.get(pos)?, *tiff.get(pos + 1)?];
+        Some(if big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    };
+    let read_u32 = |pos: usize| {
+        let bytes = [*tiff.get(pos)?, *tiff.get(pos + 1)?, *tiff.get(pos + 2)?, *tiff.get(pos + 3)?];
+        Some(if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    };
+
+    // IFD0：条目数 + 12 字节的条目
+    let ifd = read_u32(4)? as usize;
+    let count = read_u16(ifd)? as usize;
+    (0..count).find_map(|i| {
+        // ifd 已在段内，不会溢出
+        let entry = ifd + 2 + i * 12;
+        if read_u16(entry)? != ORIENTATION {
+            return None;
+        }
+        // 类型必须是 SHORT，值直接存放在条目中
+        if read_u16(entry + 2)? != 3 {
+            return None;
+        }
+        let value = read_u16(entry + 8)?;
+        (1..=8).contains(&value).then_some(value as u8)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 构造只含 IFD0 的 APP1 段
+    fn exif(big_endian: bool, entries: &[(u16, u16, u16)]) -> Vec<u8> {
+        let u16b = |v: u16| if big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+        let u32b = |v: u32| if big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+        let mut seg = EXIF_TAG.to_vec();
+        seg.extend_from_slice(if big_endian { b"MM\0\x2a" } else { b"II\x2a\0" });
+        seg.extend_from_slice(&u32b(8));
+        seg.extend_from_slice(&u16b(entries.len() as u16));
+        for &(tag, ty, value) in entries {
+            // 标签、类型、个数、值（SHORT 左对齐在 4 字节中）
+            seg.extend_from_slice(&u16b(tag));
+            seg.extend_from_slice(&u16b(ty));
+            seg.extend_from_slice(&u32b(1));
+            seg.extend_from_slice(&u16b(value));
+            seg.extend_from_slice(&[0, 0]);
+        }
+        seg.extend_from_slice(&[0; 4]);
+        seg
+    }
+
+    #[test]
+    fn test_orientation() {
+        for big_endian in [false, true] {
+            let seg = exif(big_endian, &[(0x010F, 2, 0), (ORIENTATION, 3, 6)]);
+            assert_eq!(orientation(&seg), Some(6));
+            assert_eq!(orientation(&exif(big_endian, &[(ORIENTATION, 3, 9)])), None);
+            assert_eq!(orientation(&exif(big_endian, &[(ORIENTATION, 4, 6)])), None);
+        }
+        assert_eq!(orientation(b"Exif\0\0XX\x2a\0"), None);
+        assert_eq!(orientation(b"JFIF\0"), None);
+    }
+
+    #[test]
+    fn test_truncated_never_panics() {
+        let seg = exif(true, &[(0x010F, 2, 0), (ORIENTATION, 3, 3)]);
+        for len in 0..seg.len() {
+            let _ = orientation(&seg[..len]);
+        }
+        // IFD 偏移指向段外
+        let mut bad = seg.clone();
+        bad[10..14].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(orientation(&bad), None);
+    }
+}
@@ -8,7 +8,8 @@
 //! All data allocated from user-provided workspace memory pool.
 
 use crate::types::{Error, Result};
-use crate::pool::MemoryPool;
+use crate::pool::Allocator;
+use crate::reader::JpegReader;
 
 // 确定当前使用的优化级别
 #[cfg(feature = "fast-decode-2")]
@@ -54,8 +55,8 @@ pub struct HuffmanTable<'a> {
 
 impl<'a> HuffmanTable<'a> {
     /// 从内存池中创建Huffman表
-    pub fn create_in_pool(
-        pool: &mut MemoryPool<'a>,
+    pub fn create_in_pool<A: Allocator<'a>>(
+        pool: &mut A,
         bits: &[u8],
         values: &[u8],
     ) -> Result<Self> {
@@ -71,22 +72,22 @@ impl<'a> HuffmanTable<'a> {
         }
 
         // 从池中分配codes数组
-        let codes = pool.alloc_u16(num_codes).ok_or(Error::InsufficientMemory)?;
+        let codes = pool.alloc_array::<u16>(num_codes).ok_or(Error::InsufficientMemory)?;
         
         // 从池中分配data数组  
-        let data = pool.alloc_u8(num_codes).ok_or(Error::InsufficientMemory)?;
+        let data = pool.alloc_array::<u8>(num_codes).ok_or(Error::InsufficientMemory)?;
 
         // 复制bits
         let mut bits_arr = [0u8; 16];
         bits_arr.copy_from_slice(bits);
 
-        // 构建码字表 - 与C版本逻辑一致
-        let mut code = 0u16;
+        // 构建码字表 - 与C版本逻辑一致（过满的表由下面的 validate() 拒绝）
+        let mut code = 0u32;
         let mut idx = 0;
         
-        for (_bit_len, &count) in bits.iter().enumerate() {
+        for &count in bits.iter() {
             for _ in 0..count {
-                codes[idx] = code;
+                codes[idx] = code as u16;
                 idx += 1;
                 code += 1;
             }
@@ -114,17 +115,57 @@ impl<'a> HuffmanTable<'a> {
             num_codes,
         };
 
+        table.validate()?;
+
         #[cfg(feature = "fast-decode-2")]
         table.build_fast_lut(pool)?;
 
         Ok(table)
     }
 
+    /// Check the table invariants
+    /// 
+    /// The code counts in `bits` must add up to `num_codes` and to the
+    /// lengths of `codes` and `data`, and `codes` must be the canonical
+    /// codes for `bits`: increasing within each length and inside the code
+    /// space (no overlapping codes). Returns `Error::FormatError` otherwise.
+    /// `create_in_pool()` runs this on every table it builds; call it again
+    /// after editing the public fields by hand.
+    pub fn validate(&self) -> Result<()> {
+        let total: usize = self.bits.iter().map(|&n| n as usize).sum();
+        if total != self.num_codes || self.codes.len() != total || self.data.len() != total {
+            return Err(Error::FormatError);
+        }
+
+        let mut code = 0u32;
+        let mut idx = 0;
+        for (i, &count) in self.bits.iter().enumerate() {
+            for _ in 0..count {
+                if self.codes[idx] as u32 != code {
+                    return Err(Error::FormatError);
+                }
+                idx += 1;
+                code += 1;
+            }
+            // 码字超出该码长的编码空间说明表过满
+            if code > 1 << (i + 1) {
+                return Err(Error::FormatError);
+            }
+            code <<= 1;
+        }
+        Ok(())
+    }
+
+    /// Number of codes of each length, 1 to 16 bits (the DHT `BITS` list)
+    pub fn code_lengths(&self) -> &[u8; 16] {
+        &self.bits
+    }
+
     /// 构建快速查找表 (JD_FASTDECODE == 2)
     #[cfg(feature = "fast-decode-2")]
-    fn build_fast_lut(&mut self, pool: &mut MemoryPool<'a>) -> Result<()> {
+    fn build_fast_lut<A: Allocator<'a>>(&mut self, pool: &mut A) -> Result<()> {
         // 从池中分配LUT (2048 entries * 2 bytes = 4096 bytes)
-        let lut = pool.alloc_u16(HUFF_LEN).ok_or(Error::InsufficientMemory)?;
+        let lut = pool.alloc_array::<u16>(HUFF_LEN).ok_or(Error::InsufficientMemory)?;
         
         // 初始化为0xFFFF (无效标记)
         for entry in lut.iter_mut() {
@@ -163,6 +204,18 @@ impl<'a> HuffmanTable<'a> {
         Ok(())
     }
 
+    /// Whether this table has a fast lookup table allocated
+    pub fn has_lut(&self) -> bool {
+        #[cfg(feature = "fast-decode-2")]
+        {
+            self.lut.is_some()
+        }
+        #[cfg(not(feature = "fast-decode-2"))]
+        {
+            false
+        }
+    }
+
     /// 从位流解码Huffman值
     pub fn decode(&self, bits: &mut BitStream) -> Result<u8> {
         // JD_FASTDECODE == 2: 使用 LUT 快速查找
@@ -175,6 +228,7 @@ impl<'a> HuffmanTable<'a> {
         
         // JD_FASTDECODE >= 1: 使用 32 位寄存器
         #[cfg(any(feature = "fast-decode-1", feature = "fast-decode-2"))]
+        #[allow(clippy::needless_return)]
         {
             return self.decode_fastdecode1(bits);
         }
@@ -192,6 +246,20 @@ impl<'a> HuffmanTable<'a> {
         }
     }
 
+    /// 使用指定级别的实现解码（测试用），该级别未编译进来时返回 None
+    #[cfg(test)]
+    pub(crate) fn decode_level(&self, level: u8, bits: &mut BitStream) -> Option<Result<u8>> {
+        match level {
+            #[cfg(any(feature = "fast-decode-0", not(any(feature = "fast-decode-1", feature = "fast-decode-2"))))]
+            0 => Some(self.decode_fastdecode0(bits)),
+            #[cfg(any(feature = "fast-decode-1", feature = "fast-decode-2", not(feature = "fast-decode-0")))]
+            1 => Some(self.decode_fastdecode1(bits)),
+            #[cfg(feature = "fast-decode-2")]
+            2 => self.lut.as_ref().map(|lut| self.decode_fastdecode2(bits, lut)),
+            _ => None,
+        }
+    }
+
     /// JD_FASTDECODE == 0: 基础逐位解码
     /// 适合 8/16 位 MCU，与 C 版本完全一致
     #[cfg(any(feature = "fast-decode-0", not(any(feature = "fast-decode-1", feature = "fast-decode-2"))))]
@@ -234,7 +302,6 @@ impl<'a> HuffmanTable<'a> {
         };
         let mut wbit = wbit;
         
-        let mut dc = bits.data.len() - bits.pos;
         let mut flg = false;
         
         // 填充到至少 16 位 - 与 C 版本完全一致
@@ -244,13 +311,7 @@ impl<'a> HuffmanTable<'a> {
             if bits.marker_found.is_some() {
                 d = 0xFF; // 生成填充位
             } else {
-                if dc == 0 {
-                    return Err(Error::Input);
-                }
-                
-                let byte = bits.data[bits.pos];
-                bits.pos += 1;
-                dc -= 1;
+                let byte = bits.next_byte()?;
                 
                 if flg {
                     flg = false;
@@ -269,6 +330,7 @@ impl<'a> HuffmanTable<'a> {
             
             w = (w << 8) | d as u32;
             wbit += 8;
+            bits.fetched += 1;
         }
         
         // 更新位流状态
@@ -312,7 +374,6 @@ impl<'a> HuffmanTable<'a> {
         };
         let mut wbit = wbit;
         
-        let mut dc = bits.data.len() - bits.pos;
         let mut flg = false;
         
         // 填充到至少 16 位
@@ -322,13 +383,7 @@ impl<'a> HuffmanTable<'a> {
             if bits.marker_found.is_some() {
                 d = 0xFF;
             } else {
-                if dc == 0 {
-                    return Err(Error::Input);
-                }
-                
-                let byte = bits.data[bits.pos];
-                bits.pos += 1;
-                dc -= 1;
+                let byte = bits.next_byte()?;
                 
                 if flg {
                     flg = false;
@@ -347,6 +402,7 @@ impl<'a> HuffmanTable<'a> {
             
             w = (w << 8) | d as u32;
             wbit += 8;
+            bits.fetched += 1;
         }
         
         // 更新位流状态
@@ -399,12 +455,36 @@ pub struct BitStream<'a> {
     pub bit_buffer: u32,
     pub bits_in_buffer: usize,
     pub(crate) marker_found: Option<u8>,
+    /// 已移入位寄存器的字节数（去除填充字节后）
+    pub(crate) fetched: usize,
+    /// 流式输入：`data` 为空，字节从读取器经缓冲区取得
+    stream: Option<Stream<'a>>,
     
     /// JD_FASTDECODE == 0 使用的位掩码
-    #[cfg(not(any(feature = "fast-decode-1", feature = "fast-decode-2")))]
+    #[cfg(any(feature = "fast-decode-0", not(any(feature = "fast-decode-1", feature = "fast-decode-2"))))]
     pub(crate) bit_mask: u8,
 }
 
+/// 流式输入的读取器与输入缓冲区
+struct Stream<'a> {
+    reader: &'a mut dyn JpegReader,
+    buf: &'a mut [u8],
+    len: usize,
+    pos: usize,
+}
+
+/// Snapshot of the `BitStream` read state (everything except the data slice)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct BitState {
+    pos: usize,
+    bit_buffer: u32,
+    bits_in_buffer: usize,
+    marker_found: Option<u8>,
+    fetched: usize,
+    #[cfg(any(feature = "fast-decode-0", not(any(feature = "fast-decode-1", feature = "fast-decode-2"))))]
+    bit_mask: u8,
+}
+
 impl<'a> BitStream<'a> {
     pub fn new(data: &'a [u8]) -> Self {
         Self {
@@ -413,11 +493,54 @@ impl<'a> BitStream<'a> {
             bit_buffer: 0,
             bits_in_buffer: 0,
             marker_found: None,
-            #[cfg(not(any(feature = "fast-decode-1", feature = "fast-decode-2")))]
+            fetched: 0,
+            stream: None,
+            #[cfg(any(feature = "fast-decode-0", not(any(feature = "fast-decode-1", feature = "fast-decode-2"))))]
             bit_mask: 0,
         }
     }
 
+    /// Create a bit stream that pulls its data from `reader`
+    ///
+    /// `buffer` is the input buffer refilled from the reader as the stream
+    /// is consumed (`BUFFER_SIZE` bytes is a good default).
+    pub fn from_reader(reader: &'a mut dyn JpegReader, buffer: &'a mut [u8]) -> Self {
+        let mut bits = Self::new(&[]);
+        bits.stream = Some(Stream { reader, buf: buffer, len: 0, pos: 0 });
+        bits
+    }
+
+    /// 取下一个字节，数据耗尽时返回 `Error::Input`
+    #[inline]
+    fn next_byte(&mut self) -> Result<u8> {
+        if self.pos < self.data.len() {
+            let byte = self.data[self.pos];
+            self.pos += 1;
+            Ok(byte)
+        } else {
+            self.next_stream_byte()
+        }
+    }
+
+    /// 从流式输入取字节，缓冲区读空时从读取器补充
+    #[cold]
+    fn next_stream_byte(&mut self) -> Result<u8> {
+        let stream = self.stream.as_mut().ok_or(Error::Input)?;
+        if stream.pos >= stream.len {
+            if stream.buf.is_empty() {
+                return Err(Error::InsufficientBuffer);
+            }
+            stream.len = stream.reader.read(stream.buf)?;
+            stream.pos = 0;
+            if stream.len == 0 {
+                return Err(Error::Input);
+            }
+        }
+        let byte = stream.buf[stream.pos];
+        stream.pos += 1;
+        Ok(byte)
+    }
+
     /// JD_FASTDECODE == 0: 逐位读取，与 C 版本完全一致
     #[cfg(any(feature = "fast-decode-0", not(any(feature = "fast-decode-1", feature = "fast-decode-2"))))]
     #[allow(dead_code)]
@@ -425,26 +548,18 @@ impl<'a> BitStream<'a> {
         // 检查是否需要新字节
         if self.bit_mask == 0 {
             loop {
-                if self.pos >= self.data.len() {
-                    return Err(Error::Input);
-                }
-                
-                let byte = self.data[self.pos];
-                self.pos += 1;
+                let byte = self.next_byte()?;
                 
                 // 处理 0xFF escape 序列
                 if self.marker_found.is_some() {
                     // 在 marker 后生成填充位
                     self.bit_buffer = 0xFF;
                     self.bit_mask = 0x80;
+                    self.fetched += 1;
                     break;
                 } else if byte == 0xFF {
                     // 检查下一个字节
-                    if self.pos >= self.data.len() {
-                        return Err(Error::Input);
-                    }
-                    let next = self.data[self.pos];
-                    self.pos += 1;
+                    let next = self.next_byte()?;
                     
                     if next != 0 {
                         // 这是一个 marker，不是 escape
@@ -453,10 +568,12 @@ impl<'a> BitStream<'a> {
                     // 0xFF 0x00 -> 数据 0xFF
                     self.bit_buffer = 0xFF;
                     self.bit_mask = 0x80;
+                    self.fetched += 1;
                     break;
                 } else {
                     self.bit_buffer = byte as u32;
                     self.bit_mask = 0x80;
+                    self.fetched += 1;
                     break;
                 }
             }
@@ -517,7 +634,6 @@ impl<'a> BitStream<'a> {
                 self.bit_buffer
             };
             
-            let mut dc = self.data.len() - self.pos;
             let mut flg = false;
             
             while wbit < nbit {
@@ -526,13 +642,7 @@ impl<'a> BitStream<'a> {
                 if self.marker_found.is_some() {
                     d = 0xFF;
                 } else {
-                    if dc == 0 {
-                        return Err(Error::Input);
-                    }
-                    
-                    let byte = self.data[self.pos];
-                    self.pos += 1;
-                    dc -= 1;
+                    let byte = self.next_byte()?;
                     
                     if flg {
                         flg = false;
@@ -551,6 +661,7 @@ impl<'a> BitStream<'a> {
                 
                 w = (w << 8) | d as u32;
                 wbit += 8;
+                self.fetched += 1;
             }
             
             self.bit_buffer = w;
@@ -589,12 +700,8 @@ impl<'a> BitStream<'a> {
 
     #[allow(dead_code)]
     pub fn ensure_bits(&mut self, count: usize) -> Result<()> {
-        while self.bits_in_buffer < count {
-            if self.pos >= self.data.len() && self.marker_found.is_none() {
-                break;
-            }
-            self.refill()?;
-        }
+        // 数据耗尽时停止填充
+        while self.bits_in_buffer < count && self.refill().is_ok() {}
         
         if self.bits_in_buffer < count {
             Err(Error::Input)
@@ -612,23 +719,14 @@ impl<'a> BitStream<'a> {
         if self.marker_found.is_some() {
             self.bit_buffer = (self.bit_buffer << 8) | 0xFF;
             self.bits_in_buffer += 8;
+            self.fetched += 1;
             return Ok(());
         }
 
-        if self.pos >= self.data.len() {
-            return Err(Error::Input);
-        }
-
-        let byte = self.data[self.pos];
-        self.pos += 1;
+        let byte = self.next_byte()?;
 
         if byte == 0xFF {
-            if self.pos >= self.data.len() {
-                return Err(Error::Input);
-            }
-            
-            let next = self.data[self.pos];
-            self.pos += 1;
+            let next = self.next_byte()?;
 
             if next == 0x00 {
                 self.bit_buffer = (self.bit_buffer << 8) | 0xFF;
@@ -642,6 +740,7 @@ impl<'a> BitStream<'a> {
             self.bit_buffer = (self.bit_buffer << 8) | byte as u32;
             self.bits_in_buffer += 8;
         }
+        self.fetched += 1;
 
         Ok(())
     }
@@ -656,9 +755,86 @@ impl<'a> BitStream<'a> {
         }
     }
 
+    /// Process a restart interval boundary
+    ///
+    /// Re-syncs to the next marker: skips the rest of the interval, stuffed
+    /// `0xFF 0x00` pairs and `0xFF` fill bytes, consumes the marker, resets
+    /// the bit buffer and returns the marker code. A marker already read
+    /// ahead is used directly, unless the read-ahead stopped on a fill byte
+    /// in front of it. Returns `Error::FormatError` if the marker is not
+    /// `RSTn`.
+    pub fn restart(&mut self) -> Result<u8> {
+        let marker = match self.marker_found {
+            // 预读把填充字节 0xFF 当成了 marker 代码时，真正的代码还在后面
+            Some(0xFF) | None => {
+                // 与 C 版本 restart() 一致：丢弃剩余数据直到找到 marker
+                let mut after_ff = self.marker_found.is_some();
+                loop {
+                    let byte = self.next_byte()?;
+                    if after_ff && byte != 0x00 && byte != 0xFF {
+                        break byte;
+                    }
+                    after_ff = byte == 0xFF;
+                }
+            }
+            Some(marker) => marker,
+        };
+
+        self.reset_for_restart();
+
+        if !(0xD0..=0xD7).contains(&marker) {
+            return Err(Error::FormatError);
+        }
+        Ok(marker)
+    }
+
+    /// 保存当前读取状态
+    pub(crate) fn save(&self) -> BitState {
+        BitState {
+            pos: self.pos,
+            bit_buffer: self.bit_buffer,
+            bits_in_buffer: self.bits_in_buffer,
+            marker_found: self.marker_found,
+            fetched: self.fetched,
+            #[cfg(any(feature = "fast-decode-0", not(any(feature = "fast-decode-1", feature = "fast-decode-2"))))]
+            bit_mask: self.bit_mask,
+        }
+    }
+
+    /// 恢复 `save()` 保存的读取状态
+    pub(crate) fn restore(&mut self, state: &BitState) {
+        self.pos = state.pos;
+        self.bit_buffer = state.bit_buffer;
+        self.bits_in_buffer = state.bits_in_buffer;
+        self.marker_found = state.marker_found;
+        self.fetched = state.fetched;
+        #[cfg(any(feature = "fast-decode-0", not(any(feature = "fast-decode-1", feature = "fast-decode-2"))))]
+        {
+            self.bit_mask = state.bit_mask;
+        }
+    }
+
     pub fn get_marker(&mut self) -> Option<u8> {
         self.marker_found.take()
     }
+
+    /// Number of entropy-coded bits consumed so far
+    ///
+    /// Counted on the de-stuffed stream: a stuffed `0xFF 0x00` pair
+    /// contributes 8 bits, not 16.
+    pub fn bit_position(&self) -> usize {
+        #[cfg(all(feature = "fast-decode-0", not(feature = "fast-decode-1"), not(feature = "fast-decode-2")))]
+        let buffered = if self.bit_mask == 0 {
+            0
+        } else {
+            self.bit_mask.trailing_zeros() as usize + 1
+        };
+
+        #[cfg(any(feature = "fast-decode-1", feature = "fast-decode-2", not(feature = "fast-decode-0")))]
+        let buffered = self.bits_in_buffer;
+
+        (self.fetched * 8).saturating_sub(buffered)
+    }
 }
 
 /// Get current optimization level
@@ -680,3 +856,126 @@ impl<'a> BitStream<'a> {
 pub fn fastdecode_level() -> u8 {
     FASTDECODE_LEVEL
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pool::MemoryPool;
+
+    /// 码长 2..=16 的规范 Huffman 表：长度 2 两个码字，其余各一个
+    const BITS: [u8; 16] = [0, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1];
+    const VALUES: [u8; 16] = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF];
+
+    /// 按规范码字把符号编码为熵编码数据（含 0xFF 填充，结尾补 1 并加 EOI）
+    fn encode_symbols(symbols: &[u8]) -> Vec<u8> {
+        let mut codes = Vec::new();
+        let mut code = 0u32;
+        for (len, &count) in BITS.iter().enumerate() {
+            for _ in 0..count {
+                codes.push((code, len + 1));
+                code += 1;
+            }
+            code <<= 1;
+        }
+
+        let mut bitbuf = Vec::new();
+        for &symbol in symbols {
+            let (code, len) = codes[VALUES.iter().position(|&v| v == symbol).unwrap()];
+            bitbuf.extend((0..len).rev().map(|i| (code >> i) & 1 == 1));
+        }
+        while bitbuf.len() % 8 != 0 {
+            bitbuf.push(true);
+        }
+
+        let mut out = Vec::new();
+        for byte in bitbuf.chunks(8) {
+            let b = byte.iter().fold(0u8, |acc, &bit| (acc << 1) | bit as u8);
+            out.push(b);
+            if b == 0xFF {
+                out.push(0x00);
+            }
+        }
+        out.extend_from_slice(&[0xFF, 0xD9]);
+        out
+    }
+
+    #[test]
+    fn test_all_decode_levels_agree() {
+        let mut pool_buffer = vec![0u8; 8192];
+        let mut pool = MemoryPool::new(&mut pool_buffer);
+        let table = HuffmanTable::create_in_pool(&mut pool, &BITS, &VALUES).unwrap();
+
+        // 短码、LUT 边界附近的码和 16 位长码交替出现
+        let symbols: Vec<u8> = (0..200).map(|i| VALUES[(i * 7 + i / 3) % 16]).collect();
+        let data = encode_symbols(&symbols);
+
+        let mut bits = BitStream::new(&data);
+        let decoded: Vec<u8> = symbols.iter().map(|_| table.decode(&mut bits).unwrap()).collect();
+        assert_eq!(decoded, symbols);
+
+        let mut levels = 0;
+        for level in 0..3 {
+            let mut bits = BitStream::new(&data);
+            let Some(first) = table.decode_level(level, &mut bits) else { continue };
+            let mut decoded = vec![first.unwrap()];
+            for _ in 1..symbols.len() {
+                decoded.push(table.decode_level(level, &mut bits).unwrap().unwrap());
+            }
+            assert_eq!(decoded, symbols, "fast-decode level {}", level);
+            levels += 1;
+        }
+        // 同时启用 fast-decode-0 和 fast-decode-2 时三个级别都编译进来，在同一个测试中互相比对
+        let compiled = [
+            cfg!(any(feature = "fast-decode-0", not(any(feature = "fast-decode-1", feature = "fast-decode-2")))),
+            cfg!(any(feature = "fast-decode-1", feature = "fast-decode-2", not(feature = "fast-decode-0"))),
+            cfg!(feature = "fast-decode-2"),
+        ];
+        assert_eq!(levels, compiled.iter().filter(|&&c| c).count());
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut pool_buffer = vec![0u8; 8192];
+        let mut pool = MemoryPool::new(&mut pool_buffer);
+        let mut table = HuffmanTable::create_in_pool(&mut pool, &BITS, &VALUES).unwrap();
+        assert_eq!(table.code_lengths(), &BITS);
+        assert_eq!(table.validate(), Ok(()));
+
+        // 非规范码字、码字顺序颠倒、计数与 num_codes 不符
+        table.codes[5] += 1;
+        assert_eq!(table.validate(), Err(Error::FormatError));
+        table.codes[5] -= 1;
+        table.codes.swap(0, 1);
+        assert_eq!(table.validate(), Err(Error::FormatError));
+        table.codes.swap(0, 1);
+        table.bits[15] += 1;
+        assert_eq!(table.validate(), Err(Error::FormatError));
+        table.bits[15] -= 1;
+        table.num_codes -= 1;
+        assert_eq!(table.validate(), Err(Error::FormatError));
+        table.num_codes += 1;
+        assert_eq!(table.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_oversubscribed_table_rejected() {
+        let mut pool_buffer = vec![0u8; 8192];
+
+        // 两个 1 位码字恰好占满编码空间，仍然合法
+        let mut bits = [0u8; 16];
+        bits[0] = 2;
+        let mut pool = MemoryPool::new(&mut pool_buffer);
+        assert!(HuffmanTable::create_in_pool(&mut pool, &bits, &[0, 1]).is_ok());
+
+        // 三个 1 位码字；2 位码字占满后再出现 16 位码字
+        bits[0] = 3;
+        let mut pool = MemoryPool::new(&mut pool_buffer);
+        assert_eq!(HuffmanTable::create_in_pool(&mut pool, &bits, &[0, 1, 2]).err(), Some(Error::FormatError));
+
+        let mut bits = [0u8; 16];
+        bits[1] = 4;
+        bits[15] = 1;
+        let mut pool = MemoryPool::new(&mut pool_buffer);
+        assert_eq!(HuffmanTable::create_in_pool(&mut pool, &bits, &[0; 5]).err(), Some(Error::FormatError));
+    }
+}
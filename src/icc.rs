@@ -0,0 +1,179 @@
+//! Embedded ICC profile support
+//!
+//! ICC profiles are stored in one or more APP2 segments tagged
+//! `ICC_PROFILE\0`, each carrying a 1-based sequence number and the total
+//! chunk count. `icc_profile()` reassembles them into a caller buffer.
+//!
+//! Color management itself is out of scope for this crate. With the `icc`
+//! feature, `to_srgb()` hands the profile and decoded RGB888 pixels to a
+//! user-supplied CMM (e.g. a thin wrapper around `lcms2` or `qcms`).
+
+use crate::marker::scan_markers;
+use crate::types::{Error, Result};
+
+/// APP2 标识
+const ICC_TAG: &[u8; 12] = b"ICC_PROFILE\0";
+
+/// 遍历 SOS 之前的所有 APP2 ICC 块：(序号, 总块数, 数据)
+fn for_each_chunk(data: &[u8], mut f: impl FnMut(u8, u8, &[u8]) -> Result<()>) -> Result<()> {
+    scan_markers(data, |marker, segment| {
+        if marker == 0xE2 && segment.len() >= 14 && segment[..12] == ICC_TAG[..] {
+            f(segment[12], segment[13], &segment[14..])?;
+        }
+        Ok(true)
+    })
+}
+
+/// Get the size of the embedded ICC profile in bytes
+///
+/// Returns `Ok(0)` if the image has no profile.
+pub fn icc_profile_size(data: &[u8]) -> Result<usize> {
+    let mut size = 0;
+    for_each_chunk(data, |_, _, chunk| {
+        size += chunk.len();
+        Ok(())
+    })?;
+    Ok(size)
+}
+
+/// Reassemble the embedded ICC profile
+///
+/// Copies the chunks in sequence order into `out` and returns the profile
+/// length (`0` if the image has no profile).
+///
+/// # Parameters
+///
+/// * `data` - JPEG file data (only the headers before SOS are scanned)
+/// * `out` - Destination buffer, at least `icc_profile_size()` bytes
+///
+/// # Returns
+///
+/// `Error::InsufficientMemory` if `out` is too small, `Error::FormatError`
+/// if chunks are missing, duplicated or disagree on the chunk count.
+pub fn icc_profile(data: &[u8], out: &mut [u8]) -> Result<usize> {
+    let size = icc_profile_size(data)?;
+    if size == 0 {
+        return Ok(0);
+    }
+    if out.len() < size {
+        return Err(Error::InsufficientMemory);
+    }
+
+    let mut count = 0u8;
+    for_each_chunk(data, |_, total, _| {
+        if total == 0 || (count != 0 && total != count) {
+            return Err(Error::FormatError);
+        }
+        count = total;
+        Ok(())
+    })?;
+
+    // 块可能乱序：按序号依次拷贝
+    let mut written = 0;
+    for seq in 1..=count {
+        let mut found = false;
+        for_each_chunk(data, |n, _, chunk| {
+            if n == seq {
+                if found {
+                    return Err(Error::FormatError);
+                }
+                found = true;
+                out[written..written + chunk.len()].copy_from_slice(chunk);
+                written += chunk.len();
+            }
+            Ok(())
+        })?;
+        if !found {
+            return Err(Error::FormatError);
+        }
+    }
+
+    if written != size {
+        return Err(Error::FormatError);
+    }
+    Ok(written)
+}
+
+/// Color management module used by `to_srgb()`
+///
+/// Implement this on top of a CMS crate such as `lcms2` or `qcms`.
+#[cfg(feature = "icc")]
+pub trait ColorManagement {
+    /// Convert interleaved RGB888 pixels from `profile` to sRGB in place
+    fn transform_to_srgb(&mut self, profile: &[u8], rgb: &mut [u8]) -> Result<()>;
+}
+
+/// Convert decoded RGB888 pixels to sRGB using the embedded profile
+///
+/// `rgb_buffer` holds interleaved RGB888 pixels (e.g. a full framebuffer or
+/// one callback bitmap). An empty `profile` means the image is already
+/// treated as sRGB and the buffer is left untouched.
+#[cfg(feature = "icc")]
+pub fn to_srgb<C: ColorManagement>(rgb_buffer: &mut [u8], profile: &[u8], cms: &mut C) -> Result<()> {
+    if !rgb_buffer.len().is_multiple_of(3) {
+        return Err(Error::Parameter);
+    }
+    if profile.is_empty() {
+        return Ok(());
+    }
+    cms.transform_to_srgb(profile, rgb_buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{encode, pattern, EncodeOptions};
+
+    /// 在 SOI 之后插入 APP2 ICC 块
+    fn with_icc(jpeg: &[u8], chunks: &[(u8, u8, &[u8])]) -> Vec<u8> {
+        let mut out = jpeg[..2].to_vec();
+        for &(seq, total, payload) in chunks {
+            let length = (2 + 14 + payload.len()) as u16;
+            out.extend_from_slice(&[0xFF, 0xE2]);
+            out.extend_from_slice(&length.to_be_bytes());
+            out.extend_from_slice(ICC_TAG);
+            out.extend_from_slice(&[seq, total]);
+            out.extend_from_slice(payload);
+        }
+        out.extend_from_slice(&jpeg[2..]);
+        out
+    }
+
+    #[test]
+    fn test_icc_profile_reassembly() {
+        let jpeg = encode(8, 8, &pattern(8, 8, 3), &EncodeOptions::default());
+        let mut out = [0u8; 64];
+        assert_eq!(icc_profile(&jpeg, &mut out), Ok(0));
+
+        // 乱序存放的 3 个块
+        let tagged = with_icc(&jpeg, &[(2, 3, b"def"), (1, 3, b"abc"), (3, 3, b"gh")]);
+        assert_eq!(icc_profile_size(&tagged), Ok(8));
+        assert_eq!(icc_profile(&tagged, &mut out), Ok(8));
+        assert_eq!(&out[..8], b"abcdefgh");
+        assert_eq!(icc_profile(&tagged, &mut out[..7]), Err(Error::InsufficientMemory));
+
+        let missing = with_icc(&jpeg, &[(1, 3, b"abc"), (3, 3, b"gh")]);
+        assert_eq!(icc_profile(&missing, &mut out), Err(Error::FormatError));
+    }
+
+    #[cfg(feature = "icc")]
+    #[test]
+    fn test_to_srgb_adapter() {
+        struct Invert;
+
+        impl ColorManagement for Invert {
+            fn transform_to_srgb(&mut self, profile: &[u8], rgb: &mut [u8]) -> Result<()> {
+                assert_eq!(profile, b"profile");
+                rgb.iter_mut().for_each(|v| *v = 255 - *v);
+                Ok(())
+            }
+        }
+
+        let mut rgb = [0u8, 128, 255];
+        to_srgb(&mut rgb, b"profile", &mut Invert).unwrap();
+        assert_eq!(rgb, [255, 127, 0]);
+        to_srgb(&mut rgb, b"", &mut Invert).unwrap();
+        assert_eq!(rgb, [255, 127, 0]);
+        assert_eq!(to_srgb(&mut rgb[..2], b"profile", &mut Invert), Err(Error::Parameter));
+    }
+}
@@ -5,6 +5,7 @@
 
 
 // Arai algorithm rotation constants (scaled by 4096 for fixed-point math)
+#[allow(clippy::approx_constant)]
 const M13: i32 = (1.41421 * 4096.0) as i32;  // sqrt(2) * 4096
 const M2: i32 = (1.08239 * 4096.0) as i32;   // 1.08239 * 4096
 const M4: i32 = (2.61313 * 4096.0) as i32;   // 2.61313 * 4096
@@ -13,8 +14,45 @@ const M5: i32 = (1.84776 * 4096.0) as i32;   // 1.84776 * 4096
 /// Perform 8x8 IDCT on a block using Arai algorithm
 /// Input: src - de-quantized and pre-scaled block data (already in raster order)
 /// Output: dst - transformed block as byte array (0-255)
+/// 
+/// With the `simd` feature, x86_64 (SSE2) and aarch64 (NEON) use a vector
+/// version with bit-identical results.
+#[inline]
 pub fn block_idct(src: &mut [i32; 64], dst: &mut [i16; 64]) {
-    // Process columns
+    #[cfg(all(feature = "simd", any(target_arch = "x86_64", target_arch = "aarch64")))]
+    simd::block_idct(src, dst);
+    #[cfg(not(all(feature = "simd", any(target_arch = "x86_64", target_arch = "aarch64"))))]
+    block_idct_scalar(src, dst);
+}
+
+/// Scalar 8x8 IDCT, the reference for the SIMD version
+/// 
+/// The column pass writes its intermediate results back into `src`.
+pub fn block_idct_scalar(src: &mut [i32; 64], dst: &mut [i16; 64]) {
+    idct_columns(src);
+    for (row, out) in src.chunks_exact(8).zip(dst.chunks_exact_mut(8)) {
+        for (d, v) in out.iter_mut().zip(idct_row(row)) {
+            *d = v as i16;
+        }
+    }
+}
+
+/// Perform 8x8 IDCT in place
+/// 
+/// Same numerics as `block_idct()`, but the final samples (in `i16` range)
+/// are written back over `block`, so no separate output block is needed.
+pub fn block_idct_in_place(block: &mut [i32; 64]) {
+    idct_columns(block);
+    for row in block.chunks_exact_mut(8) {
+        let out = idct_row(row);
+        row.copy_from_slice(&out);
+    }
+}
+
+/// 列变换，结果写回 `src`
+#[allow(clippy::identity_op, clippy::erasing_op)]
+#[inline(always)]
+fn idct_columns(src: &mut [i32; 64]) {
     for i in 0..8 {
         let base = i;
         
@@ -64,76 +102,354 @@ pub fn block_idct(src: &mut [i32; 64], dst: &mut [i16; 64]) {
         src[base + 8 * 3] = v3 + v4;
         src[base + 8 * 4] = v3 - v4;
     }
+}
 
-    // Process rows
-    for i in 0..8 {
-        let base = i * 8;
-        
-        // Get even elements (add DC offset removal for row 0)
-        let v0 = src[base + 0] + (128_i32 << 8);
-        let v1 = src[base + 2];
-        let v2 = src[base + 4];
-        let v3 = src[base + 6];
+/// 一行的行变换，返回去掉 8 位缩放后的 8 个样本
+#[inline(always)]
+fn idct_row(src: &[i32]) -> [i32; 8] {
+    // Get even elements (add DC offset removal for row 0)
+    let v0 = src[0] + (128_i32 << 8);
+    let v1 = src[2];
+    let v2 = src[4];
+    let v3 = src[6];
 
-        // Process the even elements
-        let t10 = v0 + v2;
-        let t12 = v0 - v2;
-        let mut t11 = ((v1 - v3) * M13) >> 12;
-        let mut v3 = v3 + v1;
-        t11 -= v3;
-        let v0 = t10 + v3;
-        v3 = t10 - v3;
-        let v1 = t11 + t12;
-        let v2 = t12 - t11;
+    // Process the even elements
+    let t10 = v0 + v2;
+    let t12 = v0 - v2;
+    let mut t11 = ((v1 - v3) * M13) >> 12;
+    let mut v3 = v3 + v1;
+    t11 -= v3;
+    let v0 = t10 + v3;
+    v3 = t10 - v3;
+    let v1 = t11 + t12;
+    let v2 = t12 - t11;
 
-        // Get odd elements
-        let v4_odd = src[base + 7];
-        let v5_odd = src[base + 1];
-        let v6_odd = src[base + 5];
-        let v7_odd = src[base + 3];
+    // Get odd elements
+    let v4_odd = src[7];
+    let v5_odd = src[1];
+    let v6_odd = src[5];
+    let v7_odd = src[3];
 
-        // Process the odd elements
-        let t10 = v5_odd - v4_odd;
-        let t11 = v5_odd + v4_odd;
-        let t12 = v6_odd - v7_odd;
-        let mut v7 = v7_odd + v6_odd;
-        let mut v5 = ((t11 - v7) * M13) >> 12;
-        v7 += t11;
-        let t13 = ((t10 + t12) * M5) >> 12;
-        let mut v4 = t13 - ((t10 * M2) >> 12);
-        let v6 = t13 - ((t12 * M4) >> 12) - v7;
-        v5 -= v6;
-        v4 -= v5;
+    // Process the odd elements
+    let t10 = v5_odd - v4_odd;
+    let t11 = v5_odd + v4_odd;
+    let t12 = v6_odd - v7_odd;
+    let mut v7 = v7_odd + v6_odd;
+    let mut v5 = ((t11 - v7) * M13) >> 12;
+    v7 += t11;
+    let t13 = ((t10 + t12) * M5) >> 12;
+    let mut v4 = t13 - ((t10 * M2) >> 12);
+    let v6 = t13 - ((t12 * M4) >> 12) - v7;
+    v5 -= v6;
+    v4 -= v5;
+
+    // Descale the transformed values 8 bits and output
+    [
+        (v0 + v7) >> 8,
+        (v1 + v6) >> 8,
+        (v2 + v5) >> 8,
+        (v3 + v4) >> 8,
+        (v3 - v4) >> 8,
+        (v2 - v5) >> 8,
+        (v1 - v6) >> 8,
+        (v0 - v7) >> 8,
+    ]
+}
+
+/// SIMD 版本：一个向量处理 4 行（列变换）或 4 列（行变换转置后），
+/// 蝶形运算与标量版本逐步相同，结果逐位一致
+#[cfg(all(feature = "simd", any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod simd {
+    use super::{M13, M2, M4, M5};
+
+    #[cfg(target_arch = "aarch64")]
+    use core::arch::aarch64::*;
+    #[cfg(target_arch = "x86_64")]
+    use core::arch::x86_64::*;
+
+    /// 4 个 i32 通道
+    #[derive(Clone, Copy)]
+    struct V(#[cfg(target_arch = "x86_64")] __m128i, #[cfg(target_arch = "aarch64")] int32x4_t);
 
-        // Descale the transformed values 8 bits and output
-        dst[base + 0] = ((v0 + v7) >> 8) as i16;
-        dst[base + 7] = ((v0 - v7) >> 8) as i16;
-        dst[base + 1] = ((v1 + v6) >> 8) as i16;
-        dst[base + 6] = ((v1 - v6) >> 8) as i16;
-        dst[base + 2] = ((v2 + v5) >> 8) as i16;
-        dst[base + 5] = ((v2 - v5) >> 8) as i16;
-        dst[base + 3] = ((v3 + v4) >> 8) as i16;
-        dst[base + 4] = ((v3 - v4) >> 8) as i16;
+    // SSE2 和 NEON 分别是 x86_64 和 aarch64 的基础指令集，总是可用
+    #[cfg(target_arch = "x86_64")]
+    impl V {
+        #[inline(always)]
+        fn load(src: &[i32]) -> Self {
+            debug_assert!(src.len() >= 4);
+            V(unsafe { _mm_loadu_si128(src.as_ptr() as *const __m128i) })
+        }
+        #[inline(always)]
+        fn store(self, dst: &mut [i32]) {
+            debug_assert!(dst.len() >= 4);
+            unsafe { _mm_storeu_si128(dst.as_mut_ptr() as *mut __m128i, self.0) }
+        }
+        #[inline(always)]
+        fn splat(v: i32) -> Self {
+            V(unsafe { _mm_set1_epi32(v) })
+        }
+        #[inline(always)]
+        fn add(self, o: Self) -> Self {
+            V(unsafe { _mm_add_epi32(self.0, o.0) })
+        }
+        #[inline(always)]
+        fn sub(self, o: Self) -> Self {
+            V(unsafe { _mm_sub_epi32(self.0, o.0) })
+        }
+        /// `(v * m) >> 12`，乘积取低 32 位（SSE2 没有 32 位乘法，用两次 32x32->64 拼出）
+        #[inline(always)]
+        fn mul_shr12(self, m: i32) -> Self {
+            unsafe {
+                let m = _mm_set1_epi32(m);
+                let even = _mm_mul_epu32(self.0, m);
+                let odd = _mm_mul_epu32(_mm_srli_epi64(self.0, 32), m);
+                let lo = _mm_unpacklo_epi32(_mm_shuffle_epi32(even, 0b00_00_10_00), _mm_shuffle_epi32(odd, 0b00_00_10_00));
+                V(_mm_srai_epi32(lo, 12))
+            }
+        }
+        #[inline(always)]
+        fn shr8(self) -> Self {
+            V(unsafe { _mm_srai_epi32(self.0, 8) })
+        }
+        #[inline(always)]
+        fn transpose(a: Self, b: Self, c: Self, d: Self) -> [Self; 4] {
+            unsafe {
+                let ab_lo = _mm_unpacklo_epi32(a.0, b.0);
+                let ab_hi = _mm_unpackhi_epi32(a.0, b.0);
+                let cd_lo = _mm_unpacklo_epi32(c.0, d.0);
+                let cd_hi = _mm_unpackhi_epi32(c.0, d.0);
+                [
+                    V(_mm_unpacklo_epi64(ab_lo, cd_lo)),
+                    V(_mm_unpackhi_epi64(ab_lo, cd_lo)),
+                    V(_mm_unpacklo_epi64(ab_hi, cd_hi)),
+                    V(_mm_unpackhi_epi64(ab_hi, cd_hi)),
+                ]
+            }
+        }
+        /// 截断为 i16（与 `as i16` 相同，不饱和）后存入 8 个样本
+        #[inline(always)]
+        fn store_i16(lo: Self, hi: Self, dst: &mut [i16]) {
+            debug_assert!(dst.len() >= 8);
+            unsafe {
+                let trunc = |v: __m128i| _mm_srai_epi32(_mm_slli_epi32(v, 16), 16);
+                let packed = _mm_packs_epi32(trunc(lo.0), trunc(hi.0));
+                _mm_storeu_si128(dst.as_mut_ptr() as *mut __m128i, packed);
+            }
+        }
     }
+
+    #[cfg(target_arch = "aarch64")]
+    impl V {
+        #[inline(always)]
+        fn load(src: &[i32]) -> Self {
+            debug_assert!(src.len() >= 4);
+            V(unsafe { vld1q_s32(src.as_ptr()) })
+        }
+        #[inline(always)]
+        fn store(self, dst: &mut [i32]) {
+            debug_assert!(dst.len() >= 4);
+            unsafe { vst1q_s32(dst.as_mut_ptr(), self.0) }
+        }
+        #[inline(always)]
+        fn splat(v: i32) -> Self {
+            V(unsafe { vdupq_n_s32(v) })
+        }
+        #[inline(always)]
+        fn add(self, o: Self) -> Self {
+            V(unsafe { vaddq_s32(self.0, o.0) })
+        }
+        #[inline(always)]
+        fn sub(self, o: Self) -> Self {
+            V(unsafe { vsubq_s32(self.0, o.0) })
+        }
+        /// `(v * m) >> 12`，乘积取低 32 位
+        #[inline(always)]
+        fn mul_shr12(self, m: i32) -> Self {
+            V(unsafe { vshrq_n_s32(vmulq_n_s32(self.0, m), 12) })
+        }
+        #[inline(always)]
+        fn shr8(self) -> Self {
+            V(unsafe { vshrq_n_s32(self.0, 8) })
+        }
+        #[inline(always)]
+        fn transpose(a: Self, b: Self, c: Self, d: Self) -> [Self; 4] {
+            unsafe {
+                let ab = vtrnq_s32(a.0, b.0);
+                let cd = vtrnq_s32(c.0, d.0);
+                [
+                    V(vcombine_s32(vget_low_s32(ab.0), vget_low_s32(cd.0))),
+                    V(vcombine_s32(vget_low_s32(ab.1), vget_low_s32(cd.1))),
+                    V(vcombine_s32(vget_high_s32(ab.0), vget_high_s32(cd.0))),
+                    V(vcombine_s32(vget_high_s32(ab.1), vget_high_s32(cd.1))),
+                ]
+            }
+        }
+        /// 截断为 i16（与 `as i16` 相同，不饱和）后存入 8 个样本
+        #[inline(always)]
+        fn store_i16(lo: Self, hi: Self, dst: &mut [i16]) {
+            debug_assert!(dst.len() >= 8);
+            unsafe { vst1q_s16(dst.as_mut_ptr(), vcombine_s16(vmovn_s32(lo.0), vmovn_s32(hi.0))) }
+        }
+    }
+
+    /// 一维 8 点变换：`x[k]` 为第 k 个系数，返回第 k 个样本
+    #[inline(always)]
+    fn idct_1d(x: [V; 8]) -> [V; 8] {
+        // 偶数部分
+        let (v0, v1, v2, v3) = (x[0], x[2], x[4], x[6]);
+        let t10 = v0.add(v2);
+        let t12 = v0.sub(v2);
+        let t11 = v1.sub(v3).mul_shr12(M13);
+        let v3 = v3.add(v1);
+        let t11 = t11.sub(v3);
+        let v0 = t10.add(v3);
+        let v3 = t10.sub(v3);
+        let v1 = t11.add(t12);
+        let v2 = t12.sub(t11);
+
+        // 奇数部分
+        let (v4, v5, v6, v7) = (x[7], x[1], x[5], x[3]);
+        let t10 = v5.sub(v4);
+        let t11 = v5.add(v4);
+        let t12 = v6.sub(v7);
+        let v7 = v7.add(v6);
+        let v5 = t11.sub(v7).mul_shr12(M13);
+        let v7 = v7.add(t11);
+        let t13 = t10.add(t12).mul_shr12(M5);
+        let v4 = t13.sub(t10.mul_shr12(M2));
+        let v6 = t13.sub(t12.mul_shr12(M4)).sub(v7);
+        let v5 = v5.sub(v6);
+        let v4 = v4.sub(v5);
+
+        [v0.add(v7), v1.add(v6), v2.add(v5), v3.add(v4), v3.sub(v4), v2.sub(v5), v1.sub(v6), v0.sub(v7)]
+    }
+
+    /// 把 4 行（每行两个半行）转置为 8 列，每列是这 4 行中的值
+    #[inline(always)]
+    fn transpose(rows: [[V; 2]; 4]) -> [V; 8] {
+        let [a0, a1, a2, a3] = V::transpose(rows[0][0], rows[1][0], rows[2][0], rows[3][0]);
+        let [b0, b1, b2, b3] = V::transpose(rows[0][1], rows[1][1], rows[2][1], rows[3][1]);
+        [a0, a1, a2, a3, b0, b1, b2, b3]
+    }
+
+    pub fn block_idct(src: &mut [i32; 64], dst: &mut [i16; 64]) {
+        // 列变换：每个向量是一行中的 4 列，结果和标量版本一样写回 src
+        for h in 0..2 {
+            let y = idct_1d(core::array::from_fn(|k| V::load(&src[k * 8 + h * 4..])));
+            for (k, v) in y.into_iter().enumerate() {
+                v.store(&mut src[k * 8 + h * 4..]);
+            }
+        }
+
+        // 行变换：每次 4 行，转置后每个向量是一列中的 4 行
+        let offset = V::splat(128 << 8);
+        for (rows, out) in src.chunks_exact(32).zip(dst.chunks_exact_mut(32)) {
+            let mut x = transpose(core::array::from_fn(|r| [V::load(&rows[r * 8..]), V::load(&rows[r * 8 + 4..])]));
+            x[0] = x[0].add(offset);
+            let y = idct_1d(x).map(V::shr8);
+
+            // 转置回来，每个向量是一行中的 4 个样本
+            let [r0, r1, r2, r3] = V::transpose(y[0], y[1], y[2], y[3]);
+            let [s0, s1, s2, s3] = V::transpose(y[4], y[5], y[6], y[7]);
+            for (r, (lo, hi)) in [(r0, s0), (r1, s1), (r2, s2), (r3, s3)].into_iter().enumerate() {
+                V::store_i16(lo, hi, &mut out[r * 8..]);
+            }
+        }
+    }
+}
+
+/// Fill a block whose AC coefficients are all zero
+/// 
+/// Equivalent to `block_idct()` when only `src[0]` is non-zero: both passes
+/// pass the DC term through unchanged, so every sample is the DC value plus
+/// the level shift.
+#[inline]
+pub fn block_idct_dc(dc: i32, dst: &mut [i16; 64]) {
+    dst.fill(((dc + (128_i32 << 8)) >> 8) as i16);
 }
 
 /// YCbCr to RGB color space conversion
 pub mod color {
-    use crate::tables::{byte_clip, CB_TO_B, CB_TO_G, CR_TO_G, CR_TO_R, CVACC};
+    use crate::tables::{byte_clip, BT709_COEFFS, CB_TO_B, CB_TO_G, CR_TO_G, CR_TO_R, CVACC, FULL601_COEFFS, YCC_BITS};
+    use crate::types::{ChromaSiting, ChromaUpsample, ColorMatrix, GamutMode};
+
+    /// Convert YCbCr to unclipped RGB with the given matrix
+    #[inline]
+    fn ycbcr_to_rgb_unclipped(y: i32, cb: i32, cr: i32, matrix: ColorMatrix) -> [i32; 3] {
+        let [cr_r, cb_g, cr_g, cb_b] = match matrix {
+            ColorMatrix::Bt601 => {
+                return [
+                    y + (CR_TO_R * cr) / CVACC,
+                    y - (CB_TO_G * cb + CR_TO_G * cr) / CVACC,
+                    y + (CB_TO_B * cb) / CVACC,
+                ];
+            }
+            ColorMatrix::Bt709 => BT709_COEFFS,
+            ColorMatrix::Full601 => FULL601_COEFFS,
+        };
+        let half = 1 << (YCC_BITS - 1);
+        [
+            y + ((cr_r * cr + half) >> YCC_BITS),
+            y + ((half - cb_g * cb - cr_g * cr) >> YCC_BITS),
+            y + ((cb_b * cb + half) >> YCC_BITS),
+        ]
+    }
 
     /// Convert YCbCr to RGB888
     #[inline]
-    pub fn ycbcr_to_rgb(y: i32, cb: i32, cr: i32) -> [u8; 3] {
-        let r = y + (CR_TO_R * cr) / CVACC;
-        let g = y - (CB_TO_G * cb + CR_TO_G * cr) / CVACC;
-        let b = y + (CB_TO_B * cb) / CVACC;
+    pub fn ycbcr_to_rgb(y: i32, cb: i32, cr: i32, matrix: ColorMatrix) -> [u8; 3] {
+        ycbcr_to_rgb_unclipped(y, cb, cr, matrix).map(byte_clip)
+    }
+
+    /// Convert YCbCr to RGB888, scaling out-of-range colors
+    /// 
+    /// If any channel exceeds 255, all three are scaled by `255 / max` so the
+    /// ratios between channels (and thus the hue) are kept. Negative values
+    /// are clamped to 0 afterwards.
+    #[inline]
+    pub fn ycbcr_to_rgb_scaled(y: i32, cb: i32, cr: i32, matrix: ColorMatrix) -> [u8; 3] {
+        let [mut r, mut g, mut b] = ycbcr_to_rgb_unclipped(y, cb, cr, matrix);
+
+        let max = r.max(g).max(b);
+        if max > 255 {
+            r = r * 255 / max;
+            g = g * 255 / max;
+            b = b * 255 / max;
+        }
 
         [byte_clip(r), byte_clip(g), byte_clip(b)]
     }
 
+    /// Convert CMYK to RGB888
+    /// 
+    /// Adobe files (those with an APP14 marker) store inverted values, i.e.
+    /// `255 - ink`; pass `inverted` for them. The conversion is the naive
+    /// `(1 - C) * (1 - K)` per channel, no color management.
+    #[cfg(feature = "cmyk")]
+    #[inline]
+    pub fn cmyk_to_rgb(c: u8, m: u8, y: u8, k: u8, inverted: bool) -> [u8; 3] {
+        // 统一成"无墨 = 255"后相乘，x * y / 255 取整
+        let (c, m, y, k) = if inverted { (c, m, y, k) } else { (255 - c, 255 - m, 255 - y, 255 - k) };
+        let mul = |a: u8| {
+            let t = a as u32 * k as u32 + 128;
+            ((t + (t >> 8)) >> 8) as u8
+        };
+        [mul(c), mul(m), mul(y)]
+    }
+
+    /// Convert YCCK to RGB888
+    /// 
+    /// YCCK stores `255 - C/M/Y` as YCbCr; K is kept as is. `inverted` has
+    /// the same meaning as for `cmyk_to_rgb()`.
+    #[cfg(feature = "cmyk")]
+    #[inline]
+    pub fn ycck_to_rgb(y: i32, cb: i32, cr: i32, k: u8, inverted: bool, matrix: ColorMatrix) -> [u8; 3] {
+        let [r, g, b] = ycbcr_to_rgb(y, cb, cr, matrix);
+        cmyk_to_rgb(255 - r, 255 - g, 255 - b, k, inverted)
+    }
+
     /// Convert RGB888 to RGB565
     #[inline]
-    #[allow(dead_code)]
     pub fn rgb888_to_rgb565(r: u8, g: u8, b: u8) -> u16 {
         let r5 = (r & 0xF8) as u16;
         let g6 = (g & 0xFC) as u16;
@@ -142,14 +458,20 @@ pub mod color {
         (r5 << 8) | (g6 << 3) | (b5 >> 3)
     }
 
+    /// Convert RGB888 to BGR565 (blue in the high bits)
+    #[inline]
+    pub fn rgb888_to_bgr565(r: u8, g: u8, b: u8) -> u16 {
+        rgb888_to_rgb565(b, g, r)
+    }
+
     /// Convert RGB565 to swapped byte order (for displays)
     #[inline]
-    #[allow(dead_code)]
     pub fn swap_rgb565(color: u16) -> u16 {
-        (color << 8) | (color >> 8)
+        color.swap_bytes()
     }
 
     /// Process MCU block for RGB output
+    #[allow(clippy::too_many_arguments)]
     pub fn mcu_to_rgb(
         y_block: &[i16],
         cb_block: &[i16],
@@ -157,62 +479,139 @@ pub mod color {
         output: &mut [u8],
         mcu_width: usize,
         mcu_height: usize,
+        out_width: usize,
+        out_height: usize,
+        sampling_h: usize,
+        sampling_v: usize,
+        gamut: GamutMode,
+        matrix: ColorMatrix,
+        siting: ChromaSiting,
+        upsample: ChromaUpsample,
+    ) {
+        debug_assert!(output.len() >= out_width * out_height * 3, "work buffer smaller than the visible RGB MCU");
+        mcu_for_each_rgb(
+            y_block, cb_block, cr_block, mcu_width, mcu_height, out_width, out_height, sampling_h, sampling_v, gamut, matrix,
+            siting, upsample,
+            |i, rgb| output[i * 3..i * 3 + 3].copy_from_slice(&rgb),
+        );
+    }
+
+    /// Convert an MCU to RGB, handing each pixel to `put` in raster order
+    /// 
+    /// Only the top-left `out_width` x `out_height` pixels are converted
+    /// (the visible part of an edge MCU); `put` receives the pixel index
+    /// within that area and its RGB value, so callers can pack straight into
+    /// a narrower format.
+    #[allow(clippy::too_many_arguments)]
+    #[inline]
+    pub fn mcu_for_each_rgb(
+        y_block: &[i16],
+        cb_block: &[i16],
+        cr_block: &[i16],
+        mcu_width: usize,
+        mcu_height: usize,
+        out_width: usize,
+        out_height: usize,
         sampling_h: usize,
         sampling_v: usize,
+        gamut: GamutMode,
+        matrix: ColorMatrix,
+        siting: ChromaSiting,
+        upsample: ChromaUpsample,
+        mut put: impl FnMut(usize, [u8; 3]),
     ) {
+        // 色度只有一个 8x8 块：4:2:2 下 abs_x 为 0..16、abs_y 为 0..8，除以采样因子后都落在 0..8 内
+        debug_assert!(mcu_width * 8 / sampling_h <= 8 && mcu_height * 8 / sampling_v <= 8, "chroma block smaller than the MCU");
+        debug_assert!(out_width <= mcu_width * 8 && out_height <= mcu_height * 8);
         let mut out_idx = 0;
 
-        for block_y in 0..mcu_height {
-            for y in 0..8 {
-                let abs_y = block_y * 8 + y;
+        for abs_y in 0..out_height {
+            let (block_y, y) = (abs_y / 8, abs_y % 8);
+
+            for abs_x in 0..out_width {
+                let (block_x, x) = (abs_x / 8, abs_x % 8);
                 
-                for block_x in 0..mcu_width {
-                    for x in 0..8 {
-                        let abs_x = block_x * 8 + x;
-                        
-                        // Get Y component
-                        let y_idx = (block_y * mcu_width + block_x) * 64 + y * 8 + x;
-                        let yy = y_block[y_idx] as i32;
-
-                        // Get Cb/Cr components (subsampled)
-                        let cb_x = abs_x / sampling_h;
-                        let cb_y = abs_y / sampling_v;
-                        let cb_idx = cb_y * 8 + cb_x;
-                        
-                        let cb = cb_block[cb_idx] as i32 - 128;
-                        let cr = cr_block[cb_idx] as i32 - 128;
-
-                        // Convert to RGB
-                        let rgb = ycbcr_to_rgb(yy, cb, cr);
-                        
-                        output[out_idx] = rgb[0];
-                        output[out_idx + 1] = rgb[1];
-                        output[out_idx + 2] = rgb[2];
-                        out_idx += 3;
+                // Get Y component
+                let y_idx = (block_y * mcu_width + block_x) * 64 + y * 8 + x;
+                let yy = y_block[y_idx] as i32;
+
+                // Get Cb/Cr components (subsampled)
+                let (cb, cr) = match siting {
+                    ChromaSiting::Centered if upsample == ChromaUpsample::Bilinear => {
+                        // 较近的色度样本权重 3，另一侧 1；两个方向的权重之积共 16
+                        let (x0, x1) = fancy_taps(abs_x, sampling_h);
+                        let (y0, y1) = fancy_taps(abs_y, sampling_v);
+                        let tap = |block: &[i16]| {
+                            let row = |y: usize| 3 * block[y * 8 + x0] as i32 + block[y * 8 + x1] as i32;
+                            ((3 * row(y0) + row(y1) + 8) >> 4) - 128
+                        };
+                        (tap(cb_block), tap(cr_block))
+                    }
+                    ChromaSiting::Centered => {
+                        let cb_idx = (abs_y / sampling_v) * 8 + abs_x / sampling_h;
+                        (cb_block[cb_idx] as i32 - 128, cr_block[cb_idx] as i32 - 128)
                     }
-                }
+                    ChromaSiting::Cosited => {
+                        // 位于两个色度样本之间的亮度取两者平均，MCU 边缘处钳位到最后一个样本
+                        let x0 = abs_x / sampling_h;
+                        let y0 = abs_y / sampling_v;
+                        let x1 = if abs_x % sampling_h != 0 { (x0 + 1).min(7) } else { x0 };
+                        let y1 = if abs_y % sampling_v != 0 { (y0 + 1).min(7) } else { y0 };
+                        let idx = [y0 * 8 + x0, y0 * 8 + x1, y1 * 8 + x0, y1 * 8 + x1];
+                        let cb: i32 = idx.iter().map(|&i| cb_block[i] as i32).sum();
+                        let cr: i32 = idx.iter().map(|&i| cr_block[i] as i32).sum();
+                        ((cb + 2) / 4 - 128, (cr + 2) / 4 - 128)
+                    }
+                };
+
+                // Convert to RGB
+                let rgb = match gamut {
+                    GamutMode::Clamp => ycbcr_to_rgb(yy, cb, cr, matrix),
+                    GamutMode::Scale => ycbcr_to_rgb_scaled(yy, cb, cr, matrix),
+                };
+
+                put(out_idx, rgb);
+                out_idx += 1;
             }
         }
     }
 
+    /// 居中采样时亮度位置 `pos` 的 (较近, 较远) 两个色度样本下标
+    /// 
+    /// 2 倍采样时色度样本位于两个亮度样本之间：偶数位置的较远样本在左（上），
+    /// 奇数位置在右（下），MCU 边缘钳位。不采样的方向两者相同。
+    #[inline]
+    fn fancy_taps(pos: usize, factor: usize) -> (usize, usize) {
+        let near = pos / factor;
+        if factor == 1 {
+            (near, near)
+        } else if pos.is_multiple_of(2) {
+            (near, near.saturating_sub(1))
+        } else {
+            (near, (near + 1).min(7))
+        }
+    }
+
     /// Process MCU block for grayscale output
+    /// 
+    /// Writes the top-left `out_width` x `out_height` pixels as tightly
+    /// packed rows.
     pub fn mcu_to_grayscale(
         y_block: &[i16],
         output: &mut [u8],
         mcu_width: usize,
         mcu_height: usize,
+        out_width: usize,
+        out_height: usize,
     ) {
-        let mut out_idx = 0;
+        debug_assert!(out_width <= mcu_width * 8 && out_height <= mcu_height * 8);
+        debug_assert!(output.len() >= out_width * out_height, "work buffer smaller than the visible grayscale MCU");
 
-        for block_y in 0..mcu_height {
-            for y in 0..8 {
-                for block_x in 0..mcu_width {
-                    for x in 0..8 {
-                        let y_idx = (block_y * mcu_width + block_x) * 64 + y * 8 + x;
-                        output[out_idx] = byte_clip(y_block[y_idx] as i32);
-                        out_idx += 1;
-                    }
-                }
+        for (abs_y, row) in output.chunks_exact_mut(out_width).take(out_height).enumerate() {
+            let (block_y, y) = (abs_y / 8, abs_y % 8);
+            for (abs_x, out) in row.iter_mut().enumerate() {
+                let y_idx = (block_y * mcu_width + abs_x / 8) * 64 + y * 8 + abs_x % 8;
+                *out = byte_clip(y_block[y_idx] as i32);
             }
         }
     }
@@ -221,6 +620,46 @@ pub mod color {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::types::ColorMatrix;
+
+
+    #[test]
+    fn test_idct_in_place_matches() {
+        let mut seed = 0x1234_5678u32;
+        for n in 0..2000 {
+            // 系数个数从稀疏到满块，幅度覆盖实际反量化后的范围
+            let mut src = [0i32; 64];
+            for v in src.iter_mut().take(1 + n % 64) {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                *v = (seed >> 16) as i32 % 4096 - 2048;
+            }
+
+            let mut two_buffer = [0i16; 64];
+            block_idct(&mut src.clone(), &mut two_buffer);
+            block_idct_in_place(&mut src);
+            assert_eq!(src.map(|v| v as i16), two_buffer, "block {}", n);
+        }
+    }
+
+    #[test]
+    fn test_idct_matches_scalar() {
+        let mut seed = 0x9E37_79B9u32;
+        for n in 0..4000 {
+            // 稀疏到满块，幅度从细节到强边缘
+            let amplitude = [256, 2048, 8192][n % 3];
+            let mut src = [0i32; 64];
+            for v in src.iter_mut().take(1 + n % 64) {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                *v = (seed >> 12) as i32 % (2 * amplitude) - amplitude;
+            }
+
+            let mut expected = [0i16; 64];
+            block_idct_scalar(&mut src.clone(), &mut expected);
+            let mut out = [0i16; 64];
+            block_idct(&mut src, &mut out);
+            assert_eq!(out, expected, "block {}", n);
+        }
+    }
 
     #[test]
     fn test_idct_dc_only() {
@@ -241,16 +680,78 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_idct_dc_fast_path() {
+        for dc in [-4096, -1000, -257, -1, 0, 1, 255, 256, 777, 4095] {
+            let mut src = [0i32; 64];
+            src[0] = dc;
+            let mut full = [0i16; 64];
+            block_idct(&mut src, &mut full);
+
+            let mut fast = [0i16; 64];
+            block_idct_dc(dc, &mut fast);
+            assert_eq!(fast, full, "dc = {}", dc);
+        }
+    }
+
     #[test]
     fn test_color_conversion() {
         use color::*;
         
         // Test white (Y=255, Cb=0, Cr=0)
-        let rgb = ycbcr_to_rgb(255, 0, 0);
+        let rgb = ycbcr_to_rgb(255, 0, 0, ColorMatrix::Bt601);
         assert_eq!(rgb, [255, 255, 255]);
 
         // Test RGB565 conversion
         let rgb565 = rgb888_to_rgb565(255, 255, 255);
         assert_eq!(rgb565, 0xFFFF);
+
+        // 红/蓝互换
+        assert_eq!(rgb888_to_rgb565(0xFF, 0x80, 0x10), 0xFC02);
+        assert_eq!(rgb888_to_bgr565(0xFF, 0x80, 0x10), 0x141F);
+    }
+
+    #[test]
+    fn test_gamut_scale_preserves_hue() {
+        use color::*;
+        use crate::tables::{CB_TO_G, CR_TO_G, CR_TO_R, CVACC};
+
+        // 饱和的橙色：R 超出范围
+        let (y, cb, cr) = (200, -60, 100);
+        assert_eq!(ycbcr_to_rgb(y, cb, cr, ColorMatrix::Bt601)[0], 255);
+
+        let clamped = ycbcr_to_rgb(y, cb, cr, ColorMatrix::Bt601);
+        let scaled = ycbcr_to_rgb_scaled(y, cb, cr, ColorMatrix::Bt601);
+        assert_eq!(scaled[0], 255);
+
+        // 通道比例与未裁剪的值一致（误差 1 以内），裁剪模式则偏离
+        let r = y + (CR_TO_R * cr) / CVACC;
+        let g = y - (CB_TO_G * cb + CR_TO_G * cr) / CVACC;
+        let expected_g = g * 255 / r;
+        assert!((scaled[1] as i32 - expected_g).abs() <= 1);
+        assert!(clamped[1] as i32 > expected_g + 10);
+
+        // 范围内的颜色两种模式相同
+        assert_eq!(ycbcr_to_rgb_scaled(128, 10, -20, ColorMatrix::Bt601), ycbcr_to_rgb(128, 10, -20, ColorMatrix::Bt601));
+    }
+
+    #[cfg(feature = "cmyk")]
+    #[test]
+    fn test_cmyk_conversion() {
+        use color::*;
+
+        // Adobe 反相：255 为无墨
+        assert_eq!(cmyk_to_rgb(255, 255, 255, 255, true), [255, 255, 255]);
+        assert_eq!(cmyk_to_rgb(0, 255, 255, 255, true), [0, 255, 255]);
+        assert_eq!(cmyk_to_rgb(255, 255, 255, 0, true), [0, 0, 0]);
+        assert_eq!(cmyk_to_rgb(255, 128, 255, 128, true), [128, 64, 128]);
+        // 未反相：0 为无墨
+        assert_eq!(cmyk_to_rgb(0, 0, 0, 0, false), [255, 255, 255]);
+        assert_eq!(cmyk_to_rgb(255, 0, 0, 0, false), [0, 255, 255]);
+
+        // 反相的 YCCK 中 YCC 黑色才是无墨
+        assert_eq!(ycck_to_rgb(255, 0, 0, 255, true, ColorMatrix::Bt601), [0, 0, 0]);
+        assert_eq!(ycck_to_rgb(0, 0, 0, 255, true, ColorMatrix::Bt601), [255, 255, 255]);
+        assert_eq!(ycck_to_rgb(0, 0, 0, 0, true, ColorMatrix::Bt601), [0, 0, 0]);
     }
 }
@@ -7,7 +7,7 @@
 //! ## Key Features
 //! 
 //! - **Memory pool allocation** - Predictable memory usage
-//! - **Small decoder struct** - Only ~120 bytes
+//! - **Small decoder struct** - At most 144 bytes on 64-bit targets (152 with `cmyk`)
 //! - **no_std compatible** - Works in embedded environments
 //! - **Three optimization levels** - Balance speed vs memory (fast-decode-0/1/2)
 //! - **No heap allocation** - All memory from user-provided pool
@@ -37,7 +37,10 @@
 //! }
 //! ```
 
-#![cfg_attr(not(feature = "std"), no_std)]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
 
 mod types;
 mod tables;
@@ -45,11 +48,47 @@ mod huffman;
 mod idct;
 mod decoder;
 mod pool;
+mod crc32;
+mod icc;
+mod marker;
+mod exif;
+#[cfg(feature = "std")]
+mod netpbm;
+#[cfg(feature = "alloc")]
+mod context;
+mod reader;
+
+#[cfg(test)]
+mod test_util;
 
-pub use types::{Result, Error, OutputFormat, Rectangle};
-pub use decoder::{JpegDecoder, OutputCallback, calculate_pool_size};
+pub use types::{Result, Error, ChromaSiting, ChromaUpsample, ColorMatrix, ComponentInfo, DensityUnit, GamutMode, McuInfo, McuLayout, MonoMode, OutputFormat, Rectangle, Rotation, SamplingFactor, Warning};
+pub use decoder::{JpegDecoder, ComplexityCallback, DecodeState, McuInfoCallback, McuIter, NotifyCallback, OutputCallback, PlaneCallback, RawMcuCallback, ScanlineCallback, SpanCallback, TileCallback, YieldCheck, calculate_pool_size, decode_block, find_soi, is_jpeg, scale_qtable, workspace_size_for};
 pub use huffman::{HuffmanTable, BitStream};
-pub use pool::{MemoryPool, RECOMMENDED_POOL_SIZE, MINIMUM_POOL_SIZE};
+pub use pool::{Allocator, MemoryPool, PoolStats, RECOMMENDED_POOL_SIZE, MINIMUM_POOL_SIZE};
+pub use crc32::{crc32, Crc32};
+pub use icc::{icc_profile, icc_profile_size};
+pub use marker::{scan_markers, MarkerIter};
+pub use reader::JpegReader;
+#[cfg(feature = "alloc")]
+pub use decoder::decode_to_vec;
+#[cfg(feature = "std")]
+pub use decoder::{decode_dc_preview, decode_frame};
+#[cfg(feature = "testing")]
+pub use decoder::decode_and_hash;
+#[cfg(feature = "std")]
+pub use types::Frame;
+#[cfg(feature = "std")]
+pub use netpbm::{write_pgm, write_ppm};
+#[cfg(feature = "alloc")]
+pub use context::{DecodeContext, FrameInfo};
+#[cfg(feature = "icc")]
+pub use icc::{to_srgb, ColorManagement};
+
+/// Internal transforms re-exported for `benches/`; not part of the public API
+#[doc(hidden)]
+pub mod bench {
+    pub use crate::idct::{block_idct, block_idct_scalar};
+}
 
 /// Size of stream input buffer
 pub const BUFFER_SIZE: usize = 512;
@@ -0,0 +1,155 @@
+//! JPEG marker segment walking
+//!
+//! `MarkerIter` walks the segment structure of a JPEG file by segment
+//! lengths, from SOI up to the first SOS or EOI. `scan_markers()` is the
+//! callback form of the same walk.
+
+use crate::types::{Error, Result};
+
+/// Pull-based iterator over the marker segments of a JPEG file
+///
+/// Yields `(marker, payload)` for every segment after SOI, where `marker` is
+/// the second marker byte (e.g. `0xE1` for APP1) and `payload` excludes the
+/// length field. Iteration ends after SOS (whose payload is the scan header)
+/// or EOI (empty payload). A malformed file yields a single `Err` and then
+/// ends.
+///
+/// ```
+/// use tjpgdec_rs::MarkerIter;
+///
+/// let data = [0xFF, 0xD8, 0xFF, 0xFE, 0x00, 0x04, b'h', b'i', 0xFF, 0xD9];
+/// let comment = MarkerIter::new(&data).flatten().find(|&(m, _)| m == 0xFE);
+/// assert_eq!(comment, Some((0xFE, &b"hi"[..])));
+/// ```
+#[derive(Debug, Clone)]
+pub struct MarkerIter<'a> {
+    data: &'a [u8],
+    pos: usize,
+    done: bool,
+}
+
+impl<'a> MarkerIter<'a> {
+    /// Create an iterator over the segments of `data`
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0, done: false }
+    }
+
+    /// Byte offset of the next segment's marker in the data
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    fn next_segment(&mut self) -> Result<(u8, &'a [u8])> {
+        let data = self.data;
+        if self.pos == 0 {
+            if data.len() < 2 || data[0] != 0xFF || data[1] != 0xD8 {
+                return Err(Error::FormatError);
+            }
+            self.pos = 2;
+        }
+
+        // 跳过填充字节
+        while self.pos + 1 < data.len() && data[self.pos] == 0xFF && data[self.pos + 1] == 0xFF {
+            self.pos += 1;
+        }
+        if self.pos + 2 > data.len() {
+            return Err(Error::Input);
+        }
+        if data[self.pos] != 0xFF {
+            return Err(Error::FormatError);
+        }
+
+        let marker = data[self.pos + 1];
+        if marker == 0xD9 {
+            self.done = true;
+            return Ok((marker, &[]));
+        }
+
+        if self.pos + 4 > data.len() {
+            return Err(Error::Input);
+        }
+        let length = u16::from_be_bytes([data[self.pos + 2], data[self.pos + 3]]) as usize;
+        if length < 2 {
+            return Err(Error::FormatError);
+        }
+        if self.pos + 2 + length > data.len() {
+            return Err(Error::Input);
+        }
+
+        let payload = &data[self.pos + 4..self.pos + 2 + length];
+        self.pos += 2 + length;
+        if marker == 0xDA {
+            self.done = true;
+        }
+        Ok((marker, payload))
+    }
+}
+
+impl<'a> Iterator for MarkerIter<'a> {
+    type Item = Result<(u8, &'a [u8])>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let item = self.next_segment();
+        if item.is_err() {
+            self.done = true;
+        }
+        Some(item)
+    }
+}
+
+/// Call `f` for every marker segment up to SOS/EOI
+///
+/// Callback form of `MarkerIter`: `f` receives the marker byte and payload
+/// and returns `Ok(false)` to stop early.
+pub fn scan_markers(data: &[u8], mut f: impl FnMut(u8, &[u8]) -> Result<bool>) -> Result<()> {
+    for segment in MarkerIter::new(data) {
+        let (marker, payload) = segment?;
+        if !f(marker, payload)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{encode, pattern, EncodeOptions};
+
+    #[test]
+    fn test_iterator_matches_callback() {
+        let opts = EncodeOptions { restart_interval: 4, ..EncodeOptions::default() };
+        let jpeg = encode(16, 16, &pattern(16, 16, 3), &opts);
+
+        let mut visited = Vec::new();
+        scan_markers(&jpeg, |marker, payload| {
+            visited.push((marker, payload.len()));
+            Ok(true)
+        })
+        .unwrap();
+
+        let iterated: Vec<_> = MarkerIter::new(&jpeg).map(|s| s.map(|(m, p)| (m, p.len()))).collect::<Result<_>>().unwrap();
+        assert_eq!(iterated, visited);
+        assert_eq!(
+            iterated.iter().map(|&(m, _)| m).collect::<Vec<_>>(),
+            [0xE0, 0xDB, 0xC0, 0xC4, 0xDD, 0xDA]
+        );
+    }
+
+    #[test]
+    fn test_iterator_errors() {
+        let mut iter = MarkerIter::new(&[0x89, b'P', b'N', b'G']);
+        assert_eq!(iter.next(), Some(Err(Error::FormatError)));
+        assert_eq!(iter.next(), None);
+
+        // 截断的段
+        let truncated = [0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, 0x00];
+        assert_eq!(MarkerIter::new(&truncated).next(), Some(Err(Error::Input)));
+
+        let empty = [0xFF, 0xD8, 0xFF, 0xFF, 0xD9];
+        assert_eq!(MarkerIter::new(&empty).collect::<Vec<_>>(), [Ok((0xD9, &[][..]))]);
+    }
+}
@@ -0,0 +1,64 @@
+//! NetPBM output for debugging
+//!
+//! Binary PPM (P6, RGB888) and PGM (P5, 8-bit gray) are the simplest image
+//! files there are: a text header followed by the raw, top-down, unpadded
+//! pixels. Handy for dumping decoder output in tests and examples; most
+//! image viewers open them.
+
+use std::io::{self, Write};
+use std::path::Path;
+
+/// 写出 NetPBM 头和像素数据
+fn write_netpbm(out: &mut impl Write, magic: &str, pixels: &[u8], width: u16, height: u16, bpp: usize) -> io::Result<()> {
+    if pixels.len() != width as usize * height as usize * bpp {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "pixel buffer doesn't match the dimensions"));
+    }
+    write!(out, "{}\n{} {}\n255\n", magic, width, height)?;
+    out.write_all(pixels)
+}
+
+/// Write packed RGB888 pixels as a binary PPM (P6) file
+///
+/// # Parameters
+///
+/// * `path` - Output file path
+/// * `rgb` - Pixels, exactly `width * height * 3` bytes
+/// * `width` / `height` - Image dimensions
+pub fn write_ppm<P: AsRef<Path>>(path: P, rgb: &[u8], width: u16, height: u16) -> io::Result<()> {
+    let mut file = io::BufWriter::new(std::fs::File::create(path)?);
+    write_netpbm(&mut file, "P6", rgb, width, height, 3)?;
+    file.flush()
+}
+
+/// Write 8-bit grayscale pixels as a binary PGM (P5) file
+///
+/// # Parameters
+///
+/// * `path` - Output file path
+/// * `gray` - Pixels, exactly `width * height` bytes
+/// * `width` / `height` - Image dimensions
+pub fn write_pgm<P: AsRef<Path>>(path: P, gray: &[u8], width: u16, height: u16) -> io::Result<()> {
+    let mut file = io::BufWriter::new(std::fs::File::create(path)?);
+    write_netpbm(&mut file, "P5", gray, width, height, 1)?;
+    file.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_netpbm() {
+        let mut out = Vec::new();
+        write_netpbm(&mut out, "P6", &[1, 2, 3, 4, 5, 6], 2, 1, 3).unwrap();
+        assert_eq!(out, b"P6\n2 1\n255\n\x01\x02\x03\x04\x05\x06");
+
+        let path = std::env::temp_dir().join(format!("tjpgdec_netpbm_{}.pgm", std::process::id()));
+        write_pgm(&path, &[0, 128, 255, 64], 2, 2).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(data, b"P5\n2 2\n255\n\x00\x80\xff\x40");
+
+        assert_eq!(write_ppm(&path, &[0; 5], 2, 1).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+}
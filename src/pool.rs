@@ -18,6 +18,70 @@
 
 use core::mem;
 
+/// Workspace allocator used by `JpegDecoder::prepare()`
+/// 
+/// Lets the decoder allocate its tables from a user-supplied bump/slab
+/// allocator (e.g. a firmware-wide arena). `MemoryPool` is the default
+/// implementation. Allocations are never freed individually; they must stay
+/// valid and must not overlap for the lifetime `'a`.
+pub trait Allocator<'a> {
+    /// Allocate `size` bytes aligned to `align` (a power of two)
+    /// 
+    /// Returns `None` if there is not enough memory.
+    fn alloc(&mut self, size: usize, align: usize) -> Option<&'a mut [u8]>;
+
+    /// Allocate a typed array initialized to `T::default()`
+    fn alloc_array<T: Copy + Default>(&mut self, count: usize) -> Option<&'a mut [T]> {
+        let size = count.checked_mul(mem::size_of::<T>())?;
+        let bytes = self.alloc(size, mem::align_of::<T>())?;
+        if bytes.len() < size || !(bytes.as_ptr() as usize).is_multiple_of(mem::align_of::<T>()) {
+            return None;
+        }
+
+        // 已检查大小与对齐
+        let typed = unsafe { core::slice::from_raw_parts_mut(bytes.as_mut_ptr() as *mut T, count) };
+        typed.fill(T::default());
+        Some(typed)
+    }
+}
+
+/// 限制分配总量的 Allocator 包装（按请求的字节数计数）
+pub(crate) struct Budget<'p, A> {
+    inner: &'p mut A,
+    remaining: usize,
+}
+
+impl<'p, A> Budget<'p, A> {
+    pub(crate) fn new(inner: &'p mut A, limit: usize) -> Self {
+        Self { inner, remaining: limit }
+    }
+}
+
+impl<'a, A: Allocator<'a>> Allocator<'a> for Budget<'_, A> {
+    fn alloc(&mut self, size: usize, align: usize) -> Option<&'a mut [u8]> {
+        if size > self.remaining {
+            return None;
+        }
+        let block = self.inner.alloc(size, align)?;
+        self.remaining -= size;
+        Some(block)
+    }
+}
+
+/// Memory pool usage, see `MemoryPool::alloc_stats()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Largest `current_offset` ever reached, i.e. the pool size that would
+    /// have been enough
+    pub high_water_mark: usize,
+    /// Bytes in use right now, including alignment padding
+    pub current_offset: usize,
+    /// Total pool size in bytes
+    pub capacity: usize,
+    /// Number of successful allocations
+    pub num_allocations: usize,
+}
+
 /// Memory pool for workspace allocation
 /// 
 /// Simple linear allocator with the following characteristics:
@@ -29,6 +93,10 @@ pub struct MemoryPool<'a> {
     buffer: &'a mut [u8],
     /// Current allocation position
     offset: usize,
+    /// 历史最大偏移量
+    high_water: usize,
+    /// 成功分配的次数
+    allocations: usize,
 }
 
 impl<'a> MemoryPool<'a> {
@@ -46,6 +114,8 @@ impl<'a> MemoryPool<'a> {
         Self {
             buffer,
             offset: 0,
+            high_water: 0,
+            allocations: 0,
         }
     }
 
@@ -57,21 +127,35 @@ impl<'a> MemoryPool<'a> {
     }
 
     /// Allocate memory with specified alignment
+    /// 
+    /// `align` must be a power of two. Returns `None` if the pool is too
+    /// small, the size computation overflows or `align` is invalid. A zero
+    /// `size` returns an empty slice inside the pool without using any of it.
     pub fn alloc_aligned(&mut self, size: usize, align: usize) -> Option<&'a mut [u8]> {
-        // 确保当前偏移量对齐
+        debug_assert!(align.is_power_of_two(), "alignment must be a power of two");
+        if !align.is_power_of_two() {
+            return None;
+        }
+
+        // 确保当前偏移量对齐（溢出即视为内存不足）
         let align_mask = align - 1;
-        let aligned_offset = (self.offset + align_mask) & !align_mask;
+        let aligned_offset = self.offset.checked_add(align_mask)? & !align_mask;
         
         // 对齐大小
-        let aligned_size = (size + align_mask) & !align_mask;
+        let aligned_size = size.checked_add(align_mask)? & !align_mask;
         
-        let remaining = self.buffer.len() - aligned_offset;
-        if remaining < aligned_size {
+        let end = aligned_offset.checked_add(aligned_size)?;
+        if end > self.buffer.len() {
             return None;
         }
 
         let start = aligned_offset;
-        self.offset = aligned_offset + aligned_size;
+        // 空分配不推进偏移量，不浪费对齐填充
+        if size > 0 {
+            self.offset = end;
+            self.high_water = self.high_water.max(end);
+        }
+        self.allocations += 1;
 
         // 使用unsafe来返回带有'a生命周期的切片
         // 这是安全的，因为我们保证不会重叠分配
@@ -94,7 +178,7 @@ impl<'a> MemoryPool<'a> {
     /// 
     /// Type T's alignment requirement must not exceed 8 bytes.
     pub fn alloc_slice<T: Copy + Default>(&mut self, count: usize) -> Option<&'a mut [T]> {
-        let size = count * mem::size_of::<T>();
+        let size = count.checked_mul(mem::size_of::<T>())?;
         let slice = self.alloc(size)?;
         
         // 将字节切片转换为类型化切片
@@ -145,11 +229,35 @@ impl<'a> MemoryPool<'a> {
     }
 
     /// Reset pool (release all allocations)
+    /// 
+    /// The statistics of `alloc_stats()` are kept, so the high-water mark
+    /// covers every image decoded with this pool.
     pub fn reset(&mut self) {
         self.offset = 0;
     }
+
+    /// Get usage statistics for sizing the pool
+    /// 
+    /// After `prepare()`, `high_water_mark` is exactly the number of bytes
+    /// the image needed. Decode your worst-case image once and use it as
+    /// the size of a static pool.
+    pub fn alloc_stats(&self) -> PoolStats {
+        PoolStats {
+            high_water_mark: self.high_water,
+            current_offset: self.offset,
+            capacity: self.buffer.len(),
+            num_allocations: self.allocations,
+        }
+    }
 }
 
+impl<'a> Allocator<'a> for MemoryPool<'a> {
+    #[inline]
+    fn alloc(&mut self, size: usize, align: usize) -> Option<&'a mut [u8]> {
+        // 至少 8 字节对齐，与 C 版本的内存占用保持一致
+        self.alloc_aligned(size, align.max(8))
+    }
+}
 
 /// Recommended workspace size
 /// 
@@ -205,6 +313,36 @@ mod tests {
         assert_eq!(pool.used(), 48);  // 24 + 24 (20 aligned to 8)
     }
 
+    /// 用户自定义的分配器：从外部缓冲区末尾向前分配
+    struct TopDown<'a> {
+        buffer: &'a mut [u8],
+    }
+
+    impl<'a> Allocator<'a> for TopDown<'a> {
+        fn alloc(&mut self, size: usize, align: usize) -> Option<&'a mut [u8]> {
+            let buffer = mem::take(&mut self.buffer);
+            let end = buffer.len().checked_sub(size)?;
+            let start = end - (buffer.as_ptr() as usize + end) % align;
+            let (rest, block) = buffer.split_at_mut(start);
+            self.buffer = rest;
+            Some(&mut block[..size])
+        }
+    }
+
+    #[test]
+    fn test_custom_allocator() {
+        let mut buffer = [0xFFu8; 256];
+        let mut alloc = TopDown { buffer: &mut buffer };
+
+        let words = alloc.alloc_array::<u32>(4).unwrap();
+        assert_eq!(words, &[0; 4]);
+        assert_eq!(words.as_ptr() as usize % mem::align_of::<u32>(), 0);
+
+        let bytes = alloc.alloc_array::<u8>(200).unwrap();
+        assert_eq!(bytes.len(), 200);
+        assert!(alloc.alloc_array::<u8>(64).is_none());
+    }
+
     #[test]
     fn test_alloc_fail() {
         let mut buffer = [0u8; 128];
@@ -214,4 +352,59 @@ mod tests {
         assert!(pool.alloc(50).is_some());  // uses another 56 bytes = 112 total
         assert!(pool.alloc(20).is_none());  // 128 - 112 = 16, not enough for 20 (needs 24 aligned)
     }
+
+    #[test]
+    fn test_alloc_stats() {
+        let mut buffer = [0u8; 128];
+        let mut pool = MemoryPool::new(&mut buffer);
+        pool.alloc(50).unwrap();
+        pool.alloc_aligned(4, 16).unwrap();  // 56 aligned to 16 = 64, + 16
+        assert!(pool.alloc(100).is_none());
+        assert_eq!(
+            pool.alloc_stats(),
+            PoolStats { high_water_mark: 80, current_offset: 80, capacity: 128, num_allocations: 2 }
+        );
+
+        // reset 后保留最大值
+        pool.reset();
+        pool.alloc(8).unwrap();
+        let stats = pool.alloc_stats();
+        assert_eq!((stats.high_water_mark, stats.current_offset, stats.num_allocations), (80, 8, 3));
+    }
+
+    #[test]
+    fn test_alloc_overflow() {
+        let mut buffer = [0u8; 64];
+        let mut pool = MemoryPool::new(&mut buffer);
+        pool.alloc(1).unwrap();
+
+        // 大小或对齐后的偏移量溢出时返回 None，且不改变状态
+        assert!(pool.alloc(usize::MAX).is_none());
+        assert!(pool.alloc_i32(usize::MAX / 2).is_none());
+        assert!(pool.alloc_aligned(usize::MAX - 2, 4).is_none());
+        assert!(pool.alloc_aligned(1, 1 << (usize::BITS - 1)).is_none());
+        assert_eq!(pool.used(), 8);
+
+        // 空分配指向池内，不占用空间
+        let base = pool.buffer.as_ptr() as usize;
+        let empty = pool.alloc_aligned(0, 16).unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(empty.as_ptr() as usize, base + 16);
+        assert_eq!(pool.used(), 8);
+        // 对齐后超出池末尾
+        assert!(pool.alloc_aligned(0, 128).is_none());
+
+        pool.alloc(56).unwrap();
+        assert_eq!(pool.remaining(), 0);
+        assert!(pool.alloc(0).is_some());
+        assert!(pool.alloc(1).is_none());
+    }
+
+    #[test]
+    #[cfg_attr(debug_assertions, should_panic(expected = "power of two"))]
+    fn test_alloc_bad_alignment() {
+        let mut buffer = [0u8; 64];
+        let mut pool = MemoryPool::new(&mut buffer);
+        assert!(pool.alloc_aligned(8, 12).is_none());
+    }
 }
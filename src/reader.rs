@@ -0,0 +1,103 @@
+//! Streaming input
+//!
+//! `JpegReader` lets `prepare_reader()` / `decompress_reader()` pull the
+//! JPEG file in chunks through a small input buffer (`BUFFER_SIZE` bytes is
+//! enough for typical files), like the input function of the C version.
+//! Files already in memory should use the `&[u8]` APIs, which are
+//! zero-copy.
+
+use crate::types::{Error, Result};
+
+/// Source of JPEG data for the streaming decode functions
+///
+/// Implemented for `&[u8]` and, with the `std` feature, for every
+/// `std::io::Read`. Implement it for flash/SD card drivers on `no_std`
+/// targets.
+pub trait JpegReader {
+    /// Read up to `buf.len()` bytes into `buf`
+    ///
+    /// Returns the number of bytes read; `0` means end of input.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize>;
+}
+
+#[cfg(not(feature = "std"))]
+impl JpegReader for &[u8] {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = buf.len().min(self.len());
+        let (head, rest) = self.split_at(n);
+        buf[..n].copy_from_slice(head);
+        *self = rest;
+        Ok(n)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: std::io::Read + ?Sized> JpegReader for R {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        loop {
+            match std::io::Read::read(self, buf) {
+                Ok(n) => return Ok(n),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(_) => return Err(Error::Input),
+            }
+        }
+    }
+}
+
+/// 读满 `buf`，数据提前结束时返回 `Error::Input`
+pub(crate) fn read_exact<R: JpegReader + ?Sized>(reader: &mut R, mut buf: &mut [u8]) -> Result<()> {
+    while !buf.is_empty() {
+        let n = reader.read(buf)?;
+        if n == 0 {
+            return Err(Error::Input);
+        }
+        buf = &mut buf[n..];
+    }
+    Ok(())
+}
+
+/// 丢弃 `count` 字节，借用 `scratch` 作为中转缓冲区
+pub(crate) fn skip<R: JpegReader + ?Sized>(reader: &mut R, mut count: usize, scratch: &mut [u8]) -> Result<()> {
+    while count > 0 {
+        let n = count.min(scratch.len());
+        read_exact(reader, &mut scratch[..n])?;
+        count -= n;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 每次最多返回 3 字节的读取器
+    struct Trickle<'a>(&'a [u8]);
+
+    impl JpegReader for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            let n = buf.len().min(self.0.len()).min(3);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_read_exact_and_skip() {
+        let data: Vec<u8> = (0..20).collect();
+        let mut reader = Trickle(&data);
+        let mut buf = [0u8; 5];
+        read_exact(&mut reader, &mut buf).unwrap();
+        assert_eq!(buf, [0, 1, 2, 3, 4]);
+
+        let mut scratch = [0u8; 4];
+        skip(&mut reader, 10, &mut scratch).unwrap();
+        read_exact(&mut reader, &mut buf).unwrap();
+        assert_eq!(buf, [15, 16, 17, 18, 19]);
+        assert_eq!(read_exact(&mut reader, &mut buf[..1]), Err(Error::Input));
+
+        let mut slice: &[u8] = &data[..3];
+        assert_eq!(JpegReader::read(&mut slice, &mut buf).unwrap(), 3);
+        assert_eq!(JpegReader::read(&mut slice, &mut buf).unwrap(), 0);
+    }
+}
@@ -43,22 +43,13 @@ pub const CLIP_TABLE: [u8; 1024] = {
         i += 1;
     }
     
-    // 512..767 (all 0)
-    while i < 768 {
-        table[i] = 0;
-        i += 1;
-    }
-    
-    // 768..1023
-    while i < 1024 {
-        table[i] = (i - 768) as u8;
-        i += 1;
-    }
-    
+    // 512..1023 (all 0): negative values -512..-1 wrap into this range
     table
 };
 
 /// Fast clipping using table lookup
+/// 
+/// Exact for `-512..=511`, which covers the IDCT and color conversion output.
 #[cfg(feature = "table-clip")]
 #[inline]
 pub fn byte_clip(val: i32) -> u8 {
@@ -93,6 +84,27 @@ pub const CR_TO_G: i32 = (0.714 * CVACC as f64) as i32;
 /// Conversion factor for Cb to B
 pub const CB_TO_B: i32 = (1.772 * CVACC as f64) as i32;
 
+/// Fraction bits of the rounded `ColorMatrix` coefficients
+/// 
+/// 14 bits keep `coefficient * sample` inside `i32` for any `i16` sample.
+pub const YCC_BITS: u32 = 14;
+
+/// Rounded BT.601 coefficients (Cr to R, Cb to G, Cr to G, Cb to B)
+pub const FULL601_COEFFS: [i32; 4] = [
+    (1.402 * (1 << YCC_BITS) as f64 + 0.5) as i32,
+    (0.344136 * (1 << YCC_BITS) as f64 + 0.5) as i32,
+    (0.714136 * (1 << YCC_BITS) as f64 + 0.5) as i32,
+    (1.772 * (1 << YCC_BITS) as f64 + 0.5) as i32,
+];
+
+/// Rounded BT.709 coefficients (Cr to R, Cb to G, Cr to G, Cb to B)
+pub const BT709_COEFFS: [i32; 4] = [
+    (1.5748 * (1 << YCC_BITS) as f64 + 0.5) as i32,
+    (0.187324 * (1 << YCC_BITS) as f64 + 0.5) as i32,
+    (0.468124 * (1 << YCC_BITS) as f64 + 0.5) as i32,
+    (1.8556 * (1 << YCC_BITS) as f64 + 0.5) as i32,
+];
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -111,5 +123,9 @@ mod tests {
         assert_eq!(byte_clip(128), 128);
         assert_eq!(byte_clip(255), 255);
         assert_eq!(byte_clip(300), 255);
+        assert_eq!(byte_clip(-1), 0);
+        assert_eq!(byte_clip(-256), 0);
+        assert_eq!(byte_clip(-512), 0);
+        assert_eq!(byte_clip(511), 255);
     }
 }
@@ -0,0 +1,489 @@
+//! Test helpers
+//!
+//! A minimal baseline JPEG encoder used to synthesize test images with
+//! specific sampling, restart and table layouts, plus a reference decode
+//! helper that stitches MCU output into a framebuffer and the decoder setup
+//! shared by most tests.
+
+#![allow(dead_code)]
+
+use crate::tables::ZIGZAG;
+use crate::{JpegDecoder, MemoryPool, Rectangle, RECOMMENDED_POOL_SIZE};
+
+/// Standard luminance quantization table (ITU T.81 K.1, raster order)
+const STD_LUMA_QT: [u8; 64] = [
+    16, 11, 10, 16, 24, 40, 51, 61,
+    12, 12, 14, 19, 26, 58, 60, 55,
+    14, 13, 16, 24, 40, 57, 69, 56,
+    14, 17, 22, 29, 51, 87, 80, 62,
+    18, 22, 37, 56, 68, 109, 103, 77,
+    24, 35, 55, 64, 81, 104, 113, 92,
+    49, 64, 78, 87, 103, 121, 120, 101,
+    72, 92, 95, 98, 112, 100, 103, 99,
+];
+
+/// Standard chrominance quantization table (ITU T.81 K.2, raster order)
+const STD_CHROMA_QT: [u8; 64] = [
+    17, 18, 24, 47, 99, 99, 99, 99,
+    18, 21, 26, 66, 99, 99, 99, 99,
+    24, 26, 56, 99, 99, 99, 99, 99,
+    47, 66, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+];
+
+const DC_LUMA_BITS: [u8; 16] = [0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0];
+const DC_LUMA_VALS: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+const DC_CHROMA_BITS: [u8; 16] = [0, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0];
+const DC_CHROMA_VALS: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+
+const AC_LUMA_BITS: [u8; 16] = [0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7d];
+const AC_LUMA_VALS: [u8; 162] = [
+    0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61, 0x07,
+    0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xa1, 0x08, 0x23, 0x42, 0xb1, 0xc1, 0x15, 0x52, 0xd1, 0xf0,
+    0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0a, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x25, 0x26, 0x27, 0x28,
+    0x29, 0x2a, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49,
+    0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69,
+    0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89,
+    0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7,
+    0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3, 0xc4, 0xc5,
+    0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda, 0xe1, 0xe2,
+    0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8,
+    0xf9, 0xfa,
+];
+
+const AC_CHROMA_BITS: [u8; 16] = [0, 2, 1, 2, 4, 4, 3, 4, 7, 5, 4, 4, 0, 1, 2, 0x77];
+const AC_CHROMA_VALS: [u8; 162] = [
+    0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41, 0x51, 0x07, 0x61, 0x71,
+    0x13, 0x22, 0x32, 0x81, 0x08, 0x14, 0x42, 0x91, 0xa1, 0xb1, 0xc1, 0x09, 0x23, 0x33, 0x52, 0xf0,
+    0x15, 0x62, 0x72, 0xd1, 0x0a, 0x16, 0x24, 0x34, 0xe1, 0x25, 0xf1, 0x17, 0x18, 0x19, 0x1a, 0x26,
+    0x27, 0x28, 0x29, 0x2a, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48,
+    0x49, 0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68,
+    0x69, 0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87,
+    0x88, 0x89, 0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5,
+    0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3,
+    0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda,
+    0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8,
+    0xf9, 0xfa,
+];
+
+/// Encoder settings for synthesized test images
+#[derive(Debug, Clone)]
+pub struct EncodeOptions {
+    /// Number of components (1 = grayscale, 3 = YCbCr, 4 = CMYK/YCCK)
+    pub components: u8,
+    /// Luma sampling factors (h, v); chroma is always 1x1. Grayscale images
+    /// only declare them in the SOF, their scan is always non-interleaved
+    pub sampling: (u8, u8),
+    /// IJG-style quality (1..=100)
+    pub quality: u8,
+    /// Restart interval in MCUs (0 = no DRI segment)
+    pub restart_interval: u16,
+    /// Color transform of an Adobe APP14 segment (None = no segment).
+    /// 2 (YCCK) stores `255 - C/M/Y` of 4-component input as YCbCr
+    pub adobe_transform: Option<u8>,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        Self {
+            components: 3,
+            sampling: (1, 1),
+            quality: 90,
+            restart_interval: 0,
+            adobe_transform: None,
+        }
+    }
+}
+
+/// Huffman code lookup: (code, length) indexed by symbol
+struct HuffCodes {
+    code: [u16; 256],
+    len: [u8; 256],
+}
+
+impl HuffCodes {
+    fn new(bits: &[u8; 16], vals: &[u8]) -> Self {
+        let mut codes = Self { code: [0; 256], len: [0; 256] };
+        let mut code = 0u16;
+        let mut k = 0;
+        for (i, &n) in bits.iter().enumerate() {
+            for _ in 0..n {
+                codes.code[vals[k] as usize] = code;
+                codes.len[vals[k] as usize] = i as u8 + 1;
+                code += 1;
+                k += 1;
+            }
+            code <<= 1;
+        }
+        codes
+    }
+}
+
+/// Entropy-coded segment writer with byte stuffing
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u32,
+    nbits: u32,
+}
+
+impl BitWriter {
+    fn put(&mut self, code: u16, len: u8) {
+        for i in (0..len).rev() {
+            self.acc = (self.acc << 1) | ((code >> i) & 1) as u32;
+            self.nbits += 1;
+            if self.nbits == 8 {
+                let b = self.acc as u8;
+                self.out.push(b);
+                if b == 0xFF {
+                    self.out.push(0x00);
+                }
+                self.acc = 0;
+                self.nbits = 0;
+            }
+        }
+    }
+
+    /// Pad the last byte with 1-bits
+    fn flush(&mut self) {
+        while self.nbits != 0 {
+            self.put(1, 1);
+        }
+    }
+}
+
+fn scaled_qtable(base: &[u8; 64], quality: u8) -> [u8; 64] {
+    let q = quality.clamp(1, 100) as u32;
+    let scale = if q < 50 { 5000 / q } else { 200 - q * 2 };
+    let mut t = [0u8; 64];
+    for i in 0..64 {
+        t[i] = ((base[i] as u32 * scale + 50) / 100).clamp(1, 255) as u8;
+    }
+    t
+}
+
+fn fdct(block: &[f32; 64]) -> [f32; 64] {
+    let mut out = [0f32; 64];
+    for v in 0..8 {
+        for u in 0..8 {
+            let cu = if u == 0 { core::f32::consts::FRAC_1_SQRT_2 } else { 1.0 };
+            let cv = if v == 0 { core::f32::consts::FRAC_1_SQRT_2 } else { 1.0 };
+            let mut sum = 0f32;
+            for y in 0..8 {
+                for x in 0..8 {
+                    let cx = (((2 * x + 1) * u) as f32 * core::f32::consts::PI / 16.0).cos();
+                    let cy = (((2 * y + 1) * v) as f32 * core::f32::consts::PI / 16.0).cos();
+                    sum += block[y * 8 + x] * cx * cy;
+                }
+            }
+            out[v * 8 + u] = 0.25 * cu * cv * sum;
+        }
+    }
+    out
+}
+
+fn magnitude(v: i32) -> (u16, u8) {
+    let a = v.unsigned_abs();
+    let n = 32 - a.leading_zeros();
+    let bits = if v < 0 { (v - 1) as u32 & ((1 << n) - 1) } else { v as u32 };
+    (bits as u16, n as u8)
+}
+
+fn encode_block(
+    w: &mut BitWriter,
+    block: &[f32; 64],
+    qt: &[u8; 64],
+    pred: &mut i32,
+    dc: &HuffCodes,
+    ac: &HuffCodes,
+) {
+    let coef = fdct(block);
+    let mut zz = [0i32; 64];
+    for (k, &raster) in ZIGZAG.iter().enumerate() {
+        let r = raster as usize;
+        zz[k] = (coef[r] / qt[r] as f32).round() as i32;
+    }
+
+    let diff = zz[0] - *pred;
+    *pred = zz[0];
+    let (bits, n) = magnitude(diff);
+    w.put(dc.code[n as usize], dc.len[n as usize]);
+    w.put(bits, n);
+
+    let mut run = 0;
+    for &c in &zz[1..] {
+        if c == 0 {
+            run += 1;
+            continue;
+        }
+        while run > 15 {
+            w.put(ac.code[0xF0], ac.len[0xF0]);
+            run -= 16;
+        }
+        let (bits, n) = magnitude(c);
+        let sym = (run << 4) as usize | n as usize;
+        w.put(ac.code[sym], ac.len[sym]);
+        w.put(bits, n);
+        run = 0;
+    }
+    if run > 0 {
+        w.put(ac.code[0], ac.len[0]);
+    }
+}
+
+fn segment(out: &mut Vec<u8>, marker: u8, payload: &[u8]) {
+    out.extend_from_slice(&[0xFF, marker]);
+    out.extend_from_slice(&((payload.len() + 2) as u16).to_be_bytes());
+    out.extend_from_slice(payload);
+}
+
+fn dht_payload(class_id: u8, bits: &[u8; 16], vals: &[u8]) -> Vec<u8> {
+    let mut p = vec![class_id];
+    p.extend_from_slice(bits);
+    p.extend_from_slice(vals);
+    p
+}
+
+/// Encode a baseline JPEG
+///
+/// `pixels` is RGB888 for 3 components, 8-bit gray for 1 component or the
+/// stored CMYK samples for 4 components (always 1x1 sampled).
+pub fn encode(width: u16, height: u16, pixels: &[u8], opts: &EncodeOptions) -> Vec<u8> {
+    let ncomp = opts.components as usize;
+    assert!(ncomp == 1 || ncomp == 3 || ncomp == 4);
+    assert!(ncomp != 4 || opts.sampling == (1, 1));
+    assert_eq!(pixels.len(), width as usize * height as usize * ncomp);
+    let ycck = ncomp == 4 && opts.adobe_transform == Some(2);
+
+    // 每个分量使用的量化表和 Huffman 表，与 libjpeg 相同：CMYK 全部用表 0，YCCK 的 K 用表 0
+    let tables: &[usize] = match ncomp {
+        1 => &[0],
+        3 => &[0, 1, 1],
+        _ if ycck => &[0, 1, 1, 0],
+        _ => &[0, 0, 0, 0],
+    };
+    let two_tables = tables.contains(&1);
+
+    let (h, v) = (opts.sampling.0 as usize, opts.sampling.1 as usize);
+    let w = width as usize;
+    let hgt = height as usize;
+
+    // Color planes in JFIF YCbCr (full range)
+    let mut planes = vec![vec![0f32; w * hgt]; ncomp];
+    for i in 0..w * hgt {
+        if ncomp == 1 {
+            planes[0][i] = pixels[i] as f32;
+        } else if ncomp == 4 && !ycck {
+            for c in 0..4 {
+                planes[c][i] = pixels[i * 4 + c] as f32;
+            }
+        } else if ncomp == 4 {
+            let r = 255.0 - pixels[i * 4] as f32;
+            let g = 255.0 - pixels[i * 4 + 1] as f32;
+            let b = 255.0 - pixels[i * 4 + 2] as f32;
+            planes[0][i] = 0.299 * r + 0.587 * g + 0.114 * b;
+            planes[1][i] = -0.168_736 * r - 0.331_264 * g + 0.5 * b + 128.0;
+            planes[2][i] = 0.5 * r - 0.418_688 * g - 0.081_312 * b + 128.0;
+            planes[3][i] = pixels[i * 4 + 3] as f32;
+        } else {
+            let r = pixels[i * 3] as f32;
+            let g = pixels[i * 3 + 1] as f32;
+            let b = pixels[i * 3 + 2] as f32;
+            planes[0][i] = 0.299 * r + 0.587 * g + 0.114 * b;
+            planes[1][i] = -0.168_736 * r - 0.331_264 * g + 0.5 * b + 128.0;
+            planes[2][i] = 0.5 * r - 0.418_688 * g - 0.081_312 * b + 128.0;
+        }
+    }
+    let sample = |plane: &Vec<f32>, x: usize, y: usize| plane[y.min(hgt - 1) * w + x.min(w - 1)];
+
+    let qt_luma = scaled_qtable(&STD_LUMA_QT, opts.quality);
+    let qt_chroma = scaled_qtable(&STD_CHROMA_QT, opts.quality);
+
+    let mut out = vec![0xFF, 0xD8];
+    segment(&mut out, 0xE0, &[b'J', b'F', b'I', b'F', 0, 1, 1, 0, 0, 1, 0, 1, 0, 0]);
+    if let Some(transform) = opts.adobe_transform {
+        segment(&mut out, 0xEE, &[b'A', b'd', b'o', b'b', b'e', 0, 100, 0, 0, 0, 0, transform]);
+    }
+
+    let mut dqt = vec![0x00];
+    dqt.extend(ZIGZAG.iter().map(|&r| qt_luma[r as usize]));
+    if two_tables {
+        dqt.push(0x01);
+        dqt.extend(ZIGZAG.iter().map(|&r| qt_chroma[r as usize]));
+    }
+    segment(&mut out, 0xDB, &dqt);
+
+    let mut sof = vec![8];
+    sof.extend_from_slice(&height.to_be_bytes());
+    sof.extend_from_slice(&width.to_be_bytes());
+    sof.push(ncomp as u8);
+    sof.extend_from_slice(&[1, ((h << 4) | v) as u8, 0]);
+    for (c, &t) in tables.iter().enumerate().skip(1) {
+        sof.extend_from_slice(&[c as u8 + 1, 0x11, t as u8]);
+    }
+    segment(&mut out, 0xC0, &sof);
+
+    let mut dht = dht_payload(0x00, &DC_LUMA_BITS, &DC_LUMA_VALS);
+    dht.extend(dht_payload(0x10, &AC_LUMA_BITS, &AC_LUMA_VALS));
+    if two_tables {
+        dht.extend(dht_payload(0x01, &DC_CHROMA_BITS, &DC_CHROMA_VALS));
+        dht.extend(dht_payload(0x11, &AC_CHROMA_BITS, &AC_CHROMA_VALS));
+    }
+    segment(&mut out, 0xC4, &dht);
+
+    if opts.restart_interval > 0 {
+        segment(&mut out, 0xDD, &opts.restart_interval.to_be_bytes());
+    }
+
+    let mut sos = vec![ncomp as u8, 1, 0x00];
+    for (c, &t) in tables.iter().enumerate().skip(1) {
+        sos.extend_from_slice(&[c as u8 + 1, t as u8 * 0x11]);
+    }
+    sos.extend_from_slice(&[0, 63, 0]);
+    segment(&mut out, 0xDA, &sos);
+
+    let dc_luma = HuffCodes::new(&DC_LUMA_BITS, &DC_LUMA_VALS);
+    let ac_luma = HuffCodes::new(&AC_LUMA_BITS, &AC_LUMA_VALS);
+    let dc_chroma = HuffCodes::new(&DC_CHROMA_BITS, &DC_CHROMA_VALS);
+    let ac_chroma = HuffCodes::new(&AC_CHROMA_BITS, &AC_CHROMA_VALS);
+    let qts = [&qt_luma, &qt_chroma];
+    let dcs = [&dc_luma, &dc_chroma];
+    let acs = [&ac_luma, &ac_chroma];
+
+    // 单分量扫描不交错：MCU 固定为一个 8x8 块，与声明的采样因子无关（T.81 A.2.2）
+    let (h, v) = if ncomp == 1 { (1, 1) } else { (h, v) };
+    let mcu_w = 8 * h;
+    let mcu_h = 8 * v;
+    let mcus_x = w.div_ceil(mcu_w);
+    let mcus_y = hgt.div_ceil(mcu_h);
+
+    let mut bw = BitWriter { out: Vec::new(), acc: 0, nbits: 0 };
+    let mut preds = [0i32; 4];
+    let mut mcu_count = 0u32;
+    let mut rst = 0u8;
+
+    for my in 0..mcus_y {
+        for mx in 0..mcus_x {
+            if opts.restart_interval > 0 && mcu_count > 0 && mcu_count.is_multiple_of(opts.restart_interval as u32) {
+                bw.flush();
+                bw.out.extend_from_slice(&[0xFF, 0xD0 + rst]);
+                rst = (rst + 1) & 7;
+                preds = [0; 4];
+            }
+            mcu_count += 1;
+
+            let x0 = mx * mcu_w;
+            let y0 = my * mcu_h;
+
+            for by in 0..v {
+                for bx in 0..h {
+                    let mut block = [0f32; 64];
+                    for y in 0..8 {
+                        for x in 0..8 {
+                            block[y * 8 + x] = sample(&planes[0], x0 + bx * 8 + x, y0 + by * 8 + y) - 128.0;
+                        }
+                    }
+                    encode_block(&mut bw, &block, &qt_luma, &mut preds[0], &dc_luma, &ac_luma);
+                }
+            }
+
+            for c in 1..ncomp {
+                let mut block = [0f32; 64];
+                for y in 0..8 {
+                    for x in 0..8 {
+                        let mut sum = 0f32;
+                        for sy in 0..v {
+                            for sx in 0..h {
+                                sum += sample(&planes[c], x0 + x * h + sx, y0 + y * v + sy);
+                            }
+                        }
+                        block[y * 8 + x] = sum / (h * v) as f32 - 128.0;
+                    }
+                }
+                let t = tables[c];
+                encode_block(&mut bw, &block, qts[t], &mut preds[c], dcs[t], acs[t]);
+            }
+        }
+    }
+    bw.flush();
+
+    out.extend_from_slice(&bw.out);
+    out.extend_from_slice(&[0xFF, 0xD9]);
+    out
+}
+
+/// Deterministic test pattern: gradients with a few hard edges
+pub fn pattern(width: u16, height: u16, components: u8) -> Vec<u8> {
+    let (w, h) = (width as usize, height as usize);
+    let mut px = Vec::with_capacity(w * h * components as usize);
+    for y in 0..h {
+        for x in 0..w {
+            let r = (x * 255 / w.max(2).saturating_sub(1).max(1)) as u8;
+            let g = (y * 255 / h.max(2).saturating_sub(1).max(1)) as u8;
+            let b = if (x / 8 + y / 8) % 2 == 0 { 40 } else { 200 };
+            if components == 1 {
+                px.push(((r as u16 + g as u16 + b as u16) / 3) as u8);
+            } else {
+                px.extend_from_slice(&[r, g, b]);
+            }
+        }
+    }
+    px
+}
+
+/// Prepare a decoder for `data` with default settings
+///
+/// Returns (decoder, mcu_buffer, work_buffer), the buffers sized for 1:1
+/// output. The pool buffer is leaked so the decoder is not tied to a local;
+/// tests that inspect the pool or change settings before `prepare()` set
+/// things up themselves.
+pub fn prepared(data: &[u8]) -> (JpegDecoder<'static>, Vec<i16>, Vec<u8>) {
+    let pool_buffer = Box::leak(vec![0u8; RECOMMENDED_POOL_SIZE].into_boxed_slice());
+    let mut pool = MemoryPool::new(pool_buffer);
+    let mut decoder = JpegDecoder::new();
+    decoder.prepare(data, &mut pool).expect("prepare");
+
+    let mcu_buffer = vec![0i16; decoder.mcu_buffer_size()];
+    let work_buffer = vec![0u8; decoder.work_buffer_size()];
+    (decoder, mcu_buffer, work_buffer)
+}
+
+/// Copy the pixels of one output callback into a packed framebuffer
+///
+/// `width` is the framebuffer width in pixels, `bpp` the bytes per pixel of
+/// both `bitmap` and `fb`.
+pub fn blit_rect(fb: &mut [u8], width: usize, bpp: usize, bitmap: &[u8], rect: &Rectangle) {
+    let row = rect.width() as usize * bpp;
+    for y in 0..rect.height() as usize {
+        let dst = ((rect.top as usize + y) * width + rect.left as usize) * bpp;
+        fb[dst..dst + row].copy_from_slice(&bitmap[y * row..(y + 1) * row]);
+    }
+}
+
+/// Decode `data` into a packed framebuffer
+///
+/// Returns (width, height, pixels) with `bpp` bytes per pixel.
+pub fn decode(data: &[u8], scale: u8, bpp: usize) -> (u16, u16, Vec<u8>) {
+    let (mut decoder, mut mcu_buffer, _) = prepared(data);
+    let mut work_buffer = vec![0u8; decoder.work_buffer_size_for_scale(scale)];
+    let width = (decoder.raw_width() >> scale) as usize;
+    let height = (decoder.raw_height() >> scale) as usize;
+    let mut fb = vec![0u8; width * height * bpp];
+
+    decoder
+        .decompress(data, scale, &mut mcu_buffer, &mut work_buffer, &mut |_, bitmap, rect| {
+            blit_rect(&mut fb, width, bpp, bitmap, rect);
+            Ok(true)
+        })
+        .expect("decompress");
+
+    (width as u16, height as u16, fb)
+}
+
+/// Largest absolute per-byte difference between two buffers
+pub fn max_diff(a: &[u8], b: &[u8]) -> u8 {
+    assert_eq!(a.len(), b.len());
+    a.iter().zip(b).map(|(&x, &y)| x.abs_diff(y)).max().unwrap_or(0)
+}
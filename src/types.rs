@@ -18,7 +18,7 @@ pub enum Error {
     Input = 2,
     /// Insufficient memory pool for the image
     InsufficientMemory = 3,
-    /// Insufficient stream input buffer
+    /// Insufficient stream input buffer or work buffer
     InsufficientBuffer = 4,
     /// Parameter error
     Parameter = 5,
@@ -28,6 +28,8 @@ pub enum Error {
     UnsupportedFormat = 7,
     /// Not supported JPEG standard
     UnsupportedStandard = 8,
+    /// Progressive JPEG, only baseline is supported (re-save as baseline)
+    ProgressiveUnsupported = 9,
 }
 
 impl Error {
@@ -43,6 +45,7 @@ impl Error {
             Error::FormatError => "Format error",
             Error::UnsupportedFormat => "Unsupported format",
             Error::UnsupportedStandard => "Unsupported JPEG standard",
+            Error::ProgressiveUnsupported => "Progressive JPEG not supported, re-save as baseline",
         }
     }
 }
@@ -57,6 +60,52 @@ impl std::fmt::Display for Error {
 #[cfg(feature = "std")]
 impl std::error::Error for Error {}
 
+/// Unit of the JFIF pixel density
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DensityUnit {
+    /// No unit, the densities only give the pixel aspect ratio
+    AspectRatio,
+    /// Dots per inch
+    PerInch,
+    /// Dots per centimeter
+    PerCm,
+}
+
+/// Per-component entry of the SOF header
+/// 
+/// Mirrors the component specification read from the frame header.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ComponentInfo {
+    /// Component identifier (usually 1 = Y, 2 = Cb, 3 = Cr)
+    pub id: u8,
+    /// Horizontal sampling factor
+    pub h_sampling: u8,
+    /// Vertical sampling factor
+    pub v_sampling: u8,
+    /// Quantization table selector
+    pub quant_table_id: u8,
+}
+
+/// Non-fatal condition noticed while parsing or decoding
+/// 
+/// Collected by the decoder and available from `JpegDecoder::warnings()`;
+/// the image was still decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Warning {
+    /// An `RSTn` marker did not carry the expected sequence number
+    RestartOutOfSequence = 0,
+    /// An APPn segment with an unrecognized identifier was skipped
+    UnknownAppSegment = 1,
+    /// Data follows the EOI marker
+    TrailingData = 2,
+}
+
+impl Warning {
+    /// Number of distinct warnings
+    pub const COUNT: usize = 3;
+}
+
 /// Rectangular region in the output image
 /// 
 /// Specifies pixel region in output callbacks. Coordinates are inclusive.
@@ -87,6 +136,11 @@ impl Rectangle {
     pub fn height(&self) -> u16 {
         self.bottom.saturating_sub(self.top).saturating_add(1)
     }
+
+    /// Check whether two rectangles share at least one pixel
+    pub fn overlaps(&self, other: &Rectangle) -> bool {
+        self.left <= other.right && other.left <= self.right && self.top <= other.bottom && other.top <= self.bottom
+    }
 }
 
 /// Output pixel format
@@ -99,6 +153,275 @@ pub enum OutputFormat {
     Rgb565 = 1,
     /// Grayscale (8-bit/pixel, 1 byte)
     Grayscale = 2,
+    /// RGB666 (18-bit/pixel, 3 bytes; 6 bits per channel in the upper bits of each byte)
+    Rgb666 = 3,
+    /// 1-bit monochrome, 8 pixels per byte (MSB = leftmost pixel, 1 = white)
+    Mono1 = 4,
+    /// 4-bit grayscale, 2 pixels per byte (high nibble = left pixel)
+    Gray4 = 5,
+    /// BGR565 (16-bit/pixel, 2 bytes; blue in the high bits)
+    Bgr565 = 6,
+    /// RGBA8888 (32-bit/pixel, 4 bytes in R, G, B, A order; alpha is always 0xFF)
+    Rgba8888 = 7,
+    /// BGRA8888 (32-bit/pixel, 4 bytes in B, G, R, A order; alpha is always 0xFF)
+    Bgra8888 = 8,
+    /// Planar RGB (3 bytes/pixel in three planes): the callback bitmap of a
+    /// `w` x `h` rectangle holds `w * h` red bytes, then `w * h` green bytes,
+    /// then `w * h` blue bytes, each plane with a row stride of `w`.
+    /// Callback-only; not accepted by rotation, cropping or the APIs that
+    /// assemble interleaved images
+    PlanarRgb = 9,
+}
+
+/// Luma to 1-bit conversion used by `OutputFormat::Mono1`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MonoMode {
+    /// Pixels with luma `>=` the threshold become white
+    Threshold(u8),
+    /// 4x4 Bayer ordered dither, stateless and seamless across MCUs
+    OrderedDither,
+}
+
+impl Default for MonoMode {
+    fn default() -> Self {
+        MonoMode::Threshold(128)
+    }
+}
+
+/// Handling of RGB values outside `0..=255` after color conversion
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GamutMode {
+    /// Clamp each channel independently (fast, may shift hue)
+    #[default]
+    Clamp,
+    /// Scale all channels down so the largest fits, preserving hue
+    Scale,
+}
+
+/// Position of the subsampled chroma samples relative to luma
+/// 
+/// Only matters for 4:2:2 and 4:2:0 images.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChromaSiting {
+    /// Chroma sample sits midway between the luma samples it covers (JFIF)
+    #[default]
+    Centered,
+    /// Chroma sample sits on the first (top-left) luma sample it covers
+    /// (MPEG-2 / BT.601 video style); luma samples in between get the
+    /// average of the two neighboring chroma samples
+    Cosited,
+}
+
+/// How subsampled chroma is expanded to the luma resolution
+/// 
+/// Only matters for 4:2:2 and 4:2:0 images.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChromaUpsample {
+    /// Every luma sample takes the chroma sample covering it (fastest,
+    /// blocky color on smooth gradients)
+    #[default]
+    Nearest,
+    /// Interpolate between the two nearest chroma samples per axis with 3:1
+    /// weights, like libjpeg's "fancy upsampling"; at the MCU edge the last
+    /// chroma sample of the MCU is repeated
+    Bilinear,
+}
+
+/// YCbCr to RGB conversion matrix
+/// 
+/// All presets treat Y, Cb and Cr as full range (0..=255), as JFIF does;
+/// they differ in coefficients and arithmetic precision.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorMatrix {
+    /// BT.601 with TJpgDec's truncated 10-bit coefficients (fastest, can be
+    /// 1-2 levels off a reference decoder)
+    #[default]
+    Bt601,
+    /// BT.709 coefficients with rounding, for HD video sources (MJPEG from
+    /// cameras and capture cards)
+    Bt709,
+    /// BT.601 coefficients with rounding, as libjpeg and other reference
+    /// decoders compute JFIF (within 1 level of them)
+    Full601,
+}
+
+/// Clockwise rotation applied to the decoded output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Rotation {
+    /// Output as stored
+    #[default]
+    None,
+    /// Rotate 90° clockwise
+    Cw90,
+    /// Rotate 180°
+    Cw180,
+    /// Rotate 270° clockwise (90° counter-clockwise)
+    Cw270,
+}
+
+impl Rotation {
+    /// Rotation that displays an image with the given EXIF orientation upright
+    /// 
+    /// Mirrored orientations (2, 4, 5, 7) and invalid values map to
+    /// `Rotation::None`, as the decoder doesn't flip images.
+    pub fn from_orientation(orientation: u8) -> Self {
+        match orientation {
+            3 => Rotation::Cw180,
+            6 => Rotation::Cw90,
+            8 => Rotation::Cw270,
+            _ => Rotation::None,
+        }
+    }
+
+    /// 是否交换宽高
+    pub(crate) fn swaps_axes(self) -> bool {
+        matches!(self, Rotation::Cw90 | Rotation::Cw270)
+    }
+}
+
+/// Position of an MCU in the image's MCU grid
+/// 
+/// Passed to `decompress_with_info()` callbacks alongside the rectangle.
+/// Always refers to the stored (unrotated) image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct McuInfo {
+    /// MCU column, counted from the left
+    pub mcu_col: u32,
+    /// MCU row, counted from the top
+    pub mcu_row: u32,
+    /// Raster-order index (`mcu_row * mcus_across + mcu_col`)
+    pub mcu_index: u32,
+    /// Number of MCUs in the image
+    pub total_mcus: u32,
+}
+
+/// Block layout of the raw MCU passed to `decompress_raw_ycbcr()` callbacks
+/// 
+/// The MCU data is a run of 8x8 blocks of `i16` samples, 64 each in
+/// row-major order: first `h_blocks * v_blocks` luma blocks (left to right,
+/// then top to bottom), then `chroma_blocks` chroma blocks (Cb, then Cr),
+/// each covering the whole MCU at the subsampled resolution. Samples are the
+/// IDCT output with the +128 level shift applied but not clipped, so they
+/// may fall slightly outside `0..=255`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct McuLayout {
+    /// Luma blocks across the MCU (the horizontal sampling factor)
+    pub h_blocks: u8,
+    /// Luma blocks down the MCU (the vertical sampling factor)
+    pub v_blocks: u8,
+    /// Chroma blocks after the luma blocks: 2 for YCbCr, 0 for grayscale
+    /// images and `OutputFormat::Grayscale`
+    pub chroma_blocks: u8,
+}
+
+impl McuLayout {
+    /// Index of the luma sample at `(x, y)` relative to the MCU's top-left
+    pub fn luma_index(&self, x: usize, y: usize) -> usize {
+        ((y / 8) * self.h_blocks as usize + x / 8) * 64 + (y % 8) * 8 + x % 8
+    }
+
+    /// Index of the first sample of chroma plane `plane` (0 = Cb, 1 = Cr)
+    pub fn chroma_offset(&self, plane: usize) -> usize {
+        (self.h_blocks as usize * self.v_blocks as usize + plane) * 64
+    }
+
+    /// Number of `i16` samples in the MCU
+    pub fn sample_count(&self) -> usize {
+        (self.h_blocks as usize * self.v_blocks as usize + self.chroma_blocks as usize) * 64
+    }
+}
+
+/// Decoded image owning its pixels
+/// 
+/// Returned by `decode_frame()`. Rows are tightly packed, `width * bpp`
+/// bytes each, where `bpp` follows `format` (3 for RGB888, 1 for grayscale).
+/// 
+/// ```
+/// use tjpgdec_rs::{Frame, OutputFormat};
+/// 
+/// let frame = Frame::new(2, 1, OutputFormat::Rgb888, vec![255, 0, 0, 0, 0, 255]).unwrap();
+/// assert_eq!(frame.pixel(1, 0), Some(&[0, 0, 255][..]));
+/// assert_eq!(frame.pixel(2, 0), None);
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pixels: Vec<u8>,
+    width: u16,
+    height: u16,
+    format: OutputFormat,
+}
+
+#[cfg(feature = "std")]
+impl Frame {
+    /// Wrap packed pixel data
+    /// 
+    /// # Returns
+    /// 
+    /// `Error::Parameter` if `format` packs several pixels per byte
+    /// (Mono1/Gray4) or `pixels` isn't exactly `width * height * bpp` bytes.
+    pub fn new(width: u16, height: u16, format: OutputFormat, pixels: Vec<u8>) -> Result<Self> {
+        let bpp = Self::format_bpp(format);
+        if bpp == 0 || pixels.len() != width as usize * height as usize * bpp {
+            return Err(Error::Parameter);
+        }
+        Ok(Self { pixels, width, height, format })
+    }
+
+    /// 每个像素的字节数，打包格式返回 0
+    fn format_bpp(format: OutputFormat) -> usize {
+        match format {
+            OutputFormat::Rgba8888 | OutputFormat::Bgra8888 => 4,
+            OutputFormat::Rgb888 | OutputFormat::Rgb666 => 3,
+            OutputFormat::Rgb565 | OutputFormat::Bgr565 => 2,
+            OutputFormat::Grayscale => 1,
+            OutputFormat::Mono1 | OutputFormat::Gray4 | OutputFormat::PlanarRgb => 0,
+        }
+    }
+
+    /// Get image width in pixels
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    /// Get image height in pixels
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    /// Get the pixel format of the data
+    pub fn format(&self) -> OutputFormat {
+        self.format
+    }
+
+    /// Get bytes per pixel
+    pub fn bytes_per_pixel(&self) -> usize {
+        Self::format_bpp(self.format)
+    }
+
+    /// Get the whole image as packed bytes
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Take ownership of the pixel data
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.pixels
+    }
+
+    /// Get row `y`, or `None` if it is outside the image
+    pub fn row(&self, y: u16) -> Option<&[u8]> {
+        let len = self.width as usize * self.bytes_per_pixel();
+        let start = y as usize * len;
+        (y < self.height).then(|| &self.pixels[start..start + len])
+    }
+
+    /// Get the bytes of pixel (`x`, `y`), or `None` if it is outside the image
+    pub fn pixel(&self, x: u16, y: u16) -> Option<&[u8]> {
+        let bpp = self.bytes_per_pixel();
+        let start = x as usize * bpp;
+        self.row(y).filter(|_| x < self.width).map(|row| &row[start..start + bpp])
+    }
 }
 
 /// YUV value type - changes based on optimization level